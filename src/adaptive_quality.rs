@@ -0,0 +1,297 @@
+//! Adjusts quality to hold a frame rate, e.g. on weak GPUs: when frames take too long, this turns
+//! off MSAA, then lowers the resolution the 3D scene renders at, in steps. When there's headroom
+//! again, it restores them in reverse order.
+//!
+//! Frame cost is measured from GPU pass times where the adapter supports timestamp queries.
+//! Otherwise, it's the time between frames; unless something other than the frame's cost sets
+//! that, e.g. vsync, `RenderMode::OnDemand`, or `max_fps`. Then, only the CPU time spent encoding
+//! the frame is known, so only CPU-bound frames lower quality.
+
+use std::time::Duration;
+
+use crate::frame_timing::FrameTiming;
+
+/// How often to re-evaluate quality, in seconds. Frame times are averaged over this.
+const INTERVAL: f32 = 0.5;
+/// The amount to change the render scale by in each step.
+const SCALE_STEP: f32 = 0.1;
+/// Only raise quality if the estimated cost after doing so is under this fraction of the budget,
+/// to avoid oscillating.
+const HEADROOM: f32 = 0.85;
+/// The estimated cost of the configured MSAA, relative to none.
+const MSAA_COST: f32 = 1.6;
+/// A frame this long after the previous one follows an idle gap, e.g. the event loop paused while
+/// the window was hidden; its interval doesn't reflect its cost.
+const IDLE_GAP: Duration = Duration::from_millis(250);
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AdaptiveQuality {
+    /// The frame rate to hold.
+    pub target_fps: f32,
+    /// The lowest render scale, as a fraction of the window's resolution on each axis.
+    pub min_scale: f32,
+    /// Turn off MSAA before lowering the resolution.
+    pub reduce_msaa: bool,
+}
+
+impl Default for AdaptiveQuality {
+    fn default() -> Self {
+        Self {
+            target_fps: 60.,
+            min_scale: 0.5,
+            reduce_msaa: true,
+        }
+    }
+}
+
+/// A change the controller made, to apply to the renderer.
+#[derive(Debug, PartialEq)]
+pub(crate) enum QualityChange {
+    Scale(f32),
+    Msaa(u32),
+}
+
+/// Applies `GraphicsSettings::adaptive_quality`.
+pub(crate) struct QualityController {
+    pub settings: Option<AdaptiveQuality>,
+    /// From `GraphicsSettings::msaa_samples`.
+    msaa_configured: u32,
+    msaa_reduced: bool,
+    pub scale: f32,
+    /// Measured frame cost, and frames measured, in the current interval.
+    cost: Duration,
+    frames: u32,
+    elapsed: f32,
+}
+
+impl Default for QualityController {
+    fn default() -> Self {
+        Self {
+            settings: None,
+            msaa_configured: 1,
+            msaa_reduced: false,
+            scale: 1.,
+            cost: Duration::ZERO,
+            frames: 0,
+            elapsed: 0.,
+        }
+    }
+}
+
+impl QualityController {
+    /// Apply new settings. Returns the render scale to use, which is 1 if this is disabled.
+    pub fn configure(&mut self, settings: Option<AdaptiveQuality>, msaa: u32) -> f32 {
+        if settings.is_none() || msaa != self.msaa_configured {
+            self.msaa_reduced = false;
+        }
+        if let Some(s) = &settings {
+            self.scale = self.scale.clamp(s.min_scale.min(1.), 1.);
+        } else {
+            self.scale = 1.;
+        }
+
+        self.settings = settings;
+        self.msaa_configured = msaa;
+        self.scale
+    }
+
+    /// The MSAA sample count to render with.
+    pub fn msaa(&self) -> u32 {
+        if self.msaa_reduced {
+            1
+        } else {
+            self.msaa_configured
+        }
+    }
+
+    /// Measure a frame. `gpu_timed` is whether the device supports timestamp queries; if so, only
+    /// frames with GPU times are counted. `paced` is whether something other than frame cost sets
+    /// the time between frames, e.g. vsync; if so, and they aren't GPU-timed, only encode time is
+    /// counted.
+    pub fn observe(
+        &mut self,
+        timing: &FrameTiming,
+        gpu_timed: bool,
+        paced: bool,
+    ) -> Option<QualityChange> {
+        let settings = self.settings.as_ref()?;
+
+        let idle = timing.frame_time >= IDLE_GAP;
+        // Don't let one gap end the interval; the frames in it are what's averaged.
+        self.elapsed += timing.frame_time.min(IDLE_GAP).as_secs_f32();
+
+        let cost = if gpu_timed {
+            timing.gpu_time()
+        } else if paced {
+            Some(timing.encode_time)
+        } else {
+            (!idle).then_some(timing.frame_time)
+        };
+        if let Some(c) = cost {
+            self.cost += c;
+            self.frames += 1;
+        }
+
+        if self.elapsed < INTERVAL || self.frames == 0 {
+            return None;
+        }
+
+        let cost = self.cost.as_secs_f32() / self.frames as f32;
+        let budget = 1. / settings.target_fps.max(1.);
+        let min_scale = settings.min_scale.clamp(0.1, 1.);
+        let can_reduce_msaa = settings.reduce_msaa && self.msaa_configured > 1;
+
+        self.cost = Duration::ZERO;
+        self.frames = 0;
+        self.elapsed = 0.;
+
+        if cost > budget {
+            if can_reduce_msaa && !self.msaa_reduced {
+                self.msaa_reduced = true;
+                return Some(QualityChange::Msaa(1));
+            }
+            if self.scale > min_scale {
+                self.scale = (self.scale - SCALE_STEP).max(min_scale);
+                return Some(QualityChange::Scale(self.scale));
+            }
+        } else if self.scale < 1. {
+            // Cost is roughly proportional to the pixel count.
+            let next = (self.scale + SCALE_STEP).min(1.);
+            if cost * (next / self.scale).powi(2) < budget * HEADROOM {
+                self.scale = next;
+                return Some(QualityChange::Scale(self.scale));
+            }
+        } else if self.msaa_reduced && cost * MSAA_COST < budget * HEADROOM {
+            self.msaa_reduced = false;
+            return Some(QualityChange::Msaa(self.msaa_configured));
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timing(frame_ms: f32, encode_ms: f32, gpu_ms: Option<f32>) -> FrameTiming {
+        let ms = |t: f32| Duration::from_secs_f32(t / 1_000.);
+        FrameTiming {
+            frame: 0,
+            frame_time: ms(frame_ms),
+            encode_time: ms(encode_ms),
+            gpu_passes: gpu_ms.map(|t| vec![("main", ms(t))]),
+        }
+    }
+
+    fn controller(msaa: u32) -> QualityController {
+        let mut result = QualityController::default();
+        result.configure(Some(AdaptiveQuality::default()), msaa);
+        result
+    }
+
+    /// Observe copies of `timing` until the controller evaluates an interval.
+    fn interval(
+        ctrl: &mut QualityController,
+        timing: &FrameTiming,
+        gpu_timed: bool,
+        paced: bool,
+    ) -> Option<QualityChange> {
+        loop {
+            let change = ctrl.observe(timing, gpu_timed, paced);
+            if ctrl.elapsed == 0. {
+                return change;
+            }
+        }
+    }
+
+    #[test]
+    fn steps_down_when_over_budget() {
+        let mut ctrl = controller(4);
+        let slow = timing(25., 2., None);
+
+        assert_eq!(
+            interval(&mut ctrl, &slow, false, false),
+            Some(QualityChange::Msaa(1))
+        );
+        assert_eq!(ctrl.msaa(), 1);
+
+        for expected in [0.9, 0.8, 0.7, 0.6, 0.5] {
+            match interval(&mut ctrl, &slow, false, false) {
+                Some(QualityChange::Scale(s)) => assert!((s - expected).abs() < 1e-4),
+                other => panic!("expected a scale step, got {other:?}"),
+            }
+        }
+        // At the minimum scale.
+        assert_eq!(interval(&mut ctrl, &slow, false, false), None);
+    }
+
+    #[test]
+    fn recovers_when_under_budget() {
+        let mut ctrl = controller(4);
+        let slow = timing(25., 2., None);
+        for _ in 0..6 {
+            interval(&mut ctrl, &slow, false, false);
+        }
+        assert!(ctrl.scale < 0.51);
+
+        let fast = timing(4., 2., None);
+        for _ in 0..5 {
+            assert!(matches!(
+                interval(&mut ctrl, &fast, false, false),
+                Some(QualityChange::Scale(_))
+            ));
+        }
+        assert!((ctrl.scale - 1.).abs() < 1e-4);
+        assert_eq!(
+            interval(&mut ctrl, &fast, false, false),
+            Some(QualityChange::Msaa(4))
+        );
+        assert_eq!(interval(&mut ctrl, &fast, false, false), None);
+    }
+
+    #[test]
+    fn settles_near_budget() {
+        let mut ctrl = controller(1);
+        // GPU cost proportional to pixel count: over budget at full scale, under it at 0.9.
+        let mut changes = 0;
+        for i in 0..40 {
+            let gpu = 20. * ctrl.scale.powi(2);
+            let change = interval(&mut ctrl, &timing(16.7, 2., Some(gpu)), true, true);
+            if change.is_some() {
+                changes += 1;
+                assert!(i < 2, "quality changed at interval {i}");
+            }
+        }
+        assert_eq!(changes, 1);
+        assert!((ctrl.scale - 0.9).abs() < 1e-4);
+    }
+
+    #[test]
+    fn vsync_interval_isnt_cost() {
+        let mut ctrl = controller(1);
+        ctrl.scale = 0.5;
+        // Pinned just over the budget by vsync, but cheap to encode; quality recovers.
+        let pinned = timing(16.8, 3., None);
+        for _ in 0..5 {
+            assert!(matches!(
+                interval(&mut ctrl, &pinned, false, true),
+                Some(QualityChange::Scale(_))
+            ));
+        }
+        assert_eq!(interval(&mut ctrl, &pinned, false, true), None);
+        assert!((ctrl.scale - 1.).abs() < 1e-4);
+    }
+
+    #[test]
+    fn idle_gap_isnt_cost() {
+        let mut ctrl = controller(4);
+        assert_eq!(ctrl.observe(&timing(5_000., 2., None), false, false), None);
+
+        let fast = timing(10., 2., None);
+        for _ in 0..4 {
+            assert_eq!(interval(&mut ctrl, &fast, false, false), None);
+        }
+        assert_eq!(ctrl.msaa(), 4);
+    }
+}
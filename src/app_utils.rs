@@ -8,6 +8,9 @@ use std::{
 };
 
 use bincode::{Decode, Encode};
+use tracing::warn;
+
+use crate::{Scene, UiSettings, WindowPlacement};
 
 /// Save to file, using Bincode. We currently use this for preference files.
 pub fn save<T: Encode>(path: &Path, data: &T) -> io::Result<()> {
@@ -31,9 +34,84 @@ pub fn load<T: Decode<()>>(path: &Path) -> io::Result<T> {
     let (decoded, _len) = match bincode::decode_from_slice(&buffer, config) {
         Ok(v) => v,
         Err(_) => {
-            eprintln!("Error loading from file. Did the format change?");
+            warn!("Error loading from file. Did the format change?");
             return Err(io::Error::new(ErrorKind::Other, "error loading"));
         }
     };
     Ok(decoded)
 }
+
+/// The window's size, position, and maximized state, and the size of the UI panel. Save this on
+/// exit, e.g. from the `on_exit` callback passed to `run_with_proxy`, and restore it before
+/// calling `run` on the next launch.
+#[derive(Clone, Debug, Encode, Decode)]
+pub struct WindowState {
+    /// In logical pixels.
+    pub size: (f32, f32),
+    /// The index in `Scene::monitors` of the monitor the window was on.
+    pub monitor: Option<usize>,
+    /// The window's top left corner, relative to the monitor's, in logical pixels.
+    pub position: Option<(f32, f32)>,
+    pub maximized: bool,
+    /// The space reserved by the UI, as reported in `EngineUpdates::ui_reserved_px`. Use this to
+    /// set the panel's default size.
+    pub ui_size: (f32, f32),
+}
+
+impl WindowState {
+    /// Capture the window state from the scene.
+    pub fn from_scene(scene: &Scene) -> Self {
+        // The monitor containing the window's top left corner.
+        let on_monitor = scene.window_position.and_then(|(x, y)| {
+            scene.monitors.iter().enumerate().find(|(_, m)| {
+                x >= m.position.0
+                    && y >= m.position.1
+                    && x < m.position.0 + m.size.0 as i32
+                    && y < m.position.1 + m.size.1 as i32
+            })
+        });
+
+        // After startup, `Scene::window_size` is in physical pixels.
+        let scale = on_monitor.map(|(_, m)| m.scale_factor as f32).unwrap_or(1.);
+
+        let (monitor, position) = match (on_monitor, scene.window_position) {
+            (Some((i, m)), Some((x, y))) => (
+                Some(i),
+                Some((
+                    (x - m.position.0) as f32 / scale,
+                    (y - m.position.1) as f32 / scale,
+                )),
+            ),
+            _ => (None, None),
+        };
+
+        Self {
+            size: (scene.window_size.0 / scale, scene.window_size.1 / scale),
+            monitor,
+            position,
+            maximized: scene.window_maximized,
+            ui_size: scene.gui_size,
+        }
+    }
+
+    /// Apply to the scene and UI settings passed to `run`.
+    pub fn apply(&self, scene: &mut Scene, ui_settings: &mut UiSettings) {
+        scene.window_size = self.size;
+
+        ui_settings.window_placement = WindowPlacement {
+            monitor: self.monitor,
+            position: self.position,
+            maximized: self.maximized,
+        };
+    }
+
+    /// Save to file.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        save(path, self)
+    }
+
+    /// Load from file. Returns `None` if there's no saved state, e.g. on first launch.
+    pub fn load(path: &Path) -> Option<Self> {
+        load(path).ok()
+    }
+}
@@ -0,0 +1,170 @@
+//! Named per-vertex data on a `Mesh`, e.g. electrostatic potential over a molecular surface, kept
+//! apart from its geometry. Each can be routed to one of the vertex shader's inputs, with scalars
+//! optionally colored by a `Colormap`, so a surface property can be shown, or switched for
+//! another, without rebuilding the mesh's vertices. Routing is applied when the mesh uploads.
+
+#[cfg(feature = "app_utils")]
+use bincode::{Decode, Encode};
+use tracing::warn;
+
+use crate::{colormap::Colormap, types::VertexRaw};
+
+/// Per-vertex values, one per vertex of the mesh, in order.
+#[cfg_attr(feature = "app_utils", derive(Encode, Decode))]
+#[derive(Clone, Debug)]
+pub enum AttributeValues {
+    Scalar(Vec<f32>),
+    Vec2(Vec<[f32; 2]>),
+    Vec3(Vec<[f32; 3]>),
+    Vec4(Vec<[f32; 4]>),
+}
+
+impl AttributeValues {
+    pub fn len(&self) -> usize {
+        match self {
+            Self::Scalar(v) => v.len(),
+            Self::Vec2(v) => v.len(),
+            Self::Vec3(v) => v.len(),
+            Self::Vec4(v) => v.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The values at these vertices, in order.
+    pub(crate) fn select(&self, vertices: &[usize]) -> Self {
+        match self {
+            Self::Scalar(v) => Self::Scalar(vertices.iter().map(|&i| v[i]).collect()),
+            Self::Vec2(v) => Self::Vec2(vertices.iter().map(|&i| v[i]).collect()),
+            Self::Vec3(v) => Self::Vec3(vertices.iter().map(|&i| v[i]).collect()),
+            Self::Vec4(v) => Self::Vec4(vertices.iter().map(|&i| v[i]).collect()),
+        }
+    }
+
+    /// The value at a vertex, padded with 0s.
+    fn get(&self, i: usize) -> [f32; 4] {
+        match self {
+            Self::Scalar(v) => [v[i], 0., 0., 0.],
+            Self::Vec2(v) => [v[i][0], v[i][1], 0., 0.],
+            Self::Vec3(v) => [v[i][0], v[i][1], v[i][2], 0.],
+            Self::Vec4(v) => v[i],
+        }
+    }
+
+    /// A scalar at a vertex: the value itself, or a vector's length.
+    fn magnitude(&self, i: usize) -> f32 {
+        match self {
+            Self::Scalar(v) => v[i],
+            _ => {
+                let v = self.get(i);
+                v.iter().map(|c| c * c).sum::<f32>().sqrt()
+            }
+        }
+    }
+
+    /// The min and max of `magnitude`, ignoring non-finite values.
+    pub fn range(&self) -> Option<(f32, f32)> {
+        (0..self.len())
+            .map(|i| self.magnitude(i))
+            .filter(|v| v.is_finite())
+            .fold(None, |acc, v| match acc {
+                Some((lo, hi)) => Some((f32::min(lo, v), f32::max(hi, v))),
+                None => Some((v, v)),
+            })
+    }
+}
+
+/// Which vertex shader input an attribute replaces. Inputs not targeted come from `Vertex`.
+#[cfg_attr(feature = "app_utils", derive(Encode, Decode))]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum AttributeTarget {
+    /// The first 2 components.
+    TexCoords,
+    /// The first 3 components; these should be normalized.
+    Normal,
+    /// sRGB, from 0 to 1: the first 3 components, and alpha from the 4th if present. As with
+    /// `Vertex::color`, vertices with an alpha of 0 use the entity's color.
+    Color,
+    /// `Vertex::scalar`, e.g. for isolines. Vectors use their length.
+    Scalar,
+    /// Colored, opaque, by this colormap; vectors by their length. `range` maps to its start and
+    /// end; if `None`, it's the attribute's min and max.
+    Colormap {
+        colormap: Colormap,
+        range: Option<(f32, f32)>,
+    },
+}
+
+impl AttributeTarget {
+    /// The `@location` of the input in the mesh shader.
+    pub fn shader_location(self) -> u32 {
+        match self {
+            Self::TexCoords => 1,
+            Self::Normal => 2,
+            Self::Color | Self::Colormap { .. } => 5,
+            Self::Scalar => 15,
+        }
+    }
+}
+
+/// Named per-vertex data on a `Mesh`. See `Mesh::set_attribute`.
+#[cfg_attr(feature = "app_utils", derive(Encode, Decode))]
+#[derive(Clone, Debug)]
+pub struct MeshAttribute {
+    pub name: String,
+    pub values: AttributeValues,
+    /// The shader input these values are drawn with; `None` keeps them on the mesh, undrawn, e.g.
+    /// to switch to later, or to read back at a picked vertex.
+    pub target: Option<AttributeTarget>,
+}
+
+impl MeshAttribute {
+    /// Write these values over the vertices' targeted input.
+    pub(crate) fn apply(&self, vertices: &mut [VertexRaw]) {
+        let Some(target) = self.target else {
+            return;
+        };
+
+        if self.values.len() != vertices.len() {
+            warn!(
+                "Vertex attribute \"{}\" has {} values, for {} vertices",
+                self.name,
+                self.values.len(),
+                vertices.len()
+            );
+        }
+
+        let colormap_range = match target {
+            AttributeTarget::Colormap { range, .. } => range.or_else(|| self.values.range()),
+            _ => None,
+        };
+
+        for (i, vertex) in vertices.iter_mut().enumerate().take(self.values.len()) {
+            let v = self.values.get(i);
+
+            match target {
+                AttributeTarget::TexCoords => vertex.tex_coords = [v[0], v[1]],
+                AttributeTarget::Normal => vertex.normal = [v[0], v[1], v[2]],
+                AttributeTarget::Color => {
+                    let alpha = match self.values {
+                        AttributeValues::Vec4(_) => v[3],
+                        _ => 1.,
+                    };
+                    vertex.color = [to_u8(v[0]), to_u8(v[1]), to_u8(v[2]), to_u8(alpha)];
+                }
+                AttributeTarget::Scalar => vertex.scalar = [self.values.magnitude(i), 1.],
+                AttributeTarget::Colormap { colormap, .. } => {
+                    let c =
+                        colormap.map(self.values.magnitude(i), colormap_range.unwrap_or((0., 1.)));
+                    vertex.color = [to_u8(c.0), to_u8(c.1), to_u8(c.2), 255];
+                }
+            }
+        }
+    }
+}
+
+fn to_u8(v: f32) -> u8 {
+    (v.clamp(0., 1.) * 255.).round() as u8
+}
@@ -0,0 +1,123 @@
+//! Backgrounds drawn behind the scene, instead of a flat clear color.
+
+use bytemuck::{Pod, Zeroable};
+use lin_alg::f32::Mat4;
+use wgpu::{
+    BindGroup, BindGroupLayout, Buffer, BufferUsages, Device, Queue, ShaderStages, util::DeviceExt,
+};
+
+use crate::{Camera, color::linearize};
+
+/// What's drawn behind the scene. Colors are sRGB.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum Background {
+    /// `Scene::background_color`.
+    #[default]
+    Solid,
+    /// A vertical gradient across the view, fixed to the screen.
+    Gradient {
+        top: (f32, f32, f32),
+        bottom: (f32, f32, f32),
+    },
+    /// A sky and ground meeting at the horizon, in world space, so they move with the camera.
+    /// +Y is up. The sky fades from `horizon` at the horizon to `sky` overhead.
+    Horizon {
+        sky: (f32, f32, f32),
+        horizon: (f32, f32, f32),
+        ground: (f32, f32, f32),
+    },
+}
+
+/// Matches `Background` in `shader_background.wgsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct BackgroundUniform {
+    proj_view_inv: [f32; 16],
+    upper: [f32; 4],
+    middle: [f32; 4],
+    lower: [f32; 4],
+    mode: u32,
+    _pad: [u32; 3],
+}
+
+impl BackgroundUniform {
+    fn new(background: Background, camera: &Camera) -> Self {
+        let vec4 = |c: (f32, f32, f32)| {
+            let c = linearize(c);
+            [c[0], c[1], c[2], 1.]
+        };
+
+        let (upper, middle, lower, mode) = match background {
+            Background::Solid => ([0.; 4], [0.; 4], [0.; 4], 0),
+            Background::Gradient { top, bottom } => (vec4(top), [0.; 4], vec4(bottom), 0),
+            Background::Horizon {
+                sky,
+                horizon,
+                ground,
+            } => (vec4(sky), vec4(horizon), vec4(ground), 1),
+        };
+
+        let proj_view = camera.proj_mat.clone() * camera.view_mat();
+
+        Self {
+            proj_view_inv: proj_view.inverse().unwrap_or_else(Mat4::new_identity).data,
+            upper,
+            middle,
+            lower,
+            mode,
+            _pad: [0; 3],
+        }
+    }
+}
+
+/// GPU state for drawing `Scene::background`.
+pub(crate) struct BackgroundRenderer {
+    pub layout: BindGroupLayout,
+    buf: Buffer,
+    pub bind_group: BindGroup,
+}
+
+impl BackgroundRenderer {
+    pub fn new(device: &Device) -> Self {
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Background bind group layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: wgpu::BufferSize::new(size_of::<BackgroundUniform>() as _),
+                },
+                count: None,
+            }],
+        });
+
+        let buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Background buffer"),
+            contents: bytemuck::bytes_of(&BackgroundUniform::zeroed()),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Background bind group"),
+            layout: &layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buf.as_entire_binding(),
+            }],
+        });
+
+        Self {
+            layout,
+            buf,
+            bind_group,
+        }
+    }
+
+    /// Write the background's colors, and the camera it's viewed from.
+    pub fn prepare(&self, queue: &Queue, background: Background, camera: &Camera) {
+        let uniform = BackgroundUniform::new(background, camera);
+        queue.write_buffer(&self.buf, 0, bytemuck::bytes_of(&uniform));
+    }
+}
@@ -0,0 +1,597 @@
+//! GPU buffers that persist across scene changes. Instead of recreating a buffer whenever its
+//! contents change, we allocate with slack capacity, and write only the byte range that differs
+//! from what's already on the GPU. We only recreate the buffer when its capacity is exceeded.
+//!
+//! Writes go through a staging belt, which reuses its staging memory across frames, instead of
+//! allocating for each `queue.write_buffer` call.
+//!
+//! Data appended past the capacity keeps the existing contents on the GPU: we copy them to the
+//! larger buffer there, instead of uploading them again. Data already in a GPU staging buffer,
+//! e.g. a mesh staged on a background thread, can be appended with a GPU-side copy too.
+//!
+//! A buffer can optionally be double-buffered: writes then go to a second buffer the current
+//! frame doesn't draw from, which becomes the drawn one at the next `flush`.
+
+use wgpu::{
+    Buffer, BufferDescriptor, BufferSize, BufferUsages, COPY_BUFFER_ALIGNMENT, CommandBuffer,
+    CommandEncoder, CommandEncoderDescriptor, Device, util::StagingBelt,
+};
+
+/// The size of each staging belt chunk. Uploads larger than this get a dedicated chunk, which is
+/// then reused for later uploads.
+const STAGING_CHUNK_SIZE: u64 = 1 << 20;
+
+/// Records buffer uploads from CPU data, via a staging belt. These are copied into the target
+/// buffers by a command buffer that's submitted ahead of the next frame's rendering commands.
+pub(crate) struct Uploader {
+    belt: StagingBelt,
+    /// Created on the first write after each submission.
+    encoder: Option<CommandEncoder>,
+    device: Device,
+}
+
+impl Uploader {
+    pub fn new(device: &Device) -> Self {
+        Self {
+            belt: StagingBelt::new(device.clone(), STAGING_CHUNK_SIZE),
+            encoder: None,
+            device: device.clone(),
+        }
+    }
+
+    /// Schedule `data` to be written to `target` at `offset`, which must be a multiple of 4. If
+    /// `data`'s length isn't, zeros are written after it up to the next one; e.g. for an odd
+    /// number of 16-bit indices. Those bytes must be unused, and within the buffer.
+    pub fn write(&mut self, target: &Buffer, offset: u64, data: &[u8]) {
+        debug_assert!(offset.is_multiple_of(COPY_BUFFER_ALIGNMENT));
+
+        let size = (data.len() as u64).next_multiple_of(COPY_BUFFER_ALIGNMENT);
+        let Some(size) = BufferSize::new(size) else {
+            return;
+        };
+
+        let encoder = encoder(&mut self.encoder, &self.device);
+        let mut view = self.belt.write_buffer(encoder, target, offset, size);
+        view.slice(..data.len()).copy_from_slice(data);
+        view.slice(data.len()..).fill(0);
+    }
+
+    /// Schedule `size` bytes to be copied from `source` at `source_offset` to `target` at
+    /// `offset`, on the GPU. Offsets and size must be multiples of 4.
+    pub fn copy(
+        &mut self,
+        source: &Buffer,
+        source_offset: u64,
+        target: &Buffer,
+        offset: u64,
+        size: u64,
+    ) {
+        if size == 0 {
+            return;
+        }
+
+        encoder(&mut self.encoder, &self.device).copy_buffer_to_buffer(
+            source,
+            source_offset,
+            target,
+            offset,
+            size,
+        );
+    }
+
+    /// Close out the writes recorded since the last call. Submit the result before, or along
+    /// with, the commands that use the written buffers, then call `recall`.
+    pub fn finish(&mut self) -> Option<CommandBuffer> {
+        let encoder = self.encoder.take()?;
+        self.belt.finish();
+
+        Some(encoder.finish())
+    }
+
+    /// Reclaim staging memory once the GPU is done with it. Call after submitting.
+    pub fn recall(&mut self) {
+        self.belt.recall();
+    }
+}
+
+/// The upload encoder, created on the first write after each submission.
+fn encoder<'a>(encoder: &'a mut Option<CommandEncoder>, device: &Device) -> &'a mut CommandEncoder {
+    encoder.get_or_insert_with(|| {
+        device.create_command_encoder(&CommandEncoderDescriptor {
+            label: Some("Upload encoder"),
+        })
+    })
+}
+
+/// When growing, allocate this multiple of the required size, so that subsequent additions
+/// don't each trigger a reallocation.
+const GROWTH_FACTOR: f32 = 1.5;
+
+pub(crate) struct GrowableBuffer {
+    /// The buffer draws read from.
+    pub buf: Buffer,
+    /// A CPU-side copy of the bytes in use on the GPU. We diff against this to find the range
+    /// to upload. When double-buffered, this includes writes not yet flushed to `buf`.
+    shadow: Vec<u8>,
+    /// When double-buffered, the buffer writes go to.
+    back: Option<BackBuffer>,
+    /// When double-buffered, the byte range written since the last flush.
+    pending: Option<(usize, usize)>,
+    label: &'static str,
+    usage: BufferUsages,
+}
+
+/// The buffer a double-buffered `GrowableBuffer` writes to.
+struct BackBuffer {
+    buf: Buffer,
+    /// The byte range this is missing relative to the front buffer: what changed at the last
+    /// flush, which went to the other buffer.
+    missed: Option<(usize, usize)>,
+}
+
+impl GrowableBuffer {
+    /// Creates an empty buffer. `COPY_DST` and `COPY_SRC` are added to `usage`; the latter to keep
+    /// the contents on the GPU when growing in `append_staged`.
+    pub fn new(device: &Device, label: &'static str, usage: BufferUsages) -> Self {
+        let usage = usage | BufferUsages::COPY_DST | BufferUsages::COPY_SRC;
+
+        Self {
+            buf: create(device, label, usage, 0),
+            shadow: Vec::new(),
+            back: None,
+            pending: None,
+            label,
+            usage,
+        }
+    }
+
+    /// The number of bytes in use. The buffer's capacity may be larger.
+    pub fn len(&self) -> usize {
+        self.shadow.len()
+    }
+
+    /// If no bytes are in use. The buffer's capacity may be larger.
+    pub fn is_empty(&self) -> bool {
+        self.shadow.is_empty()
+    }
+
+    /// The GPU memory allocated, in bytes; both buffers', if double-buffered.
+    pub fn capacity(&self) -> u64 {
+        self.buf.size() + self.back.as_ref().map(|b| b.buf.size()).unwrap_or(0)
+    }
+
+    /// Start or stop double-buffering. Stopping flushes pending writes first.
+    pub fn set_double_buffered(&mut self, device: &Device, uploader: &mut Uploader, on: bool) {
+        match (on, self.back.is_some()) {
+            (true, false) => {
+                self.back = Some(BackBuffer {
+                    buf: create(device, self.label, self.usage, self.buf.size()),
+                    missed: Some((0, self.shadow.len())),
+                });
+            }
+            (false, true) => {
+                self.flush(device, uploader);
+                self.back = None;
+            }
+            _ => (),
+        }
+    }
+
+    /// When double-buffered, upload the writes since the last flush to the back buffer, along
+    /// with those it missed at the last flush, then swap it to the front. Call once per frame,
+    /// before drawing. Does nothing otherwise.
+    pub fn flush(&mut self, device: &Device, uploader: &mut Uploader) {
+        let Some(back) = &mut self.back else {
+            return;
+        };
+        let Some(pending) = self.pending.take() else {
+            return;
+        };
+
+        let len = self.shadow.len();
+        let range = if len as u64 > back.buf.size() {
+            let capacity = (len as f32 * GROWTH_FACTOR) as u64;
+            back.buf = create(device, self.label, self.usage, capacity);
+            Some((0, len))
+        } else {
+            union(back.missed, Some(pending))
+        };
+
+        if let Some((start, end)) = range {
+            let end = end.min(len);
+            if start < end {
+                uploader.write(&back.buf, start as u64, &self.shadow[start..end]);
+            }
+        }
+
+        std::mem::swap(&mut self.buf, &mut back.buf);
+        back.missed = Some(pending);
+    }
+
+    /// Replace the buffer's contents with `data`. If it fits within the current capacity, only the
+    /// range that changed is uploaded; this covers in-place edits, as well as additions and
+    /// removals that shift the data after them.
+    pub fn write(&mut self, device: &Device, uploader: &mut Uploader, data: &[u8]) {
+        if self.back.is_some() {
+            self.pending = union(self.pending, changed_range(&self.shadow, data));
+        } else if data.len() as u64 > self.buf.size() {
+            let capacity = (data.len() as f32 * GROWTH_FACTOR) as u64;
+            self.buf = create(device, self.label, self.usage, capacity);
+
+            uploader.write(&self.buf, 0, data);
+        } else if let Some((start, end)) = changed_range(&self.shadow, data) {
+            uploader.write(&self.buf, start as u64, &data[start..end]);
+        }
+
+        self.shadow.clear();
+        self.shadow.extend_from_slice(data);
+    }
+
+    /// Append `data`, followed by zeros up to `reserve` bytes in all; e.g. to leave room for it to
+    /// grow in place. Not for double-buffered buffers.
+    pub fn append(
+        &mut self,
+        device: &Device,
+        uploader: &mut Uploader,
+        data: &[u8],
+        reserve: usize,
+    ) {
+        debug_assert!(self.back.is_none());
+
+        let offset = self.shadow.len();
+        let len = offset + reserve.max(data.len());
+        self.grow(device, uploader, len);
+
+        self.shadow.extend_from_slice(data);
+        self.shadow.resize(len, 0);
+        uploader.write(&self.buf, offset as u64, &self.shadow[offset..]);
+    }
+
+    /// Append `data`, which is already on the GPU in `staging` at `staging_offset`, with a GPU-side
+    /// copy. Zeros follow it up to `reserve` bytes in all, as with `append`. Not for
+    /// double-buffered buffers.
+    pub fn append_staged(
+        &mut self,
+        device: &Device,
+        uploader: &mut Uploader,
+        staging: &Buffer,
+        staging_offset: u64,
+        data: &[u8],
+        reserve: usize,
+    ) {
+        debug_assert!(self.back.is_none());
+
+        let offset = self.shadow.len();
+        let len = offset + reserve.max(data.len());
+        self.grow(device, uploader, len);
+
+        uploader.copy(
+            staging,
+            staging_offset,
+            &self.buf,
+            offset as u64,
+            data.len() as u64,
+        );
+        self.shadow.extend_from_slice(data);
+
+        let padding = self.shadow.len();
+        self.shadow.resize(len, 0);
+        uploader.write(&self.buf, padding as u64, &self.shadow[padding..]);
+    }
+
+    /// If `len` bytes exceed the capacity, move to a larger buffer, copying the existing contents
+    /// on the GPU.
+    fn grow(&mut self, device: &Device, uploader: &mut Uploader, len: usize) {
+        if len as u64 <= self.buf.size() {
+            return;
+        }
+
+        let capacity = (len as f32 * GROWTH_FACTOR) as u64;
+        let buf = create(device, self.label, self.usage, capacity);
+        uploader.copy(&self.buf, 0, &buf, 0, self.shadow.len() as u64);
+        self.buf = buf;
+    }
+
+    /// Overwrite a portion of the buffer's existing contents, e.g. for a single instance. The
+    /// range must be within the bytes in use.
+    pub fn write_at(&mut self, uploader: &mut Uploader, offset: usize, data: &[u8]) {
+        if self.back.is_some() {
+            self.pending = union(self.pending, Some((offset, offset + data.len())));
+        } else {
+            uploader.write(&self.buf, offset as u64, data);
+        }
+        self.shadow[offset..offset + data.len()].copy_from_slice(data);
+    }
+
+    /// Overwrite a portion of the existing contents with `data`, which is already on the GPU in
+    /// `staging` at `staging_offset`, with a GPU-side copy. Not for double-buffered buffers.
+    pub fn copy_at(
+        &mut self,
+        uploader: &mut Uploader,
+        offset: usize,
+        staging: &Buffer,
+        staging_offset: u64,
+        data: &[u8],
+    ) {
+        debug_assert!(self.back.is_none());
+
+        uploader.copy(
+            staging,
+            staging_offset,
+            &self.buf,
+            offset as u64,
+            data.len() as u64,
+        );
+        self.shadow[offset..offset + data.len()].copy_from_slice(data);
+    }
+}
+
+fn create(device: &Device, label: &'static str, usage: BufferUsages, size: u64) -> Buffer {
+    device.create_buffer(&BufferDescriptor {
+        label: Some(label),
+        size: size.next_multiple_of(COPY_BUFFER_ALIGNMENT),
+        usage,
+        mapped_at_creation: false,
+    })
+}
+
+/// The smallest range covering both.
+fn union(a: Option<(usize, usize)>, b: Option<(usize, usize)>) -> Option<(usize, usize)> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some((a.0.min(b.0), a.1.max(b.1))),
+        _ => a.or(b),
+    }
+}
+
+/// Find the byte range of `new` which differs from `old`, aligned for `write_buffer`. If `new` is
+/// longer, its tail is included. If it's shorter, the stale bytes past its end are left alone;
+/// draw calls don't read them. Returns `None` if nothing changed.
+fn changed_range(old: &[u8], new: &[u8]) -> Option<(usize, usize)> {
+    let common = old.len().min(new.len());
+
+    let start = old[..common]
+        .iter()
+        .zip(&new[..common])
+        .position(|(a, b)| a != b)
+        .unwrap_or(common);
+
+    let end = if new.len() > old.len() {
+        new.len()
+    } else {
+        match old[start..common]
+            .iter()
+            .zip(&new[start..common])
+            .rposition(|(a, b)| a != b)
+        {
+            Some(i) => start + i + 1,
+            None => return None,
+        }
+    };
+
+    let align = COPY_BUFFER_ALIGNMENT as usize;
+    let start = start / align * align;
+    let end = end.next_multiple_of(align).min(new.len());
+
+    Some((start, end))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use wgpu::{Backends, Instance, InstanceDescriptor, MapMode, PollType, Queue};
+
+    use super::*;
+
+    /// `base`, with the bytes at `changed` incremented.
+    fn with_changes(base: &[u8], changed: &[usize]) -> Vec<u8> {
+        let mut result = base.to_vec();
+        for &i in changed {
+            result[i] = result[i].wrapping_add(1);
+        }
+        result
+    }
+
+    fn base(len: usize) -> Vec<u8> {
+        (0..len as u8).collect()
+    }
+
+    #[test]
+    fn changed_range_equal() {
+        assert_eq!(changed_range(&base(16), &base(16)), None);
+        assert_eq!(changed_range(&[], &[]), None);
+    }
+
+    #[test]
+    fn changed_range_start_middle_end() {
+        let old = base(16);
+
+        assert_eq!(changed_range(&old, &with_changes(&old, &[0])), Some((0, 4)));
+        assert_eq!(
+            changed_range(&old, &with_changes(&old, &[9])),
+            Some((8, 12))
+        );
+        assert_eq!(
+            changed_range(&old, &with_changes(&old, &[15])),
+            Some((12, 16))
+        );
+        assert_eq!(
+            changed_range(&old, &with_changes(&old, &[5, 10])),
+            Some((4, 12))
+        );
+        assert_eq!(
+            changed_range(&old, &with_changes(&old, &[1, 14])),
+            Some((0, 16))
+        );
+    }
+
+    #[test]
+    fn changed_range_grow() {
+        let old = base(8);
+
+        // The added tail is always included.
+        assert_eq!(changed_range(&old, &base(16)), Some((8, 16)));
+        assert_eq!(
+            changed_range(&old, &with_changes(&base(16), &[2])),
+            Some((0, 16))
+        );
+        assert_eq!(changed_range(&[], &base(8)), Some((0, 8)));
+    }
+
+    #[test]
+    fn changed_range_shrink() {
+        let old = base(16);
+
+        // Bytes past the new end are left alone.
+        assert_eq!(changed_range(&old, &base(8)), None);
+        assert_eq!(changed_range(&old, &[]), None);
+        assert_eq!(
+            changed_range(&old, &with_changes(&base(8), &[5])),
+            Some((4, 8))
+        );
+    }
+
+    #[test]
+    fn changed_range_aligned() {
+        for len in [12, 13, 14, 15, 16] {
+            let old = base(len);
+            for i in 0..len {
+                let new = with_changes(&old, &[i]);
+                let (start, end) = changed_range(&old, &new).unwrap();
+
+                assert!(start.is_multiple_of(4));
+                // Rounded up to 4, except at the end of the data.
+                assert!(end.is_multiple_of(4) || end == len);
+                assert!(start <= i && i < end && end <= len);
+                assert!(end - start <= 4);
+            }
+        }
+    }
+
+    /// A device to upload to, or `None` if there's no adapter; tests that need one are skipped
+    /// then.
+    fn device() -> Option<(Device, Queue)> {
+        let instance = Instance::new(InstanceDescriptor {
+            backends: Backends::all(),
+            ..InstanceDescriptor::new_without_display_handle()
+        });
+        let adapter = pollster::block_on(instance.request_adapter(&Default::default())).ok()?;
+
+        pollster::block_on(adapter.request_device(&Default::default())).ok()
+    }
+
+    /// Submit the recorded uploads, and read back the first `len` bytes of `buf`.
+    fn read(device: &Device, queue: &Queue, uploader: &mut Uploader, buf: &Buffer) -> Vec<u8> {
+        let size = buf.size();
+        let readback = device.create_buffer(&BufferDescriptor {
+            label: None,
+            size,
+            usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = device.create_command_encoder(&Default::default());
+        encoder.copy_buffer_to_buffer(buf, 0, &readback, 0, size);
+        queue.submit(uploader.finish().into_iter().chain(Some(encoder.finish())));
+        uploader.recall();
+
+        let (tx, rx) = mpsc::channel();
+        readback.map_async(MapMode::Read, .., move |result| {
+            let _ = tx.send(result);
+        });
+        device.poll(PollType::wait_indefinitely()).unwrap();
+        rx.recv().unwrap().unwrap();
+
+        let result = readback.get_mapped_range(..).to_vec();
+        readback.unmap();
+        result
+    }
+
+    /// Flush, as at the start of a frame, and check the buffer drawn from matches the CPU copy.
+    fn flush_and_check(
+        device: &Device,
+        queue: &Queue,
+        uploader: &mut Uploader,
+        buf: &mut GrowableBuffer,
+    ) {
+        buf.flush(device, uploader);
+        let front = read(device, queue, uploader, &buf.buf);
+        assert_eq!(&front[..buf.len()], &buf.shadow[..]);
+    }
+
+    #[test]
+    fn double_buffered_writes_reach_both_buffers() {
+        let Some((device, queue)) = device() else {
+            eprintln!("Skipping; no adapter");
+            return;
+        };
+        let mut uploader = Uploader::new(&device);
+        let mut buf = GrowableBuffer::new(&device, "Test buffer", BufferUsages::VERTEX);
+
+        buf.write(&device, &mut uploader, &base(16));
+        buf.set_double_buffered(&device, &mut uploader, true);
+        flush_and_check(&device, &queue, &mut uploader, &mut buf);
+
+        // Each frame's write goes to one buffer at its flush, and to the other at the next.
+        buf.write_at(&mut uploader, 0, &[9; 4]);
+        flush_and_check(&device, &queue, &mut uploader, &mut buf);
+
+        buf.write_at(&mut uploader, 8, &[7; 4]);
+        flush_and_check(&device, &queue, &mut uploader, &mut buf);
+
+        // A frame without writes doesn't swap, so the next write's flush still catches the
+        // other buffer up.
+        flush_and_check(&device, &queue, &mut uploader, &mut buf);
+        buf.write_at(&mut uploader, 12, &[5; 4]);
+        flush_and_check(&device, &queue, &mut uploader, &mut buf);
+
+        let data = with_changes(&buf.shadow, &[4]);
+        buf.write(&device, &mut uploader, &data);
+        flush_and_check(&device, &queue, &mut uploader, &mut buf);
+
+        // Growing past the back buffer's capacity.
+        let mut data = buf.shadow.clone();
+        data.extend_from_slice(&[3; 64]);
+        buf.write(&device, &mut uploader, &data);
+        flush_and_check(&device, &queue, &mut uploader, &mut buf);
+
+        for _ in 0..2 {
+            buf.write_at(&mut uploader, 40, &[1; 4]);
+            flush_and_check(&device, &queue, &mut uploader, &mut buf);
+            buf.write_at(&mut uploader, 40, &[2; 4]);
+            flush_and_check(&device, &queue, &mut uploader, &mut buf);
+        }
+    }
+
+    #[test]
+    fn double_buffering_toggled() {
+        let Some((device, queue)) = device() else {
+            eprintln!("Skipping; no adapter");
+            return;
+        };
+        let mut uploader = Uploader::new(&device);
+        let mut buf = GrowableBuffer::new(&device, "Test buffer", BufferUsages::VERTEX);
+
+        buf.write(&device, &mut uploader, &base(32));
+        buf.set_double_buffered(&device, &mut uploader, true);
+        buf.write_at(&mut uploader, 4, &[9; 4]);
+        flush_and_check(&device, &queue, &mut uploader, &mut buf);
+
+        // Turning it off flushes pending writes.
+        buf.write_at(&mut uploader, 16, &[8; 4]);
+        buf.set_double_buffered(&device, &mut uploader, false);
+        flush_and_check(&device, &queue, &mut uploader, &mut buf);
+
+        // Writes go straight to the one buffer.
+        buf.write_at(&mut uploader, 20, &[7; 4]);
+        flush_and_check(&device, &queue, &mut uploader, &mut buf);
+
+        // Turning it back on fills the new back buffer with everything, including writes made
+        // while it was off.
+        buf.set_double_buffered(&device, &mut uploader, true);
+        for (offset, value) in [(0, 6), (28, 5), (0, 4)] {
+            buf.write_at(&mut uploader, offset, &[value; 4]);
+            flush_and_check(&device, &queue, &mut uploader, &mut buf);
+        }
+    }
+}
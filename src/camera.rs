@@ -5,13 +5,48 @@ use core::f32::consts::TAU;
 use lin_alg::f32::{Mat4, Quaternion, Vec3, Vec4};
 
 use crate::{
+    color::linearize,
     copy_ne,
-    types::{F32_SIZE, MAT4_SIZE, VEC3_UNIFORM_SIZE},
+    graphics::{FWD_VEC, RIGHT_VEC, UP_VEC},
+    types::{F32_SIZE, Isolines, MAT4_SIZE, VEC3_UNIFORM_SIZE, VEC4_SIZE},
 };
 
 // cam size is only the parts we pass to the shader.
 // For each of the 4 matrices in the camera, plus a padded vec3 for position.
-pub const CAMERA_SIZE: usize = MAT4_SIZE + 3 * VEC3_UNIFORM_SIZE + 16; // Final 16 is an alignment pad.
+// Then 2 vec4s for isolines, one for height fog, and one for exposure and gamma.
+pub const CAMERA_SIZE: usize = MAT4_SIZE + 3 * VEC3_UNIFORM_SIZE + 16 + 4 * VEC4_SIZE; // 16 is an alignment pad.
+
+/// Where the fog parameters start in the camera uniform, after the view matrix and position.
+pub(crate) const FOG_OFFSET: usize = MAT4_SIZE + VEC3_UNIFORM_SIZE;
+/// Density, power, start, end, and the color's 3 channels.
+pub(crate) const FOG_SIZE: usize = 7 * F32_SIZE;
+/// Where `Camera::height_fog` is in the camera uniform, after the isolines.
+pub(crate) const HEIGHT_FOG_OFFSET: usize = 160;
+
+/// Fog that thickens toward the ground, e.g. for terrain, or an atmosphere: its density falls off
+/// exponentially with height above `base`. It's integrated along each view ray, so looking down
+/// into it from above differs from looking across it. Drawn in `Camera::fog_color`, along with
+/// the distance fog, if set.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct HeightFog {
+    /// The height (Y) where the density is `density`. Fog continues to thicken below it.
+    pub base: f32,
+    /// How fast the density falls off with height above `base`, per unit of height; e.g. 0.5
+    /// halves it every 1.4 units. Higher means a thinner layer.
+    pub falloff: f32,
+    /// The fraction of light absorbed per unit of distance at `base`, e.g. 0.05. 0 is off.
+    pub density: f32,
+}
+
+impl Default for HeightFog {
+    fn default() -> Self {
+        Self {
+            base: 0.,
+            falloff: 0.5,
+            density: 0.05,
+        }
+    }
+}
 
 #[derive(Clone, Debug)]
 pub struct Camera {
@@ -34,13 +69,22 @@ pub struct Camera {
     pub fog_start: f32,
     /// Distance where fog reaches full strength
     pub fog_end: f32,
+    /// sRGB. Used by both the distance fog, and `height_fog`.
     pub fog_color: [f32; 3],
+    /// Fog by height, in addition to the distance fog above.
+    pub height_fog: Option<HeightFog>,
     /// Strength of edge cueing (silhouette darkening). 0.0 = off, 1.0 = full effect.
     /// Controlled at startup via GraphicsSettings::edge_cueing.
     pub edge_cueing: f32,
     /// World-space expansion along normals used in the depth-aware halo prepass.
     /// 0.0 = disabled. Set from GraphicsSettings::depth_aware_halos.
     pub halo_expansion: f32,
+    /// Set from GraphicsSettings::isolines.
+    pub isolines: Option<Isolines>,
+    /// Set from GraphicsSettings::exposure.
+    pub exposure: f32,
+    /// Set from GraphicsSettings::gamma.
+    pub gamma: f32,
 }
 
 impl Camera {
@@ -56,20 +100,7 @@ impl Camera {
         result[i..i + VEC3_UNIFORM_SIZE].clone_from_slice(&self.position.to_bytes_uniform());
         i += VEC3_UNIFORM_SIZE;
 
-        copy_ne!(result, self.fog_density, i..i + F32_SIZE);
-        i += F32_SIZE;
-        copy_ne!(result, self.fog_power, i..i + F32_SIZE);
-        i += F32_SIZE;
-        copy_ne!(result, self.fog_start, i..i + F32_SIZE);
-        i += F32_SIZE;
-        copy_ne!(result, self.fog_end, i..i + F32_SIZE);
-        i += F32_SIZE;
-
-        copy_ne!(result, self.fog_color[0], i..i + F32_SIZE);
-        i += F32_SIZE;
-        copy_ne!(result, self.fog_color[1], i..i + F32_SIZE);
-        i += F32_SIZE;
-        copy_ne!(result, self.fog_color[2], i..i + F32_SIZE);
+        result[i..i + FOG_SIZE].clone_from_slice(&self.fog_to_bytes());
 
         // WGSL layout: fog_color: vec3<f32> at 96..108 (12 bytes).
         // After it, edge_cueing at 108, near at 112, far at 116.
@@ -79,15 +110,172 @@ impl Camera {
         copy_ne!(result, self.far, 116..120);
         copy_ne!(result, self.halo_expansion, 120..124);
 
+        // isolines: vec4 at 128 (interval, offset, width, opacity), and isoline_color at 144.
+        // An interval of 0 disables them.
+        if let Some(iso) = &self.isolines {
+            copy_ne!(result, iso.interval, 128..132);
+            copy_ne!(result, iso.offset, 132..136);
+            copy_ne!(result, iso.width, 136..140);
+            copy_ne!(result, iso.opacity, 140..144);
+            let color = linearize(iso.color);
+            copy_ne!(result, color[0], 144..148);
+            copy_ne!(result, color[1], 148..152);
+            copy_ne!(result, color[2], 152..156);
+        }
+
+        result[HEIGHT_FOG_OFFSET..HEIGHT_FOG_OFFSET + VEC4_SIZE]
+            .clone_from_slice(&self.height_fog_to_bytes());
+
+        // exposure and 1 / gamma: vec4 at 176.
+        copy_ne!(result, self.exposure, 176..180);
+        // `pow` is undefined at 0 for a gamma of 0.
+        copy_ne!(result, 1. / self.gamma.max(1e-3), 180..184);
+
+        result
+    }
+
+    /// The fog parameters, as laid out at `FOG_OFFSET` in the camera uniform.
+    pub(crate) fn fog_to_bytes(&self) -> [u8; FOG_SIZE] {
+        let mut result = [0; FOG_SIZE];
+
+        copy_ne!(result, self.fog_density, 0..4);
+        copy_ne!(result, self.fog_power, 4..8);
+        copy_ne!(result, self.fog_start, 8..12);
+        copy_ne!(result, self.fog_end, 12..16);
+
+        let fog_color = linearize((self.fog_color[0], self.fog_color[1], self.fog_color[2]));
+        copy_ne!(result, fog_color[0], 16..20);
+        copy_ne!(result, fog_color[1], 20..24);
+        copy_ne!(result, fog_color[2], 24..28);
+
         result
     }
 
+    /// `height_fog`, as laid out at `HEIGHT_FOG_OFFSET`: base, falloff, and density. A density of
+    /// 0 disables it.
+    pub(crate) fn height_fog_to_bytes(&self) -> [u8; VEC4_SIZE] {
+        let mut result = [0; VEC4_SIZE];
+
+        if let Some(fog) = &self.height_fog {
+            copy_ne!(result, fog.base, 0..4);
+            copy_ne!(result, fog.falloff, 4..8);
+            copy_ne!(result, fog.density, 8..12);
+        }
+
+        result
+    }
+
+    /// The angle, in radians, the camera is rotated about its view direction from level; i.e.
+    /// from where its right vector is horizontal. 0 while looking straight up or down, where
+    /// level is undefined.
+    pub fn roll(&self) -> f32 {
+        let fwd = self.orientation.rotate_vec(FWD_VEC);
+        let right = self.orientation.rotate_vec(RIGHT_VEC);
+
+        let level_right = UP_VEC.cross(fwd);
+        if level_right.magnitude() < 1e-4 {
+            return 0.;
+        }
+        let level_right = level_right.to_normalized();
+
+        fwd.dot(level_right.cross(right))
+            .atan2(level_right.dot(right))
+    }
+
+    /// Rotate the camera about its view direction toward level, by at most `max_angle` radians;
+    /// e.g. by a rate times the frame time each frame, to remove accumulated roll smoothly.
+    /// Returns the roll remaining.
+    pub fn level_horizon(&mut self, max_angle: f32) -> f32 {
+        let roll = self.roll();
+        let step = roll.clamp(-max_angle, max_angle);
+
+        let fwd = self.orientation.rotate_vec(FWD_VEC);
+        self.orientation =
+            (Quaternion::from_axis_angle(fwd, -step) * self.orientation).to_normalized();
+
+        roll - step
+    }
+
     /// Updates the projection matrix based on the projection parameters.
     /// Run this after updating the parameters.
     pub fn update_proj_mat(&mut self) {
         self.proj_mat = Mat4::new_perspective_lh(self.fov_y, self.aspect, self.near, self.far);
     }
 
+    /// A camera for one eye of a stereo pair: moved `offset` along the camera's right axis
+    /// (negative for the left eye), with its frustum shifted so that points `convergence` ahead
+    /// of the original camera appear at the same place to both eyes, i.e. at screen depth.
+    pub fn stereo_eye(&self, offset: f32, convergence: f32) -> Self {
+        let mut result = self.clone();
+        result.position += self.orientation.rotate_vec(RIGHT_VEC) * offset;
+
+        // Shift clip-space x by a multiple of w; this is an off-axis projection.
+        let shift = offset * self.proj_mat.data[0] / convergence.max(f32::EPSILON);
+        result.proj_mat = Mat4::new_translation(Vec3::new(shift, 0., 0.)) * self.proj_mat.clone();
+
+        result
+    }
+
+    /// The camera seeing the mirror image of the scene in a horizontal plane at `height`, for
+    /// planar reflections. It's a rotation, not a reflection, so triangle winding is unchanged;
+    /// its image is the mirror image flipped horizontally. Its near plane is the mirror plane, so
+    /// nothing below it shows.
+    pub(crate) fn mirrored(&self, height: f32) -> Self {
+        let mut result = self.clone();
+        let (p, q) = (self.position, self.orientation);
+
+        result.position = Vec3::new(p.x, 2. * height - p.y, p.z);
+        // Reflects the forward and up axes through the plane, and negates the right one.
+        result.orientation = Quaternion::new(q.z, q.y, q.x, q.w);
+
+        // Flip the frustum horizontally too, so off-center ones still line up after flipping the
+        // image back.
+        let mut proj = self.proj_mat.clone();
+        for i in [1, 2, 3, 4, 8, 12] {
+            proj.data[i] = -proj.data[i];
+        }
+
+        // Replace the near plane with the mirror plane, in view space (Lengyel, 2005).
+        let normal = result.orientation.inverse().rotate_vec(UP_VEC);
+        let plane = Vec4::new(normal.x, normal.y, normal.z, result.position.y - height);
+
+        // The view-space point at the far corner of the frustum, on the plane's side; this is the
+        // projection's inverse applied to it, in closed form.
+        let d = proj.data;
+        let corner = Vec4::new(
+            (plane.x.signum() - d[8]) / d[0],
+            (plane.y.signum() - d[9]) / d[5],
+            1.,
+            (1. - d[10]) / d[14],
+        );
+        let c = plane / plane.dot(corner);
+
+        // The third row, for depth.
+        proj.data[2] = c.x;
+        proj.data[6] = c.y;
+        proj.data[10] = c.z;
+        proj.data[14] = c.w;
+
+        result.proj_mat = proj;
+        result
+    }
+
+    /// The world-space corners of the view frustum, between the near and far planes. Bit 0 of the
+    /// index selects right over left, bit 1 top over bottom, and bit 2 far over near.
+    pub(crate) fn frustum_corners(&self) -> [Vec3; 8] {
+        let d = &self.proj_mat.data;
+
+        core::array::from_fn(|i| {
+            let ndc_x = if i & 1 == 0 { -1. } else { 1. };
+            let ndc_y = if i & 2 == 0 { -1. } else { 1. };
+            let z = if i & 4 == 0 { self.near } else { self.far };
+
+            // Inverts the projection's x and y, in closed form; this covers off-center frusta.
+            let view = Vec3::new(z * (ndc_x - d[8]) / d[0], z * (ndc_y - d[9]) / d[5], z);
+            self.position + self.orientation.rotate_vec(view)
+        })
+    }
+
     /// Calculate the view matrix: This is a translation of the negative coordinates of the camera's
     /// position, applied before the camera's rotation.
     pub fn view_mat(&self) -> Mat4 {
@@ -140,8 +328,12 @@ impl Default for Camera {
             fog_start: 0.,
             fog_end: 0.,
             fog_color: [0., 0., 0.],
+            height_fog: None,
             edge_cueing: 0.,
             halo_expansion: 0.,
+            isolines: None,
+            exposure: 1.,
+            gamma: 1.,
         };
 
         result.update_proj_mat();
@@ -0,0 +1,89 @@
+//! Color space conversions.
+//!
+//! Colors passed to the engine (entity, vertex, light, fog, and background colors, colormaps) are
+//! sRGB, as from a color picker, a hex code, or egui; the same values look the same in the GUI and
+//! the 3D view. We convert them to linear before lighting and blending, and the output is encoded
+//! per `GraphicsSettings::output_color_space`.
+
+/// How color values relate to light intensity.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum ColorSpace {
+    /// Gamma-encoded, as displays and image files expect. Values are perceptually even: 0.5 looks
+    /// halfway between black and white.
+    #[default]
+    Srgb,
+    /// Proportional to light intensity. Lighting and blending math is done in this space.
+    Linear,
+}
+
+/// Convert an sRGB channel, from 0 to 1, to linear.
+pub fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Convert a linear channel, from 0 to 1, to sRGB.
+pub fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1. / 2.4) - 0.055
+    }
+}
+
+/// Convert an sRGB color to linear, in the layout uniforms and instances use.
+pub(crate) fn linearize(c: (f32, f32, f32)) -> [f32; 3] {
+    [
+        srgb_to_linear(c.0),
+        srgb_to_linear(c.1),
+        srgb_to_linear(c.2),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        for i in 0..=1_000 {
+            let c = i as f32 / 1_000.;
+            assert!((linear_to_srgb(srgb_to_linear(c)) - c).abs() < 1e-5, "{c}");
+            assert!((srgb_to_linear(linear_to_srgb(c)) - c).abs() < 1e-5, "{c}");
+        }
+    }
+
+    #[test]
+    fn endpoints_and_reference_values() {
+        assert_eq!(srgb_to_linear(0.), 0.);
+        assert_eq!(linear_to_srgb(0.), 0.);
+        assert!((srgb_to_linear(1.) - 1.).abs() < 1e-6);
+        assert!((linear_to_srgb(1.) - 1.).abs() < 1e-6);
+        // sRGB 0.5 is about 21% of full intensity.
+        assert!((srgb_to_linear(0.5) - 0.214_041).abs() < 1e-5);
+        assert!((linear_to_srgb(0.214_041) - 0.5).abs() < 1e-5);
+    }
+
+    #[test]
+    fn continuous_at_breakpoints() {
+        // Each side of the breakpoint between the linear segment and the curve meets the other.
+        let below = 0.04045 / 12.92;
+        let above = ((0.04045_f32 + 0.055) / 1.055).powf(2.4);
+        assert!((below - above).abs() < 1e-6);
+        assert!((srgb_to_linear(0.04045) - 0.0031308).abs() < 1e-6);
+        assert!((linear_to_srgb(0.0031308) - 0.04045).abs() < 1e-5);
+
+        for (f, x) in [
+            (srgb_to_linear as fn(f32) -> f32, 0.04045),
+            (linear_to_srgb, 0.0031308),
+        ] {
+            let step = 1e-6;
+            assert!((f(x + step) - f(x - step)).abs() < 1e-4, "jump at {x}");
+            // And increasing through it.
+            assert!(f(x - step) < f(x) && f(x) < f(x + step));
+        }
+    }
+}
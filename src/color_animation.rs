@@ -0,0 +1,97 @@
+//! Entity colors driven by a scalar that changes over time, through a colormap; e.g. temperature
+//! evolving over a simulation. The engine advances these each frame, and updates the colored
+//! entities' instances in place.
+
+use crate::colormap::Colormap;
+
+/// Colors each entity of `class` by a scalar over time, mapped through `colormap`. Add to
+/// `Scene::color_animations`; there's no need to send an `EntityUpdate`. Entities using a
+/// material keep the material's color.
+#[derive(Clone, Debug)]
+pub struct ColorAnimation {
+    /// The `Entity::class` of the entities to color.
+    pub class: u32,
+    pub colormap: Colormap,
+    /// The values that map to the colormap's start and end.
+    pub range: (f32, f32),
+    /// Times in seconds, and the scalar at each, in order of time. The scalar is interpolated
+    /// linearly between them, and holds its first and last values outside them.
+    pub keyframes: Vec<(f32, f32)>,
+    /// Start over after the last keyframe.
+    pub looped: bool,
+    /// Seconds since the start. The engine advances this each frame; set it to seek.
+    pub elapsed: f32,
+    /// The engine advances `elapsed` only while this is set.
+    pub playing: bool,
+}
+
+impl ColorAnimation {
+    pub fn new(
+        class: u32,
+        colormap: Colormap,
+        range: (f32, f32),
+        keyframes: Vec<(f32, f32)>,
+    ) -> Self {
+        Self {
+            class,
+            colormap,
+            range,
+            keyframes,
+            looped: false,
+            elapsed: 0.,
+            playing: true,
+        }
+    }
+
+    /// The time of the last keyframe, in seconds.
+    pub fn duration(&self) -> f32 {
+        self.keyframes.last().map(|k| k.0).unwrap_or(0.)
+    }
+
+    /// The scalar at `time`, in seconds.
+    pub fn value_at(&self, time: f32) -> f32 {
+        let (Some(first), Some(last)) = (self.keyframes.first(), self.keyframes.last()) else {
+            return 0.;
+        };
+
+        if time <= first.0 {
+            return first.1;
+        }
+        if time >= last.0 {
+            return last.1;
+        }
+
+        // The first keyframe after `time`; there's one before it, from the checks above.
+        let i = self.keyframes.partition_point(|k| k.0 <= time);
+        let (t0, v0) = self.keyframes[i - 1];
+        let (t1, v1) = self.keyframes[i];
+
+        v0 + (v1 - v0) * (time - t0) / (t1 - t0)
+    }
+
+    /// The color at `elapsed`; sRGB.
+    pub fn color(&self) -> (f32, f32, f32) {
+        self.colormap.map(self.value_at(self.elapsed), self.range)
+    }
+
+    /// If this changes over time from here; i.e. it's playing, and not past its end.
+    pub(crate) fn active(&self) -> bool {
+        self.playing && (self.looped || self.elapsed < self.duration())
+    }
+
+    /// Advance by `dt` seconds.
+    pub(crate) fn step(&mut self, dt: f32) {
+        if !self.playing {
+            return;
+        }
+
+        self.elapsed += dt;
+
+        let duration = self.duration();
+        if self.looped && duration > 0. {
+            self.elapsed %= duration;
+        } else {
+            self.elapsed = self.elapsed.min(duration);
+        }
+    }
+}
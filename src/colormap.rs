@@ -0,0 +1,113 @@
+//! Maps scalar values to colors, e.g. to color glyphs by magnitude, or slices by density. Colors
+//! are sRGB.
+
+#[cfg(feature = "app_utils")]
+use bincode::{Decode, Encode};
+
+use crate::color::linearize;
+
+/// The most colormap points the shaders hold.
+pub(crate) const COLORMAP_MAX: usize = 11;
+
+/// A color scale. Sample it with values from 0 to 1.
+#[cfg_attr(feature = "app_utils", derive(Encode, Decode))]
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum Colormap {
+    /// Dark blue through green to yellow. Perceptually uniform, and readable in grayscale.
+    #[default]
+    Viridis,
+    /// Black through red to pale yellow. Perceptually uniform.
+    Inferno,
+    /// Blue through light gray to red. For values with a meaningful center, e.g. signed ones.
+    Coolwarm,
+    Grayscale,
+}
+
+// Evenly-spaced points along each map; we interpolate linearly between them.
+const VIRIDIS: [(f32, f32, f32); 11] = [
+    (0.267, 0.005, 0.329),
+    (0.283, 0.141, 0.458),
+    (0.254, 0.265, 0.530),
+    (0.207, 0.372, 0.553),
+    (0.164, 0.471, 0.558),
+    (0.128, 0.567, 0.551),
+    (0.135, 0.659, 0.518),
+    (0.267, 0.749, 0.441),
+    (0.478, 0.821, 0.319),
+    (0.741, 0.873, 0.150),
+    (0.993, 0.906, 0.144),
+];
+
+const INFERNO: [(f32, f32, f32); 11] = [
+    (0.001, 0.000, 0.014),
+    (0.087, 0.045, 0.224),
+    (0.258, 0.039, 0.406),
+    (0.416, 0.090, 0.433),
+    (0.578, 0.148, 0.404),
+    (0.735, 0.216, 0.330),
+    (0.865, 0.317, 0.226),
+    (0.954, 0.469, 0.099),
+    (0.988, 0.645, 0.040),
+    (0.964, 0.843, 0.273),
+    (0.988, 0.998, 0.645),
+];
+
+const COOLWARM: [(f32, f32, f32); 5] = [
+    (0.230, 0.299, 0.754),
+    (0.552, 0.690, 0.996),
+    (0.865, 0.865, 0.865),
+    (0.958, 0.603, 0.482),
+    (0.706, 0.016, 0.150),
+];
+
+const GRAYSCALE: [(f32, f32, f32); 2] = [(0., 0., 0.), (1., 1., 1.)];
+
+impl Colormap {
+    pub(crate) fn points(self) -> &'static [(f32, f32, f32)] {
+        match self {
+            Self::Viridis => &VIRIDIS,
+            Self::Inferno => &INFERNO,
+            Self::Coolwarm => &COOLWARM,
+            Self::Grayscale => &GRAYSCALE,
+        }
+    }
+
+    /// The points in linear color, padded to `COLORMAP_MAX`, and how many there are; for shader
+    /// uniforms.
+    pub(crate) fn to_uniform(self) -> ([[f32; 4]; COLORMAP_MAX], u32) {
+        let points = self.points();
+        let mut result = [[0.; 4]; COLORMAP_MAX];
+        for (dest, p) in result.iter_mut().zip(points) {
+            let c = linearize(*p);
+            *dest = [c[0], c[1], c[2], 1.];
+        }
+
+        (result, points.len().min(COLORMAP_MAX) as u32)
+    }
+
+    /// The color at `t`, from 0 to 1. Values outside this are clamped.
+    pub fn sample(self, t: f32) -> (f32, f32, f32) {
+        let points = self.points();
+        let t = if t.is_nan() { 0. } else { t.clamp(0., 1.) };
+
+        let pos = t * (points.len() - 1) as f32;
+        let i = (pos as usize).min(points.len() - 2);
+        let frac = pos - i as f32;
+
+        let (a, b) = (points[i], points[i + 1]);
+        (
+            a.0 + (b.0 - a.0) * frac,
+            a.1 + (b.1 - a.1) * frac,
+            a.2 + (b.2 - a.2) * frac,
+        )
+    }
+
+    /// The color of `value`, scaled so `range.0` maps to the start, and `range.1` to the end.
+    pub fn map(self, value: f32, range: (f32, f32)) -> (f32, f32, f32) {
+        let span = range.1 - range.0;
+        if span.abs() < f32::EPSILON {
+            return self.sample(0.5);
+        }
+        self.sample((value - range.0) / span)
+    }
+}
@@ -0,0 +1,678 @@
+//! Block-compressed textures from DDS and KTX2 files. These upload as-is if the adapter supports
+//! their format. Otherwise, we decompress BC1 through BC5 on the CPU; other formats fail to load.
+//!
+//! We read the first layer and face only. Supercompressed KTX2 files (BasisLZ, Zstd) aren't
+//! supported; transcode them to a block format first, e.g. with `ktx transcode`.
+
+use wgpu::{AstcBlock, AstcChannel, Features, TextureFormat};
+
+use crate::texture::TextureError;
+
+const DDS_MAGIC: &[u8] = b"DDS ";
+const KTX2_IDENTIFIER: [u8; 12] = [
+    0xAB, b'K', b'T', b'X', b' ', b'2', b'0', 0xBB, b'\r', b'\n', 0x1A, b'\n',
+];
+
+/// A texture's mip chain, as stored on the GPU.
+pub(crate) struct CompressedImage {
+    pub format: TextureFormat,
+    pub width: u32,
+    pub height: u32,
+    pub mip_level_count: u32,
+    /// Each mip level, largest first, tightly packed.
+    pub data: Vec<u8>,
+}
+
+impl CompressedImage {
+    /// If `features` doesn't include what this format needs, decompress the largest mip level to
+    /// RGBA8.
+    pub fn into_supported(self, features: Features) -> Result<Self, TextureError> {
+        if features.contains(self.format.required_features()) {
+            return Ok(self);
+        }
+
+        let (rgba, srgb) = decompress_bc(&self)?;
+        Ok(Self {
+            format: if srgb {
+                TextureFormat::Rgba8UnormSrgb
+            } else {
+                TextureFormat::Rgba8Unorm
+            },
+            width: self.width,
+            height: self.height,
+            mip_level_count: 1,
+            data: rgba,
+        })
+    }
+}
+
+/// Parse `bytes` if they're a DDS or KTX2 file. Returns `None` for other formats, which the
+/// `image` crate may be able to read.
+pub(crate) fn parse(bytes: &[u8]) -> Option<Result<CompressedImage, TextureError>> {
+    if bytes.starts_with(DDS_MAGIC) {
+        Some(parse_dds(bytes))
+    } else if bytes.starts_with(&KTX2_IDENTIFIER) {
+        Some(parse_ktx2(bytes))
+    } else {
+        None
+    }
+}
+
+fn invalid(msg: impl Into<String>) -> TextureError {
+    TextureError::Container(msg.into())
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> Result<u32, TextureError> {
+    bytes
+        .get(offset..offset + 4)
+        .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+        .ok_or_else(|| invalid("Header is truncated"))
+}
+
+fn u64_at(bytes: &[u8], offset: usize) -> Result<u64, TextureError> {
+    bytes
+        .get(offset..offset + 8)
+        .map(|b| u64::from_le_bytes(b.try_into().unwrap()))
+        .ok_or_else(|| invalid("Header is truncated"))
+}
+
+/// The size in bytes of one mip level of a single image.
+fn level_size(format: TextureFormat, width: u32, height: u32, level: u32) -> usize {
+    let (block_w, block_h) = format.block_dimensions();
+    let block_size = format.block_copy_size(None).unwrap_or(4);
+
+    let w = (width >> level).max(1);
+    let h = (height >> level).max(1);
+
+    w.div_ceil(block_w) as usize * h.div_ceil(block_h) as usize * block_size as usize
+}
+
+/// Reject mip counts past a 1x1 level, e.g. from a corrupt header.
+fn check_levels(width: u32, height: u32, mip_level_count: u32) -> Result<(), TextureError> {
+    let max = 32 - width.max(height).leading_zeros();
+    if mip_level_count > max {
+        return Err(invalid(format!(
+            "{mip_level_count} mip levels is too many for {width}x{height}"
+        )));
+    }
+    Ok(())
+}
+
+/// Check that the size is valid for the format, and that `data` holds the full mip chain. Trims
+/// anything past it, e.g. further array layers.
+fn finish(
+    format: TextureFormat,
+    width: u32,
+    height: u32,
+    mip_level_count: u32,
+    mut data: Vec<u8>,
+) -> Result<CompressedImage, TextureError> {
+    if width == 0 || height == 0 {
+        return Err(invalid("Texture has no size"));
+    }
+
+    let (block_w, block_h) = format.block_dimensions();
+    if !width.is_multiple_of(block_w) || !height.is_multiple_of(block_h) {
+        return Err(invalid(format!(
+            "{width}x{height} isn't a multiple of the {block_w}x{block_h} block size"
+        )));
+    }
+    check_levels(width, height, mip_level_count)?;
+
+    let expected: usize = (0..mip_level_count)
+        .map(|level| level_size(format, width, height, level))
+        .sum();
+    if data.len() < expected {
+        return Err(invalid(format!(
+            "Expected {expected} bytes of image data; found {}",
+            data.len()
+        )));
+    }
+    data.truncate(expected);
+
+    Ok(CompressedImage {
+        format,
+        width,
+        height,
+        mip_level_count,
+        data,
+    })
+}
+
+// ── DDS ──────────────────────────────────────────────────────────────────────
+
+/// Pixel format flags.
+const DDPF_ALPHAPIXELS: u32 = 0x1;
+const DDPF_FOURCC: u32 = 0x4;
+const DDPF_RGB: u32 = 0x40;
+
+fn parse_dds(bytes: &[u8]) -> Result<CompressedImage, TextureError> {
+    // Offsets are from the start of the file, including the 4-byte magic.
+    let height = u32_at(bytes, 12)?;
+    let width = u32_at(bytes, 16)?;
+    let mip_level_count = u32_at(bytes, 28)?.max(1);
+    let pf_flags = u32_at(bytes, 80)?;
+    let four_cc = bytes
+        .get(84..88)
+        .ok_or_else(|| invalid("Header is truncated"))?;
+
+    let mut data_start = 128;
+
+    let format = if pf_flags & DDPF_FOURCC != 0 {
+        match four_cc {
+            // We assume color data is sRGB, as we do for other images.
+            b"DXT1" => TextureFormat::Bc1RgbaUnormSrgb,
+            b"DXT2" | b"DXT3" => TextureFormat::Bc2RgbaUnormSrgb,
+            b"DXT4" | b"DXT5" => TextureFormat::Bc3RgbaUnormSrgb,
+            b"ATI1" | b"BC4U" => TextureFormat::Bc4RUnorm,
+            b"BC4S" => TextureFormat::Bc4RSnorm,
+            b"ATI2" | b"BC5U" => TextureFormat::Bc5RgUnorm,
+            b"BC5S" => TextureFormat::Bc5RgSnorm,
+            b"DX10" => {
+                data_start += 20;
+                let dxgi = u32_at(bytes, 128)?;
+                dxgi_format(dxgi)
+                    .ok_or_else(|| invalid(format!("Unsupported DXGI format {dxgi}")))?
+            }
+            _ => {
+                return Err(invalid(format!(
+                    "Unsupported DDS format {}",
+                    String::from_utf8_lossy(four_cc)
+                )));
+            }
+        }
+    } else if pf_flags & DDPF_RGB != 0 && pf_flags & DDPF_ALPHAPIXELS != 0 {
+        let bit_count = u32_at(bytes, 88)?;
+        let r_mask = u32_at(bytes, 92)?;
+        match (bit_count, r_mask) {
+            (32, 0x0000_00ff) => TextureFormat::Rgba8UnormSrgb,
+            (32, 0x00ff_0000) => TextureFormat::Bgra8UnormSrgb,
+            _ => return Err(invalid("Unsupported uncompressed DDS layout")),
+        }
+    } else {
+        return Err(invalid("Unsupported DDS pixel format"));
+    };
+
+    let data = bytes
+        .get(data_start..)
+        .ok_or_else(|| invalid("Header is truncated"))?;
+
+    finish(format, width, height, mip_level_count, data.to_vec())
+}
+
+fn dxgi_format(dxgi: u32) -> Option<TextureFormat> {
+    use TextureFormat::*;
+
+    Some(match dxgi {
+        28 => Rgba8Unorm,
+        29 => Rgba8UnormSrgb,
+        87 => Bgra8Unorm,
+        91 => Bgra8UnormSrgb,
+        71 => Bc1RgbaUnorm,
+        72 => Bc1RgbaUnormSrgb,
+        74 => Bc2RgbaUnorm,
+        75 => Bc2RgbaUnormSrgb,
+        77 => Bc3RgbaUnorm,
+        78 => Bc3RgbaUnormSrgb,
+        80 => Bc4RUnorm,
+        81 => Bc4RSnorm,
+        83 => Bc5RgUnorm,
+        84 => Bc5RgSnorm,
+        95 => Bc6hRgbUfloat,
+        96 => Bc6hRgbFloat,
+        98 => Bc7RgbaUnorm,
+        99 => Bc7RgbaUnormSrgb,
+        _ => return None,
+    })
+}
+
+// ── KTX2 ─────────────────────────────────────────────────────────────────────
+
+fn parse_ktx2(bytes: &[u8]) -> Result<CompressedImage, TextureError> {
+    let vk_format = u32_at(bytes, 12)?;
+    let width = u32_at(bytes, 20)?;
+    let height = u32_at(bytes, 24)?.max(1);
+    let depth = u32_at(bytes, 28)?;
+    let level_count = u32_at(bytes, 40)?.max(1);
+    let supercompression = u32_at(bytes, 44)?;
+
+    let scheme = match supercompression {
+        0 => None,
+        1 => Some("BasisLZ"),
+        2 => Some("Zstd"),
+        n => return Err(invalid(format!("Unknown supercompression scheme {n}"))),
+    };
+    if let Some(scheme) = scheme {
+        return Err(invalid(format!(
+            "{scheme} supercompression isn't supported; transcode the file to a block format \
+            first, e.g. with `ktx transcode`"
+        )));
+    }
+
+    if depth > 1 {
+        return Err(invalid("3D textures aren't supported"));
+    }
+
+    let format = vk_format_to_wgpu(vk_format)
+        .ok_or_else(|| invalid(format!("Unsupported Vulkan format {vk_format}")))?;
+    check_levels(width, height, level_count)?;
+
+    // The level index follows the 80-byte header. Each level holds every layer and face, with the
+    // first image at its start.
+    let mut data = Vec::new();
+    for level in 0..level_count {
+        let entry = 80 + 24 * level as usize;
+        let offset = u64_at(bytes, entry)? as usize;
+
+        let len = level_size(format, width, height, level);
+        let image = bytes
+            .get(offset..offset.saturating_add(len))
+            .ok_or_else(|| invalid(format!("Mip level {level} is truncated")))?;
+        data.extend_from_slice(image);
+    }
+
+    finish(format, width, height, level_count, data)
+}
+
+fn vk_format_to_wgpu(vk: u32) -> Option<TextureFormat> {
+    use TextureFormat::*;
+
+    Some(match vk {
+        37 => Rgba8Unorm,
+        43 => Rgba8UnormSrgb,
+        44 => Bgra8Unorm,
+        50 => Bgra8UnormSrgb,
+        // BC1 RGB and RGBA differ only in how the transparent color decodes.
+        131 | 133 => Bc1RgbaUnorm,
+        132 | 134 => Bc1RgbaUnormSrgb,
+        135 => Bc2RgbaUnorm,
+        136 => Bc2RgbaUnormSrgb,
+        137 => Bc3RgbaUnorm,
+        138 => Bc3RgbaUnormSrgb,
+        139 => Bc4RUnorm,
+        140 => Bc4RSnorm,
+        141 => Bc5RgUnorm,
+        142 => Bc5RgSnorm,
+        143 => Bc6hRgbUfloat,
+        144 => Bc6hRgbFloat,
+        145 => Bc7RgbaUnorm,
+        146 => Bc7RgbaUnormSrgb,
+        147 => Etc2Rgb8Unorm,
+        148 => Etc2Rgb8UnormSrgb,
+        149 => Etc2Rgb8A1Unorm,
+        150 => Etc2Rgb8A1UnormSrgb,
+        151 => Etc2Rgba8Unorm,
+        152 => Etc2Rgba8UnormSrgb,
+        153 => EacR11Unorm,
+        154 => EacR11Snorm,
+        155 => EacRg11Unorm,
+        156 => EacRg11Snorm,
+        // ASTC formats come in UNORM, SRGB pairs, in order of block size.
+        157..=184 => {
+            const BLOCKS: [AstcBlock; 14] = [
+                AstcBlock::B4x4,
+                AstcBlock::B5x4,
+                AstcBlock::B5x5,
+                AstcBlock::B6x5,
+                AstcBlock::B6x6,
+                AstcBlock::B8x5,
+                AstcBlock::B8x6,
+                AstcBlock::B8x8,
+                AstcBlock::B10x5,
+                AstcBlock::B10x6,
+                AstcBlock::B10x8,
+                AstcBlock::B10x10,
+                AstcBlock::B12x10,
+                AstcBlock::B12x12,
+            ];
+            let i = vk - 157;
+            Astc {
+                block: BLOCKS[i as usize / 2],
+                channel: if i.is_multiple_of(2) {
+                    AstcChannel::Unorm
+                } else {
+                    AstcChannel::UnormSrgb
+                },
+            }
+        }
+        _ => return None,
+    })
+}
+
+// ── CPU decompression ────────────────────────────────────────────────────────
+
+/// The 4x4 RGBA8 texels of a decoded block, in row order.
+type Block = [[u8; 4]; 16];
+
+/// Decompress the largest mip level of a BC1 through BC5 image to RGBA8. Returns the pixels, and
+/// whether they're sRGB.
+fn decompress_bc(img: &CompressedImage) -> Result<(Vec<u8>, bool), TextureError> {
+    use TextureFormat::*;
+
+    let srgb = img.format.is_srgb();
+    let (block_size, decode): (usize, fn(&[u8]) -> Block) = match img.format {
+        Bc1RgbaUnorm | Bc1RgbaUnormSrgb => (8, |b| decode_bc1(b, true)),
+        Bc2RgbaUnorm | Bc2RgbaUnormSrgb => (16, decode_bc2),
+        Bc3RgbaUnorm | Bc3RgbaUnormSrgb => (16, decode_bc3),
+        Bc4RUnorm => (8, decode_bc4),
+        Bc5RgUnorm => (16, decode_bc5),
+        format => return Err(TextureError::UnsupportedFormat(format)),
+    };
+
+    let width = img.width as usize;
+    let height = img.height as usize;
+    let blocks_x = width.div_ceil(4);
+    let blocks_y = height.div_ceil(4);
+
+    let mut rgba = vec![0; width * height * 4];
+
+    for by in 0..blocks_y {
+        for bx in 0..blocks_x {
+            let start = (by * blocks_x + bx) * block_size;
+            let texels = decode(&img.data[start..start + block_size]);
+
+            for (i, texel) in texels.iter().enumerate() {
+                let x = bx * 4 + i % 4;
+                let y = by * 4 + i / 4;
+                if x < width && y < height {
+                    let p = (y * width + x) * 4;
+                    rgba[p..p + 4].copy_from_slice(texel);
+                }
+            }
+        }
+    }
+
+    Ok((rgba, srgb))
+}
+
+fn rgb565(c: u16) -> [u8; 3] {
+    let r = (c >> 11) & 0x1f;
+    let g = (c >> 5) & 0x3f;
+    let b = c & 0x1f;
+    [
+        ((r << 3) | (r >> 2)) as u8,
+        ((g << 2) | (g >> 4)) as u8,
+        ((b << 3) | (b >> 2)) as u8,
+    ]
+}
+
+/// Decode a BC1 color block. `punch_through` enables the 3-color mode with transparent black; BC2
+/// and BC3 color blocks always use 4 colors.
+fn decode_bc1(block: &[u8], punch_through: bool) -> Block {
+    let c0 = u16::from_le_bytes([block[0], block[1]]);
+    let c1 = u16::from_le_bytes([block[2], block[3]]);
+    let indices = u32::from_le_bytes(block[4..8].try_into().unwrap());
+
+    let a = rgb565(c0);
+    let b = rgb565(c1);
+    let mix = |wa: u16, wb: u16, d: u16| -> [u8; 4] {
+        let ch = |i: usize| ((a[i] as u16 * wa + b[i] as u16 * wb) / d) as u8;
+        [ch(0), ch(1), ch(2), 255]
+    };
+
+    let palette = if c0 > c1 || !punch_through {
+        [
+            [a[0], a[1], a[2], 255],
+            [b[0], b[1], b[2], 255],
+            mix(2, 1, 3),
+            mix(1, 2, 3),
+        ]
+    } else {
+        [
+            [a[0], a[1], a[2], 255],
+            [b[0], b[1], b[2], 255],
+            mix(1, 1, 2),
+            [0, 0, 0, 0],
+        ]
+    };
+
+    std::array::from_fn(|i| palette[((indices >> (2 * i)) & 0b11) as usize])
+}
+
+/// Decode an 8-byte BC3-style single-channel block, as used for BC3 alpha and BC4.
+fn decode_bc_channel(block: &[u8]) -> [u8; 16] {
+    let e0 = block[0] as u16;
+    let e1 = block[1] as u16;
+
+    let mut indices = 0u64;
+    for (i, &byte) in block[2..8].iter().enumerate() {
+        indices |= (byte as u64) << (8 * i);
+    }
+
+    let value = |code: u16| -> u8 {
+        match code {
+            0 => e0 as u8,
+            1 => e1 as u8,
+            _ if e0 > e1 => (((8 - code) * e0 + (code - 1) * e1) / 7) as u8,
+            6 => 0,
+            7 => 255,
+            _ => (((6 - code) * e0 + (code - 1) * e1) / 5) as u8,
+        }
+    };
+
+    std::array::from_fn(|i| value(((indices >> (3 * i)) & 0b111) as u16))
+}
+
+fn decode_bc2(block: &[u8]) -> Block {
+    let mut texels = decode_bc1(&block[8..16], false);
+    for (i, texel) in texels.iter_mut().enumerate() {
+        let nibble = (block[i / 2] >> (4 * (i % 2))) & 0xf;
+        texel[3] = nibble * 17;
+    }
+    texels
+}
+
+fn decode_bc3(block: &[u8]) -> Block {
+    let alpha = decode_bc_channel(&block[0..8]);
+    let mut texels = decode_bc1(&block[8..16], false);
+    for (texel, a) in texels.iter_mut().zip(alpha) {
+        texel[3] = a;
+    }
+    texels
+}
+
+fn decode_bc4(block: &[u8]) -> Block {
+    decode_bc_channel(block).map(|r| [r, 0, 0, 255])
+}
+
+fn decode_bc5(block: &[u8]) -> Block {
+    let r = decode_bc_channel(&block[0..8]);
+    let g = decode_bc_channel(&block[8..16]);
+    std::array::from_fn(|i| [r[i], g[i], 0, 255])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_fixture(name: &str) -> Vec<u8> {
+        std::fs::read(format!(
+            "{}/tests/fixtures/{name}",
+            env!("CARGO_MANIFEST_DIR")
+        ))
+        .unwrap()
+    }
+
+    fn fixture(name: &str) -> Result<CompressedImage, TextureError> {
+        parse(&read_fixture(name)).expect("Recognized as DDS or KTX2")
+    }
+
+    fn image(format: TextureFormat, width: u32, height: u32, data: Vec<u8>) -> CompressedImage {
+        CompressedImage {
+            format,
+            width,
+            height,
+            mip_level_count: 1,
+            data,
+        }
+    }
+
+    /// An 8-byte single-channel block, with each texel's 3-bit code.
+    fn channel_block(e0: u8, e1: u8, codes: [u64; 16]) -> [u8; 8] {
+        let indices = codes
+            .iter()
+            .enumerate()
+            .fold(0, |acc, (i, c)| acc | (c << (3 * i)));
+
+        let mut block = [e0, e1, 0, 0, 0, 0, 0, 0];
+        block[2..].copy_from_slice(&indices.to_le_bytes()[..6]);
+        block
+    }
+
+    /// Red and blue endpoints, with texels in each row using colors 0 through 3.
+    const BC1_BLOCK: [u8; 8] = [0x00, 0xf8, 0x1f, 0x00, 0xe4, 0xe4, 0xe4, 0xe4];
+    const RED: [u8; 4] = [255, 0, 0, 255];
+    const BLUE: [u8; 4] = [0, 0, 255, 255];
+
+    #[test]
+    fn parse_dds_mip_chain() {
+        let img = fixture("bc1_mips.dds").unwrap();
+
+        assert_eq!(img.format, TextureFormat::Bc1RgbaUnormSrgb);
+        assert_eq!((img.width, img.height), (8, 8));
+        assert_eq!(img.mip_level_count, 4);
+        // 4 blocks, then 1 for each of the 3 smaller levels.
+        assert_eq!(img.data, (0..56).collect::<Vec<u8>>());
+    }
+
+    #[test]
+    fn parse_dds_dx10() {
+        let img = fixture("bc3_dx10.dds").unwrap();
+
+        assert_eq!(img.format, TextureFormat::Bc3RgbaUnorm);
+        assert_eq!((img.width, img.height, img.mip_level_count), (4, 4, 1));
+        assert_eq!(img.data, (100..116).collect::<Vec<u8>>());
+    }
+
+    #[test]
+    fn parse_ktx2_levels() {
+        let img = fixture("bc5_mips.ktx2").unwrap();
+
+        assert_eq!(img.format, TextureFormat::Bc5RgUnorm);
+        assert_eq!((img.width, img.height), (8, 8));
+        assert_eq!(img.mip_level_count, 2);
+        // The file stores the smaller level first; we order them largest first.
+        let expected: Vec<u8> = (0..64).chain(200..216).collect();
+        assert_eq!(img.data, expected);
+    }
+
+    #[test]
+    fn parse_ktx2_supercompressed() {
+        let Err(TextureError::Container(msg)) = fixture("zstd.ktx2") else {
+            panic!("Supercompressed files should fail to parse");
+        };
+        assert!(msg.contains("Zstd"));
+    }
+
+    #[test]
+    fn parse_invalid() {
+        let dds = read_fixture("bc1_mips.dds");
+
+        // Not a container we read.
+        assert!(parse(b"\x89PNG\r\n\x1a\n").is_none());
+        // The header ends early.
+        assert!(matches!(parse(&dds[..60]), Some(Err(_))));
+        // The image data ends early.
+        assert!(matches!(parse(&dds[..150]), Some(Err(_))));
+    }
+
+    #[test]
+    fn decompress_bc1() {
+        let (rgba, srgb) = decompress_bc(&image(
+            TextureFormat::Bc1RgbaUnorm,
+            4,
+            4,
+            BC1_BLOCK.to_vec(),
+        ))
+        .unwrap();
+
+        assert!(!srgb);
+        let row = [RED, BLUE, [170, 0, 85, 255], [85, 0, 170, 255]].concat();
+        assert_eq!(rgba, row.repeat(4));
+
+        // With the endpoints swapped, the block is in 3-color mode, with transparent black.
+        let block = [0x1f, 0x00, 0x00, 0xf8, 0xe4, 0xe4, 0xe4, 0xe4];
+        let (rgba, _) =
+            decompress_bc(&image(TextureFormat::Bc1RgbaUnorm, 4, 4, block.to_vec())).unwrap();
+        let row = [BLUE, RED, [127, 0, 127, 255], [0, 0, 0, 0]].concat();
+        assert_eq!(rgba, row.repeat(4));
+    }
+
+    #[test]
+    fn decompress_bc2() {
+        // Each texel's alpha is its index, as 4 bits.
+        let mut block = vec![0x10, 0x32, 0x54, 0x76, 0x98, 0xba, 0xdc, 0xfe];
+        block.extend_from_slice(&BC1_BLOCK);
+
+        let (rgba, srgb) =
+            decompress_bc(&image(TextureFormat::Bc2RgbaUnormSrgb, 4, 4, block)).unwrap();
+
+        assert!(srgb);
+        for (i, texel) in rgba.chunks(4).enumerate() {
+            assert_eq!(texel[3], i as u8 * 17);
+        }
+        assert_eq!(rgba[..3], RED[..3]);
+        assert_eq!(rgba[4..7], BLUE[..3]);
+    }
+
+    #[test]
+    fn decompress_bc3() {
+        // 8 alpha values between 255 and 0; each texel uses code `i % 8`.
+        let mut block = channel_block(255, 0, std::array::from_fn(|i| i as u64 % 8)).to_vec();
+        block.extend_from_slice(&BC1_BLOCK);
+
+        let (rgba, _) = decompress_bc(&image(TextureFormat::Bc3RgbaUnorm, 4, 4, block)).unwrap();
+
+        let alpha: Vec<u8> = rgba.chunks(4).map(|t| t[3]).collect();
+        let codes = [255, 0, 218, 182, 145, 109, 72, 36];
+        assert_eq!(alpha, [codes, codes].concat());
+        assert_eq!(rgba[..3], RED[..3]);
+    }
+
+    #[test]
+    fn decompress_bc4() {
+        // 6 values between 0 and 255, then the 0 and 255 constants.
+        let block = channel_block(0, 255, std::array::from_fn(|i| i as u64 % 8));
+
+        let (rgba, _) =
+            decompress_bc(&image(TextureFormat::Bc4RUnorm, 4, 4, block.to_vec())).unwrap();
+
+        let codes = [0, 255, 51, 102, 153, 204, 0, 255];
+        let expected: Vec<u8> = [codes, codes]
+            .concat()
+            .iter()
+            .flat_map(|&r| [r, 0, 0, 255])
+            .collect();
+        assert_eq!(rgba, expected);
+    }
+
+    #[test]
+    fn decompress_bc5() {
+        let mut block = channel_block(0, 255, std::array::from_fn(|i| i as u64 % 8)).to_vec();
+        block.extend_from_slice(&channel_block(128, 128, [0; 16]));
+
+        let (rgba, _) = decompress_bc(&image(TextureFormat::Bc5RgUnorm, 4, 4, block)).unwrap();
+
+        let codes = [0, 255, 51, 102, 153, 204, 0, 255];
+        let expected: Vec<u8> = [codes, codes]
+            .concat()
+            .iter()
+            .flat_map(|&r| [r, 128, 0, 255])
+            .collect();
+        assert_eq!(rgba, expected);
+    }
+
+    #[test]
+    fn decompress_partial_blocks() {
+        // A 2x2 image still takes a whole block; only its top-left texels are kept.
+        let (rgba, _) = decompress_bc(&image(
+            TextureFormat::Bc1RgbaUnorm,
+            2,
+            2,
+            BC1_BLOCK.to_vec(),
+        ))
+        .unwrap();
+
+        assert_eq!(rgba, [RED, BLUE, RED, BLUE].concat());
+    }
+}
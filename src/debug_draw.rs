@@ -0,0 +1,329 @@
+//! Debug visualization, for inspecting meshes and their placement: entity bounding boxes, vertex
+//! normals, and tangent frames, drawn as lines over the scene.
+
+use core::f32::consts::TAU;
+
+use bytemuck::{Pod, Zeroable};
+use lin_alg::f32::{Vec3, Vec4};
+use wgpu::{
+    BufferUsages, Device, RenderPass, VertexAttribute, VertexBufferLayout, VertexFormat,
+    VertexStepMode,
+};
+
+use crate::{
+    Camera, FWD_VEC, LightType, Mesh, PointLight, RIGHT_VEC, Scene, UP_VEC, Vertex,
+    buffers::{GrowableBuffer, Uploader},
+    color::linearize,
+};
+
+// sRGB.
+const COLOR_AABB: (f32, f32, f32) = (1., 0.85, 0.);
+const COLOR_NORMAL: (f32, f32, f32) = (0., 0.8, 1.);
+const COLOR_TANGENT: (f32, f32, f32) = (1., 0.2, 0.2);
+const COLOR_BITANGENT: (f32, f32, f32) = (0.2, 1., 0.2);
+const COLOR_FRAME_NORMAL: (f32, f32, f32) = (0.3, 0.4, 1.);
+const COLOR_FRUSTUM: (f32, f32, f32) = (1., 0.3, 1.);
+
+/// Line segments per circle, for light ranges.
+const CIRCLE_SEGMENTS: usize = 48;
+
+/// Set `GraphicsSettings::debug_draw` to show these. Lines are depth-tested against the scene.
+#[derive(Clone, Debug)]
+pub struct DebugDraw {
+    /// Each entity's world-space, axis-aligned bounding box, in yellow.
+    pub aabbs: bool,
+    /// Each vertex's normal, in cyan.
+    pub normals: bool,
+    /// Each vertex's tangent in red, bitangent in green, and normal in blue. Useful for checking
+    /// normal maps.
+    pub tangent_frames: bool,
+    /// The length of normal and tangent lines, in world units. Unaffected by entity scale.
+    pub line_len: f32,
+    /// Draw the view frusta of these cameras, between their near and far planes, in magenta. E.g.
+    /// a copy of `Scene::camera`, to inspect it from another viewpoint while tuning `near` and
+    /// `far`.
+    pub frustums: Vec<Camera>,
+    /// A sphere around each point light at its `range`, or for directional lights, a cone, in the
+    /// light's color.
+    pub light_ranges: bool,
+}
+
+impl Default for DebugDraw {
+    fn default() -> Self {
+        Self {
+            aabbs: false,
+            normals: false,
+            tangent_frames: false,
+            line_len: 0.1,
+            frustums: Vec::new(),
+            light_ranges: false,
+        }
+    }
+}
+
+impl DebugDraw {
+    /// If there's anything to draw.
+    pub fn any(&self) -> bool {
+        self.aabbs
+            || self.normals
+            || self.tangent_frames
+            || !self.frustums.is_empty()
+            || self.light_ranges
+    }
+}
+
+/// Matches `VertexIn` in `shader_debug_lines.wgsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct LineVertex {
+    position: [f32; 3],
+    /// Linear RGBA.
+    color: [f32; 4],
+}
+
+pub(crate) const LINE_VERTEX_LAYOUT: VertexBufferLayout<'static> = VertexBufferLayout {
+    array_stride: size_of::<LineVertex>() as wgpu::BufferAddress,
+    step_mode: VertexStepMode::Vertex,
+    attributes: &[
+        VertexAttribute {
+            offset: 0,
+            shader_location: 0,
+            format: VertexFormat::Float32x3,
+        },
+        VertexAttribute {
+            offset: size_of::<[f32; 3]>() as wgpu::BufferAddress,
+            shader_location: 1,
+            format: VertexFormat::Float32x4,
+        },
+    ],
+};
+
+/// The 12 edges of a box, as pairs of indices into its corners. Corner bit 0 selects max x, bit 1
+/// max y, and bit 2 max z.
+const BOX_EDGES: [(usize, usize); 12] = [
+    (0, 1),
+    (2, 3),
+    (4, 5),
+    (6, 7),
+    (0, 2),
+    (1, 3),
+    (4, 6),
+    (5, 7),
+    (0, 4),
+    (1, 5),
+    (2, 6),
+    (3, 7),
+];
+
+/// GPU state for `GraphicsSettings::debug_draw`: a line list, rebuilt from the scene each frame
+/// it's enabled.
+pub(crate) struct DebugLines {
+    pub settings: DebugDraw,
+    buf: GrowableBuffer,
+    vertex_count: u32,
+}
+
+impl DebugLines {
+    pub fn new(device: &Device) -> Self {
+        Self {
+            settings: Default::default(),
+            buf: GrowableBuffer::new(device, "Debug line buffer", BufferUsages::VERTEX),
+            vertex_count: 0,
+        }
+    }
+
+    /// Build lines for the scene, in world space, and upload them.
+    pub fn prepare(&mut self, device: &Device, uploader: &mut Uploader, scene: &Scene) {
+        let s = &self.settings;
+        let mut vertices = Vec::new();
+
+        if s.aabbs || s.normals || s.tangent_frames {
+            entity_lines(&mut vertices, s, scene);
+        }
+
+        let color_frustum = with_alpha(COLOR_FRUSTUM);
+        for camera in &s.frustums {
+            let corners = camera.frustum_corners();
+            for (a, b) in BOX_EDGES {
+                line(&mut vertices, corners[a], corners[b], color_frustum);
+            }
+        }
+
+        if s.light_ranges {
+            for light in &scene.lighting.point_lights {
+                light_lines(&mut vertices, light);
+            }
+        }
+
+        self.vertex_count = vertices.len() as u32;
+        self.buf
+            .write(device, uploader, bytemuck::cast_slice(&vertices));
+    }
+
+    /// Draw the lines from `prepare`, with the debug line pipeline and a camera already bound.
+    pub fn draw(&self, rpass: &mut RenderPass) {
+        if self.vertex_count == 0 {
+            return;
+        }
+        rpass.set_vertex_buffer(0, self.buf.buf.slice(..));
+        rpass.draw(0..self.vertex_count, 0..1);
+    }
+}
+
+/// The (min, max) corners of a mesh's vertices, in its local space.
+fn local_bounds(mesh: &Mesh) -> Option<(Vec3, Vec3)> {
+    let mut iter = mesh.vertices.iter().map(
+        |&Vertex {
+             position: [x, y, z],
+             ..
+         }| Vec3::new(x, y, z),
+    );
+    let first = iter.next()?;
+
+    Some(iter.fold((first, first), |(min, max), p| (min.min(p), max.max(p))))
+}
+
+fn with_alpha(c: (f32, f32, f32)) -> [f32; 4] {
+    let c = linearize(c);
+    [c[0], c[1], c[2], 1.]
+}
+
+fn line(out: &mut Vec<LineVertex>, a: Vec3, b: Vec3, color: [f32; 4]) {
+    out.push(LineVertex {
+        position: a.to_arr(),
+        color,
+    });
+    out.push(LineVertex {
+        position: b.to_arr(),
+        color,
+    });
+}
+
+/// A circle around `center`, in the plane of the unit vectors `u` and `v`.
+fn circle(out: &mut Vec<LineVertex>, center: Vec3, u: Vec3, v: Vec3, radius: f32, color: [f32; 4]) {
+    let point = |i: usize| {
+        let angle = TAU * i as f32 / CIRCLE_SEGMENTS as f32;
+        center + (u * angle.cos() + v * angle.sin()) * radius
+    };
+
+    for i in 0..CIRCLE_SEGMENTS {
+        line(out, point(i), point(i + 1), color);
+    }
+}
+
+/// Bounding boxes, normals, and tangent frames, as enabled.
+fn entity_lines(out: &mut Vec<LineVertex>, s: &DebugDraw, scene: &Scene) {
+    let color_aabb = with_alpha(COLOR_AABB);
+    let color_normal = with_alpha(COLOR_NORMAL);
+    let color_tangent = with_alpha(COLOR_TANGENT);
+    let color_bitangent = with_alpha(COLOR_BITANGENT);
+    let color_frame_normal = with_alpha(COLOR_FRAME_NORMAL);
+
+    // Mesh-local bounds; None for meshes without vertices.
+    let bounds: Vec<Option<(Vec3, Vec3)>> = if s.aabbs {
+        scene.meshes.iter().map(local_bounds).collect()
+    } else {
+        Vec::new()
+    };
+
+    for entity in &scene.entities {
+        let Some(mesh) = scene.meshes.get(entity.mesh) else {
+            continue;
+        };
+        let inst = scene.instance(entity);
+        let model = inst.model_mat();
+        let to_world = |p: Vec3| (model.clone() * Vec4::new(p.x, p.y, p.z, 1.)).xyz();
+
+        if let Some(Some((min, max))) = bounds.get(entity.mesh) {
+            let corners: Vec<Vec3> = (0..8)
+                .map(|i| {
+                    to_world(Vec3::new(
+                        if i & 1 == 0 { min.x } else { max.x },
+                        if i & 2 == 0 { min.y } else { max.y },
+                        if i & 4 == 0 { min.z } else { max.z },
+                    ))
+                })
+                .collect();
+
+            let mut world_min = corners[0];
+            let mut world_max = corners[0];
+            for &c in &corners[1..] {
+                world_min = world_min.min(c);
+                world_max = world_max.max(c);
+            }
+
+            for (a, b) in BOX_EDGES {
+                let corner = |i: usize| {
+                    Vec3::new(
+                        if i & 1 == 0 { world_min.x } else { world_max.x },
+                        if i & 2 == 0 { world_min.y } else { world_max.y },
+                        if i & 4 == 0 { world_min.z } else { world_max.z },
+                    )
+                };
+                line(out, corner(a), corner(b), color_aabb);
+            }
+        }
+
+        if !(s.normals || s.tangent_frames) {
+            continue;
+        }
+
+        // Normals transform by the inverse scale, and tangents by the scale, before rotating.
+        let scale_inv = Vec3::new(1. / inst.scale.x, 1. / inst.scale.y, 1. / inst.scale.z);
+        let dir = |v: Vec3, scale: Vec3| {
+            inst.orientation
+                .rotate_vec(v.hadamard_product(scale))
+                .to_normalized()
+                * s.line_len
+        };
+
+        for vertex in &mesh.vertices {
+            let [x, y, z] = vertex.position;
+            let p = to_world(Vec3::new(x, y, z));
+            let normal = dir(vertex.normal, scale_inv);
+
+            if s.normals {
+                line(out, p, p + normal, color_normal);
+            }
+            if s.tangent_frames {
+                line(out, p, p + dir(vertex.tangent, inst.scale), color_tangent);
+                line(
+                    out,
+                    p,
+                    p + dir(vertex.bitangent, inst.scale),
+                    color_bitangent,
+                );
+                line(out, p, p + normal, color_frame_normal);
+            }
+        }
+    }
+}
+
+/// A sphere at a light's range, or for directional lights, a cone to it.
+fn light_lines(out: &mut Vec<LineVertex>, light: &PointLight) {
+    let c = light.diffuse_color;
+    let color = with_alpha((c[0], c[1], c[2]));
+    let range = light.range();
+    let p = light.position;
+
+    match &light.type_ {
+        LightType::Directional { direction, fov } => {
+            let dir = direction.to_normalized();
+            let u = dir.any_perpendicular();
+            let v = dir.cross(u);
+
+            let end = p + dir * range;
+            let radius = range * (fov / 2.).tan();
+            circle(out, end, u, v, radius, color);
+
+            for edge in [u, v, -u, -v] {
+                line(out, p, end + edge * radius, color);
+            }
+        }
+        _ => {
+            for (u, v) in [(RIGHT_VEC, UP_VEC), (UP_VEC, FWD_VEC), (FWD_VEC, RIGHT_VEC)] {
+                circle(out, p, u, v, range, color);
+            }
+        }
+    }
+}
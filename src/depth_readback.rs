@@ -0,0 +1,228 @@
+//! Copies the depth buffer back to the CPU, e.g. to find the surface under the cursor.
+//!
+//! We copy the 1-sample prepass depth texture into a staging buffer after rendering, and map it
+//! asynchronously; results arrive a frame or two later, without stalling the GPU. Only one copy is
+//! in flight at a time, so frames rendered meanwhile are skipped.
+
+use std::{
+    fmt,
+    sync::{
+        Arc,
+        mpsc::{self, Receiver, TryRecvError},
+    },
+};
+
+use lin_alg::f32::{Mat4, Vec3, Vec4};
+use tracing::warn;
+use wgpu::{
+    Buffer, BufferAsyncError, BufferUsages, CommandEncoder, Device, MapMode, PollType, Texture,
+};
+
+/// The depth buffer of a rendered frame, from `GraphicsSettings::depth_readback`. Query it with
+/// `Scene::query_depth` and `Scene::world_pos_at`.
+#[derive(Clone)]
+pub struct DepthSnapshot {
+    width: u32,
+    height: u32,
+    /// Row-major, top row first. 0 is the near plane, and 1 the far plane, or no geometry.
+    depth: Arc<[f32]>,
+    /// The camera the frame was rendered with; it may have moved since.
+    proj_view_inv: Mat4,
+    /// The 3D viewport, in physical pixels: x, y, width, height.
+    viewport: (f32, f32, f32, f32),
+}
+
+impl fmt::Debug for DepthSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DepthSnapshot")
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .field("viewport", &self.viewport)
+            .finish_non_exhaustive()
+    }
+}
+
+impl DepthSnapshot {
+    /// Convert a screen position, in physical pixels, to normalized device coordinates; `None` if
+    /// it's outside the 3D viewport.
+    fn screen_to_ndc(&self, screen_pos: (f32, f32)) -> Option<(f32, f32)> {
+        let (x, y, w, h) = self.viewport;
+
+        let sx = (screen_pos.0 - x) / w;
+        let sy = (screen_pos.1 - y) / h;
+
+        if !(0. ..1.).contains(&sx) || !(0. ..1.).contains(&sy) {
+            return None;
+        }
+
+        Some((2. * sx - 1., 1. - 2. * sy))
+    }
+
+    /// The depth buffer value at a screen position; `None` if there's no geometry there.
+    pub(crate) fn depth_at(&self, screen_pos: (f32, f32)) -> Option<f32> {
+        let (ndc_x, ndc_y) = self.screen_to_ndc(screen_pos)?;
+
+        // The prepass covers the whole surface, instead of just the 3D viewport.
+        let col = ((ndc_x + 1.) / 2. * self.width as f32) as u32;
+        let row = ((1. - ndc_y) / 2. * self.height as f32) as u32;
+
+        let depth = *self
+            .depth
+            .get((row.min(self.height - 1) * self.width + col.min(self.width - 1)) as usize)?;
+
+        if depth >= 1. { None } else { Some(depth) }
+    }
+
+    /// The world-space position of the surface at a screen position, if any.
+    pub(crate) fn world_pos_at(&self, screen_pos: (f32, f32)) -> Option<Vec3> {
+        let depth = self.depth_at(screen_pos)?;
+        let (ndc_x, ndc_y) = self.screen_to_ndc(screen_pos)?;
+
+        let world = self.proj_view_inv.clone() * Vec4::new(ndc_x, ndc_y, depth, 1.);
+        Some(world.xyz() / world.w)
+    }
+}
+
+/// A copy waiting for the GPU, and what's needed to interpret it.
+struct InFlight {
+    buf: Buffer,
+    width: u32,
+    height: u32,
+    bytes_per_row: u32,
+    proj_view_inv: Mat4,
+    viewport: (f32, f32, f32, f32),
+    /// Set once the copy is submitted and mapping requested.
+    mapped: Option<Receiver<Result<(), BufferAsyncError>>>,
+}
+
+/// Reads back the prepass depth texture, when `GraphicsSettings::depth_readback` is set.
+#[derive(Default)]
+pub(crate) struct DepthReadback {
+    pub enabled: bool,
+    in_flight: Option<InFlight>,
+    /// Reused between copies of the same size.
+    spare: Option<Buffer>,
+}
+
+impl DepthReadback {
+    /// Record a copy of `depth`, unless one is already in flight. Call after the prepass.
+    pub fn copy(
+        &mut self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        depth: &Texture,
+        proj_view_inv: Mat4,
+        viewport: (f32, f32, f32, f32),
+    ) {
+        if !self.enabled || self.in_flight.is_some() {
+            return;
+        }
+
+        let (width, height) = (depth.width(), depth.height());
+        let bytes_per_row = (width * 4).next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+        let size = (bytes_per_row * height) as u64;
+
+        let buf = match self.spare.take() {
+            Some(b) if b.size() == size => b,
+            _ => device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Depth readback buffer"),
+                size,
+                usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+                mapped_at_creation: false,
+            }),
+        };
+
+        encoder.copy_texture_to_buffer(
+            wgpu::TexelCopyTextureInfo {
+                texture: depth,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::DepthOnly,
+            },
+            wgpu::TexelCopyBufferInfo {
+                buffer: &buf,
+                layout: wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(bytes_per_row),
+                    rows_per_image: Some(height),
+                },
+            },
+            depth.size(),
+        );
+
+        self.in_flight = Some(InFlight {
+            buf,
+            width,
+            height,
+            bytes_per_row,
+            proj_view_inv,
+            viewport,
+            mapped: None,
+        });
+    }
+
+    /// Request mapping of a copy recorded this frame. Call after submitting it.
+    pub fn map(&mut self) {
+        let Some(in_flight) = &mut self.in_flight else {
+            return;
+        };
+        if in_flight.mapped.is_some() {
+            return;
+        }
+
+        let (tx, rx) = mpsc::channel();
+        in_flight.buf.map_async(MapMode::Read, .., move |result| {
+            let _ = tx.send(result);
+        });
+        in_flight.mapped = Some(rx);
+    }
+
+    pub fn in_flight(&self) -> bool {
+        self.in_flight.is_some()
+    }
+
+    /// Check for a finished copy, without blocking.
+    pub fn poll(&mut self, device: &Device) -> Option<DepthSnapshot> {
+        let rx = self.in_flight.as_ref()?.mapped.as_ref()?;
+
+        if let Err(e) = device.poll(PollType::Poll) {
+            warn!("Error polling the device for depth readback: {e}");
+        }
+
+        let result = match rx.try_recv() {
+            Ok(r) => r,
+            Err(TryRecvError::Empty) => return None,
+            Err(TryRecvError::Disconnected) => Err(BufferAsyncError),
+        };
+
+        let in_flight = self.in_flight.take()?;
+        if let Err(e) = result {
+            warn!("Depth readback failed: {e}");
+            return None;
+        }
+
+        let depth: Arc<[f32]> = {
+            let data = in_flight.buf.get_mapped_range(..);
+            data.chunks(in_flight.bytes_per_row as usize)
+                .flat_map(|row| {
+                    row[..in_flight.width as usize * 4]
+                        .chunks_exact(4)
+                        .map(|b| f32::from_ne_bytes([b[0], b[1], b[2], b[3]]))
+                })
+                .collect()
+        };
+        in_flight.buf.unmap();
+
+        if self.enabled {
+            self.spare = Some(in_flight.buf);
+        }
+
+        Some(DepthSnapshot {
+            width: in_flight.width,
+            height: in_flight.height,
+            depth,
+            proj_view_inv: in_flight.proj_view_inv,
+            viewport: in_flight.viewport,
+        })
+    }
+}
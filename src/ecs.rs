@@ -0,0 +1,336 @@
+//! An optional adapter for applications that already store their world state in an ECS. It syncs
+//! a small set of components from a `bevy_ecs` `World` into `Scene::entities` each frame, and
+//! reports the minimal `EntityUpdate` required. Feature-gated.
+//!
+//! Every ECS entity with both a `Transform` and a `MeshRef` is rendered; `Color` and `Visibility`
+//! are optional. The adapter owns `Scene::entities`; don't mix it with entities added manually.
+
+use std::collections::HashMap;
+
+use bevy_ecs::{
+    change_detection::{DetectChanges, Ref, Tick},
+    component::Component,
+    entity::Entity as EcsEntity,
+    world::World,
+};
+use lin_alg::f32::{Quaternion, Vec3};
+
+use crate::{EntityUpdate, types::Entity};
+
+/// Position, orientation, and per-axis scale of a rendered ECS entity.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct Transform {
+    pub position: Vec3,
+    pub orientation: Quaternion,
+    pub scale: Vec3,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self {
+            position: Vec3::new_zero(),
+            orientation: Quaternion::new_identity(),
+            scale: Vec3::new(1., 1., 1.),
+        }
+    }
+}
+
+impl Transform {
+    pub fn new(position: Vec3, orientation: Quaternion, scale: f32) -> Self {
+        Self {
+            position,
+            orientation,
+            scale: Vec3::new(scale, scale, scale),
+        }
+    }
+}
+
+/// Index of the mesh in `Scene::meshes` this ECS entity is drawn with.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct MeshRef(pub usize);
+
+/// If omitted, the entity is drawn white and opaque.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct Color {
+    pub color: (f32, f32, f32),
+    pub opacity: f32,
+    pub shinyness: f32,
+}
+
+impl Default for Color {
+    fn default() -> Self {
+        Self {
+            color: (1., 1., 1.),
+            opacity: 1.,
+            shinyness: 0.,
+        }
+    }
+}
+
+/// If omitted, the entity is visible.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct Visibility(pub bool);
+
+/// Keeps track of which ECS entities map to which scene entities, and when they were last synced.
+pub struct EcsSync {
+    /// ECS entities, in the order of `Scene::entities`.
+    order: Vec<EcsEntity>,
+    /// ECS entity to its index in `Scene::entities`.
+    indices: HashMap<EcsEntity, usize>,
+    /// The world change tick as of the last sync. Components changed after this are dirty.
+    last_tick: Option<Tick>,
+}
+
+impl Default for EcsSync {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EcsSync {
+    pub fn new() -> Self {
+        Self {
+            order: Vec::new(),
+            indices: HashMap::new(),
+            last_tick: None,
+        }
+    }
+
+    /// Copy changed components from `world` into `entities`. Call this once per frame, e.g. from
+    /// the render handler, and assign the result to `EngineUpdates::entities`.
+    ///
+    /// If entities were spawned, despawned, hidden, or shown, this rebuilds `entities` and returns
+    /// `EntityUpdate::All`. Otherwise, it only overwrites changed entities in place, and returns
+    /// the index range containing them.
+    pub fn sync(&mut self, world: &mut World, entities: &mut Vec<Entity>) -> EntityUpdate {
+        let this_tick = world.change_tick();
+
+        let mut query = world.query::<(
+            EcsEntity,
+            Ref<Transform>,
+            Ref<MeshRef>,
+            Option<Ref<Color>>,
+            Option<Ref<Visibility>>,
+        )>();
+
+        let mut order = Vec::with_capacity(self.order.len());
+        let mut dirty = Vec::new();
+
+        for (ecs_ent, transform, mesh, color, visibility) in query.iter(world) {
+            if let Some(v) = &visibility
+                && !v.0
+            {
+                continue;
+            }
+
+            let changed = match self.last_tick {
+                Some(last) => {
+                    let is_dirty = |t: Tick| t.is_newer_than(last, this_tick);
+
+                    is_dirty(transform.last_changed())
+                        || is_dirty(mesh.last_changed())
+                        || color.as_ref().is_some_and(|c| is_dirty(c.last_changed()))
+                        || visibility
+                            .as_ref()
+                            .is_some_and(|v| is_dirty(v.last_changed()))
+                }
+                None => true,
+            };
+
+            if changed {
+                dirty.push((
+                    order.len(),
+                    to_entity(ecs_ent, &transform, &mesh, color.as_deref()),
+                ));
+            }
+            order.push(ecs_ent);
+        }
+
+        // Advance the tick, so changes made after this sync compare as newer.
+        world.increment_change_tick();
+        self.last_tick = Some(this_tick);
+
+        if order != self.order || entities.len() != order.len() {
+            // The set of rendered entities changed; rebuild everything, since instance buffer
+            // slots are assigned during the rebuild.
+            let mut dirty = dirty.into_iter().peekable();
+            let mut rebuilt = Vec::with_capacity(order.len());
+
+            for (i, ecs_ent) in order.iter().enumerate() {
+                if let Some((_, ent)) = dirty.next_if(|(j, _)| *j == i) {
+                    rebuilt.push(ent);
+                    continue;
+                }
+                // Unchanged; reuse the previous scene entity.
+                match self.indices.get(ecs_ent).and_then(|&j| entities.get(j)) {
+                    Some(ent) => rebuilt.push(ent.clone()),
+                    None => {
+                        // Not previously synced, e.g. after being shown again. Read it fresh.
+                        let mut q = world.query::<(&Transform, &MeshRef, Option<&Color>)>();
+                        let Ok((transform, mesh, color)) = q.get(world, *ecs_ent) else {
+                            continue;
+                        };
+                        rebuilt.push(to_entity(*ecs_ent, transform, mesh, color));
+                    }
+                }
+            }
+
+            *entities = rebuilt;
+            self.indices = order.iter().enumerate().map(|(i, e)| (*e, i)).collect();
+            self.order = order;
+
+            return EntityUpdate::All;
+        }
+
+        if dirty.is_empty() {
+            return EntityUpdate::None;
+        }
+
+        let start = dirty[0].0;
+        let end = dirty[dirty.len() - 1].0 + 1;
+
+        for (i, ent) in dirty {
+            // Preserve the instance buffer slot, so this can be updated in place.
+            let buf_i = entities[i].buf_i;
+            let buf_is_transparent = entities[i].buf_is_transparent;
+
+            entities[i] = Entity {
+                buf_i,
+                buf_is_transparent,
+                ..ent
+            };
+        }
+
+        EntityUpdate::Indexes((start, end))
+    }
+}
+
+fn to_entity(
+    ecs_ent: EcsEntity,
+    transform: &Transform,
+    mesh: &MeshRef,
+    color: Option<&Color>,
+) -> Entity {
+    let color = color.copied().unwrap_or_default();
+
+    Entity {
+        id: ecs_ent.index_u32(),
+        mesh: mesh.0,
+        position: transform.position,
+        orientation: transform.orientation,
+        scale_partial: Some(transform.scale),
+        color: color.color,
+        opacity: color.opacity,
+        shinyness: color.shinyness,
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spawn(world: &mut World, x: f32) -> EcsEntity {
+        world
+            .spawn((
+                Transform::new(Vec3::new(x, 0., 0.), Quaternion::new_identity(), 1.),
+                MeshRef(0),
+                Color::default(),
+            ))
+            .id()
+    }
+
+    /// The index in `entities` of the scene entity synced from `ecs_ent`.
+    fn index_of(entities: &[Entity], ecs_ent: EcsEntity) -> usize {
+        entities
+            .iter()
+            .position(|e| e.id == ecs_ent.index_u32())
+            .unwrap()
+    }
+
+    #[test]
+    fn first_sync_is_all_then_none() {
+        let mut world = World::new();
+        let ecs_ents: Vec<_> = (0..3).map(|i| spawn(&mut world, i as f32)).collect();
+
+        let mut sync = EcsSync::new();
+        let mut entities = Vec::new();
+
+        assert_eq!(sync.sync(&mut world, &mut entities), EntityUpdate::All);
+        assert_eq!(entities.len(), 3);
+        for (i, &e) in ecs_ents.iter().enumerate() {
+            assert_eq!(entities[index_of(&entities, e)].position.x, i as f32);
+        }
+
+        assert_eq!(sync.sync(&mut world, &mut entities), EntityUpdate::None);
+    }
+
+    #[test]
+    fn changed_components_update_in_place() {
+        let mut world = World::new();
+        let ecs_ents: Vec<_> = (0..4).map(|i| spawn(&mut world, i as f32)).collect();
+
+        let mut sync = EcsSync::new();
+        let mut entities = Vec::new();
+        sync.sync(&mut world, &mut entities);
+
+        // As the engine assigns them on the full rebuild.
+        for (i, ent) in entities.iter_mut().enumerate() {
+            ent.buf_i = Some(i);
+        }
+
+        let moved = index_of(&entities, ecs_ents[1]);
+        world.get_mut::<Transform>(ecs_ents[1]).unwrap().position = Vec3::new(5., 6., 7.);
+
+        assert_eq!(
+            sync.sync(&mut world, &mut entities),
+            EntityUpdate::Indexes((moved, moved + 1))
+        );
+        assert_eq!(entities[moved].position, Vec3::new(5., 6., 7.));
+        assert_eq!(entities[moved].buf_i, Some(moved));
+
+        let recolored = index_of(&entities, ecs_ents[0]);
+        let moved = index_of(&entities, ecs_ents[3]);
+        world.get_mut::<Color>(ecs_ents[0]).unwrap().color = (1., 0., 0.);
+        world.get_mut::<Transform>(ecs_ents[3]).unwrap().scale = Vec3::new(2., 2., 2.);
+
+        let (start, end) = (recolored.min(moved), recolored.max(moved) + 1);
+        assert_eq!(
+            sync.sync(&mut world, &mut entities),
+            EntityUpdate::Indexes((start, end))
+        );
+        assert_eq!(entities[recolored].color, (1., 0., 0.));
+        assert_eq!(entities[moved].scale_partial, Some(Vec3::new(2., 2., 2.)));
+        // Unchanged entities keep their data.
+        let other = index_of(&entities, ecs_ents[2]);
+        assert_eq!(entities[other].position.x, 2.);
+
+        assert_eq!(sync.sync(&mut world, &mut entities), EntityUpdate::None);
+    }
+
+    #[test]
+    fn spawn_despawn_and_hide_rebuild() {
+        let mut world = World::new();
+        let ecs_ents: Vec<_> = (0..3).map(|i| spawn(&mut world, i as f32)).collect();
+
+        let mut sync = EcsSync::new();
+        let mut entities = Vec::new();
+        sync.sync(&mut world, &mut entities);
+
+        world.despawn(ecs_ents[0]);
+        assert_eq!(sync.sync(&mut world, &mut entities), EntityUpdate::All);
+        assert_eq!(entities.len(), 2);
+
+        world.entity_mut(ecs_ents[1]).insert(Visibility(false));
+        assert_eq!(sync.sync(&mut world, &mut entities), EntityUpdate::All);
+        assert_eq!(entities.len(), 1);
+        assert_eq!(entities[0].id, ecs_ents[2].index_u32());
+
+        world.get_mut::<Visibility>(ecs_ents[1]).unwrap().0 = true;
+        let spawned = spawn(&mut world, 9.);
+        assert_eq!(sync.sync(&mut world, &mut entities), EntityUpdate::All);
+        assert_eq!(entities.len(), 3);
+        assert_eq!(entities[index_of(&entities, ecs_ents[1])].position.x, 1.);
+        assert_eq!(entities[index_of(&entities, spawned)].position.x, 9.);
+    }
+}
@@ -0,0 +1,230 @@
+//! Ellipsoids with per-axis radii, e.g. thermal ellipsoids of atoms, or the covariance of a
+//! distribution. Drawn as entities of a unit sphere mesh, scaled along each axis; the mesh
+//! shader's normal matrix keeps their lighting correct under the non-uniform scale.
+
+use lin_alg::f32::{Quaternion, Vec3};
+
+use crate::Entity;
+
+#[derive(Clone, Debug)]
+pub struct Ellipsoid {
+    pub center: Vec3,
+    /// Along the ellipsoid's local x, y, and z axes.
+    pub radii: Vec3,
+    /// Rotates the local axes to world space.
+    pub orientation: Quaternion,
+}
+
+impl Ellipsoid {
+    pub fn new(center: Vec3, radii: Vec3, orientation: Quaternion) -> Self {
+        Self {
+            center,
+            radii,
+            orientation,
+        }
+    }
+
+    /// From a symmetric 3x3 covariance matrix, e.g. an atom's anisotropic displacement
+    /// parameters (U, in Å²). The radii are `scale` standard deviations along each principal
+    /// axis; 1.5382 gives the 50% probability ellipsoids of ORTEP-style figures. Negative
+    /// variances, from a matrix that isn't positive definite, are drawn with a radius of 0.
+    pub fn from_covariance(center: Vec3, covariance: [[f32; 3]; 3], scale: f32) -> Self {
+        let (variances, mut axes) = eigen_symmetric(covariance);
+
+        // Keep the axes right-handed, so they're a rotation.
+        if axes[0].cross(axes[1]).dot(axes[2]) < 0. {
+            axes[2] = -axes[2];
+        }
+
+        let radius = |v: f32| v.max(0.).sqrt() * scale;
+
+        Self {
+            center,
+            radii: Vec3::new(
+                radius(variances[0]),
+                radius(variances[1]),
+                radius(variances[2]),
+            ),
+            orientation: quat_from_axes(axes[0], axes[1], axes[2]),
+        }
+    }
+
+    /// An entity drawing this. `mesh` is the index in `Scene::meshes` of a sphere with radius 1,
+    /// e.g. `Mesh::new_sphere(1., 3)`.
+    pub fn to_entity(&self, mesh: usize, color: (f32, f32, f32), shinyness: f32) -> Entity {
+        let mut entity = Entity::new(mesh, self.center, self.orientation, 1., color, shinyness);
+        entity.scale_partial = Some(self.radii);
+        entity
+    }
+}
+
+/// Eigenvalues, and unit eigenvectors, of a symmetric 3x3 matrix, by Jacobi rotation.
+fn eigen_symmetric(mut a: [[f32; 3]; 3]) -> ([f32; 3], [Vec3; 3]) {
+    // Columns are the eigenvectors.
+    let mut v = [[1., 0., 0.], [0., 1., 0.], [0., 0., 1.]];
+
+    for _ in 0..32 {
+        let off_diag = a[0][1].abs() + a[0][2].abs() + a[1][2].abs();
+        if off_diag < 1e-12 {
+            break;
+        }
+
+        for (p, q) in [(0, 1), (0, 2), (1, 2)] {
+            if a[p][q].abs() < 1e-20 {
+                continue;
+            }
+
+            // A rotation in the p-q plane that zeroes `a[p][q]`.
+            let theta = (a[q][q] - a[p][p]) / (2. * a[p][q]);
+            let t = theta.signum() / (theta.abs() + (theta * theta + 1.).sqrt());
+            let c = 1. / (t * t + 1.).sqrt();
+            let s = t * c;
+
+            for row in a.iter_mut().chain(v.iter_mut()) {
+                let (rp, rq) = (row[p], row[q]);
+                row[p] = c * rp - s * rq;
+                row[q] = s * rp + c * rq;
+            }
+            // `p` is always before `q`.
+            let (before, after) = a.split_at_mut(q);
+            for (pk, qk) in before[p].iter_mut().zip(after[0].iter_mut()) {
+                (*pk, *qk) = (c * *pk - s * *qk, s * *pk + c * *qk);
+            }
+        }
+    }
+
+    let axis = |i: usize| Vec3::new(v[0][i], v[1][i], v[2][i]).to_normalized();
+    ([a[0][0], a[1][1], a[2][2]], [axis(0), axis(1), axis(2)])
+}
+
+/// The rotation taking the world axes to `x`, `y`, and `z`, which must be orthonormal and
+/// right-handed.
+fn quat_from_axes(x: Vec3, y: Vec3, z: Vec3) -> Quaternion {
+    let trace = x.x + y.y + z.z;
+
+    let result = if trace > 0. {
+        let s = (trace + 1.).sqrt() * 2.;
+        Quaternion::new(s / 4., (y.z - z.y) / s, (z.x - x.z) / s, (x.y - y.x) / s)
+    } else if x.x > y.y && x.x > z.z {
+        let s = (1. + x.x - y.y - z.z).sqrt() * 2.;
+        Quaternion::new((y.z - z.y) / s, s / 4., (y.x + x.y) / s, (z.x + x.z) / s)
+    } else if y.y > z.z {
+        let s = (1. + y.y - x.x - z.z).sqrt() * 2.;
+        Quaternion::new((z.x - x.z) / s, (y.x + x.y) / s, s / 4., (z.y + y.z) / s)
+    } else {
+        let s = (1. + z.z - x.x - y.y).sqrt() * 2.;
+        Quaternion::new((x.y - y.x) / s, (z.x + x.z) / s, (z.y + y.z) / s, s / 4.)
+    };
+
+    result.to_normalized()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPS: f32 = 1e-4;
+
+    /// `orientation` * diag(`variances`) * `orientation`ᵀ.
+    fn covariance(orientation: Quaternion, variances: [f32; 3]) -> [[f32; 3]; 3] {
+        let axes = [
+            Vec3::new(1., 0., 0.),
+            Vec3::new(0., 1., 0.),
+            Vec3::new(0., 0., 1.),
+        ]
+        .map(|a| orientation.rotate_vec(a));
+        let coord = |v: Vec3, i: usize| [v.x, v.y, v.z][i];
+
+        let mut result = [[0.; 3]; 3];
+        for (r, row) in result.iter_mut().enumerate() {
+            for (c, value) in row.iter_mut().enumerate() {
+                *value = (0..3)
+                    .map(|k| variances[k] * coord(axes[k], r) * coord(axes[k], c))
+                    .sum();
+            }
+        }
+        result
+    }
+
+    /// Checks that `ellipsoid`'s axes and radii reproduce `expected`, at `scale` 1.
+    fn check_reconstructs(ellipsoid: &Ellipsoid, expected: [[f32; 3]; 3]) {
+        let r = ellipsoid.radii;
+        let actual = covariance(ellipsoid.orientation, [r.x * r.x, r.y * r.y, r.z * r.z]);
+
+        for (a, e) in actual.iter().flatten().zip(expected.iter().flatten()) {
+            assert!((a - e).abs() < EPS, "{actual:?} isn't {expected:?}");
+        }
+    }
+
+    #[test]
+    fn diagonal_covariance() {
+        let cov = [[4., 0., 0.], [0., 1., 0.], [0., 0., 9.]];
+        let ellipsoid = Ellipsoid::from_covariance(Vec3::new(1., 2., 3.), cov, 1.);
+
+        assert_eq!(ellipsoid.center, Vec3::new(1., 2., 3.));
+        assert!((ellipsoid.radii - Vec3::new(2., 1., 3.)).magnitude() < EPS);
+        // Already along the world axes.
+        let x = ellipsoid.orientation.rotate_vec(Vec3::new(1., 0., 0.));
+        assert!((x.x.abs() - 1.).abs() < EPS);
+        check_reconstructs(&ellipsoid, cov);
+
+        let scaled = Ellipsoid::from_covariance(Vec3::new_zero(), cov, 1.5382);
+        assert!((scaled.radii - Vec3::new(2., 1., 3.) * 1.5382).magnitude() < EPS);
+    }
+
+    #[test]
+    fn rotated_covariance() {
+        let rotation = Quaternion::from_axis_angle(Vec3::new(1., 2., -0.5).to_normalized(), 0.9);
+        let cov = covariance(rotation, [0.04, 0.25, 0.01]);
+        let ellipsoid = Ellipsoid::from_covariance(Vec3::new_zero(), cov, 1.);
+
+        check_reconstructs(&ellipsoid, cov);
+
+        // The radii are the standard deviations, in some order.
+        let mut radii = [ellipsoid.radii.x, ellipsoid.radii.y, ellipsoid.radii.z];
+        radii.sort_by(f32::total_cmp);
+        for (r, expected) in radii.iter().zip([0.1, 0.2, 0.5]) {
+            assert!((r - expected).abs() < EPS);
+        }
+
+        // A rotation: unit length, and right-handed.
+        let o = ellipsoid.orientation;
+        assert!((o.magnitude() - 1.).abs() < EPS);
+        let x = o.rotate_vec(Vec3::new(1., 0., 0.));
+        let y = o.rotate_vec(Vec3::new(0., 1., 0.));
+        let z = o.rotate_vec(Vec3::new(0., 0., 1.));
+        assert!((x.cross(y) - z).magnitude() < EPS);
+    }
+
+    #[test]
+    fn degenerate_covariance() {
+        // A flat disk: no variance along one rotated axis.
+        let rotation = Quaternion::from_axis_angle(Vec3::new(0., 1., 1.).to_normalized(), 0.6);
+        let cov = covariance(rotation, [1., 0.36, 0.]);
+        let ellipsoid = Ellipsoid::from_covariance(Vec3::new_zero(), cov, 1.);
+
+        check_reconstructs(&ellipsoid, cov);
+        let r = ellipsoid.radii;
+        let mut radii = [r.x, r.y, r.z];
+        radii.sort_by(f32::total_cmp);
+        assert!(radii[0].abs() < 1e-3);
+        assert!((radii[1] - 0.6).abs() < EPS && (radii[2] - 1.).abs() < EPS);
+
+        // Along a single axis, with a repeated 0 eigenvalue.
+        let cov = [[1., 1., 0.], [1., 1., 0.], [0., 0., 0.]];
+        let ellipsoid = Ellipsoid::from_covariance(Vec3::new_zero(), cov, 1.);
+        check_reconstructs(&ellipsoid, cov);
+
+        // A negative variance, from a matrix that isn't positive definite, has a radius of 0.
+        let ellipsoid = Ellipsoid::from_covariance(
+            Vec3::new_zero(),
+            [[1., 0., 0.], [0., -0.5, 0.], [0., 0., 4.]],
+            1.,
+        );
+        assert!((ellipsoid.radii - Vec3::new(1., 0., 2.)).magnitude() < EPS);
+
+        let zero = Ellipsoid::from_covariance(Vec3::new_zero(), [[0.; 3]; 3], 1.);
+        assert_eq!(zero.radii, Vec3::new_zero());
+        assert!((zero.orientation.magnitude() - 1.).abs() < EPS);
+    }
+}
@@ -0,0 +1,82 @@
+//! Errors surfaced to the application, instead of panicking.
+
+use std::{error::Error, fmt, sync::Arc};
+
+use wgpu::{CreateSurfaceError, RequestAdapterError, RequestDeviceError};
+use winit::error::{EventLoopError, OsError};
+
+#[derive(Debug)]
+pub enum GraphicsError {
+    /// The event loop couldn't be created, or exited with an error.
+    EventLoop(EventLoopError),
+    /// The window couldn't be created.
+    Window(OsError),
+    /// No GPU adapter matches `GraphicsSettings::backends`, and can present to the window.
+    NoAdapter(RequestAdapterError),
+    /// The adapter was found, but refused to create a device.
+    NoDevice(RequestDeviceError),
+    /// The window's surface couldn't be created, or doesn't support the format we render to.
+    SurfaceUnsupported(String),
+    /// A shader failed to compile, or a pipeline using it failed validation.
+    ShaderCompile(String),
+    /// The GPU ran out of memory.
+    OutOfMemory,
+    /// A frame rendered headlessly couldn't be copied back from the GPU.
+    Readback(String),
+}
+
+impl fmt::Display for GraphicsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::EventLoop(e) => write!(f, "Event loop error: {e}"),
+            Self::Window(e) => write!(f, "Unable to create the window: {e}"),
+            Self::NoAdapter(e) => write!(f, "Unable to find a suitable GPU adapter: {e}"),
+            Self::NoDevice(e) => write!(f, "Unable to create the GPU device: {e}"),
+            Self::SurfaceUnsupported(e) => write!(f, "Unsupported window surface: {e}"),
+            Self::ShaderCompile(e) => write!(f, "Shader compilation failed: {e}"),
+            Self::OutOfMemory => write!(f, "The GPU ran out of memory"),
+            Self::Readback(e) => write!(f, "Unable to read the rendered frame back: {e}"),
+        }
+    }
+}
+
+impl Error for GraphicsError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::EventLoop(e) => Some(e),
+            Self::Window(e) => Some(e),
+            Self::NoAdapter(e) => Some(e),
+            Self::NoDevice(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<EventLoopError> for GraphicsError {
+    fn from(e: EventLoopError) -> Self {
+        Self::EventLoop(e)
+    }
+}
+
+impl From<CreateSurfaceError> for GraphicsError {
+    fn from(e: CreateSurfaceError) -> Self {
+        Self::SurfaceUnsupported(e.to_string())
+    }
+}
+
+/// Called with errors the engine can't recover from by itself, e.g. running out of GPU memory, or
+/// failing to initialize. This may be called from a thread other than the event loop's.
+#[derive(Clone)]
+pub struct ErrorHandler(pub Arc<dyn Fn(&GraphicsError) + Send + Sync>);
+
+impl ErrorHandler {
+    pub fn new(f: impl Fn(&GraphicsError) + Send + Sync + 'static) -> Self {
+        Self(Arc::new(f))
+    }
+}
+
+impl fmt::Debug for ErrorHandler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ErrorHandler")
+    }
+}
@@ -0,0 +1,331 @@
+//! Per-frame timing reported to the application, e.g. to adjust quality settings to hold a frame
+//! rate.
+//!
+//! CPU times are measured directly. GPU pass times use timestamp queries, where the adapter
+//! supports them: we resolve them into a staging buffer after rendering, and map it
+//! asynchronously, as with depth readback. Only one readback is in flight at a time; frames
+//! rendered meanwhile aren't timed on the GPU. Timings are reported in frame order, so a frame
+//! waits for the GPU results of earlier ones.
+
+use std::{
+    collections::VecDeque,
+    fmt,
+    sync::{
+        Arc,
+        mpsc::{self, Receiver, TryRecvError},
+    },
+    time::Duration,
+};
+
+use tracing::warn;
+use web_time::Instant;
+use wgpu::{
+    Buffer, BufferAsyncError, BufferUsages, CommandEncoder, Device, Features, MapMode, PollType,
+    QuerySet, Queue, RenderPassTimestampWrites,
+};
+
+/// The most render passes timed per frame. Passes past this aren't timed.
+const MAX_PASSES: u32 = 12;
+
+/// How long a rendered frame took, from `GraphicsSettings::on_frame_complete`.
+#[derive(Clone, Debug)]
+pub struct FrameTiming {
+    /// Counts frames rendered, from 0.
+    pub frame: u64,
+    /// Wall-clock time since the previous frame started; the reciprocal of the frame rate. This
+    /// is measured even with `FrameClock::Fixed`. Zero for the first frame measured.
+    pub frame_time: Duration,
+    /// CPU time spent preparing the frame, and recording its GPU commands; includes the GUI.
+    pub encode_time: Duration,
+    /// GPU time of each pass timed, in the order they ran: `"prepass"`, `"reflection"`, `"main"`,
+    /// `"contour"`, `"ssao"`, `"motion_blur"`, `"resample"`, and `"gui"`, for those that ran. A pass
+    /// run more than once, e.g. per stereo eye, is summed. `None` if the adapter doesn't support
+    /// timestamp queries, or this frame wasn't timed on the GPU.
+    pub gpu_passes: Option<Vec<(&'static str, Duration)>>,
+}
+
+impl FrameTiming {
+    /// The total of `gpu_passes`.
+    pub fn gpu_time(&self) -> Option<Duration> {
+        self.gpu_passes
+            .as_ref()
+            .map(|passes| passes.iter().map(|(_, t)| *t).sum())
+    }
+}
+
+/// Called with the timing of each frame, once it's known; GPU times arrive a frame or two after
+/// the frame is submitted. Called on the event loop's thread.
+#[derive(Clone)]
+pub struct FrameTimingHandler(pub Arc<dyn Fn(&FrameTiming) + Send + Sync>);
+
+impl FrameTimingHandler {
+    pub fn new(f: impl Fn(&FrameTiming) + Send + Sync + 'static) -> Self {
+        Self(Arc::new(f))
+    }
+}
+
+impl fmt::Debug for FrameTimingHandler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("FrameTimingHandler")
+    }
+}
+
+/// Timestamp writes for one render pass. Pass `writes()` to its descriptor.
+pub(crate) struct PassTimestamps {
+    query_set: QuerySet,
+    begin: u32,
+}
+
+impl PassTimestamps {
+    pub fn writes(&self) -> RenderPassTimestampWrites<'_> {
+        RenderPassTimestampWrites {
+            query_set: &self.query_set,
+            beginning_of_pass_write_index: Some(self.begin),
+            end_of_pass_write_index: Some(self.begin + 1),
+        }
+    }
+}
+
+/// Resolved timestamps waiting for the GPU, and the passes they're from.
+struct InFlight {
+    passes: Vec<&'static str>,
+    /// Set once the copy is submitted and mapping requested.
+    mapped: Option<Receiver<Result<(), BufferAsyncError>>>,
+}
+
+/// Timestamp queries; only present if the device supports them.
+struct GpuTimer {
+    query_set: QuerySet,
+    resolve_buf: Buffer,
+    read_buf: Buffer,
+    /// Nanoseconds per timestamp tick.
+    period: f32,
+    /// Passes timed in the frame being recorded, in query order; `None` if it isn't being timed.
+    recording: Option<Vec<&'static str>>,
+    in_flight: Option<InFlight>,
+}
+
+impl GpuTimer {
+    fn new(device: &Device, queue: &Queue) -> Self {
+        let count = MAX_PASSES * 2;
+        let size = count as u64 * 8;
+
+        Self {
+            query_set: device.create_query_set(&wgpu::QuerySetDescriptor {
+                label: Some("Frame timing queries"),
+                ty: wgpu::QueryType::Timestamp,
+                count,
+            }),
+            resolve_buf: device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Frame timing resolve buffer"),
+                size,
+                usage: BufferUsages::QUERY_RESOLVE | BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            }),
+            read_buf: device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Frame timing readback buffer"),
+                size,
+                usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+                mapped_at_creation: false,
+            }),
+            period: queue.get_timestamp_period(),
+            recording: None,
+            in_flight: None,
+        }
+    }
+
+    /// Check for finished timestamps, without blocking.
+    fn poll(&mut self, device: &Device) -> Option<Vec<(&'static str, Duration)>> {
+        let rx = self.in_flight.as_ref()?.mapped.as_ref()?;
+
+        if let Err(e) = device.poll(PollType::Poll) {
+            warn!("Error polling the device for frame timing: {e}");
+        }
+
+        let result = match rx.try_recv() {
+            Ok(r) => r,
+            Err(TryRecvError::Empty) => return None,
+            Err(TryRecvError::Disconnected) => Err(BufferAsyncError),
+        };
+
+        let in_flight = self.in_flight.take()?;
+        if let Err(e) = result {
+            warn!("Frame timing readback failed: {e}");
+            return Some(Vec::new());
+        }
+
+        let ticks: Vec<u64> = {
+            let data = self.read_buf.get_mapped_range(..);
+            data.chunks_exact(8)
+                .take(in_flight.passes.len() * 2)
+                .map(|b| u64::from_ne_bytes(b.try_into().unwrap()))
+                .collect()
+        };
+        self.read_buf.unmap();
+
+        let mut result: Vec<(&'static str, Duration)> = Vec::new();
+        for (name, pair) in in_flight.passes.iter().zip(ticks.chunks_exact(2)) {
+            // Some drivers report an end before the beginning for empty passes.
+            let ns = pair[1].saturating_sub(pair[0]) as f64 * self.period as f64;
+            let time = Duration::from_nanos(ns as u64);
+
+            match result.iter_mut().find(|(n, _)| n == name) {
+                Some((_, t)) => *t += time,
+                None => result.push((name, time)),
+            }
+        }
+
+        Some(result)
+    }
+}
+
+/// Measures frames, for `GraphicsSettings::on_frame_complete` and `adaptive_quality`.
+#[derive(Default)]
+pub(crate) struct FrameTimer {
+    /// Set if anything uses the timings; frames aren't measured otherwise.
+    pub enabled: bool,
+    gpu: Option<GpuTimer>,
+    frame: u64,
+    /// When the frame being recorded started.
+    started: Option<Instant>,
+    /// When the previous frame started, and the time between the two.
+    last_started: Option<Instant>,
+    interval: Duration,
+    /// Frames not yet reported, oldest first, and whether each awaits its GPU times.
+    pending: VecDeque<(FrameTiming, bool)>,
+}
+
+impl FrameTimer {
+    pub fn new(device: &Device, queue: &Queue) -> Self {
+        let supported = device.features().contains(Features::TIMESTAMP_QUERY);
+
+        Self {
+            gpu: supported.then(|| GpuTimer::new(device, queue)),
+            ..Default::default()
+        }
+    }
+
+    /// Start measuring a frame. Call before preparing it.
+    pub fn begin(&mut self) {
+        if !self.enabled {
+            self.pending.clear();
+            self.last_started = None;
+            return;
+        }
+        let now = Instant::now();
+        self.interval = self
+            .last_started
+            .map_or(Duration::ZERO, |last| now.duration_since(last));
+        self.started = Some(now);
+        self.last_started = Some(now);
+
+        if let Some(gpu) = &mut self.gpu {
+            gpu.recording = gpu.in_flight.is_none().then(Vec::new);
+        }
+    }
+
+    /// Timestamp writes for a pass, if this frame is being timed on the GPU, and there are
+    /// queries left.
+    pub fn pass(&mut self, name: &'static str) -> Option<PassTimestamps> {
+        let gpu = self.gpu.as_mut()?;
+        let passes = gpu.recording.as_mut()?;
+
+        if passes.len() as u32 >= MAX_PASSES {
+            return None;
+        }
+
+        let begin = passes.len() as u32 * 2;
+        passes.push(name);
+
+        Some(PassTimestamps {
+            query_set: gpu.query_set.clone(),
+            begin,
+        })
+    }
+
+    /// Finish measuring a frame. Call after recording its passes, and before submitting them.
+    pub fn end(&mut self, encoder: &mut CommandEncoder) {
+        let Some(started) = self.started.take() else {
+            return;
+        };
+
+        let mut awaiting = false;
+        if let Some(gpu) = &mut self.gpu
+            && let Some(passes) = gpu.recording.take()
+            && !passes.is_empty()
+        {
+            let count = passes.len() as u32 * 2;
+            encoder.resolve_query_set(&gpu.query_set, 0..count, &gpu.resolve_buf, 0);
+            encoder.copy_buffer_to_buffer(
+                &gpu.resolve_buf,
+                0,
+                &gpu.read_buf,
+                0,
+                Some(count as u64 * 8),
+            );
+
+            gpu.in_flight = Some(InFlight {
+                passes,
+                mapped: None,
+            });
+            awaiting = true;
+        }
+
+        let timing = FrameTiming {
+            frame: self.frame,
+            frame_time: self.interval,
+            encode_time: started.elapsed(),
+            gpu_passes: None,
+        };
+        self.frame += 1;
+
+        self.pending.push_back((timing, awaiting));
+    }
+
+    /// Request mapping of timestamps resolved this frame. Call after submitting it.
+    pub fn map(&mut self) {
+        let Some(gpu) = &mut self.gpu else {
+            return;
+        };
+        let Some(in_flight) = &mut gpu.in_flight else {
+            return;
+        };
+        if in_flight.mapped.is_some() {
+            return;
+        }
+
+        let (tx, rx) = mpsc::channel();
+        gpu.read_buf.map_async(MapMode::Read, .., move |result| {
+            let _ = tx.send(result);
+        });
+        in_flight.mapped = Some(rx);
+    }
+
+    /// Whether GPU pass times are measured, i.e. the device supports timestamp queries.
+    pub fn gpu_timed(&self) -> bool {
+        self.gpu.is_some()
+    }
+
+    /// If there are frames not yet reported.
+    pub fn in_flight(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Check for finished GPU times, without blocking. Returns frames that are complete, in order:
+    /// from the front of the queue, up to the first awaiting the GPU.
+    pub fn poll(&mut self, device: &Device) -> Vec<FrameTiming> {
+        if let Some(passes) = self.gpu.as_mut().and_then(|g| g.poll(device))
+            && let Some((timing, awaiting)) = self.pending.iter_mut().find(|(_, a)| *a)
+        {
+            // Empty if the readback failed.
+            timing.gpu_passes = (!passes.is_empty()).then_some(passes);
+            *awaiting = false;
+        }
+
+        let mut result = Vec::new();
+        while self.pending.front().is_some_and(|(_, awaiting)| !awaiting) {
+            result.push(self.pending.pop_front().unwrap().0);
+        }
+        result
+    }
+}
@@ -2,8 +2,15 @@
 
 //! This module contains code specific to gaussians. Not all gauss-specific code is here though.
 
-use lin_alg::f32::{Mat4, Vec3};
-use wgpu::{VertexAttribute, VertexBufferLayout, VertexFormat, VertexStepMode};
+use bytemuck::{Pod, Zeroable};
+use lin_alg::f32::Vec3;
+use wgpu::{
+    BindGroup, BindGroupLayout, BlendComponent, BlendFactor, BlendOperation, BlendState, Buffer,
+    BufferUsages, Device, Queue, ShaderStages, VertexAttribute, VertexBufferLayout, VertexFormat,
+    VertexStepMode, util::DeviceExt,
+};
+
+use crate::{Camera, Colormap, RIGHT_VEC, UP_VEC, color::linearize, colormap::COLORMAP_MAX};
 
 pub(crate) const CAM_BASIS_SIZE: usize = 32; // Includes padding.
 
@@ -17,17 +24,16 @@ pub(crate) struct CameraBasis {
 }
 
 impl CameraBasis {
-    pub fn new(view: Mat4) -> Self {
-        let view_inv = view.inverse().unwrap();
-        let cols = view_inv.to_cols();
-
-        let right = cols.0.xyz().to_normalized();
-        let up = cols.1.xyz().to_normalized();
+    /// The camera's right and up axes in world space, to orient gaussian billboards. These are the
+    /// first two columns of the inverse view matrix; we rotate the axes by the camera's orientation
+    /// directly instead of inverting it.
+    pub fn new(camera: &Camera) -> Self {
+        let orientation = camera.orientation.to_normalized();
 
         Self {
-            right,
+            right: orientation.rotate_vec(RIGHT_VEC),
             _pad0: 0.,
-            up,
+            up: orientation.rotate_vec(UP_VEC),
             _pad1: 0.,
         }
     }
@@ -79,11 +85,12 @@ pub(crate) const QUAD_VERTICES: &[QuadVertex] = &[
 ];
 
 /// This is publicly accessible; set by the user, and stored in Scene.
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Gaussian {
     pub center: Vec3,
     pub amplitude: f32,
     pub width: f32,
+    /// sRGB, and opacity.
     pub color: [f32; 4],
 }
 
@@ -97,20 +104,222 @@ impl Gaussian {
         }
     }
 
+    /// Density at `posit`: `amplitude * exp(-r² / (2σ²))`, with σ = `width`. Unlike rendering,
+    /// this keeps a negative amplitude's sign.
+    pub fn density(&self, posit: Vec3) -> f32 {
+        let r_sq = (posit - self.center).magnitude_squared();
+        self.amplitude * (-r_sq / (2. * self.width * self.width)).exp()
+    }
+
     pub(crate) fn to_instance(&self) -> GaussianInstance {
         GaussianInstance {
             center: self.center.to_arr(),
             // We may have negative-amplitude in practice, but will always render as a positive.
             amplitude: self.amplitude.abs(),
             width: self.width,
-            color: self.color,
+            color: {
+                let c = linearize((self.color[0], self.color[1], self.color[2]));
+                [c[0], c[1], c[2], self.color[3]]
+            },
             _pad: [0.; 3],
         }
     }
 }
 
+/// How overlapping gaussians combine.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum GaussianBlendMode {
+    /// Each gaussian is composited over those behind it, by its intensity. For splatting, where
+    /// gaussians represent a surface or object.
+    #[default]
+    Alpha,
+    /// Gaussians sum, so overlapping regions are brighter; e.g. for viewing density, where they're
+    /// terms of a sum.
+    Additive,
+    /// The brightest gaussian at each pixel shows; overlaps don't accumulate.
+    Max,
+}
+
+impl GaussianBlendMode {
+    pub(crate) fn blend_state(self) -> BlendState {
+        // The shader outputs color premultiplied by intensity.
+        let component = |operation| BlendComponent {
+            src_factor: BlendFactor::One,
+            dst_factor: BlendFactor::One,
+            operation,
+        };
+
+        match self {
+            Self::Alpha => BlendState::ALPHA_BLENDING,
+            Self::Additive => BlendState {
+                color: component(BlendOperation::Add),
+                alpha: component(BlendOperation::Add),
+            },
+            Self::Max => BlendState {
+                color: component(BlendOperation::Max),
+                alpha: component(BlendOperation::Max),
+            },
+        }
+    }
+}
+
+/// How `Scene::gaussians` are drawn. Changes apply on the next frame; in `RenderMode::OnDemand`,
+/// request one with `EngineUpdates::redraw`.
+///
+/// Each pixel of a gaussian has a value: its amplitude (as a magnitude) times the falloff from its
+/// center. The display controls map this to the intensity drawn, so dense scalar data can be
+/// explored without changing the gaussians.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GaussianRenderSettings {
+    pub blend_mode: GaussianBlendMode,
+    /// Values at or below the first are transparent, and values at or above the second are at
+    /// full intensity, with a linear ramp between. If `None`, intensity is proportional to value.
+    pub amplitude_window: Option<(f32, f32)>,
+    /// Applied to intensity after windowing, from 0 to 1: below 1 brightens faint regions, and
+    /// above 1 suppresses them.
+    pub gamma: f32,
+    /// If set, gaussians are colored by this map, sampled at their intensity, instead of by their
+    /// own color. Their opacity is kept.
+    pub colormap: Option<Colormap>,
+}
+
+impl Default for GaussianRenderSettings {
+    fn default() -> Self {
+        Self {
+            blend_mode: Default::default(),
+            amplitude_window: None,
+            gamma: 1.,
+            colormap: None,
+        }
+    }
+}
+
+/// Matches `Display` in `shader_gauss.wgsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct DisplayUniform {
+    window: [f32; 2],
+    gamma: f32,
+    windowed: u32,
+    /// 0 if no colormap is applied.
+    colormap_len: u32,
+    _pad: [u32; 3],
+    colormap: [[f32; 4]; COLORMAP_MAX],
+}
+
+impl GaussianRenderSettings {
+    fn to_uniform(&self) -> DisplayUniform {
+        let (colormap, colormap_len) = match self.colormap {
+            Some(c) => c.to_uniform(),
+            None => ([[0.; 4]; COLORMAP_MAX], 0),
+        };
+        let window = self.amplitude_window.unwrap_or((0., 1.));
+
+        DisplayUniform {
+            window: [window.0, window.1],
+            // `pow` is undefined at 0 for a gamma of 0.
+            gamma: self.gamma.max(1e-3),
+            windowed: self.amplitude_window.is_some() as u32,
+            colormap_len,
+            _pad: [0; 3],
+            colormap,
+        }
+    }
+}
+
+/// The uniform buffer for `GaussianRenderSettings`' display controls.
+pub(crate) struct GaussianDisplay {
+    pub layout: BindGroupLayout,
+    buf: Buffer,
+    pub bind_group: BindGroup,
+}
+
+impl GaussianDisplay {
+    pub fn new(device: &Device) -> Self {
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Gaussian display bind group layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: wgpu::BufferSize::new(size_of::<DisplayUniform>() as _),
+                },
+                count: None,
+            }],
+        });
+
+        let buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Gaussian display buffer"),
+            contents: bytemuck::bytes_of(&GaussianRenderSettings::default().to_uniform()),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Gaussian display bind group"),
+            layout: &layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buf.as_entire_binding(),
+            }],
+        });
+
+        Self {
+            layout,
+            buf,
+            bind_group,
+        }
+    }
+
+    pub fn prepare(&self, queue: &Queue, settings: &GaussianRenderSettings) {
+        queue.write_buffer(&self.buf, 0, bytemuck::bytes_of(&settings.to_uniform()));
+    }
+}
+
+/// The extent of changes to `Scene::gaussians`, and their effect on the instance buffer; the
+/// gaussian analog of `EntityUpdate`.
+#[derive(Clone, Debug, PartialEq, Default)]
+pub enum GaussianUpdate {
+    #[default]
+    None,
+    /// Upload all gaussians. Only the range that changed is transferred, so this is cheap for
+    /// appends, but compares the whole buffer.
+    All,
+    /// Upload the gaussians at these indices into `Scene::gaussians`, in place. Indices past the
+    /// end of the uploaded buffer, e.g. from `Scene::add_gaussian` appending, upload the rest.
+    Indices(Vec<usize>),
+}
+
+impl GaussianUpdate {
+    pub fn push_index(&mut self, i: usize) {
+        match self {
+            GaussianUpdate::All => (),
+            GaussianUpdate::Indices(v) => v.push(i),
+            GaussianUpdate::None => *self = GaussianUpdate::Indices(vec![i]),
+        }
+    }
+}
+
+/// The summed density of `gaussians` on a regular grid from `min` to `max` inclusive, with `dims`
+/// points along each axis, in x-major order. E.g. for `TextureSource::Volume`, to view through a
+/// `SlicePlane`.
+pub fn gaussian_density_grid(
+    gaussians: &[Gaussian],
+    min: Vec3,
+    max: Vec3,
+    dims: (u32, u32, u32),
+) -> Vec<f32> {
+    let counts = (dims.0 as usize, dims.1 as usize, dims.2 as usize);
+
+    crate::grid_points(min, max, counts)
+        .into_iter()
+        .map(|p| gaussians.iter().map(|g| g.density(p)).sum())
+        .collect()
+}
+
 #[repr(C, align(16))]
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Pod, Zeroable)]
 pub(crate) struct GaussianInstance {
     pub center: [f32; 3],
     pub amplitude: f32,
@@ -150,19 +359,69 @@ pub(crate) const GAUSS_INST_LAYOUT: VertexBufferLayout<'static> = VertexBufferLa
     ],
 };
 
-impl GaussianInstance {
-    pub fn to_bytes(&self) -> [u8; 48] {
-        let mut result = [0; 48];
-        result[0..4].clone_from_slice(&self.center[0].to_ne_bytes());
-        result[4..8].clone_from_slice(&self.center[1].to_ne_bytes());
-        result[8..12].clone_from_slice(&self.center[2].to_ne_bytes());
-        result[12..16].clone_from_slice(&self.amplitude.to_ne_bytes());
-        result[16..20].clone_from_slice(&self.width.to_ne_bytes());
-        result[20..24].clone_from_slice(&self.color[0].to_ne_bytes());
-        result[24..28].clone_from_slice(&self.color[1].to_ne_bytes());
-        result[28..32].clone_from_slice(&self.color[2].to_ne_bytes());
-        result[32..36].clone_from_slice(&self.color[3].to_ne_bytes());
+#[cfg(test)]
+mod tests {
+    use lin_alg::f32::{Quaternion, Vec3};
 
-        result
+    use super::*;
+
+    const EPS: f32 = 1e-5;
+
+    /// Orientations about off-axis directions, including unnormalized ones.
+    fn orientations() -> Vec<Quaternion> {
+        vec![
+            Quaternion::new_identity(),
+            Quaternion::from_axis_angle(Vec3::new(1., 2., 3.).to_normalized(), 0.7),
+            Quaternion::from_axis_angle(Vec3::new(-0.3, 0.9, 0.1).to_normalized(), 2.9),
+            Quaternion::from_axis_angle(Vec3::new(0., 0., 1.), -1.2)
+                * Quaternion::from_axis_angle(Vec3::new(1., 0., 0.), 0.4),
+            Quaternion::new(0.8, -0.5, 1.3, 0.2),
+            Quaternion::new(0.01, 0.02, -0.03, 0.015),
+        ]
+    }
+
+    #[test]
+    fn basis_is_orthonormal() {
+        for orientation in orientations() {
+            let camera = Camera {
+                orientation,
+                ..Default::default()
+            };
+            let basis = CameraBasis::new(&camera);
+
+            assert!(
+                (basis.right.magnitude() - 1.).abs() < EPS,
+                "{orientation:?}"
+            );
+            assert!((basis.up.magnitude() - 1.).abs() < EPS, "{orientation:?}");
+            assert!(basis.right.dot(basis.up).abs() < EPS, "{orientation:?}");
+        }
+    }
+
+    #[test]
+    fn basis_matches_inverse_view() {
+        for orientation in orientations() {
+            let camera = Camera {
+                orientation,
+                position: Vec3::new(3., -2., 10.),
+                ..Default::default()
+            };
+            let basis = CameraBasis::new(&camera);
+
+            // The view matrix assumes a unit orientation, which the basis normalizes to.
+            let reference = Camera {
+                orientation: orientation.to_normalized(),
+                ..camera.clone()
+            };
+            let inv = reference.view_mat().inverse().unwrap();
+            // `Mat4::inverse` can return the inverse up to scale; the view is affine, so divide
+            // through by the homogeneous term. Column-major: the first two columns.
+            let w = inv.data[15];
+            let right = Vec3::new(inv.data[0], inv.data[1], inv.data[2]) / w;
+            let up = Vec3::new(inv.data[4], inv.data[5], inv.data[6]) / w;
+
+            assert!((basis.right - right).magnitude() < 1e-4, "{orientation:?}");
+            assert!((basis.up - up).magnitude() < 1e-4, "{orientation:?}");
+        }
     }
 }
@@ -0,0 +1,300 @@
+//! Evaluates `Scene::gaussians` on a grid in a compute pass, into the volume texture of a
+//! `TextureSource::GaussianField`; e.g. to view an analytic wavefunction basis through a
+//! `SlicePlane`, without summing it on the CPU as `gaussian_density_grid` does.
+//!
+//! The texture stores 16-bit floats, which can't be written from shaders on all GPUs, so we pack
+//! pairs of them into a storage buffer, then copy it into the texture. Large grids are evaluated in
+//! blocks of Z layers, or of rows within a layer if one layer is too large, to keep the buffer
+//! within the device's binding size limit, and each dispatch within its workgroup count limit.
+
+use bytemuck::{Pod, Zeroable};
+use lin_alg::f32::Vec3;
+use wgpu::{
+    BindGroupLayout, BufferUsages, CommandEncoder, ComputePipeline, Device, ShaderStages,
+    util::DeviceExt,
+};
+
+use crate::Gaussian;
+
+const WORKGROUP_SIZE: u32 = 64;
+
+/// Matches `Gaussian` in `shader_gauss_field.wgsl`. Unlike the instance data we draw with, this
+/// keeps the amplitude's sign.
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct GaussianRaw {
+    center: [f32; 3],
+    amplitude: f32,
+    width: f32,
+    _pad: [f32; 3],
+}
+
+/// Matches `Params` in `shader_gauss_field.wgsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct Params {
+    grid_min: [f32; 4],
+    grid_step: [f32; 4],
+    dims: [u32; 4],
+    y_offset: u32,
+    z_offset: u32,
+    rows: u32,
+    row_words: u32,
+    gauss_count: u32,
+    _pad: [u32; 3],
+}
+
+/// The rows, and Z layers, to evaluate per block: as many as fit in `max_bytes`, with
+/// `row_bytes` per row, and at most `max_groups` of each, the workgroup count limit per dispatch
+/// dimension. Whole layers are evaluated at once unless one doesn't fit.
+fn block_size(
+    dims: (u32, u32, u32),
+    row_bytes: u64,
+    max_bytes: u64,
+    max_groups: u32,
+) -> (u32, u32) {
+    let (_, y, z) = dims;
+    let max_groups = max_groups.max(1) as u64;
+
+    let rows = (max_bytes / row_bytes).clamp(1, (y as u64).min(max_groups));
+    let layers = if rows < y as u64 {
+        1
+    } else {
+        (max_bytes / (row_bytes * rows)).clamp(1, (z as u64).min(max_groups))
+    };
+
+    (rows as u32, layers as u32)
+}
+
+pub(crate) struct GaussianFieldEvaluator {
+    layout: BindGroupLayout,
+    pipeline: ComputePipeline,
+}
+
+impl GaussianFieldEvaluator {
+    pub fn new(device: &Device) -> Self {
+        let buffer_entry = |binding, ty| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Gaussian field bind group layout"),
+            entries: &[
+                buffer_entry(0, wgpu::BufferBindingType::Uniform),
+                buffer_entry(1, wgpu::BufferBindingType::Storage { read_only: true }),
+                buffer_entry(2, wgpu::BufferBindingType::Storage { read_only: false }),
+            ],
+        });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Gaussian field shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("shader_gauss_field.wgsl").into()),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Gaussian field pipeline layout"),
+            bind_group_layouts: &[Some(&layout)],
+            immediate_size: 0,
+        });
+
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Gaussian field pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader,
+            entry_point: Some("main"),
+            compilation_options: Default::default(),
+            cache: None,
+        });
+
+        Self { layout, pipeline }
+    }
+
+    /// Record passes summing `gaussians` on the grid spanning `bounds` inclusive, with `dims`
+    /// points along each axis, and copying the result into `texture`, which must be an R16Float
+    /// volume of that size. With one point along an axis, it's at the min bound, as with
+    /// `grid_points`.
+    pub fn evaluate(
+        &self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        gaussians: &[Gaussian],
+        texture: &wgpu::Texture,
+        dims: (u32, u32, u32),
+        bounds: (Vec3, Vec3),
+    ) {
+        let (x, y, z) = dims;
+        if x == 0 || y == 0 || z == 0 {
+            return;
+        }
+
+        let mut raw: Vec<_> = gaussians
+            .iter()
+            .map(|g| GaussianRaw {
+                center: g.center.to_arr(),
+                amplitude: g.amplitude,
+                width: g.width,
+                _pad: [0.; 3],
+            })
+            .collect();
+        // Storage bindings can't be empty.
+        if raw.is_empty() {
+            raw.push(GaussianRaw::zeroed());
+        }
+        let gauss_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Gaussian field gaussians"),
+            contents: bytemuck::cast_slice(&raw),
+            usage: BufferUsages::STORAGE,
+        });
+
+        let step = |lo: f32, hi: f32, n: u32| {
+            if n > 1 {
+                (hi - lo) / (n - 1) as f32
+            } else {
+                0.
+            }
+        };
+        let (min, max) = bounds;
+
+        // Texture copies need rows aligned to 256 bytes.
+        let row_bytes = (x * 2).next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+
+        let limits = device.limits();
+        let max_bytes = limits
+            .max_storage_buffer_binding_size
+            .min(limits.max_buffer_size);
+        let max_groups = limits.max_compute_workgroups_per_dimension;
+        let (block_rows, block_layers) = block_size(dims, row_bytes as u64, max_bytes, max_groups);
+
+        let out_buf = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Gaussian field output"),
+            size: row_bytes as u64 * block_rows as u64 * block_layers as u64,
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+
+        // Volume textures are at most `max_texture_dimension_3d` wide, far fewer points than
+        // `max_groups` workgroups cover along X.
+        let groups_x = x.div_ceil(2).div_ceil(WORKGROUP_SIZE);
+
+        let blocks = (0..z).step_by(block_layers as usize).flat_map(|z_offset| {
+            (0..y)
+                .step_by(block_rows as usize)
+                .map(move |y_offset| (y_offset, z_offset))
+        });
+
+        for (y_offset, z_offset) in blocks {
+            let rows = block_rows.min(y - y_offset);
+            let layers = block_layers.min(z - z_offset);
+
+            let params = Params {
+                grid_min: [min.x, min.y, min.z, 0.],
+                grid_step: [
+                    step(min.x, max.x, x),
+                    step(min.y, max.y, y),
+                    step(min.z, max.z, z),
+                    0.,
+                ],
+                dims: [x, y, z, 0],
+                y_offset,
+                z_offset,
+                rows,
+                row_words: row_bytes / 4,
+                gauss_count: gaussians.len() as u32,
+                _pad: [0; 3],
+            };
+            let params_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Gaussian field params"),
+                contents: bytemuck::bytes_of(&params),
+                usage: BufferUsages::UNIFORM,
+            });
+
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("Gaussian field bind group"),
+                layout: &self.layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: params_buf.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: gauss_buf.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: out_buf.as_entire_binding(),
+                    },
+                ],
+            });
+
+            {
+                let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                    label: Some("Gaussian field"),
+                    timestamp_writes: None,
+                });
+                pass.set_pipeline(&self.pipeline);
+                pass.set_bind_group(0, &bind_group, &[]);
+                pass.dispatch_workgroups(groups_x, rows, layers);
+            }
+
+            encoder.copy_buffer_to_texture(
+                wgpu::TexelCopyBufferInfo {
+                    buffer: &out_buf,
+                    layout: wgpu::TexelCopyBufferLayout {
+                        offset: 0,
+                        bytes_per_row: Some(row_bytes),
+                        rows_per_image: Some(rows),
+                    },
+                },
+                wgpu::TexelCopyTextureInfo {
+                    texture,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d {
+                        x: 0,
+                        y: y_offset,
+                        z: z_offset,
+                    },
+                    aspect: wgpu::TextureAspect::All,
+                },
+                wgpu::Extent3d {
+                    width: x,
+                    height: rows,
+                    depth_or_array_layers: layers,
+                },
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn whole_layers_when_they_fit() {
+        // 4 layers of 8 rows of 256 bytes fit in 8 KiB.
+        assert_eq!(block_size((100, 8, 10), 256, 8_192, 65_535), (8, 4));
+        // All of them, if the grid fits.
+        assert_eq!(block_size((100, 8, 3), 256, 1 << 20, 65_535), (8, 3));
+    }
+
+    #[test]
+    fn splits_rows_when_a_layer_doesnt_fit() {
+        assert_eq!(block_size((100, 64, 10), 256, 4_096, 65_535), (16, 1));
+        // At least one row, even if it doesn't fit; there's nothing smaller to split into.
+        assert_eq!(block_size((100, 64, 10), 256, 100, 65_535), (1, 1));
+    }
+
+    #[test]
+    fn limits_workgroups_per_dispatch() {
+        assert_eq!(block_size((100, 8, 1_000), 256, 1 << 30, 100), (8, 100));
+        assert_eq!(block_size((100, 1_000, 4), 256, 1 << 30, 100), (100, 1));
+    }
+}
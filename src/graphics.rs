@@ -12,17 +12,21 @@
 //!
 //! 2022-08-21: https://github.com/gfx-rs/wgpu/blob/master/wgpu/examples/cube/main.rs
 
-use std::{collections::HashSet, sync::Arc, time::Duration};
+use std::{borrow::Cow, collections::HashSet, mem, sync::Arc, time::Duration};
 
+use bytemuck::Zeroable;
 use egui::Ui;
 use lin_alg::f32::{Mat4, Vec3};
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+use tracing::{debug, debug_span, trace_span, warn};
 use wgpu::{
     self, BindGroup, BindGroupLayout, BindingType, BlendState, Buffer, BufferBindingType,
     BufferUsages, CommandEncoder, CommandEncoderDescriptor, DepthStencilState, Device, Face,
-    FragmentState, Queue, RenderPass, RenderPassDepthStencilAttachment, RenderPassDescriptor,
-    RenderPipeline, ShaderStages, StoreOp, SurfaceConfiguration, SurfaceTexture, TextureDescriptor,
-    TextureView, VertexBufferLayout, VertexState,
-    util::{BufferInitDescriptor, DeviceExt},
+    FragmentState, IndexFormat, Queue, RenderPass, RenderPassDepthStencilAttachment,
+    RenderPassDescriptor, RenderPipeline, ShaderStages, StoreOp, SurfaceConfiguration,
+    SurfaceTexture, TextureDescriptor, TextureView, VertexBufferLayout, VertexState,
+    util::{BufferInitDescriptor, DeviceExt, DrawIndexedIndirectArgs},
 };
 use winit::{
     event::{DeviceEvent, WindowEvent},
@@ -30,20 +34,46 @@ use winit::{
 };
 
 use crate::{
-    camera::CAMERA_SIZE,
-    gauss::{CAM_BASIS_SIZE, CameraBasis, GAUSS_INST_LAYOUT, QUAD_VERTEX_LAYOUT, QUAD_VERTICES},
+    adaptive_quality::{QualityChange, QualityController},
+    background::{Background, BackgroundRenderer},
+    buffers::{GrowableBuffer, Uploader},
+    camera::{CAMERA_SIZE, FOG_OFFSET, HEIGHT_FOG_OFFSET},
+    color::linearize,
+    debug_draw::{DebugLines, LINE_VERTEX_LAYOUT},
+    depth_readback::DepthReadback,
+    frame_timing::{FrameTimer, FrameTimingHandler, PassTimestamps},
+    gauss::{
+        CAM_BASIS_SIZE, CameraBasis, GAUSS_INST_LAYOUT, Gaussian, GaussianBlendMode,
+        GaussianDisplay, GaussianInstance, GaussianUpdate, QUAD_VERTEX_LAYOUT, QUAD_VERTICES,
+    },
+    gauss_field::GaussianFieldEvaluator,
     gui::GuiState,
+    impostor::{CYLINDER_INSTANCE_LAYOUT, ImpostorRenderer, SPHERE_INSTANCE_LAYOUT},
     input::{self, InputsCommanded},
-    system::{COLOR_FORMAT, DEPTH_FORMAT, process_engine_updates},
-    text_overlay::{draw_framerate, draw_text_overlay},
-    texture::Texture,
+    letterbox,
+    lines::{LINE_SEGMENT_LAYOUT, LineRenderer},
+    mesh_upload::MeshUploads,
+    motion_blur::MotionBlurRenderer,
+    pipelines::{PipelineCache, PipelineKey, PipelineKind},
+    reflection::ReflectionRenderer,
+    resample::ResampleRenderer,
+    slice::SliceRenderer,
+    stereo::{Eye, StereoMode, StereoRenderer},
+    system::{DEPTH_FORMAT, process_engine_updates},
+    text_overlay::{draw_framerate, draw_text_overlay, draw_upload_progress},
+    texture::{Texture, TextureHandle, TextureSource, TextureStore},
     types::{
-        AmbientOcclusion, ControlScheme, EngineUpdates, FramerateDisplay, GraphicsSettings,
-        INSTANCE_LAYOUT, INSTANCE_SIZE, InputSettings, Instance, Scene, UiSettings, VERTEX_LAYOUT,
-        VERTEX_SIZE,
+        AmbientOcclusion, ControlScheme, EngineUpdates, FrameClock, FramerateDisplay,
+        GraphicsSettings, INSTANCE_LAYOUT, INSTANCE_SIZE, InstanceRaw, MemoryUsage, Mesh,
+        PresentMode, RenderMode, Scene, UiSettings, VERTEX_LAYOUT, VERTEX_SIZE, VertexRaw,
     },
     viewport_rect,
 };
+#[cfg(feature = "hot_reload")]
+use crate::{
+    hot_reload::{SHADER_ERROR_DURATION, ShaderFile, ShaderWatcher},
+    text_overlay::draw_shader_error,
+};
 
 pub const UP_VEC: Vec3 = Vec3 {
     x: 0.,
@@ -79,6 +109,9 @@ pub enum EntityUpdate {
     Indexes((usize, usize)),
     /// Append this many to the end
     Append(usize),
+    /// Update the members of these groups, by index into `Scene::groups`, in place; e.g. after
+    /// moving the groups.
+    Groups(Vec<usize>),
 }
 
 impl EntityUpdate {
@@ -101,17 +134,97 @@ impl EntityUpdate {
     }
 }
 
+/// A mesh that outgrows its space in the vertex and index buffers moves to their ends, reserving
+/// this multiple of what it needs; so one that keeps growing doesn't move each time.
+const MESH_SLACK: f32 = 1.5;
+
+/// The location of a mesh's data in the vertex and index buffers, in vertices and indices.
+#[derive(Clone, Copy, Debug)]
+struct MeshRange {
+    vertex_start: usize,
+    vertex_count: usize,
+    /// The vertices reserved at `vertex_start`; at least `vertex_count`. The mesh can change size
+    /// in place within this.
+    vertex_capacity: usize,
+    /// In the index buffer of `index_format`.
+    index_start: usize,
+    index_count: usize,
+    /// The index slots reserved at `index_start`.
+    index_capacity: usize,
+    index_format: IndexFormat,
+}
+
+/// The slack for a mesh uploaded in the background. One just added, with nothing reserved yet,
+/// takes no more than it needs.
+fn upload_slack(range: &MeshRange) -> f32 {
+    if range.vertex_capacity == 0 && range.index_capacity == 0 {
+        1.
+    } else {
+        MESH_SLACK
+    }
+}
+
+/// The number of slots `count` indices take in the buffer of `format`. 16-bit indices are padded
+/// to an even number, so each mesh's data stays 4-byte aligned for uploads.
+fn index_slots(format: IndexFormat, count: usize) -> usize {
+    match format {
+        IndexFormat::Uint16 => count.next_multiple_of(2),
+        IndexFormat::Uint32 => count,
+    }
+}
+
+/// The size of one index of `format`, in bytes.
+fn index_size(format: IndexFormat) -> usize {
+    match format {
+        IndexFormat::Uint16 => 2,
+        IndexFormat::Uint32 => 4,
+    }
+}
+
+/// 16-bit indices for meshes small enough, since indices are relative to each mesh's first
+/// vertex; this halves their index memory. 32-bit otherwise.
+pub(crate) fn index_format(mesh: &Mesh) -> IndexFormat {
+    if mesh.vertices.len() <= u16::MAX as usize + 1 {
+        IndexFormat::Uint16
+    } else {
+        IndexFormat::Uint32
+    }
+}
+
+/// The size of a mesh's indices on the GPU, including padding.
+pub(crate) fn mesh_index_bytes(mesh: &Mesh) -> usize {
+    match index_format(mesh) {
+        IndexFormat::Uint16 => mesh.indices.len().next_multiple_of(2) * 2,
+        IndexFormat::Uint32 => mesh.indices.len() * 4,
+    }
+}
+
+/// Which meshes `GraphicsState::draw_meshes` draws.
+#[derive(Clone, Copy, PartialEq)]
+enum MeshSubset {
+    All,
+    /// Meshes without `Mesh::two_sided` set.
+    OneSided,
+    TwoSided,
+}
+
 /// Code related to our specific engine. Buffers, texture data etc.
 pub(crate) struct GraphicsState {
-    pub vertex_buf: Buffer,
+    pub vertex_buf: GrowableBuffer,
     // pub vertex_buf_transparent: Buffer,
     pub vertex_buf_quad: Buffer, // For gaussians.
-    pub index_buf: Buffer,
+    pub index_buf: GrowableBuffer,
+    /// Indices of meshes with few enough vertices for 16 bits.
+    index_buf_u16: GrowableBuffer,
+    /// Where each mesh's data is in the vertex and index buffers; indexed the same as `Scene::meshes`.
+    mesh_ranges: Vec<MeshRange>,
     // pub index_buf_transparent: Buffer,
-    instance_buf: Buffer,
-    instance_buf_transparent: Buffer,
-    instance_buf_gauss: Buffer,
+    instance_buf: GrowableBuffer,
+    instance_buf_transparent: GrowableBuffer,
+    instance_buf_gauss: GrowableBuffer,
     pub bind_groups: BindGroupData,
+    /// Textures from `Scene::textures`, for meshes.
+    pub(crate) texture_store: TextureStore,
     pub camera_buf: Buffer,
     /// Separate camera buffer for the depth-aware halo prepass (halo_expansion > 0).
     camera_buf_halo: Buffer,
@@ -126,17 +239,55 @@ pub(crate) struct GraphicsState {
     /// We use this two-pipeline approach for transparent meshes for rendering ones that
     /// are transparent, and double-sided.
     pub pipeline_mesh_transparent_back: RenderPipeline, // todo: Move to renderer.
-    pub pipeline_gauss: RenderPipeline, // todo: Move to renderer.
-    /// Depth-only, front-face-culled pipeline for the halo prepass.
-    pipeline_halo: RenderPipeline,
+    /// The uniform buffer for `Scene::gaussian_settings`.
+    gauss_display: GaussianDisplay,
+    pipeline_slice: RenderPipeline,
+    /// Uniform buffers for `Scene::slice_planes`.
+    slices: SliceRenderer,
+    /// The uniform buffer for `Scene::background`. Its pipeline is in the cache.
+    background: BackgroundRenderer,
+    /// Per-eye cameras, and the anaglyph composite, for `GraphicsSettings::stereo`.
+    stereo: StereoRenderer,
+    /// The mirrored camera and offscreen texture for `Scene::reflection_plane`.
+    reflection: ReflectionRenderer,
+    motion_blur: MotionBlurRenderer,
+    /// Resamples the scene to the window, when `render_scale` isn't 1.
+    resample: ResampleRenderer,
+    /// Lines for `GraphicsSettings::debug_draw`. Its pipeline is in the cache.
+    debug_lines: DebugLines,
+    pub(crate) lines: LineRenderer,
+    impostors: ImpostorRenderer,
+    /// Pipelines by shader and sample count; the halo prepass pipeline is retrieved from here
+    /// when ready.
+    pipeline_cache: PipelineCache,
+    /// Meshes from `EngineUpdates::meshes_async`, staging on a background thread.
+    pub(crate) mesh_uploads: MeshUploads,
     pub depth_texture: Texture,
     pub msaa_texture: Option<TextureView>, // MSAA Multisampled texture
     pub inputs_commanded: InputsCommanded,
-    // staging_belt: wgpu::util::StagingBelt, // todo: Do we want this? Probably in sys, not here.
+    /// Stages buffer writes for camera, lighting, meshes, and instances.
+    uploader: Uploader,
     pub scene: Scene,
     mesh_mappings: Vec<(i32, u32, u32)>,
     mesh_mappings_transparent: Vec<(i32, u32, u32)>,
-    pub window: Arc<Window>,
+    /// Slots in each mesh's range of the instance buffers that hold no entity, by mesh. Entities
+    /// crossing the opacity threshold move into one in the other buffer, instead of a rebuild.
+    free_slots_opaque: Vec<Vec<usize>>,
+    free_slots_transparent: Vec<Vec<usize>>,
+    /// Indirect draw arguments built from the mesh mappings; one per mesh that has instances.
+    indirect_buf: GrowableBuffer,
+    indirect_buf_transparent: GrowableBuffer,
+    /// If set, we draw all meshes in each pass with a single `multi_draw_indexed_indirect` call,
+    /// instead of one `draw_indexed` call per mesh. Set at init, based on adapter support.
+    pub multi_draw_indirect: bool,
+    /// If the device runs compute shaders; set at init. Not on WebGL.
+    pub compute_supported: bool,
+    /// Created once a `TextureSource::GaussianField` is loaded.
+    gauss_field: Option<GaussianFieldEvaluator>,
+    /// The gaussians `TextureSource::GaussianField` textures were last evaluated from.
+    gauss_field_source: Vec<Gaussian>,
+    /// None when rendering headlessly, with `render_headless`.
+    window: Option<Arc<Window>>,
     /// World-space expansion (along normals) used in the halo prepass. 0 = disabled.
     pub halo_expansion: f32,
     /// 1-sample depth texture written by the contour depth prepass, sampled by the overlay.
@@ -165,6 +316,8 @@ pub(crate) struct GraphicsState {
     shader_mesh: wgpu::ShaderModule,
     /// Stored Gaussian shader (same reason).
     shader_gauss: wgpu::ShaderModule,
+    shader_slice: wgpu::ShaderModule,
+    shader_background: wgpu::ShaderModule,
     /// Full-screen SSAO overlay pipeline.
     pipeline_ssao: RenderPipeline,
     /// Bind-group layout for the SSAO pass (depth tex + uniform buf).
@@ -182,26 +335,73 @@ pub(crate) struct GraphicsState {
     fps_accum_time: f32,
     /// Frames counted in the current frame rate measurement window.
     fps_accum_frames: u32,
+    pub display_memory_usage: bool,
+    pub display_upload_progress: bool,
+    /// From `GraphicsSettings::double_buffer_instances`; applied when instances are next flushed.
+    double_buffer_instances: bool,
+    /// Time since `Scene::memory_usage` was last updated, in seconds.
+    memory_usage_timer: f32,
+    pub render_mode: RenderMode,
+    /// Minimum time between frames, from `GraphicsSettings::max_fps`.
+    pub min_frame_time: Option<Duration>,
+    /// From `GraphicsSettings::clock`.
+    pub clock: FrameClock,
+    /// When set, the event loop will reconfigure the surface with this present mode before the
+    /// next frame, then clear this field.
+    pub pending_present_mode: Option<PresentMode>,
+    /// Set when something changed that requires rendering a frame, in `RenderMode::OnDemand`.
+    pub redraw_pending: bool,
+    /// Copies the prepass depth texture to `Scene::depth_snapshot`, if enabled.
+    depth_readback: DepthReadback,
+    /// Measures frames, for `on_frame_complete` and `quality`.
+    frame_timer: FrameTimer,
+    on_frame_complete: Option<FrameTimingHandler>,
+    /// Applies `GraphicsSettings::adaptive_quality`.
+    pub(crate) quality: QualityController,
+    /// The resolution the 3D scene renders at, relative to the window's, on each axis: from
+    /// `supersampling`, and adaptive quality.
+    render_scale: f32,
+    /// From `GraphicsSettings::supersampling`.
+    supersampling: f32,
+    /// The largest texture the device supports, on each axis. This limits `render_scale`.
+    max_texture_size: u32,
+    /// Set while a fade, color animation, or camera movement is in progress; from `Scene::step`.
+    pub(crate) animating: bool,
+    /// `None` if watching failed.
+    #[cfg(feature = "hot_reload")]
+    shader_watcher: Option<ShaderWatcher>,
+    /// Set if the application replaced the mesh shader; we don't reload the built-in one over it.
+    #[cfg(feature = "hot_reload")]
+    custom_mesh_shader: bool,
+    /// The last shader compile error, and how long it's been displayed, in seconds.
+    #[cfg(feature = "hot_reload")]
+    pub shader_error: Option<(String, f32)>,
 }
 
 /// How often the frame rate readout updates, in seconds. Averaging over this window
 /// keeps the displayed value steady enough to read.
 const FPS_UPDATE_INTERVAL: f32 = 0.25;
 
+/// Hidden instances reserved per mesh in each instance buffer, at most, for entities crossing the
+/// opacity threshold to move into. They cost vertex work each frame, so we keep this small; past
+/// it, crossing entities trigger a rebuild.
+const SPARE_INSTANCE_SLOTS: usize = 16;
+
 impl GraphicsState {
     pub(crate) fn new(
         device: &Device,
         queue: &Queue,
         surface_cfg: &SurfaceConfiguration,
         mut scene: Scene,
-        window: Arc<Window>,
+        window: Option<Arc<Window>>,
         msaa_samples: u32,
+        mesh_shader: Option<&str>,
     ) -> Self {
-        let vertex_buf = device.create_buffer_init(&BufferInitDescriptor {
-            label: Some("Vertex buffer"),
-            contents: &[], // Populated later.
-            usage: BufferUsages::VERTEX,
-        });
+        scene.device_features = device.features();
+        scene.device_limits = device.limits();
+
+        // Populated later.
+        let vertex_buf = GrowableBuffer::new(device, "Vertex buffer", BufferUsages::VERTEX);
 
         let mut quad_bytes = Vec::with_capacity(QUAD_VERTICES.len() * 8);
         for q in QUAD_VERTICES {
@@ -214,11 +414,8 @@ impl GraphicsState {
             usage: BufferUsages::VERTEX,
         });
 
-        let index_buf = device.create_buffer_init(&BufferInitDescriptor {
-            label: Some("Index buffer"),
-            contents: &[], // Populated later.
-            usage: BufferUsages::INDEX,
-        });
+        let index_buf = GrowableBuffer::new(device, "Index buffer", BufferUsages::INDEX);
+        let index_buf_u16 = GrowableBuffer::new(device, "Index buffer u16", BufferUsages::INDEX);
 
         scene.camera.update_proj_mat();
 
@@ -246,6 +443,7 @@ impl GraphicsState {
         //
 
         let bind_groups = create_bindgroups(device, &cam_buf, &cam_basis_buf, &lighting_buf);
+        let texture_store = TextureStore::new(device, queue);
 
         // Halo prepass resources: a separate camera buffer (halo_expansion = 0 until
         // apply_graphics_settings writes the real value).
@@ -268,14 +466,21 @@ impl GraphicsState {
 
         let shader_mesh = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: Some("Graphics shader"),
-            source: wgpu::ShaderSource::Wgsl(include_str!("shader.wgsl").into()),
+            source: wgpu::ShaderSource::Wgsl(mesh_shader_source(
+                mesh_shader.unwrap_or(include_str!("shader.wgsl")),
+            )),
         });
 
+        let reflection =
+            ReflectionRenderer::new(device, &bind_groups, &cam_basis_buf, surface_cfg.format);
+
         let pipeline_layout_mesh = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: Some("Render pipeline layout"),
             bind_group_layouts: &[
                 Some(&bind_groups.layout_cam),
                 Some(&bind_groups.layout_lighting),
+                Some(&texture_store.layout),
+                Some(&reflection.layout),
             ],
             immediate_size: 0,
         });
@@ -293,67 +498,110 @@ impl GraphicsState {
         // for transparent objects. You would set depth to write only, for opaque objects,
         // and read only, for alpha blending transparent meshes.
 
-        let pipeline_mesh = create_render_pipeline(
-            device,
-            &pipeline_layout_mesh,
-            shader_mesh.clone(),
-            surface_cfg,
-            msaa_samples,
-            &[VERTEX_LAYOUT, INSTANCE_LAYOUT],
-            Some(depth_stencil_mesh.clone()),
-            // Some(depth_stencil_mesh),
-            None,
-            Some(Face::Back),
-            "Render pipeline mesh opaque",
+        let mut pipeline_cache = PipelineCache::default();
+
+        let pipeline_mesh = pipeline_cache.get_or_create(
+            PipelineKey::new(PipelineKind::MeshOpaque, msaa_samples),
+            || {
+                create_render_pipeline(
+                    device,
+                    &pipeline_layout_mesh,
+                    shader_mesh.clone(),
+                    surface_cfg,
+                    msaa_samples,
+                    &[VERTEX_LAYOUT, INSTANCE_LAYOUT],
+                    Some(depth_stencil_mesh.clone()),
+                    // Some(depth_stencil_mesh),
+                    None,
+                    Some(Face::Back),
+                    "Render pipeline mesh opaque",
+                )
+            },
         );
 
         // Separate mesh for transparent meshes, so we disable back culling.
-        let pipeline_mesh_transparent = create_render_pipeline(
-            device,
-            &pipeline_layout_mesh,
-            shader_mesh.clone(),
-            surface_cfg,
-            msaa_samples,
-            &[VERTEX_LAYOUT, INSTANCE_LAYOUT],
-            // Some(depth_stencil_mesh_transparent.clone()),
-            Some(depth_stencil_mesh.clone()),
-            Some(BlendState::ALPHA_BLENDING),
-            Some(Face::Back),
-            "Render pipeline mesh transparent",
+        let pipeline_mesh_transparent = pipeline_cache.get_or_create(
+            PipelineKey::new(PipelineKind::MeshTransparent, msaa_samples),
+            || {
+                create_render_pipeline_fs(
+                    device,
+                    &pipeline_layout_mesh,
+                    shader_mesh.clone(),
+                    "fs_blend",
+                    surface_cfg,
+                    msaa_samples,
+                    &[VERTEX_LAYOUT, INSTANCE_LAYOUT],
+                    // Some(depth_stencil_mesh_transparent.clone()),
+                    Some(depth_stencil_mesh.clone()),
+                    Some(BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                    Some(Face::Back),
+                    "Render pipeline mesh transparent",
+                )
+            },
         );
 
-        let pipeline_mesh_transparent_back = create_render_pipeline(
-            device,
-            &pipeline_layout_mesh,
-            shader_mesh.clone(),
-            surface_cfg,
-            msaa_samples,
-            &[VERTEX_LAYOUT, INSTANCE_LAYOUT],
-            // Some(depth_stencil_mesh_transparent),
-            Some(depth_stencil_mesh.clone()),
-            Some(BlendState::ALPHA_BLENDING),
-            Some(Face::Front),
-            "Render pipeline mesh transparent – backfaces",
+        let pipeline_mesh_transparent_back = pipeline_cache.get_or_create(
+            PipelineKey::new(PipelineKind::MeshTransparentBack, msaa_samples),
+            || {
+                create_render_pipeline_fs(
+                    device,
+                    &pipeline_layout_mesh,
+                    shader_mesh.clone(),
+                    "fs_blend",
+                    surface_cfg,
+                    msaa_samples,
+                    &[VERTEX_LAYOUT, INSTANCE_LAYOUT],
+                    // Some(depth_stencil_mesh_transparent),
+                    Some(depth_stencil_mesh.clone()),
+                    Some(BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                    Some(Face::Front),
+                    "Render pipeline mesh transparent – backfaces",
+                )
+            },
         );
 
         // Halo prepass: depth-only, front-face culled, inflated by halo_expansion in vs.
-        // Only needs the camera bind group (no fragment stage → no lighting needed).
+        // Its fragment stage writes no color, but shares `fs_main`, so needs all of its groups.
         let pipeline_layout_halo = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: Some("Halo pipeline layout"),
             bind_group_layouts: &[
                 Some(&bind_groups.layout_cam),
                 Some(&bind_groups.layout_lighting),
+                Some(&texture_store.layout),
+                Some(&reflection.layout),
             ],
             immediate_size: 0,
         });
-        let pipeline_halo = create_render_pipeline_depth_only(
+        // This is only used if halos are enabled, so compile it in the background.
+        request_halo_pipeline(
+            &mut pipeline_cache,
+            device,
+            pipeline_layout_halo,
+            shader_mesh.clone(),
+            surface_cfg.format,
+            msaa_samples,
+            depth_stencil_mesh.clone(),
+        );
+        // Only used if a mesh is two-sided.
+        request_two_sided_pipeline(
+            &mut pipeline_cache,
             device,
-            &pipeline_layout_halo,
+            pipeline_layout_mesh.clone(),
             shader_mesh.clone(),
+            surface_cfg.clone(),
             msaa_samples,
-            &[VERTEX_LAYOUT, INSTANCE_LAYOUT],
             depth_stencil_mesh.clone(),
         );
+        // Also only used if an entity has x-ray set.
+        #[cfg(feature = "xray")]
+        request_xray_pipeline(
+            &mut pipeline_cache,
+            device,
+            pipeline_layout_mesh.clone(),
+            shader_mesh.clone(),
+            surface_cfg.format,
+            msaa_samples,
+        );
 
         // ── Contour lines ────────────────────────────────────────────────────────────
         // A 1-sample depth texture populated by a prepass; always 1-sample so it can
@@ -483,70 +731,114 @@ impl GraphicsState {
 
         // We initialize instances, the instance buffer and mesh mappings in `setup_entities`.
         // let instances = Vec::new();
-        let instance_buf = device.create_buffer_init(&BufferInitDescriptor {
-            label: Some("Instance buffer"),
-            contents: &[], // empty on init
-            usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
-        });
-
-        let instance_buf_transparent = device.create_buffer_init(&BufferInitDescriptor {
-            label: Some("Instance buffer transparent"),
-            contents: &[], // empty on init
-            usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
-        });
+        let instance_buf = GrowableBuffer::new(device, "Instance buffer", BufferUsages::VERTEX);
+        let instance_buf_transparent =
+            GrowableBuffer::new(device, "Instance buffer transparent", BufferUsages::VERTEX);
 
         let shader_gauss = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: Some("Graphics shader"),
             source: wgpu::ShaderSource::Wgsl(include_str!("shader_gauss.wgsl").into()),
         });
 
-        let pipeline_layout_gauss =
+        let gauss_display = GaussianDisplay::new(device);
+        request_gauss_pipelines(
+            &mut pipeline_cache,
+            device,
+            &[&bind_groups.layout_cam_gauss, &gauss_display.layout],
+            shader_gauss.clone(),
+            surface_cfg.clone(),
+            msaa_samples,
+        );
+
+        let instance_gauss_buf =
+            GrowableBuffer::new(device, "Gaussian Instance buffer", BufferUsages::VERTEX);
+
+        let shader_slice = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Slice plane shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("shader_slice.wgsl").into()),
+        });
+
+        let slices = SliceRenderer::new(device);
+
+        let pipeline_layout_slice =
             device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: Some("Gaussian pipeline layout"),
-                bind_group_layouts: &[Some(&bind_groups.layout_cam_gauss)],
+                label: Some("Slice plane pipeline layout"),
+                bind_group_layouts: &[
+                    Some(&bind_groups.layout_cam),
+                    Some(&texture_store.layout_volume),
+                    Some(&slices.layout),
+                ],
                 immediate_size: 0,
             });
 
-        let depth_stencil_gauss = Some(DepthStencilState {
-            format: DEPTH_FORMAT,
-            // Seems to be required to be false to prevent gaussians from popping in and out.
-            depth_write_enabled: Some(false),
-            depth_compare: Some(wgpu::CompareFunction::Less),
-            stencil: wgpu::StencilState::default(),
-            bias: wgpu::DepthBiasState::default(),
+        let pipeline_slice = pipeline_cache.get_or_create(
+            PipelineKey::new(PipelineKind::Slice, msaa_samples),
+            || {
+                create_render_pipeline(
+                    device,
+                    &pipeline_layout_slice,
+                    shader_slice.clone(),
+                    surface_cfg,
+                    msaa_samples,
+                    &[],
+                    Some(depth_stencil_slice()),
+                    Some(BlendState::ALPHA_BLENDING),
+                    None,
+                    "Render pipeline slice plane",
+                )
+            },
+        );
+
+        let shader_background = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Background shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("shader_background.wgsl").into()),
         });
 
-        let pipeline_gauss = create_render_pipeline(
+        let background = BackgroundRenderer::new(device);
+        let stereo = StereoRenderer::new(device, &bind_groups, &cam_basis_buf, surface_cfg.format);
+        let motion_blur = MotionBlurRenderer::new(device, surface_cfg.format);
+        let resample = ResampleRenderer::new(device, surface_cfg.format);
+        let debug_lines = DebugLines::new(device);
+        let lines = LineRenderer::new(device);
+        let impostors = ImpostorRenderer::new(device);
+
+        // Only used if the scene has a non-solid background.
+        request_background_pipeline(
+            &mut pipeline_cache,
             device,
-            &pipeline_layout_gauss,
-            shader_gauss.clone(),
-            surface_cfg,
+            &background.layout,
+            shader_background.clone(),
+            surface_cfg.clone(),
             msaa_samples,
-            &[QUAD_VERTEX_LAYOUT, GAUSS_INST_LAYOUT],
-            depth_stencil_gauss,
-            // todo These two blend styles approaches produce noticibly different results. Experiment.
-            Some(BlendState::ALPHA_BLENDING),
-            None,
-            // Some(BlendState {
-            //     color: BlendComponent {
-            //         src_factor: BlendFactor::One,
-            //         dst_factor: BlendFactor::One,
-            //         operation: BlendOperation::Add,
-            //     },
-            //     alpha: BlendComponent {
-            //         src_factor: BlendFactor::One,
-            //         dst_factor: BlendFactor::One,
-            //         operation: BlendOperation::Add,
-            //     },
-            // }),
-            "Render pipeline gaussian",
         );
 
-        let instance_gauss_buf = device.create_buffer_init(&BufferInitDescriptor {
-            label: Some("Gaussian Instance buffer"),
-            contents: &[], // empty on init
-            usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
-        });
+        // Only used if `GraphicsSettings::debug_draw` is on.
+        request_debug_lines_pipeline(
+            &mut pipeline_cache,
+            device,
+            &bind_groups.layout_cam,
+            surface_cfg.format,
+            msaa_samples,
+        );
+
+        // Only used if the scene has lines.
+        request_lines_pipeline(
+            &mut pipeline_cache,
+            device,
+            &bind_groups.layout_cam,
+            &lines.layout,
+            surface_cfg.format,
+            msaa_samples,
+        );
+
+        // Only used if the scene has spheres or cylinders.
+        request_impostor_pipelines(
+            &mut pipeline_cache,
+            device,
+            &bind_groups,
+            surface_cfg.format,
+            msaa_samples,
+        );
 
         // Placeholder value
         let mesh_mappings = Vec::new();
@@ -554,7 +846,9 @@ impl GraphicsState {
 
         // todo: Logical (scaling by device?) vs physical pixels
         // let window_size = winit::dpi::LogicalSize::new(scene.window_size.0, scene.window_size.1);
-        window.set_title(&scene.window_title);
+        if let Some(window) = &window {
+            window.set_title(&scene.window_title);
+        }
 
         let msaa_texture = if msaa_samples > 1 {
             Some(Self::create_msaa_texture(device, surface_cfg, msaa_samples))
@@ -566,10 +860,13 @@ impl GraphicsState {
             vertex_buf,
             vertex_buf_quad,
             index_buf,
+            index_buf_u16,
+            mesh_ranges: Vec::new(),
             instance_buf,
             instance_buf_transparent,
             instance_buf_gauss: instance_gauss_buf,
             bind_groups,
+            texture_store,
             camera_buf: cam_buf,
             camera_buf_halo: cam_halo_buf,
             bind_group_cam_halo,
@@ -578,8 +875,19 @@ impl GraphicsState {
             pipeline_mesh,
             pipeline_mesh_transparent,
             pipeline_mesh_transparent_back,
-            pipeline_gauss,
-            pipeline_halo,
+            gauss_display,
+            pipeline_slice,
+            slices,
+            background,
+            stereo,
+            reflection,
+            motion_blur,
+            resample,
+            debug_lines,
+            lines,
+            impostors,
+            pipeline_cache,
+            mesh_uploads: Default::default(),
             depth_texture_contour,
             pipeline_contour_depth,
             pipeline_contour_overlay,
@@ -594,16 +902,30 @@ impl GraphicsState {
             surface_cfg: surface_cfg.clone(),
             shader_mesh,
             shader_gauss,
+            shader_slice,
+            shader_background,
             pipeline_ssao,
             layout_ssao,
             bind_group_ssao,
             ssao_uniform_buf,
             depth_texture,
-            // staging_belt: wgpu::util::StagingBelt::new(0x100),
+            uploader: Uploader::new(device),
             scene,
             inputs_commanded: Default::default(),
             mesh_mappings,
             mesh_mappings_transparent,
+            free_slots_opaque: Vec::new(),
+            free_slots_transparent: Vec::new(),
+            indirect_buf: GrowableBuffer::new(device, "Indirect buffer", BufferUsages::INDIRECT),
+            indirect_buf_transparent: GrowableBuffer::new(
+                device,
+                "Indirect buffer transparent",
+                BufferUsages::INDIRECT,
+            ),
+            multi_draw_indirect: false,
+            compute_supported: false,
+            gauss_field: None,
+            gauss_field_source: Vec::new(),
             window,
             msaa_texture,
             halo_expansion: 0.,
@@ -611,14 +933,52 @@ impl GraphicsState {
             fps_value: 0.,
             fps_accum_time: 0.,
             fps_accum_frames: 0,
+            display_memory_usage: false,
+            display_upload_progress: true,
+            double_buffer_instances: false,
+            // Update on the first frame.
+            memory_usage_timer: FPS_UPDATE_INTERVAL,
+            render_mode: RenderMode::Continuous,
+            min_frame_time: None,
+            clock: Default::default(),
+            pending_present_mode: None,
+            redraw_pending: true,
+            depth_readback: Default::default(),
+            frame_timer: FrameTimer::new(device, queue),
+            on_frame_complete: None,
+            quality: Default::default(),
+            render_scale: 1.,
+            supersampling: 1.,
+            max_texture_size: device.limits().max_texture_dimension_2d,
+            animating: false,
+            #[cfg(feature = "hot_reload")]
+            shader_watcher: ShaderWatcher::new()
+                .inspect_err(|e| warn!("Unable to watch shaders for changes: {e}"))
+                .ok(),
+            #[cfg(feature = "hot_reload")]
+            custom_mesh_shader: mesh_shader.is_some(),
+            #[cfg(feature = "hot_reload")]
+            shader_error: None,
         };
 
         result.setup_vertices_indices(device);
-        result.setup_entities(device, queue);
+        result.setup_entities(device);
+        result.setup_lines(device);
+        result.setup_spheres(device);
+        result.setup_cylinders(device);
+        result.scene.spatial_index.invalidate();
 
         result
     }
 
+    /// The window we draw to. Only headless rendering lacks one, and it doesn't handle window
+    /// events or run the GUI.
+    pub(crate) fn window(&self) -> &Arc<Window> {
+        self.window
+            .as_ref()
+            .expect("The window is only missing when rendering headlessly")
+    }
+
     pub(crate) fn create_msaa_texture(
         device: &Device,
         surface_cfg: &SurfaceConfiguration,
@@ -642,11 +1002,8 @@ impl GraphicsState {
         msaa_texture.create_view(&wgpu::TextureViewDescriptor::default())
     }
 
-    pub(crate) fn handle_input_device(
-        &mut self,
-        event: &DeviceEvent,
-        input_settings: &InputSettings,
-    ) {
+    pub(crate) fn handle_input_device(&mut self, event: &DeviceEvent) {
+        let input_settings = &self.scene.input_settings;
         match input_settings.control_scheme {
             ControlScheme::FreeCamera | ControlScheme::Arc { center: _ } => {
                 input::add_input_cmd_device(
@@ -659,11 +1016,8 @@ impl GraphicsState {
         }
     }
 
-    pub(crate) fn handle_input_window(
-        &mut self,
-        event: &WindowEvent,
-        input_settings: &InputSettings,
-    ) {
+    pub(crate) fn handle_input_window(&mut self, event: &WindowEvent) {
+        let input_settings = &self.scene.input_settings;
         match input_settings.control_scheme {
             ControlScheme::FreeCamera | ControlScheme::Arc { center: _ } => {
                 input::add_input_cmd_window(
@@ -676,8 +1030,12 @@ impl GraphicsState {
         }
     }
 
-    /// Updates meshes.
+    /// Updates all meshes. Only the portions of the vertex and index buffers that changed are
+    /// uploaded.
     pub(crate) fn setup_vertices_indices(&mut self, device: &Device) {
+        let _span =
+            debug_span!("setup_vertices_indices", meshes = self.scene.meshes.len()).entered();
+
         let mut n_vertices = 0;
         let mut n_indices = 0;
         for mesh in &self.scene.meshes {
@@ -685,266 +1043,977 @@ impl GraphicsState {
             n_indices += mesh.indices.len();
         }
 
-        // Convert the vertex and index data to u8 buffers.
-        let mut vertex_data = Vec::with_capacity(n_vertices * VERTEX_SIZE);
-        let mut index_data = Vec::with_capacity(n_indices * 4);
+        let mut vertex_data = Vec::with_capacity(n_vertices);
+        let mut index_data = Vec::new();
+        let mut index_data_u16 = Vec::with_capacity(n_indices);
+
+        self.mesh_ranges.clear();
 
         for mesh in &self.scene.meshes {
-            for vertex in &mesh.vertices {
-                vertex_data.extend_from_slice(&vertex.to_bytes());
-            }
+            let index_format = index_format(mesh);
+            self.mesh_ranges.push(MeshRange {
+                vertex_start: vertex_data.len(),
+                vertex_count: mesh.vertices.len(),
+                vertex_capacity: mesh.vertices.len(),
+                index_start: match index_format {
+                    IndexFormat::Uint16 => index_data_u16.len(),
+                    IndexFormat::Uint32 => index_data.len(),
+                },
+                index_count: mesh.indices.len(),
+                index_capacity: index_slots(index_format, mesh.indices.len()),
+                index_format,
+            });
 
-            for index in &mesh.indices {
-                index_data.extend_from_slice(&(*index as u32).to_ne_bytes());
+            vertex_data.extend_from_slice(&mesh_vertices_raw(mesh));
+            match index_format {
+                IndexFormat::Uint16 => index_data_u16.extend_from_slice(&mesh_indices_u16(mesh)),
+                IndexFormat::Uint32 => index_data.extend_from_slice(&mesh_indices_raw(mesh)),
             }
         }
 
-        // We can't update using a queue due to buffer size mismatches.
-        let vertex_buf = device.create_buffer_init(&BufferInitDescriptor {
-            label: Some("Vertex buffer"),
-            contents: &vertex_data,
-            usage: BufferUsages::VERTEX,
-        });
-
-        let index_buf = device.create_buffer_init(&BufferInitDescriptor {
-            label: Some("Index buffer"),
-            contents: &index_data,
-            usage: BufferUsages::INDEX,
-        });
-
-        self.vertex_buf = vertex_buf;
+        self.vertex_buf.write(
+            device,
+            &mut self.uploader,
+            bytemuck::cast_slice(&vertex_data),
+        );
         // Note: Gauss vertex buf is static; we set it up at init, and don't change it.
 
-        self.index_buf = index_buf;
+        self.index_buf.write(
+            device,
+            &mut self.uploader,
+            bytemuck::cast_slice(&index_data),
+        );
+        self.index_buf_u16.write(
+            device,
+            &mut self.uploader,
+            bytemuck::cast_slice(&index_data_u16),
+        );
     }
 
-    /// Replace instance buffer entries directly for specific entities. This is cheaper than
-    /// rebuilding the instance buffers whenever an entitity changes. This only supports in-place
-    /// changes; no adding or removing instances.
-    pub(crate) fn replace_instance_entries(
+    /// Write the data of the mesh at `i` in `Scene::meshes`, from `vertices` and `indices` in
+    /// their GPU layout. It's written in place if it fits in the space its range reserves.
+    /// Otherwise it moves to the end of the buffers, reserving `slack` times what it needs, and
+    /// leaves a hole; the data of other meshes doesn't move. Indices whose format changed move to
+    /// the other index buffer the same way.
+    ///
+    /// If `staging` is set, the same data is already on the GPU there, from `mesh_upload::stage`:
+    /// the vertices at its start, and the indices at the offset given. It's copied from there
+    /// instead of uploaded.
+    fn write_mesh_data(
         &mut self,
-        queue: &Queue,
         device: &Device,
-        update_type: &EntityUpdate,
+        i: usize,
+        vertices: &[u8],
+        indices: &[u8],
+        staging: Option<(&Buffer, u64)>,
+        slack: f32,
     ) {
-        let classes_or_ids: HashSet<_> = match update_type {
-            EntityUpdate::Classes(v) | EntityUpdate::Ids(v) => v.iter().copied().collect(),
-            _ => HashSet::new(), // Unused
-        };
-
-        let mut needs_full_rebuild = false;
+        let mesh = &self.scene.meshes[i];
+        let range = &mut self.mesh_ranges[i];
 
-        let ents_to_update = match update_type {
-            EntityUpdate::Indexes((start, end)) => &self.scene.entities[*start..*end],
-            _ => &self.scene.entities,
+        // An empty mesh, e.g. a removed one, has no indices to move.
+        let format = if mesh.indices.is_empty() {
+            range.index_format
+        } else {
+            index_format(mesh)
         };
-
-        // Gather updates first, so contiguous slots can be coalesced into a single
-        // queue.write_buffer call each, rather than one call per entity.
-        let mut writes_opaque: Vec<(usize, [u8; INSTANCE_SIZE])> = Vec::new();
-        let mut writes_transparent: Vec<(usize, [u8; INSTANCE_SIZE])> = Vec::new();
-
-        for ent in ents_to_update {
-            match update_type {
-                EntityUpdate::Classes(_) => {
-                    if !classes_or_ids.contains(&ent.class) {
-                        continue;
-                    }
-                }
-                EntityUpdate::Ids(_) => {
-                    if !classes_or_ids.contains(&ent.id) {
-                        continue;
-                    }
+        let vertex_count = vertices.len() / VERTEX_SIZE;
+        let slots = indices.len() / index_size(format);
+
+        if vertex_count > range.vertex_capacity {
+            range.vertex_start = self.vertex_buf.len() / VERTEX_SIZE;
+            range.vertex_capacity = (vertex_count as f32 * slack) as usize;
+            let reserve = range.vertex_capacity * VERTEX_SIZE;
+
+            match staging {
+                Some((buf, _)) => self.vertex_buf.append_staged(
+                    device,
+                    &mut self.uploader,
+                    buf,
+                    0,
+                    vertices,
+                    reserve,
+                ),
+                None => self
+                    .vertex_buf
+                    .append(device, &mut self.uploader, vertices, reserve),
+            }
+        } else {
+            let offset = range.vertex_start * VERTEX_SIZE;
+            match staging {
+                Some((buf, _)) => {
+                    self.vertex_buf
+                        .copy_at(&mut self.uploader, offset, buf, 0, vertices)
                 }
-                _ => (),
-            };
-
-            let Some(slot) = ent.buf_i else {
-                needs_full_rebuild = true;
-                break;
-            };
-
-            // If opacity bucket changed since last rebuild, our slot is invalid → full rebuild.
-            let now_is_transparent = ent.opacity < 0.99;
-            if now_is_transparent != ent.buf_is_transparent {
-                needs_full_rebuild = true;
-                break;
+                None => self
+                    .vertex_buf
+                    .write_at(&mut self.uploader, offset, vertices),
             }
+        }
 
-            let instance: Instance = ent.into();
-            if ent.buf_is_transparent {
-                writes_transparent.push((slot, instance.to_bytes()));
-            } else {
-                writes_opaque.push((slot, instance.to_bytes()));
+        let index_buf = match format {
+            IndexFormat::Uint16 => &mut self.index_buf_u16,
+            IndexFormat::Uint32 => &mut self.index_buf,
+        };
+        if format != range.index_format || slots > range.index_capacity {
+            range.index_start = index_buf.len() / index_size(format);
+            range.index_capacity = index_slots(format, (slots as f32 * slack) as usize);
+            range.index_format = format;
+            let reserve = range.index_capacity * index_size(format);
+
+            match staging {
+                Some((buf, offset)) => index_buf.append_staged(
+                    device,
+                    &mut self.uploader,
+                    buf,
+                    offset,
+                    indices,
+                    reserve,
+                ),
+                None => index_buf.append(device, &mut self.uploader, indices, reserve),
+            }
+        } else {
+            let offset = range.index_start * index_size(format);
+            match staging {
+                Some((buf, staging_offset)) => {
+                    index_buf.copy_at(&mut self.uploader, offset, buf, staging_offset, indices)
+                }
+                None => index_buf.write_at(&mut self.uploader, offset, indices),
             }
         }
 
-        if needs_full_rebuild {
-            // todo: Put back A/R to help diagnose problems.
-            // println!("Performing a full entity rebuild; unable to update in-place");
-            self.setup_entities(device, queue);
-            return;
-        }
+        range.vertex_count = mesh.vertices.len();
+        range.index_count = mesh.indices.len();
+    }
 
-        for (buf, mut writes) in [
-            (&self.instance_buf, writes_opaque),
-            (&self.instance_buf_transparent, writes_transparent),
-        ] {
-            if writes.is_empty() {
-                continue;
+    /// Repack the vertex and index buffers if the holes left by meshes that moved take more of any
+    /// of them than the data in use.
+    fn compact_mesh_bufs(&mut self, device: &Device) {
+        let mut vertices = 0;
+        let mut index_bytes = [0; 2];
+        for range in &self.mesh_ranges {
+            vertices += range.vertex_count;
+            let slots = index_slots(range.index_format, range.index_count);
+            match range.index_format {
+                IndexFormat::Uint16 => index_bytes[0] += slots * 2,
+                IndexFormat::Uint32 => index_bytes[1] += slots * 4,
             }
+        }
 
-            // Slots are unique per entity, so sorting then merging adjacent runs writes
-            // the same bytes to the same offsets as individual writes would.
-            writes.sort_unstable_by_key(|(slot, _)| *slot);
+        if self.vertex_buf.len() > 2 * vertices * VERTEX_SIZE
+            || self.index_buf_u16.len() > 2 * index_bytes[0]
+            || self.index_buf.len() > 2 * index_bytes[1]
+        {
+            debug!("Repacking the vertex and index buffers");
+            self.setup_vertices_indices(device);
+        }
+    }
 
-            let mut start = 0;
-            while start < writes.len() {
-                let mut end = start + 1;
-                while end < writes.len() && writes[end].0 == writes[end - 1].0 + 1 {
-                    end += 1;
-                }
+    /// Updates specific meshes, by index into `Scene::meshes`. Changed meshes are written in place
+    /// if they fit, or moved to the end of the buffers otherwise, and meshes past the end of the
+    /// ones already uploaded are appended. Only their own data is uploaded. This is cheaper than
+    /// `setup_vertices_indices` when only a few meshes of many change. Returns `true` if the
+    /// instance layout must be rebuilt, i.e. the number of meshes changed.
+    pub(crate) fn update_meshes(&mut self, device: &Device, meshes: &[usize]) -> bool {
+        let _span = debug_span!("update_meshes", changed = meshes.len()).entered();
+
+        // The application replaced these, so uploads still in flight for them are stale.
+        for &i in meshes {
+            self.mesh_uploads.cancel(i);
+        }
 
-                let run = &writes[start..end];
-                let mut data = Vec::with_capacity(run.len() * INSTANCE_SIZE);
-                for (_, bytes) in run {
-                    data.extend_from_slice(bytes);
-                }
-                queue.write_buffer(buf, (run[0].0 * INSTANCE_SIZE) as u64, &data);
+        let n_prev = self.mesh_ranges.len();
 
-                start = end;
-            }
+        if self.scene.meshes.len() < n_prev {
+            // Meshes were removed; we can't tell which from this list.
+            self.setup_vertices_indices(device);
+            return true;
         }
-    }
-
-    /// Sets up entities (And the associated instance buffer), but doesn't change
-    /// meshes, lights, or the camera. The vertex and index buffers aren't changed; only the instances.
-    /// This rebuilds the instance buffers from scratch from entities.
-    pub(crate) fn setup_entities(&mut self, device: &Device, queue: &Queue) {
-        let scene = &mut self.scene;
-        let n_meshes = scene.meshes.len();
 
-        // Bucket entity indices by mesh in a single pass, instead of scanning every
-        // entity once per mesh.
-        let mut ents_by_mesh: Vec<Vec<usize>> = vec![Vec::new(); n_meshes];
-        for (i, entity) in scene.entities.iter().enumerate() {
-            if entity.mesh < n_meshes {
-                ents_by_mesh[entity.mesh].push(i);
+        for &i in meshes {
+            if i >= n_prev {
+                continue;
             }
+            let mesh = &self.scene.meshes[i];
+            let vertices = mesh_vertices_raw(mesh);
+            let indices = mesh_indices_bytes(mesh);
+            self.write_mesh_data(
+                device,
+                i,
+                bytemuck::cast_slice(&vertices),
+                &indices,
+                None,
+                MESH_SLACK,
+            );
         }
 
-        let mut instance_data = Vec::new();
-        let mut instance_data_transparent = Vec::new();
-
-        let mut mesh_mappings = Vec::with_capacity(n_meshes);
-        let mut mesh_mappings_transparent = Vec::with_capacity(n_meshes);
+        // Append meshes added since the last upload. This includes any not explicitly listed,
+        // since there's no other place to put them. They reserve no more than they need.
+        for i in n_prev..self.scene.meshes.len() {
+            self.mesh_ranges.push(MeshRange {
+                vertex_start: 0,
+                vertex_count: 0,
+                vertex_capacity: 0,
+                index_start: 0,
+                index_count: 0,
+                index_capacity: 0,
+                index_format: index_format(&self.scene.meshes[i]),
+            });
 
-        let mut vertex_start_this_mesh = 0;
+            let mesh = &self.scene.meshes[i];
+            let vertices = mesh_vertices_raw(mesh);
+            let indices = mesh_indices_bytes(mesh);
+            self.write_mesh_data(
+                device,
+                i,
+                bytemuck::cast_slice(&vertices),
+                &indices,
+                None,
+                1.,
+            );
+        }
 
-        let mut instance_start_this_mesh = 0;
-        let mut instance_start_this_mesh_transparent = 0;
+        self.compact_mesh_bufs(device);
+        self.update_mesh_mappings(device, n_prev)
+    }
 
-        let mut i_opaque = 0;
-        let mut i_transparent = 0;
+    /// After meshes changed in place, point the instance mappings at their new vertex offsets, and
+    /// rebuild the indirect draw arguments. Returns `true` if the instance layout must be rebuilt
+    /// instead, i.e. the number of meshes changed from `n_prev`.
+    fn update_mesh_mappings(&mut self, device: &Device, n_prev: usize) -> bool {
+        if self.scene.meshes.len() != n_prev {
+            return true;
+        }
 
-        // Build mesh-based instances.
-        for (i, mesh) in scene.meshes.iter().enumerate() {
-            let mut instance_count_this_mesh = 0;
-            let mut instance_count_this_mesh_transparent = 0;
+        // Mesh count is unchanged, so instance slots are too; only the vertex offsets may have moved.
+        for (i, range) in self.mesh_ranges.iter().enumerate() {
+            self.mesh_mappings[i].0 = range.vertex_start as i32;
+            self.mesh_mappings_transparent[i].0 = range.vertex_start as i32;
+        }
+        self.update_indirect_bufs(device);
 
-            for &ent_i in &ents_by_mesh[i] {
-                let entity = &mut scene.entities[ent_i];
-                let instance: Instance = (&*entity).into();
+        false
+    }
 
-                if entity.opacity < 0.99 {
-                    instance_data_transparent.extend_from_slice(&instance.to_bytes());
-                    instance_count_this_mesh_transparent += 1;
+    /// Upload meshes, by index into `Scene::meshes`, from a background thread. They stay in the
+    /// scene; until each is uploaded, the GPU buffers keep what was there before, i.e. nothing for
+    /// a mesh just added. Returns `true` if the instance layout must be rebuilt, as with
+    /// `update_meshes`.
+    pub(crate) fn upload_meshes_async(&mut self, device: &Device, meshes: &[usize]) -> bool {
+        let mut meshes = meshes.to_vec();
+        meshes.sort_unstable();
+        meshes.dedup();
+        meshes.retain(|&i| i < self.scene.meshes.len());
+
+        let n_prev = self.mesh_ranges.len();
+        if self.scene.meshes.len() < n_prev {
+            // Meshes were removed; we can't tell which from this list. The full upload includes
+            // these too.
+            self.setup_vertices_indices(device);
+            return true;
+        }
 
-                    // For our in-place replacement system.
-                    entity.buf_i = Some(i_transparent);
-                    entity.buf_is_transparent = true;
-                    i_transparent += 1;
-                } else {
-                    instance_data.extend_from_slice(&instance.to_bytes());
-                    instance_count_this_mesh += 1;
+        // Lay out slots added since the last upload, empty until their data arrives. Those not
+        // listed here are uploaded now, as in `update_meshes`.
+        for i in n_prev..self.scene.meshes.len() {
+            let end = |buf: &GrowableBuffer, size: usize| buf.len() / size;
+            let format = index_format(&self.scene.meshes[i]);
+            self.mesh_ranges.push(MeshRange {
+                vertex_start: end(&self.vertex_buf, VERTEX_SIZE),
+                vertex_count: 0,
+                vertex_capacity: 0,
+                index_start: match format {
+                    IndexFormat::Uint16 => end(&self.index_buf_u16, 2),
+                    IndexFormat::Uint32 => end(&self.index_buf, 4),
+                },
+                index_count: 0,
+                index_capacity: 0,
+                index_format: format,
+            });
 
-                    entity.buf_i = Some(i_opaque);
-                    entity.buf_is_transparent = false;
-                    i_opaque += 1;
-                }
+            if meshes.binary_search(&i).is_err() {
+                let mesh = &self.scene.meshes[i];
+                let vertices = mesh_vertices_raw(mesh);
+                let indices = mesh_indices_bytes(mesh);
+                self.write_mesh_data(
+                    device,
+                    i,
+                    bytemuck::cast_slice(&vertices),
+                    &indices,
+                    None,
+                    1.,
+                );
             }
+        }
 
-            mesh_mappings.push((
-                vertex_start_this_mesh,
-                instance_start_this_mesh,
-                instance_count_this_mesh,
-            ));
+        let copies = meshes
+            .iter()
+            .map(|&i| (i, self.scene.meshes[i].clone()))
+            .collect();
+        self.mesh_uploads.request(copies, device);
 
-            mesh_mappings_transparent.push((
-                vertex_start_this_mesh,
-                instance_start_this_mesh_transparent,
-                instance_count_this_mesh_transparent,
-            ));
+        self.update_mesh_mappings(device, n_prev)
+    }
 
-            vertex_start_this_mesh += mesh.vertices.len() as i32;
+    /// Copy meshes done staging into the vertex and index buffers from their staging buffers, on
+    /// the GPU: in place if they fit, or at the end of the buffers otherwise, as in
+    /// `update_meshes`. Meshes whose staging failed are uploaded from `Scene::meshes` instead.
+    fn finish_mesh_uploads(&mut self, device: &Device) {
+        let staged = self.mesh_uploads.poll();
+        let lost = self.mesh_uploads.take_lost();
+        self.scene.mesh_upload_progress = self.mesh_uploads.progress();
+        if staged.is_empty() && lost.is_empty() {
+            return;
+        }
 
-            instance_start_this_mesh += instance_count_this_mesh;
-            instance_start_this_mesh_transparent += instance_count_this_mesh_transparent;
+        for (i, staged) in staged {
+            if i >= self.mesh_ranges.len() {
+                // The slot was removed since.
+                continue;
+            }
+
+            self.write_mesh_data(
+                device,
+                i,
+                bytemuck::cast_slice(&staged.vertices),
+                &staged.indices,
+                Some((&staged.staging, staged.index_offset)),
+                upload_slack(&self.mesh_ranges[i]),
+            );
         }
 
-        self.mesh_mappings = mesh_mappings;
-        self.mesh_mappings_transparent = mesh_mappings_transparent;
+        for i in lost {
+            if i >= self.mesh_ranges.len() {
+                continue;
+            }
+            warn!("Staging mesh {i} in the background failed; uploading it on the render thread");
 
-        // Build gaussian-based instances. (48 bytes per serialized GaussianInstance.)
-        let mut instance_data_gauss = Vec::with_capacity(scene.gaussians.len() * 48);
-        for gauss in &scene.gaussians {
-            instance_data_gauss.extend_from_slice(&gauss.to_instance().to_bytes());
+            let mesh = &self.scene.meshes[i];
+            let vertices = mesh_vertices_raw(mesh);
+            let indices = mesh_indices_bytes(mesh);
+            self.write_mesh_data(
+                device,
+                i,
+                bytemuck::cast_slice(&vertices),
+                &indices,
+                None,
+                upload_slack(&self.mesh_ranges[i]),
+            );
         }
 
-        upload_instance_data(
-            device,
-            queue,
-            &mut self.instance_buf,
-            &instance_data,
-            "Instance buffer",
-        );
-        upload_instance_data(
-            device,
-            queue,
-            &mut self.instance_buf_transparent,
-            &instance_data_transparent,
-            "Instance buffer transparent",
+        self.compact_mesh_bufs(device);
+        if self.update_mesh_mappings(device, self.mesh_ranges.len()) {
+            self.setup_entities(device);
+        }
+    }
+
+    /// Rebuild the indirect draw arguments from the mesh ranges and mappings. Call this whenever
+    /// either changes.
+    fn update_indirect_bufs(&mut self, device: &Device) {
+        for (buf, mappings) in [
+            (&mut self.indirect_buf, &self.mesh_mappings),
+            (
+                &mut self.indirect_buf_transparent,
+                &self.mesh_mappings_transparent,
+            ),
+        ] {
+            // 32-bit meshes first, then 16-bit ones, so each format's are drawn together.
+            let args: Vec<_> = [IndexFormat::Uint32, IndexFormat::Uint16]
+                .into_iter()
+                .flat_map(|format| {
+                    self.mesh_ranges
+                        .iter()
+                        .zip(mappings)
+                        .filter(move |(r, m)| m.2 > 0 && r.index_format == format)
+                })
+                .map(|(range, &(vertex_start, instance_start, instance_count))| {
+                    DrawIndexedIndirectArgs {
+                        index_count: range.index_count as u32,
+                        instance_count,
+                        first_index: range.index_start as u32,
+                        base_vertex: vertex_start,
+                        first_instance: instance_start,
+                    }
+                })
+                .collect();
+
+            buf.write(device, &mut self.uploader, bytemuck::cast_slice(&args));
+        }
+    }
+
+    /// Write new pixels to textures. Uploads textures added to the scene first, so a texture
+    /// can be created and written to in the same update.
+    pub(crate) fn update_textures(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        updates: &[(TextureHandle, Vec<u8>)],
+    ) {
+        self.texture_store.sync(device, queue, &self.scene.textures);
+
+        for (handle, data) in updates {
+            self.texture_store.write(queue, *handle, data);
+        }
+    }
+
+    /// Sizes of the GPU buffers and textures we've allocated.
+    pub(crate) fn memory_usage(&self, device: &Device) -> MemoryUsage {
+        // Compressed formats store a block of texels in `block_copy_size` bytes.
+        let texture_bytes = |tex: &wgpu::Texture| {
+            let format = tex.format();
+            let block = format.block_copy_size(None).unwrap_or(4) as u64;
+            let (block_w, block_h) = format.block_dimensions();
+
+            (0..tex.mip_level_count())
+                .map(|level| {
+                    let size = tex.size().mip_level_size(level, tex.dimension());
+                    size.width.div_ceil(block_w) as u64
+                        * size.height.div_ceil(block_h) as u64
+                        * size.depth_or_array_layers as u64
+                })
+                .sum::<u64>()
+                * tex.sample_count() as u64
+                * block
+        };
+
+        // Render targets, then loaded textures, including volumes.
+        let targets = [
+            Some(&self.depth_texture._texture),
+            Some(&self.depth_texture_contour._texture),
+            self.msaa_texture.as_ref().map(|v| v.texture()),
+            self.reflection.target_texture(),
+            self.motion_blur.target_texture(),
+            self.resample.target_texture(),
+            self.stereo.target_texture(),
+        ];
+        let textures = targets
+            .into_iter()
+            .flatten()
+            .map(texture_bytes)
+            .sum::<u64>()
+            + self
+                .texture_store
+                .textures()
+                .map(texture_bytes)
+                .sum::<u64>();
+
+        MemoryUsage {
+            vertex: self.vertex_buf.buf.size(),
+            index: self.index_buf.buf.size() + self.index_buf_u16.buf.size(),
+            instance: self.instance_buf.capacity() + self.instance_buf_transparent.capacity(),
+            gauss: self.instance_buf_gauss.buf.size(),
+            indirect: self.indirect_buf.buf.size() + self.indirect_buf_transparent.buf.size(),
+            textures,
+            allocator: device
+                .generate_allocator_report()
+                .map(|r| (r.total_allocated_bytes, r.total_reserved_bytes)),
+        }
+    }
+
+    /// Replace instance buffer entries directly for specific entities. This is cheaper than
+    /// rebuilding the instance buffers whenever an entitity changes. This only supports in-place
+    /// changes; no adding or removing instances.
+    pub(crate) fn replace_instance_entries(&mut self, device: &Device, update_type: &EntityUpdate) {
+        let _span = debug_span!("replace_instance_entries").entered();
+
+        let classes_or_ids: HashSet<_> = match update_type {
+            EntityUpdate::Classes(v) | EntityUpdate::Ids(v) => v.iter().copied().collect(),
+            _ => HashSet::new(), // Unused
+        };
+
+        let groups: HashSet<_> = match update_type {
+            EntityUpdate::Groups(v) => v.iter().copied().collect(),
+            _ => HashSet::new(), // Unused
+        };
+
+        let range = match update_type {
+            EntityUpdate::Indexes((start, end)) => *start..*end,
+            _ => 0..self.scene.entities.len(),
+        };
+
+        let indices: Vec<_> = range
+            .filter(|&i| {
+                let ent = &self.scene.entities[i];
+                match update_type {
+                    EntityUpdate::Classes(_) => classes_or_ids.contains(&ent.class),
+                    EntityUpdate::Ids(_) => classes_or_ids.contains(&ent.id),
+                    EntityUpdate::Groups(_) => ent.group.is_some_and(|g| groups.contains(&g)),
+                    _ => true,
+                }
+            })
+            .collect();
+
+        self.update_instances_in_place(device, &indices);
+    }
+
+    /// Update the instances of entities using these materials, in place.
+    pub(crate) fn update_materials(&mut self, device: &Device, materials: &[usize]) {
+        let materials: HashSet<_> = materials.iter().copied().collect();
+
+        let indices: Vec<_> = (0..self.scene.entities.len())
+            .filter(|&i| {
+                self.scene.entities[i]
+                    .material
+                    .is_some_and(|m| materials.contains(&m))
+            })
+            .collect();
+
+        self.update_instances_in_place(device, &indices);
+    }
+
+    /// Write the instances of these entities to their slots. Entities that crossed the opacity
+    /// threshold move to a free slot in the other buffer; if there's none, or an entity has no
+    /// slot yet, we rebuild the instance buffers instead.
+    fn update_instances_in_place(&mut self, device: &Device, indices: &[usize]) {
+        // Gather updates first, so contiguous slots can be coalesced into a single
+        // queue.write_buffer call each, rather than one call per entity.
+        let mut writes_opaque: Vec<(usize, InstanceRaw)> = Vec::new();
+        let mut writes_transparent: Vec<(usize, InstanceRaw)> = Vec::new();
+
+        for &i in indices {
+            #[cfg(feature = "f64")]
+            {
+                let origin = self.scene.floating_origin;
+                self.scene.entities[i].sync_f64(origin);
+            }
+
+            let ent = &self.scene.entities[i];
+            let crossed = ent.buf_i.is_some() && ent.is_transparent() != ent.buf_is_transparent;
+
+            if ent.buf_i.is_none() || (crossed && !self.move_instance(i)) {
+                debug!("Performing a full entity rebuild; unable to update in-place");
+                self.setup_entities(device);
+                return;
+            }
+
+            let ent = &self.scene.entities[i];
+            let Some(slot) = ent.buf_i else {
+                continue;
+            };
+
+            let instance = self.scene.instance(ent);
+            if ent.buf_is_transparent {
+                writes_transparent.push((slot, instance.to_raw()));
+            } else {
+                writes_opaque.push((slot, instance.to_raw()));
+            }
+        }
+
+        self.write_instances(writes_opaque, writes_transparent);
+    }
+
+    /// Move an entity that crossed the opacity threshold to a free slot in its mesh's range of
+    /// the other instance buffer, and hide the instance in the slot it leaves, freeing that.
+    /// Returns false if there's no free slot.
+    fn move_instance(&mut self, ent_i: usize) -> bool {
+        let ent = &self.scene.entities[ent_i];
+        let Some(slot) = ent.buf_i else {
+            return false;
+        };
+
+        let (mappings, free_from, free_to, buf) = if ent.buf_is_transparent {
+            (
+                &self.mesh_mappings_transparent,
+                &mut self.free_slots_transparent,
+                &mut self.free_slots_opaque,
+                &mut self.instance_buf_transparent,
+            )
+        } else {
+            (
+                &self.mesh_mappings,
+                &mut self.free_slots_opaque,
+                &mut self.free_slots_transparent,
+                &mut self.instance_buf,
+            )
+        };
+
+        // The mesh whose range the slot is in; it's the last one starting at or before it.
+        let Some(mesh) = mappings
+            .partition_point(|&(_, start, _)| start as usize <= slot)
+            .checked_sub(1)
+        else {
+            return false;
+        };
+        let Some(new_slot) = free_to.get_mut(mesh).and_then(|f| f.pop()) else {
+            return false;
+        };
+
+        buf.write_at(
+            &mut self.uploader,
+            slot * INSTANCE_SIZE,
+            bytemuck::bytes_of(&InstanceRaw::zeroed()),
+        );
+        free_from[mesh].push(slot);
+
+        let ent = &mut self.scene.entities[ent_i];
+        ent.buf_i = Some(new_slot);
+        ent.buf_is_transparent = !ent.buf_is_transparent;
+
+        true
+    }
+
+    /// With `double_buffer_instances`, swap in the instance buffers holding this frame's entity
+    /// updates. Call after the frame's last entity update, before drawing.
+    fn flush_instances(&mut self, device: &Device) {
+        for buf in [&mut self.instance_buf, &mut self.instance_buf_transparent] {
+            buf.set_double_buffered(device, &mut self.uploader, self.double_buffer_instances);
+            buf.flush(device, &mut self.uploader);
+        }
+    }
+
+    /// Write instances to their slots in the opaque and transparent buffers.
+    fn write_instances(
+        &mut self,
+        writes_opaque: Vec<(usize, InstanceRaw)>,
+        writes_transparent: Vec<(usize, InstanceRaw)>,
+    ) {
+        for (buf, mut writes) in [
+            (&mut self.instance_buf, writes_opaque),
+            (&mut self.instance_buf_transparent, writes_transparent),
+        ] {
+            if writes.is_empty() {
+                continue;
+            }
+
+            // Slots are unique per entity, so sorting then merging adjacent runs writes
+            // the same bytes to the same offsets as individual writes would.
+            writes.sort_unstable_by_key(|(slot, _)| *slot);
+
+            let mut start = 0;
+            while start < writes.len() {
+                let mut end = start + 1;
+                while end < writes.len() && writes[end].0 == writes[end - 1].0 + 1 {
+                    end += 1;
+                }
+
+                let run = &writes[start..end];
+                let data: Vec<_> = run.iter().map(|(_, inst)| *inst).collect();
+                buf.write_at(
+                    &mut self.uploader,
+                    run[0].0 * INSTANCE_SIZE,
+                    bytemuck::cast_slice(&data),
+                );
+
+                start = end;
+            }
+        }
+    }
+
+    /// Upload `Scene::spheres`.
+    pub(crate) fn setup_spheres(&mut self, device: &Device) {
+        self.impostors
+            .upload_spheres(device, &mut self.uploader, &self.scene.spheres);
+    }
+
+    /// Upload `Scene::cylinders`.
+    pub(crate) fn setup_cylinders(&mut self, device: &Device) {
+        self.impostors
+            .upload_cylinders(device, &mut self.uploader, &self.scene.cylinders);
+    }
+
+    /// Upload `Scene::lines`.
+    pub(crate) fn setup_lines(&mut self, device: &Device) {
+        self.lines
+            .upload(device, &mut self.uploader, &self.scene.lines);
+    }
+
+    /// Sets up entities (And the associated instance buffer), but doesn't change
+    /// meshes, lights, or the camera. The vertex and index buffers aren't changed; only the instances.
+    /// This rebuilds the instance data from scratch from entities, but only uploads the portions
+    /// that changed.
+    pub(crate) fn setup_entities(&mut self, device: &Device) {
+        let _span = debug_span!("setup_entities", entities = self.scene.entities.len()).entered();
+
+        #[cfg(feature = "f64")]
+        self.scene.sync_f64_transforms();
+
+        let scene = &mut self.scene;
+        let n_meshes = scene.meshes.len();
+
+        // Bucket entity indices by mesh in a single pass, instead of scanning every
+        // entity once per mesh.
+        let mut ents_by_mesh: Vec<Vec<usize>> = vec![Vec::new(); n_meshes];
+        for (i, entity) in scene.entities.iter().enumerate() {
+            if entity.mesh < n_meshes {
+                ents_by_mesh[entity.mesh].push(i);
+            }
+        }
+
+        // Computing each instance's matrices is the expensive part of this, and is independent
+        // per entity; do it up front, in parallel if available. Placement into buffers below is
+        // cheap, and stays serial.
+        #[cfg(feature = "rayon")]
+        let instances: Vec<InstanceRaw> = scene
+            .entities
+            .par_iter()
+            .map(|e| scene.instance(e).to_raw())
+            .collect();
+        #[cfg(not(feature = "rayon"))]
+        let instances: Vec<InstanceRaw> = scene
+            .entities
+            .iter()
+            .map(|e| scene.instance(e).to_raw())
+            .collect();
+
+        let mut instance_data = Vec::with_capacity(instances.len());
+        let mut instance_data_transparent = Vec::new();
+
+        let mut mesh_mappings = Vec::with_capacity(n_meshes);
+        let mut mesh_mappings_transparent = Vec::with_capacity(n_meshes);
+
+        let mut instance_start_this_mesh = 0;
+        let mut instance_start_this_mesh_transparent = 0;
+
+        let mut i_opaque = 0;
+        let mut i_transparent = 0;
+
+        let mut free_slots_opaque = Vec::with_capacity(n_meshes);
+        let mut free_slots_transparent = Vec::with_capacity(n_meshes);
+
+        // Build mesh-based instances.
+        for (mesh_range, ents) in self.mesh_ranges.iter().zip(&ents_by_mesh) {
+            let vertex_start_this_mesh = mesh_range.vertex_start as i32;
+            let mut instance_count_this_mesh = 0;
+            let mut instance_count_this_mesh_transparent = 0;
+
+            for &ent_i in ents {
+                let entity = &mut scene.entities[ent_i];
+                let instance = instances[ent_i];
+
+                if entity.is_transparent() {
+                    instance_data_transparent.push(instance);
+                    instance_count_this_mesh_transparent += 1;
+
+                    // For our in-place replacement system.
+                    entity.buf_i = Some(i_transparent);
+                    entity.buf_is_transparent = true;
+                    i_transparent += 1;
+                } else {
+                    instance_data.push(instance);
+                    instance_count_this_mesh += 1;
+
+                    entity.buf_i = Some(i_opaque);
+                    entity.buf_is_transparent = false;
+                    i_opaque += 1;
+                }
+            }
+
+            // Spare slots, holding hidden instances, for entities that cross the opacity
+            // threshold to move into without a rebuild.
+            let spare_opaque =
+                (instance_count_this_mesh_transparent as usize).min(SPARE_INSTANCE_SLOTS);
+            let spare_transparent = (instance_count_this_mesh as usize).min(SPARE_INSTANCE_SLOTS);
+
+            free_slots_opaque.push((i_opaque..i_opaque + spare_opaque).collect::<Vec<_>>());
+            free_slots_transparent
+                .push((i_transparent..i_transparent + spare_transparent).collect::<Vec<_>>());
+
+            instance_data.extend((0..spare_opaque).map(|_| InstanceRaw::zeroed()));
+            instance_data_transparent.extend((0..spare_transparent).map(|_| InstanceRaw::zeroed()));
+
+            instance_count_this_mesh += spare_opaque as u32;
+            instance_count_this_mesh_transparent += spare_transparent as u32;
+            i_opaque += spare_opaque;
+            i_transparent += spare_transparent;
+
+            mesh_mappings.push((
+                vertex_start_this_mesh,
+                instance_start_this_mesh,
+                instance_count_this_mesh,
+            ));
+
+            mesh_mappings_transparent.push((
+                vertex_start_this_mesh,
+                instance_start_this_mesh_transparent,
+                instance_count_this_mesh_transparent,
+            ));
+
+            instance_start_this_mesh += instance_count_this_mesh;
+            instance_start_this_mesh_transparent += instance_count_this_mesh_transparent;
+        }
+
+        self.mesh_mappings = mesh_mappings;
+        self.mesh_mappings_transparent = mesh_mappings_transparent;
+        self.free_slots_opaque = free_slots_opaque;
+        self.free_slots_transparent = free_slots_transparent;
+
+        // These only reallocate if capacity is exceeded; otherwise, they upload the changed range.
+        self.instance_buf.write(
+            device,
+            &mut self.uploader,
+            bytemuck::cast_slice(&instance_data),
         );
-        upload_instance_data(
+        self.instance_buf_transparent.write(
             device,
-            queue,
-            &mut self.instance_buf_gauss,
-            &instance_data_gauss,
-            "Instance buffer Gaussian",
+            &mut self.uploader,
+            bytemuck::cast_slice(&instance_data_transparent),
         );
+
+        self.update_indirect_bufs(device);
+        // Gaussians are uploaded here too, for applications that rely on `EntityUpdate::All`.
+        self.setup_gaussians(device);
+    }
+
+    /// Upload `Scene::gaussians`. Only the range that changed is transferred.
+    pub(crate) fn setup_gaussians(&mut self, device: &Device) {
+        let data: Vec<_> = self
+            .scene
+            .gaussians
+            .iter()
+            .map(|g| g.to_instance())
+            .collect();
+
+        self.instance_buf_gauss
+            .write(device, &mut self.uploader, bytemuck::cast_slice(&data));
+    }
+
+    /// Evaluate `TextureSource::GaussianField` textures that are new, or all of them if the
+    /// gaussians changed since they were evaluated.
+    fn evaluate_gauss_fields(&mut self, device: &Device, encoder: &mut CommandEncoder) {
+        let fields: Vec<_> = self
+            .scene
+            .textures
+            .iter()
+            .enumerate()
+            .filter_map(|(i, source)| match source {
+                TextureSource::GaussianField { dims, bounds } => {
+                    Some((TextureHandle(i as u32), *dims, *bounds))
+                }
+                _ => None,
+            })
+            .collect();
+
+        let added = mem::take(&mut self.texture_store.fields_added);
+        if fields.is_empty() || (!added && self.gauss_field_source == self.scene.gaussians) {
+            return;
+        }
+
+        if !self.compute_supported {
+            if added {
+                warn!("Gaussian fields require compute shaders, which this device lacks");
+            }
+            return;
+        }
+
+        let _span = debug_span!("evaluate_gauss_fields", fields = fields.len()).entered();
+
+        let evaluator = self
+            .gauss_field
+            .get_or_insert_with(|| GaussianFieldEvaluator::new(device));
+
+        for (handle, dims, bounds) in fields {
+            if let Some(texture) = self.texture_store.texture(handle) {
+                evaluator.evaluate(
+                    device,
+                    encoder,
+                    &self.scene.gaussians,
+                    texture,
+                    dims,
+                    bounds,
+                );
+            }
+        }
+
+        self.gauss_field_source.clone_from(&self.scene.gaussians);
+    }
+
+    /// Upload changes to `Scene::gaussians`, writing changed gaussians in place where possible.
+    pub(crate) fn update_gaussians(&mut self, device: &Device, update: &GaussianUpdate) {
+        let mut indices = match update {
+            GaussianUpdate::None => return,
+            GaussianUpdate::All => return self.setup_gaussians(device),
+            GaussianUpdate::Indices(v) => v.clone(),
+        };
+
+        let uploaded = self.instance_buf_gauss.len() / size_of::<GaussianInstance>();
+        if self.scene.gaussians.len() != uploaded || indices.iter().any(|&i| i >= uploaded) {
+            return self.setup_gaussians(device);
+        }
+
+        indices.sort_unstable();
+        indices.dedup();
+
+        // Coalesce contiguous indices into a single write each, as with entity instances.
+        let mut start = 0;
+        while start < indices.len() {
+            let mut end = start + 1;
+            while end < indices.len() && indices[end] == indices[end - 1] + 1 {
+                end += 1;
+            }
+
+            let first = indices[start];
+            let data: Vec<_> = self.scene.gaussians[first..first + end - start]
+                .iter()
+                .map(|g| g.to_instance())
+                .collect();
+            self.instance_buf_gauss.write_at(
+                &mut self.uploader,
+                first * size_of::<GaussianInstance>(),
+                bytemuck::cast_slice(&data),
+            );
+
+            start = end;
+        }
+    }
+
+    /// Advance the scene by `dt` with `Scene::step`, and upload what changed. Called each frame
+    /// before drawing.
+    pub(crate) fn step(&mut self, device: &Device, dt: Duration) {
+        let step = self
+            .scene
+            .step(&mut self.inputs_commanded, dt.as_secs_f32());
+
+        if step.camera_changed {
+            self.update_camera();
+        }
+        if !step.entities_changed.is_empty() {
+            self.update_instances_in_place(device, &step.entities_changed);
+        }
+        self.animating = step.animating;
     }
 
-    pub(crate) fn update_camera(&mut self, queue: &Queue) {
-        queue.write_buffer(&self.camera_buf, 0, &self.scene.camera.to_bytes());
+    pub(crate) fn update_camera(&mut self) {
+        self.uploader
+            .write(&self.camera_buf, 0, &self.scene.camera.to_bytes());
 
         if self.halo_expansion > 0.0 {
             let mut halo_cam = self.scene.camera.clone();
             halo_cam.halo_expansion = self.halo_expansion;
-            queue.write_buffer(&self.camera_buf_halo, 0, &halo_cam.to_bytes());
+            self.uploader
+                .write(&self.camera_buf_halo, 0, &halo_cam.to_bytes());
         }
 
         // Required due to not being able to take inverse of 4x4 matrices in shaders?
         if !self.scene.gaussians.is_empty() {
-            queue.write_buffer(
+            self.uploader.write(
                 &self.cam_basis_buf,
                 0,
-                &CameraBasis::new(self.scene.camera.view_mat()).to_bytes(),
+                &CameraBasis::new(&self.scene.camera).to_bytes(),
             );
         }
     }
 
-    pub(crate) fn update_lighting(&mut self, queue: &Queue) {
-        queue.write_buffer(&self.lighting_buf, 0, &self.scene.lighting.to_bytes());
+    /// Write only the camera's fog parameters; cheaper than `update_camera`.
+    pub(crate) fn update_fog(&mut self) {
+        let fog = self.scene.camera.fog_to_bytes();
+        let height_fog = self.scene.camera.height_fog_to_bytes();
+
+        self.uploader
+            .write(&self.camera_buf, FOG_OFFSET as u64, &fog);
+        self.uploader
+            .write(&self.camera_buf, HEIGHT_FOG_OFFSET as u64, &height_fog);
+
+        if self.halo_expansion > 0.0 {
+            self.uploader
+                .write(&self.camera_buf_halo, FOG_OFFSET as u64, &fog);
+            self.uploader
+                .write(&self.camera_buf_halo, HEIGHT_FOG_OFFSET as u64, &height_fog);
+        }
+    }
+
+    pub(crate) fn update_lighting(&mut self) {
+        self.uploader
+            .write(&self.lighting_buf, 0, &self.scene.lighting.to_bytes());
     }
 
     /// Write SSAO uniform buffer from the current camera state.
@@ -973,14 +2042,29 @@ impl GraphicsState {
         let new_edge = settings.edge_cueing.unwrap_or(0.0);
         if self.scene.camera.edge_cueing != new_edge {
             self.scene.camera.edge_cueing = new_edge;
-            self.update_camera(queue);
+            self.update_camera();
+        }
+
+        // ── Isolines ──────────────────────────────────────────────────────────
+        if self.scene.camera.isolines != settings.isolines {
+            self.scene.camera.isolines = settings.isolines;
+            self.update_camera();
+        }
+
+        // ── Exposure and gamma ────────────────────────────────────────────────
+        if self.scene.camera.exposure != settings.exposure
+            || self.scene.camera.gamma != settings.gamma
+        {
+            self.scene.camera.exposure = settings.exposure;
+            self.scene.camera.gamma = settings.gamma;
+            self.update_camera();
         }
 
         // ── Depth-aware halos ─────────────────────────────────────────────────
         let new_halo = settings.depth_aware_halos.unwrap_or(0.0);
         if self.halo_expansion != new_halo {
             self.halo_expansion = new_halo;
-            self.update_camera(queue);
+            self.update_camera();
         }
 
         // ── Contour lines ─────────────────────────────────────────────────────
@@ -1016,24 +2100,105 @@ impl GraphicsState {
             self.fps_accum_time = 0.;
             self.fps_accum_frames = 0;
         }
+        self.display_memory_usage = settings.display_memory_usage;
+        self.display_upload_progress = settings.display_upload_progress;
+        self.double_buffer_instances = settings.double_buffer_instances;
+
+        self.stereo.settings = settings.stereo;
+        self.motion_blur.strength = settings.motion_blur.unwrap_or(0.);
+        self.debug_lines.settings = settings.debug_draw.clone();
+
+        self.on_frame_complete = settings.on_frame_complete.clone();
+        self.frame_timer.enabled =
+            settings.on_frame_complete.is_some() || settings.adaptive_quality.is_some();
+        // The render targets are resized at the next frame, if this changes.
+        self.supersampling = settings.supersampling.unwrap_or(1).clamp(1, 4) as f32;
+        self.render_scale = self.supersampling
+            * self
+                .quality
+                .configure(settings.adaptive_quality.clone(), settings.msaa_samples);
+
+        self.depth_readback.enabled = settings.depth_readback;
+        if !settings.depth_readback {
+            self.scene.depth_snapshot = None;
+        }
+
+        self.render_mode = settings.render_mode;
+        self.min_frame_time = settings
+            .max_fps
+            .filter(|fps| *fps > 0.)
+            .map(|fps| Duration::from_secs_f32(1. / fps));
+        self.clock = settings.clock;
+
+        // ── Present mode ──────────────────────────────────────────────────────
+        // This requires the surface, so is applied by the event loop.
+        if settings.present_mode.to_wgpu() != self.surface_cfg.present_mode {
+            self.pending_present_mode = Some(settings.present_mode);
+        }
     }
 
-    /// Recreate all MSAA-dependent resources after a sample-count change.
-    /// Call this from the event loop (which also has access to GuiState for its renderer).
-    pub(crate) fn apply_msaa_change(&mut self, device: &Device) {
-        let new_msaa = self.msaa_samples;
+    /// The scale the 3D scene renders at: `render_scale`, limited to the largest texture the
+    /// device supports.
+    fn scene_scale(&self) -> f32 {
+        let largest = self.surface_cfg.width.max(self.surface_cfg.height) as f32;
+        self.render_scale
+            .min(self.max_texture_size as f32 / largest)
+    }
 
-        self.depth_texture =
-            Texture::create_depth_texture(device, &self.surface_cfg, "Depth texture", new_msaa);
-        self.msaa_texture = if new_msaa > 1 {
-            Some(Self::create_msaa_texture(
-                device,
-                &self.surface_cfg,
-                new_msaa,
-            ))
-        } else {
-            None
-        };
+    /// The size the 3D scene renders at: the surface's, scaled by `scene_scale`.
+    pub(crate) fn render_size(&self) -> (u32, u32) {
+        let scale = self.scene_scale();
+        let size = |v: u32| ((v as f32 * scale).round() as u32).clamp(1, self.max_texture_size);
+        (size(self.surface_cfg.width), size(self.surface_cfg.height))
+    }
+
+    /// Recreate the depth and MSAA textures the scene renders with, e.g. after a resize, or a
+    /// change to the render scale or sample count.
+    pub(crate) fn resize_render_targets(&mut self, device: &Device) {
+        let (width, height) = self.render_size();
+        let cfg = SurfaceConfiguration {
+            width,
+            height,
+            ..self.surface_cfg.clone()
+        };
+
+        self.depth_texture =
+            Texture::create_depth_texture(device, &cfg, "Depth texture", self.msaa_samples);
+
+        self.depth_texture_contour =
+            Texture::create_depth_texture(device, &cfg, "Depth texture contour", 1);
+        self.bind_group_contour = create_contour_bind_group(
+            device,
+            &self.layout_contour,
+            &self.depth_texture_contour.view,
+            &self.contour_uniform_buf,
+        );
+        self.bind_group_ssao = create_ssao_bind_group(
+            device,
+            &self.layout_ssao,
+            &self.depth_texture_contour.view,
+            &self.ssao_uniform_buf,
+        );
+
+        self.msaa_texture = if self.msaa_samples > 1 {
+            Some(Self::create_msaa_texture(device, &cfg, self.msaa_samples))
+        } else {
+            None
+        };
+    }
+
+    /// Recreate all MSAA-dependent resources after a sample-count change.
+    /// Call this from the event loop (which also has access to GuiState for its renderer).
+    pub(crate) fn apply_msaa_change(&mut self, device: &Device) {
+        let _span = debug_span!("apply_msaa_change", samples = self.msaa_samples).entered();
+
+        self.resize_render_targets(device);
+        self.create_pipelines(device);
+    }
+
+    /// Get the MSAA-dependent pipelines for the current sample count, from the cache if present.
+    fn create_pipelines(&mut self, device: &Device) {
+        let new_msaa = self.msaa_samples;
 
         let depth_stencil_mesh = DepthStencilState {
             format: DEPTH_FORMAT,
@@ -1048,45 +2213,65 @@ impl GraphicsState {
             bind_group_layouts: &[
                 Some(&self.bind_groups.layout_cam),
                 Some(&self.bind_groups.layout_lighting),
+                Some(&self.texture_store.layout),
+                Some(&self.reflection.layout),
             ],
             immediate_size: 0,
         });
 
-        self.pipeline_mesh = create_render_pipeline(
-            device,
-            &pipeline_layout_mesh,
-            self.shader_mesh.clone(),
-            &self.surface_cfg,
-            new_msaa,
-            &[VERTEX_LAYOUT, INSTANCE_LAYOUT],
-            Some(depth_stencil_mesh.clone()),
-            None,
-            Some(Face::Back),
-            "Render pipeline mesh opaque",
+        // Pipelines for a sample count used previously are reused from the cache.
+        self.pipeline_mesh = self.pipeline_cache.get_or_create(
+            PipelineKey::new(PipelineKind::MeshOpaque, new_msaa),
+            || {
+                create_render_pipeline(
+                    device,
+                    &pipeline_layout_mesh,
+                    self.shader_mesh.clone(),
+                    &self.surface_cfg,
+                    new_msaa,
+                    &[VERTEX_LAYOUT, INSTANCE_LAYOUT],
+                    Some(depth_stencil_mesh.clone()),
+                    None,
+                    Some(Face::Back),
+                    "Render pipeline mesh opaque",
+                )
+            },
         );
-        self.pipeline_mesh_transparent = create_render_pipeline(
-            device,
-            &pipeline_layout_mesh,
-            self.shader_mesh.clone(),
-            &self.surface_cfg,
-            new_msaa,
-            &[VERTEX_LAYOUT, INSTANCE_LAYOUT],
-            Some(depth_stencil_mesh.clone()),
-            Some(BlendState::ALPHA_BLENDING),
-            Some(Face::Back),
-            "Render pipeline mesh transparent",
+        self.pipeline_mesh_transparent = self.pipeline_cache.get_or_create(
+            PipelineKey::new(PipelineKind::MeshTransparent, new_msaa),
+            || {
+                create_render_pipeline_fs(
+                    device,
+                    &pipeline_layout_mesh,
+                    self.shader_mesh.clone(),
+                    "fs_blend",
+                    &self.surface_cfg,
+                    new_msaa,
+                    &[VERTEX_LAYOUT, INSTANCE_LAYOUT],
+                    Some(depth_stencil_mesh.clone()),
+                    Some(BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                    Some(Face::Back),
+                    "Render pipeline mesh transparent",
+                )
+            },
         );
-        self.pipeline_mesh_transparent_back = create_render_pipeline(
-            device,
-            &pipeline_layout_mesh,
-            self.shader_mesh.clone(),
-            &self.surface_cfg,
-            new_msaa,
-            &[VERTEX_LAYOUT, INSTANCE_LAYOUT],
-            Some(depth_stencil_mesh.clone()),
-            Some(BlendState::ALPHA_BLENDING),
-            Some(Face::Front),
-            "Render pipeline mesh transparent – backfaces",
+        self.pipeline_mesh_transparent_back = self.pipeline_cache.get_or_create(
+            PipelineKey::new(PipelineKind::MeshTransparentBack, new_msaa),
+            || {
+                create_render_pipeline_fs(
+                    device,
+                    &pipeline_layout_mesh,
+                    self.shader_mesh.clone(),
+                    "fs_blend",
+                    &self.surface_cfg,
+                    new_msaa,
+                    &[VERTEX_LAYOUT, INSTANCE_LAYOUT],
+                    Some(depth_stencil_mesh.clone()),
+                    Some(BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                    Some(Face::Front),
+                    "Render pipeline mesh transparent – backfaces",
+                )
+            },
         );
 
         let pipeline_layout_halo = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
@@ -1094,45 +2279,199 @@ impl GraphicsState {
             bind_group_layouts: &[
                 Some(&self.bind_groups.layout_cam),
                 Some(&self.bind_groups.layout_lighting),
+                Some(&self.texture_store.layout),
+                Some(&self.reflection.layout),
             ],
             immediate_size: 0,
         });
-        self.pipeline_halo = create_render_pipeline_depth_only(
+        request_two_sided_pipeline(
+            &mut self.pipeline_cache,
+            device,
+            pipeline_layout_mesh.clone(),
+            self.shader_mesh.clone(),
+            self.surface_cfg.clone(),
+            new_msaa,
+            depth_stencil_mesh.clone(),
+        );
+        request_halo_pipeline(
+            &mut self.pipeline_cache,
             device,
-            &pipeline_layout_halo,
+            pipeline_layout_halo,
             self.shader_mesh.clone(),
+            self.surface_cfg.format,
             new_msaa,
-            &[VERTEX_LAYOUT, INSTANCE_LAYOUT],
             depth_stencil_mesh,
         );
+        #[cfg(feature = "xray")]
+        request_xray_pipeline(
+            &mut self.pipeline_cache,
+            device,
+            pipeline_layout_mesh.clone(),
+            self.shader_mesh.clone(),
+            self.surface_cfg.format,
+            new_msaa,
+        );
 
-        let depth_stencil_gauss = Some(DepthStencilState {
-            format: DEPTH_FORMAT,
-            depth_write_enabled: Some(false),
-            depth_compare: Some(wgpu::CompareFunction::Less),
-            stencil: wgpu::StencilState::default(),
-            bias: wgpu::DepthBiasState::default(),
-        });
-        let pipeline_layout_gauss =
+        request_gauss_pipelines(
+            &mut self.pipeline_cache,
+            device,
+            &[
+                &self.bind_groups.layout_cam_gauss,
+                &self.gauss_display.layout,
+            ],
+            self.shader_gauss.clone(),
+            self.surface_cfg.clone(),
+            new_msaa,
+        );
+
+        let pipeline_layout_slice =
             device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: Some("Gaussian pipeline layout"),
-                bind_group_layouts: &[Some(&self.bind_groups.layout_cam_gauss)],
+                label: Some("Slice plane pipeline layout"),
+                bind_group_layouts: &[
+                    Some(&self.bind_groups.layout_cam),
+                    Some(&self.texture_store.layout_volume),
+                    Some(&self.slices.layout),
+                ],
                 immediate_size: 0,
             });
-        self.pipeline_gauss = create_render_pipeline(
+        self.pipeline_slice = self.pipeline_cache.get_or_create(
+            PipelineKey::new(PipelineKind::Slice, new_msaa),
+            || {
+                create_render_pipeline(
+                    device,
+                    &pipeline_layout_slice,
+                    self.shader_slice.clone(),
+                    &self.surface_cfg,
+                    new_msaa,
+                    &[],
+                    Some(depth_stencil_slice()),
+                    Some(BlendState::ALPHA_BLENDING),
+                    None,
+                    "Render pipeline slice plane",
+                )
+            },
+        );
+
+        request_background_pipeline(
+            &mut self.pipeline_cache,
             device,
-            &pipeline_layout_gauss,
-            self.shader_gauss.clone(),
-            &self.surface_cfg,
+            &self.background.layout,
+            self.shader_background.clone(),
+            self.surface_cfg.clone(),
+            new_msaa,
+        );
+
+        request_debug_lines_pipeline(
+            &mut self.pipeline_cache,
+            device,
+            &self.bind_groups.layout_cam,
+            self.surface_cfg.format,
+            new_msaa,
+        );
+
+        request_lines_pipeline(
+            &mut self.pipeline_cache,
+            device,
+            &self.bind_groups.layout_cam,
+            &self.lines.layout,
+            self.surface_cfg.format,
+            new_msaa,
+        );
+
+        request_impostor_pipelines(
+            &mut self.pipeline_cache,
+            device,
+            &self.bind_groups,
+            self.surface_cfg.format,
             new_msaa,
-            &[QUAD_VERTEX_LAYOUT, GAUSS_INST_LAYOUT],
-            depth_stencil_gauss,
-            Some(BlendState::ALPHA_BLENDING),
-            None,
-            "Render pipeline gaussian",
         );
     }
 
+    /// Recompile shaders changed on disk, and rebuild the pipelines that use them. If one fails
+    /// to compile, we keep the previous version, and show the error as a toast.
+    #[cfg(feature = "hot_reload")]
+    pub(crate) fn reload_shaders(&mut self, device: &Device) {
+        let Some(watcher) = &self.shader_watcher else {
+            return;
+        };
+
+        for file in watcher.changed() {
+            if file == ShaderFile::Mesh && self.custom_mesh_shader {
+                continue;
+            }
+
+            let source = match file.source() {
+                Ok(s) => s,
+                Err(e) => {
+                    warn!("Unable to read {}: {e}", file.path().display());
+                    continue;
+                }
+            };
+
+            // Catch shader and pipeline validation errors, instead of panicking.
+            let error_scope = device.push_error_scope(wgpu::ErrorFilter::Validation);
+
+            let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some(file.name()),
+                source: wgpu::ShaderSource::Wgsl(source.into()),
+            });
+            let prev = match file {
+                ShaderFile::Mesh => mem::replace(&mut self.shader_mesh, shader),
+                ShaderFile::Gauss => mem::replace(&mut self.shader_gauss, shader),
+                ShaderFile::Slice => mem::replace(&mut self.shader_slice, shader),
+                ShaderFile::Background => mem::replace(&mut self.shader_background, shader),
+            };
+            self.rebuild_shader_pipelines(device, file);
+
+            match pollster::block_on(error_scope.pop()) {
+                Some(e) => {
+                    match file {
+                        ShaderFile::Mesh => self.shader_mesh = prev,
+                        ShaderFile::Gauss => self.shader_gauss = prev,
+                        ShaderFile::Slice => self.shader_slice = prev,
+                        ShaderFile::Background => self.shader_background = prev,
+                    }
+                    self.rebuild_shader_pipelines(device, file);
+
+                    warn!("Unable to compile {}: {e}", file.name());
+                    self.shader_error = Some((format!("{}: {e}", file.name()), 0.));
+                }
+                None => {
+                    debug!("Reloaded {}", file.name());
+                    self.shader_error = None;
+                }
+            }
+
+            self.redraw_pending = true;
+        }
+    }
+
+    /// Discard the cached pipelines that use a shader, and rebuild them.
+    #[cfg(feature = "hot_reload")]
+    fn rebuild_shader_pipelines(&mut self, device: &Device, file: ShaderFile) {
+        self.pipeline_cache.invalidate(file.pipelines());
+        // Compile on this thread, so the caller's error scope catches failures.
+        self.pipeline_cache.blocking = true;
+
+        // The contour prepass isn't cached; it doesn't depend on the sample count.
+        if file == ShaderFile::Mesh {
+            let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Contour depth prepass layout"),
+                bind_group_layouts: &[Some(&self.bind_groups.layout_cam)],
+                immediate_size: 0,
+            });
+            self.pipeline_contour_depth = create_contour_depth_pipeline(
+                device,
+                &layout,
+                self.shader_mesh.clone(),
+                &[VERTEX_LAYOUT, INSTANCE_LAYOUT],
+            );
+        }
+
+        self.create_pipelines(device);
+        self.pipeline_cache.blocking = false;
+    }
+
     fn setup_render_pass<'a>(
         &mut self,
         encoder: &'a mut CommandEncoder,
@@ -1142,9 +2481,25 @@ impl GraphicsState {
         ui_settings: &UiSettings,
         ui_size: (f32, f32),
         pixels_per_pt: f32, // todo: Currently unused.
+        eyes: &[Eye],
     ) -> RenderPass<'a> {
-        let (x, y, eff_width, eff_height) =
-            viewport_rect(ui_size, win_width, win_height, ui_settings, pixels_per_pt);
+        let (x, y, eff_width, eff_height) = letterbox(
+            viewport_rect(ui_size, win_width, win_height, ui_settings, pixels_per_pt),
+            self.scene.viewport_aspect,
+        );
+
+        // Applies to both paths, so changes to the scene's background color show on the next
+        // frame, with or without MSAA. With a fixed aspect, this is the color of the bars.
+        let background = match self.scene.viewport_aspect {
+            Some(_) => [0.; 3],
+            None => linearize(self.scene.background_color),
+        };
+        let clear_color = wgpu::Color {
+            r: background[0] as f64,
+            g: background[1] as f64,
+            b: background[2] as f64,
+            a: 1.0,
+        };
 
         let color_attachment = if let Some(msaa_texture) = &self.msaa_texture {
             // Use MSAA texture as render target, resolve to the swap chain texture
@@ -1153,7 +2508,7 @@ impl GraphicsState {
                 depth_slice: None, // todo: Introduced in GPU27. Should we use it?
                 resolve_target: Some(output_view), // Resolve the multisample texture
                 ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    load: wgpu::LoadOp::Clear(clear_color),
                     store: StoreOp::Discard,
                 },
             }
@@ -1163,17 +2518,19 @@ impl GraphicsState {
                 depth_slice: None,
                 resolve_target: None,
                 ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(wgpu::Color {
-                        r: self.scene.background_color.0 as f64,
-                        g: self.scene.background_color.1 as f64,
-                        b: self.scene.background_color.2 as f64,
-                        a: 1.0,
-                    }),
+                    load: wgpu::LoadOp::Clear(clear_color),
                     store: StoreOp::Store,
                 },
             }
         };
 
+        let name = if eyes == [Eye::Reflection] {
+            "reflection"
+        } else {
+            "main"
+        };
+        let timestamps = self.frame_timer.pass(name);
+
         let mut rpass = encoder.begin_render_pass(&RenderPassDescriptor {
             label: Some("Render pass"),
             color_attachments: &[Some(color_attachment)],
@@ -1185,108 +2542,786 @@ impl GraphicsState {
                 }),
                 stencil_ops: None,
             }),
-            timestamp_writes: None,
+            timestamp_writes: timestamps.as_ref().map(PassTimestamps::writes),
             occlusion_query_set: None,
             multiview_mask: None,
         });
 
-        rpass.set_viewport(x, y, eff_width, eff_height, 0., 1.);
+        // With stereo side-by-side, each eye gets half the viewport.
+        let eye_width = eff_width / eyes.len() as f32;
+
+        for (i, &eye) in eyes.iter().enumerate() {
+            rpass.set_viewport(x + i as f32 * eye_width, y, eye_width, eff_height, 0., 1.);
+
+            let (cam, cam_gauss, cam_halo) = match eye {
+                Eye::Reflection => self.reflection.cam_bind_groups(),
+                _ => self.stereo.bind_groups(eye).unwrap_or((
+                    &self.bind_groups.cam,
+                    &self.bind_groups.cam_gauss,
+                    &self.bind_group_cam_halo,
+                )),
+            };
+            let reflection = self.reflection.bind_group(eye);
+
+            // Until its pipeline is ready, we show the solid background color.
+            if self.drawn_background().is_some() {
+                let pipeline = self.pipeline_cache.get(PipelineKey::new(
+                    PipelineKind::Background,
+                    self.msaa_samples,
+                ));
+                self.redraw_pending |= pipeline.is_none();
+
+                if let Some(pipeline) = pipeline {
+                    rpass.set_pipeline(&pipeline);
+                    rpass.set_bind_group(0, &self.background.bind_group, &[]);
+                    rpass.draw(0..3, 0..1);
+                }
+            }
+
+            // Depth-aware halo prepass: render opaque instances inflated along normals, front-face
+            // culled, writing only to the depth buffer. Background fragments near a foreground
+            // silhouette then fail the depth test in the main render, producing a halo ring.
+            // Skipped until the pipeline is ready; it's compiled in the background.
+            let pipeline_halo = if self.halo_expansion > 0.0 && !self.instance_buf.is_empty() {
+                let pipeline = self
+                    .pipeline_cache
+                    .get(PipelineKey::new(PipelineKind::Halo, self.msaa_samples));
+                // Render again once it's ready, in `RenderMode::OnDemand`.
+                self.redraw_pending |= pipeline.is_none();
+                pipeline
+            } else {
+                None
+            };
+
+            if let Some(pipeline_halo) = pipeline_halo {
+                rpass.set_pipeline(&pipeline_halo);
+                rpass.set_bind_group(0, cam_halo, &[]);
+                rpass.set_bind_group(1, &self.bind_groups.lighting, &[]);
+                rpass.set_bind_group(2, self.texture_store.bind_group(None), &[]);
+                rpass.set_bind_group(3, reflection, &[]);
+                rpass.set_vertex_buffer(0, self.vertex_buf.buf.slice(..));
+                rpass.set_vertex_buffer(1, self.instance_buf.buf.slice(..));
+
+                self.draw_meshes(
+                    &mut rpass,
+                    &self.mesh_mappings,
+                    &self.indirect_buf,
+                    false,
+                    MeshSubset::All,
+                );
+            }
 
-        // Depth-aware halo prepass: render opaque instances inflated along normals, front-face
-        // culled, writing only to the depth buffer. Background fragments near a foreground
-        // silhouette then fail the depth test in the main render, producing a halo ring.
-        if self.halo_expansion > 0.0 && self.instance_buf.size() > 0 {
-            rpass.set_pipeline(&self.pipeline_halo);
-            rpass.set_bind_group(0, &self.bind_group_cam_halo, &[]);
-            rpass.set_bind_group(1, &self.bind_groups.lighting, &[]);
-            rpass.set_vertex_buffer(0, self.vertex_buf.slice(..));
-            rpass.set_vertex_buffer(1, self.instance_buf.slice(..));
-            rpass.set_index_buffer(self.index_buf.slice(..), wgpu::IndexFormat::Uint32);
-
-            let mut start_ind = 0u32;
-            for (i, mesh) in self.scene.meshes.iter().enumerate() {
-                let (vertex_start, instance_start, instance_count) = self.mesh_mappings[i];
-                if instance_count == 0 {
-                    start_ind += mesh.indices.len() as u32;
+            // Opaque two-sided meshes are drawn separately, without culling. Until that pipeline is
+            // ready, they're drawn with the rest, one-sided.
+            let pipeline_two_sided = if self.scene.meshes.iter().any(|m| m.two_sided) {
+                let pipeline = self.pipeline_cache.get(PipelineKey::new(
+                    PipelineKind::MeshOpaqueTwoSided,
+                    self.msaa_samples,
+                ));
+                self.redraw_pending |= pipeline.is_none();
+                pipeline
+            } else {
+                None
+            };
+
+            // Sphere and cylinder impostors. They're opaque, so are drawn before transparent
+            // meshes. Each kind is skipped until its pipeline is ready.
+            for (instances, kind) in [
+                (&self.impostors.spheres, PipelineKind::ImpostorSphere),
+                (&self.impostors.cylinders, PipelineKind::ImpostorCylinder),
+            ] {
+                if instances.is_empty() {
+                    continue;
+                }
+                let pipeline = self
+                    .pipeline_cache
+                    .get(PipelineKey::new(kind, self.msaa_samples));
+                self.redraw_pending |= pipeline.is_none();
+
+                if let Some(pipeline) = pipeline {
+                    rpass.set_pipeline(&pipeline);
+                    rpass.set_bind_group(0, cam, &[]);
+                    rpass.set_bind_group(1, &self.bind_groups.lighting, &[]);
+                    instances.draw(&mut rpass);
+                }
+            }
+
+            // Make a render pass for opaque meshes, and transparent ones. We separate them to only
+            // back-cull opaque ones.
+            // We draw transparent meshes in two passes, for proper surface culling.
+            for (inst_buf, pipeline, mappings, indirect, two_sided) in [
+                (
+                    &self.instance_buf,
+                    &self.pipeline_mesh,
+                    &self.mesh_mappings,
+                    &self.indirect_buf,
+                    pipeline_two_sided.as_ref(),
+                ),
+                // The order might matter here, i.e. running the back transparent pipeline before
+                // the front transparent one.
+                (
+                    &self.instance_buf_transparent,
+                    &self.pipeline_mesh_transparent_back,
+                    &self.mesh_mappings_transparent,
+                    &self.indirect_buf_transparent,
+                    None,
+                ),
+                (
+                    &self.instance_buf_transparent,
+                    &self.pipeline_mesh_transparent,
+                    &self.mesh_mappings_transparent,
+                    &self.indirect_buf_transparent,
+                    None,
+                ),
+            ]
+            .into_iter()
+            {
+                if inst_buf.is_empty() {
                     continue;
                 }
-                rpass.draw_indexed(
-                    start_ind..start_ind + mesh.indices.len() as u32,
-                    vertex_start,
-                    instance_start..instance_start + instance_count,
+
+                rpass.set_pipeline(pipeline);
+                rpass.set_bind_group(0, cam, &[]);
+                rpass.set_bind_group(1, &self.bind_groups.lighting, &[]);
+                rpass.set_bind_group(2, self.texture_store.bind_group(None), &[]);
+                rpass.set_bind_group(3, reflection, &[]);
+
+                rpass.set_vertex_buffer(0, self.vertex_buf.buf.slice(..));
+                rpass.set_vertex_buffer(1, inst_buf.buf.slice(..));
+
+                let subset = match two_sided {
+                    Some(_) => MeshSubset::OneSided,
+                    None => MeshSubset::All,
+                };
+                self.draw_meshes(&mut rpass, mappings, indirect, true, subset);
+
+                if let Some(pipeline) = two_sided {
+                    rpass.set_pipeline(pipeline);
+                    self.draw_meshes(&mut rpass, mappings, indirect, true, MeshSubset::TwoSided);
+                }
+            }
+
+            // X-ray: draw the hidden parts of entities that have it set, faintly, over what's in front.
+            // Skipped until the pipeline is ready; it's compiled in the background.
+            #[cfg(feature = "xray")]
+            if self.scene.entities.iter().any(|e| e.xray.is_some()) {
+                let pipeline = self
+                    .pipeline_cache
+                    .get(PipelineKey::new(PipelineKind::XRay, self.msaa_samples));
+                self.redraw_pending |= pipeline.is_none();
+
+                if let Some(pipeline) = pipeline {
+                    rpass.set_pipeline(&pipeline);
+                    rpass.set_bind_group(0, cam, &[]);
+                    rpass.set_bind_group(1, &self.bind_groups.lighting, &[]);
+                    rpass.set_bind_group(2, self.texture_store.bind_group(None), &[]);
+                    rpass.set_bind_group(3, reflection, &[]);
+                    rpass.set_vertex_buffer(0, self.vertex_buf.buf.slice(..));
+
+                    for (inst_buf, mappings, indirect) in [
+                        (&self.instance_buf, &self.mesh_mappings, &self.indirect_buf),
+                        (
+                            &self.instance_buf_transparent,
+                            &self.mesh_mappings_transparent,
+                            &self.indirect_buf_transparent,
+                        ),
+                    ] {
+                        if inst_buf.is_empty() {
+                            continue;
+                        }
+                        rpass.set_vertex_buffer(1, inst_buf.buf.slice(..));
+                        self.draw_meshes(&mut rpass, mappings, indirect, false, MeshSubset::All);
+                    }
+                }
+            }
+
+            // Draw slice planes. Skip ones whose volume isn't loaded.
+            if !self.scene.slice_planes.is_empty() {
+                rpass.set_pipeline(&self.pipeline_slice);
+                rpass.set_bind_group(0, cam, &[]);
+
+                for (i, plane) in self.scene.slice_planes.iter().enumerate() {
+                    let (Some(volume), Some(params)) = (
+                        self.texture_store.volume_bind_group(plane.volume),
+                        self.slices.bind_group(i),
+                    ) else {
+                        continue;
+                    };
+
+                    rpass.set_bind_group(1, volume, &[]);
+                    rpass.set_bind_group(2, params, &[]);
+                    rpass.draw(0..6, 0..1); // Vertices are generated in the shader.
+                }
+            }
+
+            // Draw gaussians.
+            let pipeline_gauss = if self.scene.gaussians.is_empty() {
+                None
+            } else {
+                let blend = self.scene.gaussian_settings.blend_mode;
+                let pipeline = self.pipeline_cache.get(PipelineKey::new(
+                    PipelineKind::Gauss(blend),
+                    self.msaa_samples,
+                ));
+                self.redraw_pending |= pipeline.is_none();
+                pipeline
+            };
+            if let Some(pipeline) = pipeline_gauss {
+                rpass.set_pipeline(&pipeline);
+
+                rpass.set_bind_group(0, cam_gauss, &[]);
+                rpass.set_bind_group(1, &self.gauss_display.bind_group, &[]);
+
+                rpass.set_vertex_buffer(0, self.vertex_buf_quad.slice(..));
+                rpass.set_vertex_buffer(1, self.instance_buf_gauss.buf.slice(..)); // stride = 32 B
+
+                rpass.draw(0..6, 0..self.scene.gaussians.len() as _); // 6 indices for the quad
+            }
+
+            // Lines. Skipped until the pipeline is ready.
+            if !self.lines.is_empty() {
+                let pipeline = self
+                    .pipeline_cache
+                    .get(PipelineKey::new(PipelineKind::Lines, self.msaa_samples));
+                self.redraw_pending |= pipeline.is_none();
+
+                if let Some(pipeline) = pipeline {
+                    rpass.set_pipeline(&pipeline);
+                    rpass.set_bind_group(0, cam, &[]);
+                    self.lines.draw(&mut rpass);
+                }
+            }
+
+            // Debug lines, e.g. bounding boxes and normals. Skipped until the pipeline is ready.
+            if self.debug_lines.settings.any() {
+                let pipeline = self.pipeline_cache.get(PipelineKey::new(
+                    PipelineKind::DebugLines,
+                    self.msaa_samples,
+                ));
+                self.redraw_pending |= pipeline.is_none();
+
+                if let Some(pipeline) = pipeline {
+                    rpass.set_pipeline(&pipeline);
+                    rpass.set_bind_group(0, cam, &[]);
+                    self.debug_lines.draw(&mut rpass);
+                }
+            }
+        }
+
+        // Apply the calculated viewport
+        rpass.set_viewport(x, y, eff_width, eff_height, 0., 1.);
+
+        rpass
+    }
+
+    /// Draw each mesh that has instances, using the vertex, index, and instance buffers already
+    /// bound to the pass. `indirect` must be the indirect buffer built from `mappings`.
+    ///
+    /// If `textured`, binds each mesh's texture at group 2. Textured meshes are drawn one at a
+    /// time, since the texture changes between draws, as are meshes when drawing a subset.
+    fn draw_meshes(
+        &self,
+        rpass: &mut RenderPass,
+        mappings: &[(i32, u32, u32)],
+        indirect: &GrowableBuffer,
+        textured: bool,
+        subset: MeshSubset,
+    ) {
+        let per_mesh_textures = textured && self.scene.meshes.iter().any(|m| m.texture.is_some());
+
+        if self.multi_draw_indirect && !per_mesh_textures && subset == MeshSubset::All {
+            // The arguments are ordered by index format; see `update_indirect_bufs`.
+            let count = indirect.len() / size_of::<DrawIndexedIndirectArgs>();
+            let count_u32 = self
+                .mesh_ranges
+                .iter()
+                .zip(mappings)
+                .filter(|(r, m)| m.2 > 0 && r.index_format == IndexFormat::Uint32)
+                .count();
+
+            if count_u32 > 0 {
+                rpass.set_index_buffer(self.index_buf.buf.slice(..), IndexFormat::Uint32);
+                rpass.multi_draw_indexed_indirect(&indirect.buf, 0, count_u32 as u32);
+            }
+            if count > count_u32 {
+                rpass.set_index_buffer(self.index_buf_u16.buf.slice(..), IndexFormat::Uint16);
+                rpass.multi_draw_indexed_indirect(
+                    &indirect.buf,
+                    (count_u32 * size_of::<DrawIndexedIndirectArgs>()) as u64,
+                    (count - count_u32) as u32,
                 );
-                start_ind += mesh.indices.len() as u32;
             }
+            return;
         }
 
-        // Make a render pass for opaque meshes, and transparent ones. We separate them to only
-        // back-cull opaque ones.
-        // We draw transparent meshes in two passes, for proper surface culling.
-        for (inst_buf, pipeline, mappings) in [
-            (&self.instance_buf, &self.pipeline_mesh, &self.mesh_mappings),
-            // The order might matter here, i.e. running the back transparent pipeline before
-            // the front transparent one.
-            (
-                &self.instance_buf_transparent,
-                &self.pipeline_mesh_transparent_back,
-                &self.mesh_mappings_transparent,
-            ),
-            (
-                &self.instance_buf_transparent,
-                &self.pipeline_mesh_transparent,
-                &self.mesh_mappings_transparent,
-            ),
-        ]
-        .into_iter()
+        let mut bound_format = None;
+
+        for (i, (range, &(vertex_start, instance_start, instance_count))) in
+            self.mesh_ranges.iter().zip(mappings).enumerate()
         {
-            if inst_buf.size() == 0 {
+            if instance_count == 0 {
                 continue;
             }
 
-            rpass.set_pipeline(pipeline);
-            rpass.set_bind_group(0, &self.bind_groups.cam, &[]);
-            rpass.set_bind_group(1, &self.bind_groups.lighting, &[]);
+            let two_sided = self.scene.meshes.get(i).is_some_and(|m| m.two_sided);
+            match subset {
+                MeshSubset::OneSided if two_sided => continue,
+                MeshSubset::TwoSided if !two_sided => continue,
+                _ => (),
+            }
+
+            if per_mesh_textures {
+                let texture = self.scene.meshes.get(i).and_then(|m| m.texture);
+                rpass.set_bind_group(2, self.texture_store.bind_group(texture), &[]);
+            }
+
+            if bound_format != Some(range.index_format) {
+                let buf = match range.index_format {
+                    IndexFormat::Uint16 => &self.index_buf_u16,
+                    IndexFormat::Uint32 => &self.index_buf,
+                };
+                rpass.set_index_buffer(buf.buf.slice(..), range.index_format);
+                bound_format = Some(range.index_format);
+            }
+
+            let index_start = range.index_start as u32;
+            rpass.draw_indexed(
+                index_start..index_start + range.index_count as u32,
+                vertex_start,
+                instance_start..instance_start + instance_count,
+            );
+        }
+    }
+
+    /// Start moving the arc camera's center to `center`. Does nothing with other control schemes.
+    pub(crate) fn recenter_arc(&mut self, center: Vec3) {
+        self.inputs_commanded
+            .recenter_arc(&self.scene.input_settings, center);
+        self.redraw_pending = true;
+    }
+
+    /// The background to draw behind the scene, if it's not just the clear color. With a fixed
+    /// viewport aspect, the clear color is for the bars, so a solid background is drawn too.
+    fn drawn_background(&self) -> Option<Background> {
+        match self.scene.background {
+            Background::Solid if self.scene.viewport_aspect.is_some() => {
+                let c = self.scene.background_color;
+                Some(Background::Gradient { top: c, bottom: c })
+            }
+            Background::Solid => None,
+            b => Some(b),
+        }
+    }
+
+    /// The 3D viewport, in pixels of a `width` by `height` target: x, y, width, height. It's the
+    /// space `gui_size` leaves, letterboxed to `Scene::viewport_aspect` if set.
+    fn viewport(
+        &self,
+        gui_size: (f32, f32),
+        width: u32,
+        height: u32,
+        ui_settings: &UiSettings,
+    ) -> (f32, f32, f32, f32) {
+        letterbox(
+            viewport_rect(gui_size, width, height, ui_settings, 0.),
+            self.scene.viewport_aspect,
+        )
+    }
+
+    /// Move a finished depth readback to the scene, and report frames whose GPU times arrived.
+    /// Returns true if either readback is still in flight.
+    pub(crate) fn poll_readbacks(&mut self, device: &Device) -> bool {
+        if let Some(snapshot) = self.depth_readback.poll(device) {
+            self.scene.depth_snapshot = Some(snapshot);
+        }
+
+        let gpu_timed = self.frame_timer.gpu_timed();
+        // The time between frames doesn't reflect their cost if something else sets it.
+        let paced = matches!(
+            self.surface_cfg.present_mode,
+            wgpu::PresentMode::Fifo | wgpu::PresentMode::FifoRelaxed
+        ) || self.render_mode == RenderMode::OnDemand
+            || self.min_frame_time.is_some();
+
+        for timing in self.frame_timer.poll(device) {
+            if let Some(handler) = &self.on_frame_complete {
+                (handler.0)(&timing);
+            }
+
+            match self.quality.observe(&timing, gpu_timed, paced) {
+                // Applied to the render targets at the next frame.
+                Some(QualityChange::Scale(scale)) => self.render_scale = self.supersampling * scale,
+                // Applied by the event loop, after this frame.
+                Some(QualityChange::Msaa(samples)) => self.pending_msaa = Some(samples),
+                None => (),
+            }
+        }
+
+        self.depth_readback.in_flight() || self.frame_timer.in_flight()
+    }
 
-            rpass.set_vertex_buffer(0, self.vertex_buf.slice(..));
-            rpass.set_vertex_buffer(1, inst_buf.slice(..));
-            rpass.set_index_buffer(self.index_buf.slice(..), wgpu::IndexFormat::Uint32);
+    /// Draw the 3D scene, with its overlays and post passes, into `output_texture`. Everything but
+    /// the GUI. `gui_size` is the space the GUI reserves at the window's edges.
+    pub(crate) fn draw_scene(
+        &mut self,
+        encoder: &mut CommandEncoder,
+        output_texture: &TextureView,
+        device: &Device,
+        queue: &Queue,
+        dt: Duration,
+        width: u32,
+        height: u32,
+        ui_settings: &UiSettings,
+        gui_size: (f32, f32),
+    ) {
+        self.finish_mesh_uploads(device);
+        self.flush_instances(device);
 
-            let mut start_ind = 0;
-            for (i, mesh) in self.scene.meshes.iter().enumerate() {
-                let (vertex_start_this_mesh, instance_start_this_mesh, instance_count_this_mesh) =
-                    mappings[i];
+        self.evaluate_gauss_fields(device, encoder);
+        self.slices.prepare(device, queue, &self.scene.slice_planes);
+        if !self.scene.gaussians.is_empty() {
+            self.gauss_display
+                .prepare(queue, &self.scene.gaussian_settings);
+        }
+        if !self.lines.is_empty() {
+            // Line widths are relative to each eye's viewport, in window pixels.
+            let (_, _, vp_width, vp_height) = self.viewport(gui_size, width, height, ui_settings);
+            let eyes = self.stereo.main_pass_eyes().len() as f32;
+            let scale = self.scene_scale();
+            self.lines.prepare(
+                queue,
+                (vp_width / eyes / scale, vp_height / scale),
+                dt.as_secs_f32(),
+            );
+        }
+        if self.debug_lines.settings.any() {
+            self.debug_lines
+                .prepare(device, &mut self.uploader, &self.scene);
+        }
+        if let Some(background) = self.drawn_background() {
+            self.background
+                .prepare(queue, background, &self.scene.camera);
+        }
 
-                if instance_count_this_mesh == 0 {
-                    start_ind += mesh.indices.len() as u32;
+        // Geometry prepass: render opaque geometry into the 1-sample depth texture used
+        // by both contour lines and SSAO.
+        // These overlays are from the center camera's view, so don't line up with stereo eyes.
+        let stereo = self.stereo.settings.mode != StereoMode::Off;
+        let contours_active =
+            (self.depth_revealing > 0. || self.intersection_revealing > 0.) && !stereo;
+        let ssao_active = self.ssao_strength > 0. && !stereo;
+        let blur = self.motion_blur.strength > 0. && !stereo;
+        let prepass_active = contours_active || ssao_active;
+        // Depth readback and motion blur run it without instances too, so the depth shows an
+        // empty scene.
+        let readback = self.depth_readback.enabled;
+        let prepass_geometry = !self.instance_buf.is_empty() || !self.impostors.is_empty();
+        if (prepass_active && prepass_geometry) || readback || blur {
+            let _span = trace_span!("depth_prepass").entered();
+            let timestamps = self.frame_timer.pass("prepass");
+            let mut pre = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Contour depth prepass"),
+                color_attachments: &[],
+                depth_stencil_attachment: Some(RenderPassDepthStencilAttachment {
+                    view: &self.depth_texture_contour.view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }),
+                timestamp_writes: timestamps.as_ref().map(PassTimestamps::writes),
+                occlusion_query_set: None,
+                multiview_mask: None,
+            });
+            if !self.instance_buf.is_empty() {
+                pre.set_pipeline(&self.pipeline_contour_depth);
+                pre.set_bind_group(0, &self.bind_groups.cam, &[]);
+                pre.set_vertex_buffer(0, self.vertex_buf.buf.slice(..));
+                pre.set_vertex_buffer(1, self.instance_buf.buf.slice(..));
+                self.draw_meshes(
+                    &mut pre,
+                    &self.mesh_mappings,
+                    &self.indirect_buf,
+                    false,
+                    MeshSubset::All,
+                );
+            }
+            for (instances, kind) in [
+                (&self.impostors.spheres, PipelineKind::ImpostorSphereDepth),
+                (
+                    &self.impostors.cylinders,
+                    PipelineKind::ImpostorCylinderDepth,
+                ),
+            ] {
+                if instances.is_empty() {
                     continue;
                 }
+                let pipeline = self.pipeline_cache.get(PipelineKey::new(kind, 1));
+                self.redraw_pending |= pipeline.is_none();
 
-                rpass.draw_indexed(
-                    start_ind..start_ind + mesh.indices.len() as u32,
-                    vertex_start_this_mesh,
-                    instance_start_this_mesh..instance_start_this_mesh + instance_count_this_mesh,
-                );
+                if let Some(pipeline) = pipeline {
+                    pre.set_pipeline(&pipeline);
+                    pre.set_bind_group(0, &self.bind_groups.cam, &[]);
+                    instances.draw(&mut pre);
+                }
+            }
+            drop(pre);
+        }
 
-                start_ind += mesh.indices.len() as u32;
+        if readback {
+            let proj_view = self.scene.camera.proj_mat.clone() * self.scene.camera.view_mat();
+            if let Some(proj_view_inv) = proj_view.inverse() {
+                // Snapshots are queried in window pixels.
+                let (x, y, w, h) = self.viewport(gui_size, width, height, ui_settings);
+                let s = self.scene_scale();
+                let viewport = (x / s, y / s, w / s, h / s);
+                self.depth_readback.copy(
+                    device,
+                    encoder,
+                    &self.depth_texture_contour._texture,
+                    proj_view_inv,
+                    viewport,
+                );
             }
         }
 
-        // Draw gaussians.
-        if !self.scene.gaussians.is_empty() {
-            rpass.set_pipeline(&self.pipeline_gauss);
+        // With motion blur, the scene and its overlays render offscreen, then blur into the output.
+        let scene_view = if blur {
+            let viewport = self.viewport(gui_size, width, height, ui_settings);
+            self.motion_blur
+                .prepare(
+                    device,
+                    queue,
+                    &self.scene.camera,
+                    &self.depth_texture_contour.view,
+                    (width, height),
+                    viewport,
+                )
+                .clone()
+        } else {
+            self.motion_blur.reset();
+            output_texture.clone()
+        };
+
+        let main_span = trace_span!("main_pass").entered();
+        self.stereo
+            .prepare(queue, &self.scene.camera, self.halo_expansion);
+
+        // Planar reflection: draw the scene mirrored through the plane, for reflective entities to
+        // sample in the main pass. It's from the center camera, so is skipped in stereo.
+        let reflection_plane = self
+            .scene
+            .reflection_plane
+            .filter(|_| !stereo && self.scene.entities.iter().any(|e| e.reflectivity > 0.));
+        let viewport = self.viewport(gui_size, width, height, ui_settings);
+        if let Some(reflection_view) = self.reflection.prepare(
+            device,
+            queue,
+            &self.scene.camera,
+            reflection_plane,
+            self.halo_expansion,
+            (width, height),
+            viewport,
+        ) {
+            let _span = trace_span!("reflection_pass").entered();
+            let rpass = self.setup_render_pass(
+                encoder,
+                &reflection_view,
+                width,
+                height,
+                ui_settings,
+                gui_size,
+                0.,
+                &[Eye::Reflection],
+            );
+            drop(rpass);
+        }
+
+        let rpass = self.setup_render_pass(
+            encoder,
+            &scene_view,
+            width,
+            height,
+            ui_settings, // Pass settings
+            gui_size,    // Pass current size
+            0.,          // pixels per point. A/R.
+            self.stereo.main_pass_eyes(),
+        );
+
+        // Update aspect ratio based on the ACTUAL 3D viewport size,
+        // not the window size.
+        let (_, _, viewport_w, viewport_h) = self.viewport(gui_size, width, height, ui_settings);
+        self.scene.camera.aspect = viewport_w / viewport_h;
+        self.scene.camera.update_proj_mat();
+        self.update_camera();
 
-            rpass.set_bind_group(0, &self.bind_groups.cam_gauss, &[]);
+        drop(rpass); // End the 3D render pass (MSAA resolve happens here).
 
-            rpass.set_vertex_buffer(0, self.vertex_buf_quad.slice(..));
-            rpass.set_vertex_buffer(1, self.instance_buf_gauss.slice(..)); // stride = 32 B
+        // Anaglyph: draw the right eye separately, then combine it with the left.
+        if self.stereo.settings.mode == StereoMode::Anaglyph {
+            let right_eye = self.stereo.right_eye_view(device, width, height).clone();
+            let rpass = self.setup_render_pass(
+                encoder,
+                &right_eye,
+                width,
+                height,
+                ui_settings,
+                gui_size,
+                0.,
+                &[Eye::Right],
+            );
+            drop(rpass);
 
-            rpass.draw(0..6, 0..self.scene.gaussians.len() as _); // 6 indices for the quad
+            self.stereo.composite(encoder, &scene_view);
         }
+        drop(main_span);
 
-        // Apply the calculated viewport
-        rpass.set_viewport(x, y, eff_width, eff_height, 0., 1.);
+        // Contour overlay: alpha-blend dark lines on top of the resolved scene.
+        if contours_active {
+            let _span = trace_span!("contour_overlay").entered();
+            let timestamps = self.frame_timer.pass("contour");
+            let mut overlay = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Contour overlay"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &scene_view,
+                    depth_slice: None,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load, // preserve the rendered scene
+                        store: StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: timestamps.as_ref().map(PassTimestamps::writes),
+                occlusion_query_set: None,
+                multiview_mask: None,
+            });
+            overlay.set_pipeline(&self.pipeline_contour_overlay);
+            overlay.set_bind_group(0, &self.bind_group_contour, &[]);
+            overlay.draw(0..3, 0..1); // full-screen triangle
+            drop(overlay);
+        }
 
-        rpass
+        // SSAO overlay: darken ambient-occluded areas via multiplicative blend.
+        if ssao_active {
+            let _span = trace_span!("ssao_overlay").entered();
+            self.update_ssao_uniforms(queue);
+            let timestamps = self.frame_timer.pass("ssao");
+            let mut overlay = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("SSAO overlay"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &scene_view,
+                    depth_slice: None,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load, // preserve the rendered scene
+                        store: StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: timestamps.as_ref().map(PassTimestamps::writes),
+                occlusion_query_set: None,
+                multiview_mask: None,
+            });
+            overlay.set_pipeline(&self.pipeline_ssao);
+            overlay.set_bind_group(0, &self.bind_group_ssao, &[]);
+            overlay.draw(0..3, 0..1); // full-screen triangle
+            drop(overlay);
+        }
+
+        if blur {
+            let _span = trace_span!("motion_blur").entered();
+            let timestamps = self.frame_timer.pass("motion_blur");
+            self.motion_blur.apply(encoder, output_texture, timestamps);
+        }
+    }
+
+    /// Draw the 3D scene into `output_texture`, as `draw_scene`. If the scene renders at a
+    /// different resolution, e.g. with supersampling or adaptive quality, it's drawn offscreen at
+    /// that resolution, then resampled to `output_texture`.
+    fn draw_scene_resampled(
+        &mut self,
+        encoder: &mut CommandEncoder,
+        output_texture: &TextureView,
+        device: &Device,
+        queue: &Queue,
+        dt: Duration,
+        width: u32,
+        height: u32,
+        ui_settings: &UiSettings,
+        gui_size: (f32, f32),
+    ) {
+        let render_size = self.render_size();
+        let depth = &self.depth_texture._texture;
+        if (depth.width(), depth.height()) != render_size {
+            self.resize_render_targets(device);
+        }
+
+        let resampled = render_size != (width, height);
+        let scene_target = if resampled {
+            self.resample.target(device, render_size).clone()
+        } else {
+            self.resample.release();
+            output_texture.clone()
+        };
+
+        let scale = self.scene_scale();
+        self.draw_scene(
+            encoder,
+            &scene_target,
+            device,
+            queue,
+            dt,
+            render_size.0,
+            render_size.1,
+            ui_settings,
+            (gui_size.0 * scale, gui_size.1 * scale),
+        );
+
+        if resampled {
+            let timestamps = self.frame_timer.pass("resample");
+            self.resample.apply(encoder, output_texture, timestamps);
+        }
+    }
+
+    /// Draw a frame into `output`, without the GUI, and submit it; for `render_headless`, and
+    /// screenshots. The 3D viewport leaves room for a GUI of `gui_size`, as in the window. This
+    /// waits for pipelines compiling in the background, so nothing is skipped.
+    pub(crate) fn render_offscreen(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        output: &TextureView,
+        width: u32,
+        height: u32,
+        ui_settings: &UiSettings,
+        gui_size: (f32, f32),
+    ) {
+        self.texture_store.sync(device, queue, &self.scene.textures);
+        self.texture_store.wait_all(device, queue);
+        self.pipeline_cache.wait_all();
+        self.mesh_uploads.wait_all();
+
+        let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+            label: Some("Offscreen render encoder"),
+        });
+
+        self.draw_scene_resampled(
+            &mut encoder,
+            output,
+            device,
+            queue,
+            Duration::ZERO,
+            width,
+            height,
+            ui_settings,
+            gui_size,
+        );
+
+        let uploads = self.uploader.finish();
+        queue.submit(uploads.into_iter().chain(Some(encoder.finish())));
+        self.uploader.recall();
     }
 
     /// The entry point to 3D and GUI rendering.
@@ -1305,6 +3340,13 @@ impl GraphicsState {
         gui_handler: impl FnMut(&mut T, &mut Ui, &mut Scene) -> EngineUpdates,
         user_state: &mut T,
     ) -> bool {
+        let _span = trace_span!("frame").entered();
+
+        self.poll_readbacks(device);
+        self.frame_timer.begin();
+
+        self.texture_store.sync(device, queue, &self.scene.textures);
+
         // Track the frame rate for the optional on-screen readout, averaging over
         // a fixed window to keep the displayed value steady.
         if self.framerate_display != FramerateDisplay::Disabled {
@@ -1318,40 +3360,23 @@ impl GraphicsState {
             }
         }
 
-        // Adjust camera inputs using the in-engine control scheme.
-        // Note that camera settings adjusted by the application code are handled in
-        // `update_camera`.
-
-        if self.inputs_commanded.inputs_present() {
-            let dt_secs = dt.as_secs() as f32 + dt.subsec_micros() as f32 / 1_000_000.;
-
-            let cam_changed = match self.scene.input_settings.control_scheme {
-                ControlScheme::FreeCamera => input::adjust_camera_free(
-                    &mut self.scene.camera,
-                    &mut self.inputs_commanded,
-                    &self.scene.input_settings,
-                    dt_secs,
-                ),
-                ControlScheme::Arc { center } => input::adjust_camera_arc(
-                    &mut self.scene.camera,
-                    &mut self.inputs_commanded,
-                    &self.scene.input_settings,
-                    center,
-                    dt_secs,
-                ),
-                ControlScheme::None => false,
-                ControlScheme::Fps => unimplemented!(),
-            };
-
-            if cam_changed {
-                self.update_camera(queue);
-            }
+        // This is cheap, except for the allocator report, so we update it on the same interval.
+        self.memory_usage_timer += dt.as_secs_f32();
+        if self.memory_usage_timer >= FPS_UPDATE_INTERVAL {
+            self.scene.memory_usage = self.memory_usage(device);
+            self.memory_usage_timer = 0.;
+        }
 
-            // Reset the mouse inputs; keyboard inputs are reset by their release event.
-            self.inputs_commanded.mouse_delta_x = 0.;
-            self.inputs_commanded.mouse_delta_y = 0.;
+        #[cfg(feature = "hot_reload")]
+        if let Some((_, shown)) = &mut self.shader_error {
+            *shown += dt.as_secs_f32();
+            if *shown >= SHADER_ERROR_DURATION {
+                self.shader_error = None;
+            }
         }
 
+        self.step(device, dt);
+
         // We create a CommandEncoder to create the actual commands to send to the
         // gpu. Most modern graphics frameworks expect commands to be stored in a command buffer
         // before being sent to the gpu. The encoder builds a command buffer that we can then
@@ -1362,6 +3387,7 @@ impl GraphicsState {
 
         let mut updates_gui = Default::default();
 
+        let gui_span = trace_span!("gui").entered();
         let (gui_full_output, tris, screen_descriptor, resize_required) = gui.render_gui_pre_rpass(
             self,
             user_state,
@@ -1377,137 +3403,42 @@ impl GraphicsState {
         // Draw text on the screen.
         draw_text_overlay(self, gui, ui_settings, width, height);
         draw_framerate(self, gui, ui_settings, width, height);
+        draw_upload_progress(self, gui, ui_settings, width, height);
+        #[cfg(feature = "hot_reload")]
+        draw_shader_error(self, gui, ui_settings, width, height);
 
         // Note: If we process engine updates after setting up the render pass, we will not be
         // able to add meshes at runtime; code run from the `engine_updates.meshes` flag must be
         // done along with a mesh change prior to setting up the render pass, or else we will get
         // an error about an index being out of bounds.
         process_engine_updates(&updates_gui, self, device, queue);
+        drop(gui_span);
 
-        // Geometry prepass: render opaque geometry into the 1-sample depth texture used
-        // by both contour lines and SSAO.
-        let contours_active = self.depth_revealing > 0. || self.intersection_revealing > 0.;
-        let ssao_active = self.ssao_strength > 0.;
-        let prepass_active = contours_active || ssao_active;
-        if prepass_active && self.instance_buf.size() > 0 {
-            let mut pre = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("Contour depth prepass"),
-                color_attachments: &[],
-                depth_stencil_attachment: Some(RenderPassDepthStencilAttachment {
-                    view: &self.depth_texture_contour.view,
-                    depth_ops: Some(wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(1.0),
-                        store: StoreOp::Store,
-                    }),
-                    stencil_ops: None,
-                }),
-                timestamp_writes: None,
-                occlusion_query_set: None,
-                multiview_mask: None,
-            });
-            pre.set_pipeline(&self.pipeline_contour_depth);
-            pre.set_bind_group(0, &self.bind_groups.cam, &[]);
-            pre.set_vertex_buffer(0, self.vertex_buf.slice(..));
-            pre.set_vertex_buffer(1, self.instance_buf.slice(..));
-            pre.set_index_buffer(self.index_buf.slice(..), wgpu::IndexFormat::Uint32);
-            let mut start_ind = 0u32;
-            for (i, mesh) in self.scene.meshes.iter().enumerate() {
-                let (vertex_start, instance_start, instance_count) = self.mesh_mappings[i];
-                if instance_count == 0 {
-                    start_ind += mesh.indices.len() as u32;
-                    continue;
-                }
-                pre.draw_indexed(
-                    start_ind..start_ind + mesh.indices.len() as u32,
-                    vertex_start,
-                    instance_start..instance_start + instance_count,
-                );
-                start_ind += mesh.indices.len() as u32;
-            }
-            drop(pre);
-        }
+        // The GUI reports its size in points; the 3D viewport is laid out in physical pixels.
+        let pixels_per_point = self.window().scale_factor() as f32;
+        let gui_size = (gui.size.0 * pixels_per_point, gui.size.1 * pixels_per_point);
 
-        let rpass = self.setup_render_pass(
+        self.scene.pixels_per_point = pixels_per_point;
+        self.scene.viewport_rect = Some(self.viewport(gui_size, width, height, ui_settings));
+
+        self.draw_scene_resampled(
             &mut encoder,
             output_texture,
+            device,
+            queue,
+            dt,
             width,
             height,
-            ui_settings, // Pass settings
-            gui.size,    // Pass current size
-            0.,          // pixels per point. A/R.
+            ui_settings,
+            gui_size,
         );
 
-        // Update aspect ratio based on the ACTUAL 3D viewport size,
-        // not the window size.
-        // We have to calculate the effective size locally here again, or return it
-        // from setup_render_pass. Calculating it simply here:
-        let mut viewport_w = width as f32;
-        let mut viewport_h = height as f32;
-
-        viewport_w -= gui.size.0;
-        viewport_h -= gui.size.1;
-
-        if viewport_w > 0.0 && viewport_h > 0.0 {
-            self.scene.camera.aspect = viewport_w / viewport_h;
-            self.scene.camera.update_proj_mat();
-            self.update_camera(queue);
-        }
-
-        drop(rpass); // End the 3D render pass (MSAA resolve happens here).
-
-        // Contour overlay: alpha-blend dark lines on top of the resolved scene.
-        if contours_active {
-            let mut overlay = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("Contour overlay"),
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: output_texture,
-                    depth_slice: None,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Load, // preserve the rendered scene
-                        store: StoreOp::Store,
-                    },
-                })],
-                depth_stencil_attachment: None,
-                timestamp_writes: None,
-                occlusion_query_set: None,
-                multiview_mask: None,
-            });
-            overlay.set_pipeline(&self.pipeline_contour_overlay);
-            overlay.set_bind_group(0, &self.bind_group_contour, &[]);
-            overlay.draw(0..3, 0..1); // full-screen triangle
-            drop(overlay);
-        }
-
-        // SSAO overlay: darken ambient-occluded areas via multiplicative blend.
-        if ssao_active {
-            self.update_ssao_uniforms(queue);
-            let mut overlay = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("SSAO overlay"),
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: output_texture,
-                    depth_slice: None,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Load, // preserve the rendered scene
-                        store: StoreOp::Store,
-                    },
-                })],
-                depth_stencil_attachment: None,
-                timestamp_writes: None,
-                occlusion_query_set: None,
-                multiview_mask: None,
-            });
-            overlay.set_pipeline(&self.pipeline_ssao);
-            overlay.set_bind_group(0, &self.bind_group_ssao, &[]);
-            overlay.draw(0..3, 0..1); // full-screen triangle
-            drop(overlay);
-        }
-
         // Egui pass – runs after all overlays so scene effects never paint over
         // the UI.  Always 1× MSAA so it never needs to be recreated when the
         // 3D MSAA level changes.
         {
+            let _span = trace_span!("egui_pass").entered();
+            let timestamps = self.frame_timer.pass("gui");
             let mut egui_pass = encoder
                 .begin_render_pass(&wgpu::RenderPassDescriptor {
                     label: Some("egui render pass"),
@@ -1521,7 +3452,7 @@ impl GraphicsState {
                         },
                     })],
                     depth_stencil_attachment: None,
-                    timestamp_writes: None,
+                    timestamp_writes: timestamps.as_ref().map(PassTimestamps::writes),
                     occlusion_query_set: None,
                     multiview_mask: None,
                 })
@@ -1534,7 +3465,16 @@ impl GraphicsState {
             gui.egui_renderer.free_texture(x)
         }
 
-        queue.submit(Some(encoder.finish()));
+        let _span = trace_span!("submit").entered();
+
+        self.frame_timer.end(&mut encoder);
+
+        // Staged uploads are submitted first, so this frame's draws see them.
+        let uploads = self.uploader.finish();
+        queue.submit(uploads.into_iter().chain(Some(encoder.finish())));
+        self.uploader.recall();
+        self.depth_readback.map();
+        self.frame_timer.map();
 
         surface_texture.present();
 
@@ -1555,6 +3495,35 @@ fn create_render_pipeline(
     blend: Option<BlendState>,
     cull_mode: Option<Face>,
     label: &str,
+) -> RenderPipeline {
+    create_render_pipeline_fs(
+        device,
+        layout,
+        shader,
+        "fs_main",
+        config,
+        sample_count,
+        vertex_buffers,
+        depth_stencil,
+        blend,
+        cull_mode,
+        label,
+    )
+}
+
+/// As `create_render_pipeline`, with a fragment entry point other than `fs_main`.
+fn create_render_pipeline_fs(
+    device: &Device,
+    layout: &wgpu::PipelineLayout,
+    shader: wgpu::ShaderModule,
+    fs_entry_point: &str,
+    config: &SurfaceConfiguration,
+    sample_count: u32,
+    vertex_buffers: &'static [VertexBufferLayout<'static>],
+    depth_stencil: Option<DepthStencilState>,
+    blend: Option<BlendState>,
+    cull_mode: Option<Face>,
+    label: &str,
 ) -> RenderPipeline {
     device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
         label: Some(label),
@@ -1568,7 +3537,7 @@ fn create_render_pipeline(
         },
         fragment: Some(FragmentState {
             module: &shader,
-            entry_point: Some("fs_main"),
+            entry_point: Some(fs_entry_point),
             compilation_options: Default::default(),
             // This configures with alpha blending. (?)
             targets: &[Some(wgpu::ColorTargetState {
@@ -1600,11 +3569,510 @@ fn create_render_pipeline(
     })
 }
 
+/// Slice planes are depth-tested against meshes, but don't occlude each other, or gaussians.
+fn depth_stencil_slice() -> DepthStencilState {
+    DepthStencilState {
+        format: DEPTH_FORMAT,
+        depth_write_enabled: Some(false),
+        depth_compare: Some(wgpu::CompareFunction::Less),
+        stencil: wgpu::StencilState::default(),
+        bias: wgpu::DepthBiasState::default(),
+    }
+}
+
+/// Compile the gaussian pipelines, one per `GaussianBlendMode`, on a background thread. They test
+/// depth against meshes, but don't write it; writing it makes overlapping gaussians pop in and out.
+fn request_gauss_pipelines(
+    cache: &mut PipelineCache,
+    device: &Device,
+    bind_group_layouts: &[&BindGroupLayout; 2],
+    shader: wgpu::ShaderModule,
+    config: SurfaceConfiguration,
+    sample_count: u32,
+) {
+    let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Gaussian pipeline layout"),
+        bind_group_layouts: &[Some(bind_group_layouts[0]), Some(bind_group_layouts[1])],
+        immediate_size: 0,
+    });
+
+    for mode in [
+        GaussianBlendMode::Alpha,
+        GaussianBlendMode::Additive,
+        GaussianBlendMode::Max,
+    ] {
+        let device = device.clone();
+        let layout = layout.clone();
+        let shader = shader.clone();
+        let config = config.clone();
+
+        cache.request(
+            PipelineKey::new(PipelineKind::Gauss(mode), sample_count),
+            move || {
+                let depth_stencil = DepthStencilState {
+                    format: DEPTH_FORMAT,
+                    depth_write_enabled: Some(false),
+                    depth_compare: Some(wgpu::CompareFunction::Less),
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState::default(),
+                };
+
+                create_render_pipeline(
+                    &device,
+                    &layout,
+                    shader,
+                    &config,
+                    sample_count,
+                    &[QUAD_VERTEX_LAYOUT, GAUSS_INST_LAYOUT],
+                    Some(depth_stencil),
+                    Some(mode.blend_state()),
+                    None,
+                    "Render pipeline gaussian",
+                )
+            },
+        );
+    }
+}
+
+/// Compile the background pipeline on a background thread. It's drawn first, and neither tests nor
+/// writes depth.
+fn request_background_pipeline(
+    cache: &mut PipelineCache,
+    device: &Device,
+    bind_group_layout: &BindGroupLayout,
+    shader: wgpu::ShaderModule,
+    config: SurfaceConfiguration,
+    sample_count: u32,
+) {
+    let device = device.clone();
+    let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Background pipeline layout"),
+        bind_group_layouts: &[Some(bind_group_layout)],
+        immediate_size: 0,
+    });
+
+    cache.request(
+        PipelineKey::new(PipelineKind::Background, sample_count),
+        move || {
+            create_render_pipeline(
+                &device,
+                &layout,
+                shader,
+                &config,
+                sample_count,
+                &[],
+                Some(DepthStencilState {
+                    format: DEPTH_FORMAT,
+                    depth_write_enabled: Some(false),
+                    depth_compare: Some(wgpu::CompareFunction::Always),
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState::default(),
+                }),
+                None,
+                None,
+                "Render pipeline background",
+            )
+        },
+    );
+}
+
+/// Compile the debug line pipeline on a background thread. Lines are tested against the scene's
+/// depth, but don't write it.
+fn request_debug_lines_pipeline(
+    cache: &mut PipelineCache,
+    device: &Device,
+    bind_group_layout: &BindGroupLayout,
+    format: wgpu::TextureFormat,
+    sample_count: u32,
+) {
+    let device = device.clone();
+    let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Debug line pipeline layout"),
+        bind_group_layouts: &[Some(bind_group_layout)],
+        immediate_size: 0,
+    });
+
+    cache.request(
+        PipelineKey::new(PipelineKind::DebugLines, sample_count),
+        move || {
+            let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("Debug line shader"),
+                source: wgpu::ShaderSource::Wgsl(include_str!("shader_debug_lines.wgsl").into()),
+            });
+
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("Render pipeline debug lines"),
+                layout: Some(&layout),
+                vertex: VertexState {
+                    module: &shader,
+                    entry_point: Some("vs_main"),
+                    compilation_options: Default::default(),
+                    buffers: &[LINE_VERTEX_LAYOUT],
+                },
+                fragment: Some(FragmentState {
+                    module: &shader,
+                    entry_point: Some("fs_main"),
+                    compilation_options: Default::default(),
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format,
+                        blend: None,
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::LineList,
+                    ..Default::default()
+                },
+                depth_stencil: Some(DepthStencilState {
+                    format: DEPTH_FORMAT,
+                    depth_write_enabled: Some(false),
+                    depth_compare: Some(wgpu::CompareFunction::LessEqual),
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState::default(),
+                }),
+                multisample: wgpu::MultisampleState {
+                    count: sample_count,
+                    ..Default::default()
+                },
+                multiview_mask: None,
+                cache: None,
+            })
+        },
+    );
+}
+
+/// Compile the pipelines for `Scene::spheres` and `Scene::cylinders` on background threads: for
+/// the main pass, and depth-only, for the 1-sample prepass.
+fn request_impostor_pipelines(
+    cache: &mut PipelineCache,
+    device: &Device,
+    bind_groups: &BindGroupData,
+    format: wgpu::TextureFormat,
+    sample_count: u32,
+) {
+    let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Impostor pipeline layout"),
+        bind_group_layouts: &[
+            Some(&bind_groups.layout_cam),
+            Some(&bind_groups.layout_lighting),
+        ],
+        immediate_size: 0,
+    });
+    let layout_depth = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Impostor depth pipeline layout"),
+        bind_group_layouts: &[Some(&bind_groups.layout_cam)],
+        immediate_size: 0,
+    });
+
+    // Kind, layout, sample count, vertex and fragment entry points, and instance layout.
+    for (kind, layout, sample_count, vs, fs, instance_layout) in [
+        (
+            PipelineKind::ImpostorSphere,
+            layout.clone(),
+            sample_count,
+            "vs_sphere",
+            "fs_sphere",
+            SPHERE_INSTANCE_LAYOUT,
+        ),
+        (
+            PipelineKind::ImpostorSphereDepth,
+            layout_depth.clone(),
+            1,
+            "vs_sphere",
+            "fs_sphere_depth",
+            SPHERE_INSTANCE_LAYOUT,
+        ),
+        (
+            PipelineKind::ImpostorCylinder,
+            layout,
+            sample_count,
+            "vs_cylinder",
+            "fs_cylinder",
+            CYLINDER_INSTANCE_LAYOUT,
+        ),
+        (
+            PipelineKind::ImpostorCylinderDepth,
+            layout_depth,
+            1,
+            "vs_cylinder",
+            "fs_cylinder_depth",
+            CYLINDER_INSTANCE_LAYOUT,
+        ),
+    ] {
+        let device = device.clone();
+        let depth_only = matches!(
+            kind,
+            PipelineKind::ImpostorSphereDepth | PipelineKind::ImpostorCylinderDepth
+        );
+
+        cache.request(PipelineKey::new(kind, sample_count), move || {
+            let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("Impostor shader"),
+                source: wgpu::ShaderSource::Wgsl(include_str!("shader_impostor.wgsl").into()),
+            });
+
+            // With MSAA, edges are anti-aliased by converting the shader's coverage to samples.
+            let alpha_to_coverage = sample_count > 1;
+            let constants = [("ALPHA_TO_COVERAGE", alpha_to_coverage as u32 as f64)];
+
+            let fragment = if depth_only {
+                FragmentState {
+                    module: &shader,
+                    entry_point: Some(fs),
+                    compilation_options: Default::default(),
+                    targets: &[],
+                }
+            } else {
+                FragmentState {
+                    module: &shader,
+                    entry_point: Some(fs),
+                    compilation_options: wgpu::PipelineCompilationOptions {
+                        constants: &constants,
+                        ..Default::default()
+                    },
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format,
+                        blend: None,
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }
+            };
+
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("Render pipeline impostors"),
+                layout: Some(&layout),
+                vertex: VertexState {
+                    module: &shader,
+                    entry_point: Some(vs),
+                    compilation_options: Default::default(),
+                    buffers: &[instance_layout],
+                },
+                fragment: Some(fragment),
+                primitive: wgpu::PrimitiveState {
+                    cull_mode: None,
+                    ..Default::default()
+                },
+                depth_stencil: Some(DepthStencilState {
+                    format: DEPTH_FORMAT,
+                    depth_write_enabled: Some(true),
+                    depth_compare: Some(wgpu::CompareFunction::Less),
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState::default(),
+                }),
+                multisample: wgpu::MultisampleState {
+                    count: sample_count,
+                    alpha_to_coverage_enabled: alpha_to_coverage && !depth_only,
+                    ..Default::default()
+                },
+                multiview_mask: None,
+                cache: None,
+            })
+        });
+    }
+}
+
+/// Compile the pipeline for `Scene::lines` on a background thread.
+fn request_lines_pipeline(
+    cache: &mut PipelineCache,
+    device: &Device,
+    layout_cam: &BindGroupLayout,
+    layout_lines: &BindGroupLayout,
+    format: wgpu::TextureFormat,
+    sample_count: u32,
+) {
+    let device = device.clone();
+    let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Line pipeline layout"),
+        bind_group_layouts: &[Some(layout_cam), Some(layout_lines)],
+        immediate_size: 0,
+    });
+
+    cache.request(
+        PipelineKey::new(PipelineKind::Lines, sample_count),
+        move || {
+            let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("Line shader"),
+                source: wgpu::ShaderSource::Wgsl(include_str!("shader_lines.wgsl").into()),
+            });
+
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("Render pipeline lines"),
+                layout: Some(&layout),
+                vertex: VertexState {
+                    module: &shader,
+                    entry_point: Some("vs_main"),
+                    compilation_options: Default::default(),
+                    buffers: &[LINE_SEGMENT_LAYOUT],
+                },
+                fragment: Some(FragmentState {
+                    module: &shader,
+                    entry_point: Some("fs_main"),
+                    compilation_options: Default::default(),
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format,
+                        blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                // Quads may face either way, depending on the line's direction on screen.
+                primitive: wgpu::PrimitiveState {
+                    cull_mode: None,
+                    ..Default::default()
+                },
+                // Without depth writes, the blended edges of segments don't hide each other
+                // where they overlap, e.g. at joins.
+                depth_stencil: Some(DepthStencilState {
+                    format: DEPTH_FORMAT,
+                    depth_write_enabled: Some(false),
+                    depth_compare: Some(wgpu::CompareFunction::LessEqual),
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState::default(),
+                }),
+                multisample: wgpu::MultisampleState {
+                    count: sample_count,
+                    ..Default::default()
+                },
+                multiview_mask: None,
+                cache: None,
+            })
+        },
+    );
+}
+
+/// Compile the depth-only halo prepass pipeline on a background thread.
+fn request_halo_pipeline(
+    cache: &mut PipelineCache,
+    device: &Device,
+    layout: wgpu::PipelineLayout,
+    shader: wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
+    sample_count: u32,
+    depth_stencil: DepthStencilState,
+) {
+    let device = device.clone();
+
+    cache.request(
+        PipelineKey::new(PipelineKind::Halo, sample_count),
+        move || {
+            create_render_pipeline_depth_only(
+                &device,
+                &layout,
+                shader,
+                format,
+                sample_count,
+                &[VERTEX_LAYOUT, INSTANCE_LAYOUT],
+                depth_stencil,
+            )
+        },
+    );
+}
+
+/// Compile the pipeline for opaque two-sided meshes on a background thread. As the opaque mesh
+/// pipeline, without culling; the shader lights back faces with flipped normals.
+fn request_two_sided_pipeline(
+    cache: &mut PipelineCache,
+    device: &Device,
+    layout: wgpu::PipelineLayout,
+    shader: wgpu::ShaderModule,
+    config: SurfaceConfiguration,
+    sample_count: u32,
+    depth_stencil: DepthStencilState,
+) {
+    let device = device.clone();
+
+    cache.request(
+        PipelineKey::new(PipelineKind::MeshOpaqueTwoSided, sample_count),
+        move || {
+            create_render_pipeline(
+                &device,
+                &layout,
+                shader,
+                &config,
+                sample_count,
+                &[VERTEX_LAYOUT, INSTANCE_LAYOUT],
+                Some(depth_stencil),
+                None,
+                None,
+                "Render pipeline mesh opaque two-sided",
+            )
+        },
+    );
+}
+
+/// The mesh shader to compile from `source`: with the `xray` feature, this adds the x-ray pass's
+/// fragment shader.
+fn mesh_shader_source(source: &str) -> Cow<'_, str> {
+    if cfg!(feature = "xray") {
+        Cow::Owned(format!("{source}\n{}", include_str!("shader_xray.wgsl")))
+    } else {
+        Cow::Borrowed(source)
+    }
+}
+
+/// Compile the x-ray pipeline on a background thread. It draws only fragments behind the depth
+/// buffer, blended faintly over it, without writing depth.
+#[cfg(feature = "xray")]
+fn request_xray_pipeline(
+    cache: &mut PipelineCache,
+    device: &Device,
+    layout: wgpu::PipelineLayout,
+    shader: wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
+    sample_count: u32,
+) {
+    let device = device.clone();
+
+    cache.request(
+        PipelineKey::new(PipelineKind::XRay, sample_count),
+        move || {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("X-ray pipeline"),
+                layout: Some(&layout),
+                vertex: VertexState {
+                    module: &shader,
+                    entry_point: Some("vs_main"),
+                    compilation_options: Default::default(),
+                    buffers: &[VERTEX_LAYOUT, INSTANCE_LAYOUT],
+                },
+                fragment: Some(FragmentState {
+                    module: &shader,
+                    entry_point: Some("fs_xray"),
+                    compilation_options: Default::default(),
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format,
+                        blend: Some(BlendState::ALPHA_BLENDING),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                primitive: wgpu::PrimitiveState {
+                    cull_mode: Some(Face::Back),
+                    ..Default::default()
+                },
+                depth_stencil: Some(DepthStencilState {
+                    format: DEPTH_FORMAT,
+                    depth_write_enabled: Some(false),
+                    depth_compare: Some(wgpu::CompareFunction::Greater),
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState::default(),
+                }),
+                multisample: wgpu::MultisampleState {
+                    count: sample_count,
+                    mask: !0,
+                    alpha_to_coverage_enabled: false,
+                },
+                multiview_mask: None,
+                cache: None,
+            })
+        },
+    );
+}
+
 /// Depth-only pipeline (no fragment stage). Used for the halo prepass.
 fn create_render_pipeline_depth_only(
     device: &Device,
     layout: &wgpu::PipelineLayout,
     shader: wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
     sample_count: u32,
     vertex_buffers: &'static [VertexBufferLayout<'static>],
     depth_stencil: DepthStencilState,
@@ -1625,7 +4093,7 @@ fn create_render_pipeline_depth_only(
             entry_point: Some("fs_main"),
             compilation_options: Default::default(),
             targets: &[Some(wgpu::ColorTargetState {
-                format: COLOR_FORMAT,
+                format,
                 blend: None,
                 write_mask: wgpu::ColorWrites::empty(),
             })],
@@ -1907,9 +4375,6 @@ pub(crate) struct BindGroupData {
     pub cam_gauss: BindGroup,
     pub layout_lighting: BindGroupLayout,
     pub lighting: BindGroup,
-    /// We use this for GUI.
-    pub _layout_texture: BindGroupLayout,
-    // pub texture: BindGroup,
 }
 
 fn create_bindgroups(
@@ -2005,65 +4470,6 @@ fn create_bindgroups(
         label: Some("Lighting bind group"),
     });
 
-    // todo: Don't create these (diffuse tex view, sampler every time. Pass as args.
-    // We don't need to configure the texture view much, so let's
-    // let wgpu define it.
-    // let diffuse_bytes = include_bytes!("happy-tree.png");
-    // let diffuse_bytes = [];
-    // let diffuse_texture = wgpu::texture::Texture::from_bytes(&device, &queue, diffuse_bytes, "happy-tree.png").unwrap();
-    //
-    // let diffuse_texture_view = diffuse_texture.create_view(&wgpu::TextureViewDescriptor::default());
-    // let diffuse_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-    //     address_mode_u: wgpu::AddressMode::ClampToEdge,
-    //     address_mode_v: wgpu::AddressMode::ClampToEdge,
-    //     address_mode_w: wgpu::AddressMode::ClampToEdge,
-    //     mag_filter: wgpu::FilterMode::Linear,
-    //     min_filter: wgpu::FilterMode::Nearest,
-    //     mipmap_filter: wgpu::FilterMode::Nearest,
-    //     ..Default::default()
-    // });
-
-    let layout_texture = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-        label: Some("egui_texture_bind_group_layout"),
-        entries: &[
-            wgpu::BindGroupLayoutEntry {
-                binding: 0,
-                visibility: ShaderStages::FRAGMENT,
-                ty: BindingType::Texture {
-                    multisampled: false,
-                    view_dimension: wgpu::TextureViewDimension::D2,
-                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                },
-                count: None,
-            },
-            wgpu::BindGroupLayoutEntry {
-                binding: 1,
-                visibility: ShaderStages::FRAGMENT,
-                // This should match the filterable field of the
-                // corresponding Texture entry above.
-                ty: BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                count: None,
-            },
-        ],
-    });
-
-    // let texture = device.create_bind_group(
-    //     &wgpu::BindGroupDescriptor {
-    //         layout: &layout_texture,
-    //         entries: &[
-    //             wgpu::BindGroupEntry {
-    //                 binding: 0,
-    //                 resource: wgpu::BindingResource::TextureView(&diffuse_texture_view),
-    //                 // resource: wgpu::BindingResource::TextureView(&[]), // todo?
-    //             },
-    //             wgpu::BindGroupEntry {
-    //                 binding: 1,
-    //                 resource: wgpu::BindingResource::Sampler(&diffuse_sampler),
-    //             }
-    //         ],
-    //         label: Some("Texture bind group"),
-    //     });
-
     BindGroupData {
         layout_cam,
         cam,
@@ -2071,25 +4477,34 @@ fn create_bindgroups(
         cam_gauss,
         layout_lighting,
         lighting,
-        _layout_texture: layout_texture,
-        // texture
     }
 }
 
-/// Upload instance data, reusing the existing buffer via a queue write when the size is
-/// unchanged (the common case when entities update without being added or removed), and
-/// recreating the buffer only when the size differs.
-fn upload_instance_data(device: &Device, queue: &Queue, buf: &mut Buffer, data: &[u8], name: &str) {
-    if buf.size() == data.len() as u64 {
-        if !data.is_empty() {
-            queue.write_buffer(buf, 0, data);
-        }
-    } else {
-        *buf = device.create_buffer_init(&BufferInitDescriptor {
-            label: Some(name),
-            contents: data,
-            // COPY_DST allows us to copy updates into an existing buffer.
-            usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
-        });
+pub(crate) fn mesh_vertices_raw(mesh: &Mesh) -> Vec<VertexRaw> {
+    let mut result: Vec<_> = mesh.vertices.iter().map(|v| v.to_raw()).collect();
+    for attribute in &mesh.attributes {
+        attribute.apply(&mut result);
+    }
+    result
+}
+
+fn mesh_indices_raw(mesh: &Mesh) -> Vec<u32> {
+    mesh.indices.iter().map(|&i| i as u32).collect()
+}
+
+/// As `mesh_indices_raw`, for meshes using 16-bit indices; padded to an even count.
+fn mesh_indices_u16(mesh: &Mesh) -> Vec<u16> {
+    let mut result: Vec<u16> = mesh.indices.iter().map(|&i| i as u16).collect();
+    if result.len() % 2 == 1 {
+        result.push(0);
+    }
+    result
+}
+
+/// A mesh's indices in its index format, as bytes.
+pub(crate) fn mesh_indices_bytes(mesh: &Mesh) -> Vec<u8> {
+    match index_format(mesh) {
+        IndexFormat::Uint16 => bytemuck::cast_slice(&mesh_indices_u16(mesh)).to_vec(),
+        IndexFormat::Uint32 => bytemuck::cast_slice(&mesh_indices_raw(mesh)).to_vec(),
     }
 }
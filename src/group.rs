@@ -0,0 +1,159 @@
+//! Groups of entities sharing a transform, e.g. a molecule, or a vehicle's parts. Moving the
+//! group moves its members, without a parent-child graph, or rewriting each member's transform.
+
+use lin_alg::f32::{Quaternion, Vec3};
+
+use crate::types::Instance;
+
+/// A transform applied to each entity whose `group` is this one's index in `Scene::groups`, after
+/// the entity's own; members' transforms are relative to the group. After changing it, send
+/// `EntityUpdate::Groups` with its index to update its members in place.
+#[derive(Clone, Debug)]
+pub struct EntityGroup {
+    /// Up to the application, e.g. to find the group with `Scene::group_index`.
+    pub name: String,
+    pub position: Vec3,
+    pub orientation: Quaternion,
+    pub scale: f32,
+}
+
+impl Default for EntityGroup {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            position: Vec3::new_zero(),
+            orientation: Quaternion::new_identity(),
+            scale: 1.,
+        }
+    }
+}
+
+impl EntityGroup {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    /// Maps a point relative to the group to world space.
+    pub fn to_world(&self, point: Vec3) -> Vec3 {
+        self.position + self.orientation.rotate_vec(point * self.scale)
+    }
+
+    /// An instance of a member, with this transform applied after its own.
+    pub(crate) fn apply(&self, instance: Instance) -> Instance {
+        // Fold the pivot into the position, so the result rotates about the group's origin.
+        let position = match instance.pivot {
+            Some(p) => instance.position + p - instance.orientation.rotate_vec(p),
+            None => instance.position,
+        };
+
+        Instance {
+            position: self.to_world(position),
+            orientation: self.orientation * instance.orientation,
+            pivot: None,
+            scale: instance.scale * self.scale,
+            ..instance
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Entity;
+
+    const EPS: f32 = 1e-4;
+
+    /// `point`, transformed by a column-major model matrix.
+    fn transform(model: &[f32; 16], p: Vec3) -> Vec3 {
+        let row =
+            |r: usize| model[r] * p.x + model[4 + r] * p.y + model[8 + r] * p.z + model[12 + r];
+        Vec3::new(row(0), row(1), row(2))
+    }
+
+    fn group() -> EntityGroup {
+        EntityGroup {
+            position: Vec3::new(5., -2., 1.),
+            orientation: Quaternion::from_axis_angle(Vec3::new(0., 1., 0.), 1.1),
+            scale: 2.5,
+            ..EntityGroup::new("test")
+        }
+    }
+
+    #[test]
+    fn to_world() {
+        let g = group();
+        assert!((g.to_world(Vec3::new_zero()) - g.position).magnitude() < EPS);
+
+        // Scaled, then rotated a quarter turn about Y, then moved.
+        let g = EntityGroup {
+            orientation: Quaternion::from_axis_angle(
+                Vec3::new(0., 1., 0.),
+                std::f32::consts::TAU / 4.,
+            ),
+            ..g
+        };
+        let x = g.to_world(Vec3::new(1., 0., 0.));
+        assert!((x - (g.position + Vec3::new(0., 0., -2.5))).magnitude() < EPS);
+        let y = g.to_world(Vec3::new(0., 1., 0.));
+        assert!((y - (g.position + Vec3::new(0., 2.5, 0.))).magnitude() < EPS);
+    }
+
+    #[test]
+    fn apply_composes_transforms() {
+        let g = group();
+        let points = [
+            Vec3::new_zero(),
+            Vec3::new(1., 0., 0.),
+            Vec3::new(0., 1., 0.),
+            Vec3::new(0.3, -0.7, 2.),
+        ];
+
+        let mut entity = Entity::new(
+            0,
+            Vec3::new(1., 2., 3.),
+            Quaternion::from_axis_angle(Vec3::new(1., 0., 1.).to_normalized(), 0.7),
+            1.,
+            (1., 1., 1.),
+            0.,
+        );
+        entity.scale_partial = Some(Vec3::new(0.5, 2., 1.5));
+
+        for pivot in [None, Some(Vec3::new(0.5, -1., 0.2))] {
+            entity.pivot = pivot;
+            let instance = Instance::from(&entity);
+            let own = instance.to_raw().model;
+            let grouped = g.apply(instance).to_raw().model;
+
+            // The same as the member's own transform, followed by the group's.
+            for p in points {
+                let expected = g.to_world(transform(&own, p));
+                assert!(
+                    (transform(&grouped, p) - expected).magnitude() < EPS,
+                    "{pivot:?}, {p:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn default_group_is_identity() {
+        let entity = Entity::new(
+            0,
+            Vec3::new(1., 2., 3.),
+            Quaternion::from_axis_angle(Vec3::new(0., 0., 1.), 0.4),
+            1.5,
+            (1., 1., 1.),
+            0.,
+        );
+        let instance = Instance::from(&entity);
+        let own = instance.to_raw().model;
+        let grouped = EntityGroup::default().apply(instance).to_raw().model;
+
+        for (a, b) in own.iter().zip(&grouped) {
+            assert!((a - b).abs() < EPS);
+        }
+    }
+}
@@ -1,17 +1,20 @@
 //! GUI code for EGUI, to run on the WGPU painter.
 //! See [this unofficial example](https://github.com/kaphula/winit-egui-wgpu-template/tree/master/src)
 //! https://github.com/rust-windowing/winit/issues/3626
+//!
+//! Also contains widgets for use in the application's GUI handler.
 
 use std::sync::Arc;
 
-use egui::{ClippedPrimitive, Context, FullOutput, Ui};
+use egui::{ClippedPrimitive, Context, FullOutput, Slider, Ui};
 use egui_wgpu::{Renderer, RendererOptions, ScreenDescriptor};
 use wgpu::{self, CommandEncoder, Device, Queue, TextureFormat};
 use winit::window::Window;
 
 use crate::{
+    camera::{Camera, HeightFog},
     graphics::GraphicsState,
-    types::{EngineUpdates, Scene},
+    types::{EngineUpdates, GraphicsSettings, Scene},
 };
 
 /// State related to the GUI.
@@ -80,7 +83,7 @@ impl GuiState {
     ) -> (FullOutput, Vec<ClippedPrimitive>, ScreenDescriptor, bool) {
         let screen_descriptor = ScreenDescriptor {
             size_in_pixels: [width, height],
-            pixels_per_point: graphics.window.scale_factor() as f32,
+            pixels_per_point: graphics.window().scale_factor() as f32,
         };
 
         self.egui_state
@@ -89,7 +92,7 @@ impl GuiState {
 
         let mut resize_required = false;
 
-        let raw_input = self.egui_state.take_egui_input(&graphics.window);
+        let raw_input = self.egui_state.take_egui_input(graphics.window());
 
         // let full_output = self.egui_state.egui_ctx().run(raw_input, |ctx| {
         let mut full_output = self.egui_state.egui_ctx().run_ui(raw_input, |ui| {
@@ -110,7 +113,7 @@ impl GuiState {
         // Take these fields rather than cloning them; the caller only needs
         // `textures_delta` from the returned FullOutput.
         self.egui_state.handle_platform_output(
-            &graphics.window,
+            graphics.window(),
             std::mem::take(&mut full_output.platform_output),
         );
 
@@ -130,3 +133,80 @@ impl GuiState {
         (full_output, tris, screen_descriptor, resize_required)
     }
 }
+
+/// Controls for the camera's fog, including height fog, e.g. for a settings panel. Returns true if anything changed;
+/// set `EngineUpdates::fog` then. Fog is off while `fog_end` is at or before `fog_start`.
+pub fn fog_controls(ui: &mut Ui, camera: &mut Camera) -> bool {
+    let mut changed = false;
+
+    ui.horizontal(|ui| {
+        ui.label("Fog color:");
+        // `fog_color` is sRGB, as is this picker.
+        let mut color = camera
+            .fog_color
+            .map(|c| (c.clamp(0., 1.) * 255.).round() as u8);
+        if ui.color_edit_button_srgb(&mut color).changed() {
+            camera.fog_color = color.map(|c| c as f32 / 255.);
+            changed = true;
+        }
+    });
+
+    let max_dist = camera.far;
+    changed |= ui
+        .add(Slider::new(&mut camera.fog_start, 0.0..=max_dist).text("Fog start"))
+        .changed();
+    changed |= ui
+        .add(Slider::new(&mut camera.fog_end, 0.0..=max_dist).text("Fog end"))
+        .changed();
+    changed |= ui
+        .add(Slider::new(&mut camera.fog_density, 0.0..=4.).text("Fog density"))
+        .changed();
+    changed |= ui
+        .add(Slider::new(&mut camera.fog_power, 1.0..=16.).text("Fog power"))
+        .changed();
+
+    let mut height_fog = camera.height_fog.is_some();
+    if ui.checkbox(&mut height_fog, "Height fog").changed() {
+        camera.height_fog = height_fog.then(HeightFog::default);
+        changed = true;
+    }
+
+    if let Some(fog) = &mut camera.height_fog {
+        changed |= ui
+            .add(Slider::new(&mut fog.base, -max_dist..=max_dist).text("Base height"))
+            .changed();
+        changed |= ui
+            .add(
+                Slider::new(&mut fog.falloff, 0.01..=4.)
+                    .logarithmic(true)
+                    .text("Falloff"),
+            )
+            .changed();
+        changed |= ui
+            .add(
+                Slider::new(&mut fog.density, 0.001..=1.)
+                    .logarithmic(true)
+                    .text("Density"),
+            )
+            .changed();
+    }
+
+    changed
+}
+
+/// Sliders for `GraphicsSettings::exposure` and `gamma`. Returns true if either changed; pass the
+/// settings in `EngineUpdates::graphics_settings` then.
+pub fn exposure_controls(ui: &mut Ui, settings: &mut GraphicsSettings) -> bool {
+    let mut changed = ui
+        .add(
+            Slider::new(&mut settings.exposure, 0.1..=10.)
+                .logarithmic(true)
+                .text("Exposure"),
+        )
+        .changed();
+    changed |= ui
+        .add(Slider::new(&mut settings.gamma, 0.2..=3.).text("Gamma"))
+        .changed();
+
+    changed
+}
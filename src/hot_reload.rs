@@ -0,0 +1,128 @@
+//! Watches shader sources in this crate's `src` directory, so changes to them can be tried without
+//! recompiling the application. Enabled with the `hot_reload` feature.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver},
+};
+
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+
+use crate::{gauss::GaussianBlendMode, pipelines::PipelineKind};
+
+/// How long a shader compile error stays on screen, in seconds.
+pub(crate) const SHADER_ERROR_DURATION: f32 = 10.;
+
+/// Added to the mesh shader with the `xray` feature.
+const XRAY_FILE: &str = "shader_xray.wgsl";
+
+/// A shader we reload when its source changes.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub(crate) enum ShaderFile {
+    Mesh,
+    Gauss,
+    Slice,
+    Background,
+}
+
+impl ShaderFile {
+    const ALL: [Self; 4] = [Self::Mesh, Self::Gauss, Self::Slice, Self::Background];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Mesh => "shader.wgsl",
+            Self::Gauss => "shader_gauss.wgsl",
+            Self::Slice => "shader_slice.wgsl",
+            Self::Background => "shader_background.wgsl",
+        }
+    }
+
+    pub fn path(self) -> PathBuf {
+        src_dir().join(self.name())
+    }
+
+    /// The shader's current source. With the `xray` feature, the mesh shader includes
+    /// `shader_xray.wgsl`.
+    pub fn source(self) -> io::Result<String> {
+        let mut result = fs::read_to_string(self.path())?;
+        if self == Self::Mesh && cfg!(feature = "xray") {
+            result.push('\n');
+            result.push_str(&fs::read_to_string(src_dir().join(XRAY_FILE))?);
+        }
+        Ok(result)
+    }
+
+    /// Pipelines built from this shader.
+    pub fn pipelines(self) -> &'static [PipelineKind] {
+        match self {
+            Self::Mesh => &[
+                PipelineKind::MeshOpaque,
+                PipelineKind::MeshOpaqueTwoSided,
+                PipelineKind::MeshTransparent,
+                PipelineKind::MeshTransparentBack,
+                PipelineKind::Halo,
+                #[cfg(feature = "xray")]
+                PipelineKind::XRay,
+            ],
+            Self::Gauss => &[
+                PipelineKind::Gauss(GaussianBlendMode::Alpha),
+                PipelineKind::Gauss(GaussianBlendMode::Additive),
+                PipelineKind::Gauss(GaussianBlendMode::Max),
+            ],
+            Self::Slice => &[PipelineKind::Slice],
+            Self::Background => &[PipelineKind::Background],
+        }
+    }
+}
+
+fn src_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("src")
+}
+
+pub(crate) struct ShaderWatcher {
+    /// Watching stops when this is dropped.
+    _watcher: RecommendedWatcher,
+    events: Receiver<notify::Result<Event>>,
+}
+
+impl ShaderWatcher {
+    pub fn new() -> notify::Result<Self> {
+        let (tx, events) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(tx)?;
+
+        // Watch the directory instead of the files; some editors save by replacing the file.
+        watcher.watch(&src_dir(), RecursiveMode::NonRecursive)?;
+
+        Ok(Self {
+            _watcher: watcher,
+            events,
+        })
+    }
+
+    /// Shaders whose source changed since the last call.
+    pub fn changed(&self) -> Vec<ShaderFile> {
+        let mut result = Vec::new();
+
+        for event in self.events.try_iter().flatten() {
+            if !(event.kind.is_modify() || event.kind.is_create()) {
+                continue;
+            }
+
+            for path in &event.paths {
+                let Some(name) = path.file_name() else {
+                    continue;
+                };
+                for file in ShaderFile::ALL {
+                    let matches =
+                        name == file.name() || (file == ShaderFile::Mesh && name == XRAY_FILE);
+                    if matches && !result.contains(&file) {
+                        result.push(file);
+                    }
+                }
+            }
+        }
+
+        result
+    }
+}
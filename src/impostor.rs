@@ -0,0 +1,259 @@
+//! Sphere and cylinder impostors: simple proxy geometry, ray-traced per fragment against the
+//! true shape, with its depth. They're perfectly smooth at any distance, and far cheaper than
+//! tessellated meshes; e.g. for the atoms and bonds of large molecules.
+
+use bytemuck::{Pod, Zeroable};
+use lin_alg::f32::Vec3;
+use wgpu::{
+    BufferUsages, Device, RenderPass, VertexAttribute, VertexBufferLayout, VertexFormat,
+    VertexStepMode,
+};
+
+use crate::{
+    buffers::{GrowableBuffer, Uploader},
+    color::linearize,
+};
+
+/// An opaque, lit sphere. Add these to `Scene::spheres`.
+#[derive(Clone, Debug)]
+pub struct SphereImpostor {
+    pub position: Vec3,
+    pub radius: f32,
+    /// sRGB.
+    pub color: (f32, f32, f32),
+    /// As `Entity::shinyness`.
+    pub shinyness: f32,
+}
+
+impl Default for SphereImpostor {
+    fn default() -> Self {
+        Self {
+            position: Vec3::new_zero(),
+            radius: 1.,
+            color: (1., 1., 1.),
+            shinyness: 0.,
+        }
+    }
+}
+
+impl SphereImpostor {
+    pub fn new(position: Vec3, radius: f32, color: (f32, f32, f32)) -> Self {
+        Self {
+            position,
+            radius,
+            color,
+            ..Default::default()
+        }
+    }
+}
+
+/// Matches `SphereIn` in `shader_impostor.wgsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+pub(crate) struct SphereInstance {
+    /// Center, and radius.
+    sphere: [f32; 4],
+    /// Linear RGB, and shinyness.
+    color: [f32; 4],
+}
+
+impl From<&SphereImpostor> for SphereInstance {
+    fn from(s: &SphereImpostor) -> Self {
+        let c = linearize(s.color);
+        Self {
+            sphere: [s.position.x, s.position.y, s.position.z, s.radius],
+            color: [c[0], c[1], c[2], s.shinyness],
+        }
+    }
+}
+
+pub(crate) const SPHERE_INSTANCE_LAYOUT: VertexBufferLayout<'static> = VertexBufferLayout {
+    array_stride: size_of::<SphereInstance>() as wgpu::BufferAddress,
+    step_mode: VertexStepMode::Instance,
+    attributes: &[
+        VertexAttribute {
+            offset: 0,
+            shader_location: 0,
+            format: VertexFormat::Float32x4,
+        },
+        VertexAttribute {
+            offset: 16,
+            shader_location: 1,
+            format: VertexFormat::Float32x4,
+        },
+    ],
+};
+
+/// A capped cylinder between two points, e.g. a bond. Add these to `Scene::cylinders`.
+#[derive(Clone, Debug)]
+pub struct CylinderImpostor {
+    pub start: Vec3,
+    pub end: Vec3,
+    pub radius: f32,
+    /// sRGB.
+    pub color: (f32, f32, f32),
+    /// If set, the half nearer `end` is this color; e.g. for a bond between differently-colored
+    /// atoms.
+    pub color_end: Option<(f32, f32, f32)>,
+    /// As `Entity::shinyness`.
+    pub shinyness: f32,
+}
+
+impl Default for CylinderImpostor {
+    fn default() -> Self {
+        Self {
+            start: Vec3::new_zero(),
+            end: Vec3::new(0., 1., 0.),
+            radius: 0.15,
+            color: (1., 1., 1.),
+            color_end: None,
+            shinyness: 0.,
+        }
+    }
+}
+
+impl CylinderImpostor {
+    pub fn new(start: Vec3, end: Vec3, radius: f32, color: (f32, f32, f32)) -> Self {
+        Self {
+            start,
+            end,
+            radius,
+            color,
+            ..Default::default()
+        }
+    }
+}
+
+/// Matches `CylinderIn` in `shader_impostor.wgsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+pub(crate) struct CylinderInstance {
+    /// Start, and radius.
+    start: [f32; 4],
+    /// End, and shinyness.
+    end: [f32; 4],
+    /// Linear RGB, of the half nearer the start. The 4th value is unused.
+    color: [f32; 4],
+    /// Linear RGB, of the half nearer the end.
+    color_end: [f32; 4],
+}
+
+impl From<&CylinderImpostor> for CylinderInstance {
+    fn from(c: &CylinderImpostor) -> Self {
+        let color = linearize(c.color);
+        let color_end = linearize(c.color_end.unwrap_or(c.color));
+        Self {
+            start: [c.start.x, c.start.y, c.start.z, c.radius],
+            end: [c.end.x, c.end.y, c.end.z, c.shinyness],
+            color: [color[0], color[1], color[2], 0.],
+            color_end: [color_end[0], color_end[1], color_end[2], 0.],
+        }
+    }
+}
+
+pub(crate) const CYLINDER_INSTANCE_LAYOUT: VertexBufferLayout<'static> = VertexBufferLayout {
+    array_stride: size_of::<CylinderInstance>() as wgpu::BufferAddress,
+    step_mode: VertexStepMode::Instance,
+    attributes: &[
+        VertexAttribute {
+            offset: 0,
+            shader_location: 0,
+            format: VertexFormat::Float32x4,
+        },
+        VertexAttribute {
+            offset: 16,
+            shader_location: 1,
+            format: VertexFormat::Float32x4,
+        },
+        VertexAttribute {
+            offset: 32,
+            shader_location: 2,
+            format: VertexFormat::Float32x4,
+        },
+        VertexAttribute {
+            offset: 48,
+            shader_location: 3,
+            format: VertexFormat::Float32x4,
+        },
+    ],
+};
+
+/// Instances of one impostor shape, and the vertices each is drawn with, generated in the shader.
+pub(crate) struct ImpostorInstances {
+    buf: GrowableBuffer,
+    count: u32,
+    vertices_per_instance: u32,
+}
+
+impl ImpostorInstances {
+    fn new(device: &Device, label: &'static str, vertices_per_instance: u32) -> Self {
+        Self {
+            buf: GrowableBuffer::new(device, label, BufferUsages::VERTEX),
+            count: 0,
+            vertices_per_instance,
+        }
+    }
+
+    fn upload<T: Pod>(&mut self, device: &Device, uploader: &mut Uploader, instances: &[T]) {
+        self.count = instances.len() as u32;
+        self.buf
+            .write(device, uploader, bytemuck::cast_slice(instances));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Draw these, with their impostor pipeline and its bind groups already set.
+    pub fn draw(&self, rpass: &mut RenderPass) {
+        if self.is_empty() {
+            return;
+        }
+        rpass.set_vertex_buffer(0, self.buf.buf.slice(..));
+        rpass.draw(0..self.vertices_per_instance, 0..self.count);
+    }
+}
+
+/// GPU state for `Scene::spheres` and `Scene::cylinders`: their instances, uploaded when they
+/// change.
+pub(crate) struct ImpostorRenderer {
+    /// Each is a quad.
+    pub spheres: ImpostorInstances,
+    /// Each is a box around the cylinder, so it's covered from any direction.
+    pub cylinders: ImpostorInstances,
+}
+
+impl ImpostorRenderer {
+    pub fn new(device: &Device) -> Self {
+        Self {
+            spheres: ImpostorInstances::new(device, "Sphere impostor buffer", 6),
+            cylinders: ImpostorInstances::new(device, "Cylinder impostor buffer", 36),
+        }
+    }
+
+    /// Upload these spheres. Run this when they change.
+    pub fn upload_spheres(
+        &mut self,
+        device: &Device,
+        uploader: &mut Uploader,
+        spheres: &[SphereImpostor],
+    ) {
+        let instances: Vec<SphereInstance> = spheres.iter().map(Into::into).collect();
+        self.spheres.upload(device, uploader, &instances);
+    }
+
+    /// Upload these cylinders. Run this when they change.
+    pub fn upload_cylinders(
+        &mut self,
+        device: &Device,
+        uploader: &mut Uploader,
+        cylinders: &[CylinderImpostor],
+    ) {
+        let instances: Vec<CylinderInstance> = cylinders.iter().map(Into::into).collect();
+        self.cylinders.upload(device, uploader, &instances);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.spheres.is_empty() && self.cylinders.is_empty()
+    }
+}
@@ -9,11 +9,27 @@ use crate::{
     ScrollBehavior,
     camera::Camera,
     graphics::{FWD_VEC, RIGHT_VEC, UP_VEC},
-    types::InputSettings,
+    types::{ControlScheme, InputSettings},
 };
 
 const EPS_MOUSE: f32 = 0.00001;
 
+/// With `InputSettings::lock_roll`, how fast existing roll is removed, in radians per second.
+pub(crate) const ROLL_LEVEL_RATE: f32 = 3.;
+/// Roll below this, in radians, counts as level.
+pub(crate) const ROLL_EPS: f32 = 1e-4;
+/// With `InputSettings::lock_roll`, pitch stops this far from looking straight up or down, as
+/// the Y component of the camera's up vector.
+const PITCH_LIMIT: f32 = 0.02;
+/// With `InputSettings::scroll_easing`, pending scroll movement or roll smaller than this is
+/// applied all at once.
+const EPS_SCROLL: f32 = 1e-4;
+/// The most frame time, in seconds, one step of scroll easing covers.
+const MAX_EASE_DT: f32 = 1. / 30.;
+/// With `InputSettings::arc_sens_reference_dist`, sensitivity doesn't drop below this fraction,
+/// so the camera can still turn near the center.
+const MIN_SENS_SCALE: f32 = 0.01;
+
 #[derive(Default, Debug)]
 pub struct InputsCommanded {
     pub fwd: bool,
@@ -34,6 +50,15 @@ pub struct InputsCommanded {
     // todo: Move this A/R. Currently use it e.g. to disable scrolling moving if cursor
     // todo is not in window,
     pub cursor_out_of_window: bool,
+    /// Re-center the arc camera on the surface under the cursor. Cleared once handled.
+    pub recenter: bool,
+    /// Scroll movement, and roll, not yet applied, with `InputSettings::scroll_easing`.
+    pub(crate) scroll_move_pending: f32,
+    pub(crate) scroll_roll_pending: f32,
+    /// The state of `InputSettings::mouse_filter`'s smoothing.
+    pub(crate) mouse_velocity: (f32, f32),
+    /// A move of the arc camera's center in progress, e.g. from `recenter`.
+    pub(crate) arc_transition: Option<ArcTransition>,
 }
 
 impl InputsCommanded {
@@ -52,6 +77,79 @@ impl InputsCommanded {
             || self.mouse_delta_y.abs() > EPS_MOUSE
             || self.scroll_up
             || self.scroll_down
+            || self.scroll_move_pending != 0.
+            || self.scroll_roll_pending != 0.
+            || self.mouse_velocity != (0., 0.)
+    }
+
+    /// Filter this frame's mouse movement, before it's applied.
+    pub(crate) fn filter_mouse(&mut self, filter: &InputFilter, dt: f32) {
+        (self.mouse_delta_x, self.mouse_delta_y) = filter.apply(
+            (self.mouse_delta_x, self.mouse_delta_y),
+            &mut self.mouse_velocity,
+            dt,
+        );
+    }
+
+    /// Start moving the arc camera's center to `center`. Does nothing with other control schemes.
+    pub(crate) fn recenter_arc(&mut self, input_settings: &InputSettings, center: Vec3) {
+        let ControlScheme::Arc { center: current } = input_settings.control_scheme else {
+            return;
+        };
+
+        self.arc_transition = Some(ArcTransition::new(
+            current,
+            center,
+            input_settings.recenter_time,
+        ));
+    }
+}
+
+/// Filtering for noisy 2D input, e.g. mouse movement on high-DPI mice and trackpads. Suitable for
+/// other axes, e.g. a gamepad stick's. The default does nothing.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct InputFilter {
+    /// Movement in a frame shorter than this, in pixels, is ignored. Longer movement is shortened
+    /// by it, so there's no jump at the edge.
+    pub dead_zone: f32,
+    /// The time constant of an exponential filter on the speed of movement, in seconds, e.g. 0.03.
+    /// Higher is smoother, but lags more. 0 is off.
+    pub smoothing: f32,
+}
+
+impl InputFilter {
+    /// Filter `delta`, the movement over a frame of `dt` seconds. `velocity` holds the smoothing
+    /// state between frames; start it at 0.
+    pub fn apply(&self, delta: (f32, f32), velocity: &mut (f32, f32), dt: f32) -> (f32, f32) {
+        let (mut x, mut y) = delta;
+
+        if self.dead_zone > 0. {
+            let len = (x * x + y * y).sqrt();
+            let scale = if len > self.dead_zone {
+                (len - self.dead_zone) / len
+            } else {
+                0.
+            };
+            x *= scale;
+            y *= scale;
+        }
+
+        if self.smoothing <= 0. || dt <= 0. {
+            *velocity = (0., 0.);
+            return (x, y);
+        }
+
+        let fraction = 1. - (-dt / self.smoothing).exp();
+        velocity.0 += (x / dt - velocity.0) * fraction;
+        velocity.1 += (y / dt - velocity.1) * fraction;
+
+        let result = (velocity.0 * dt, velocity.1 * dt);
+        // Settle, instead of decaying forever.
+        if x == 0. && y == 0. && result.0.abs() < EPS_MOUSE && result.1.abs() < EPS_MOUSE {
+            *velocity = (0., 0.);
+        }
+
+        result
     }
 }
 
@@ -175,7 +273,9 @@ fn handle_mouse_buttons(inputs: &mut InputsCommanded, button: MouseButton, state
         inputs.free_look = match state {
             ElementState::Pressed => true,
             ElementState::Released => false,
-        }
+        };
+        // Smoothed movement doesn't carry over to the next drag.
+        inputs.mouse_velocity = (0., 0.);
     }
 }
 
@@ -225,6 +325,9 @@ fn handle_physical_key(inputs: &mut InputsCommanded, code: KeyCode, state: Eleme
             KeyCode::ShiftLeft => {
                 inputs.run = true;
             }
+            KeyCode::KeyF => {
+                inputs.recenter = true;
+            }
             _ => (),
         },
         ElementState::Released => match code {
@@ -277,26 +380,38 @@ fn handle_scroll(
             rotate_amt,
         } = input_settings.scroll_behavior
         {
-            if inputs.free_look {
-                // Roll if left button down while scrolling
-                let fwd = cam.orientation.rotate_vec(FWD_VEC);
+            let roll_locked = input_settings.lock_roll
+                && matches!(input_settings.control_scheme, ControlScheme::FreeCamera);
 
+            if inputs.free_look && roll_locked {
+                // Scroll-roll is disabled; don't move instead.
+            } else if inputs.free_look {
+                // Roll if left button down while scrolling
                 let mut rot_amt = -rotate_amt * dt;
                 if inputs.scroll_down {
                     rot_amt *= -1.; // todo: Allow reversed behavior for arc cam?
                 }
 
-                *rotation = Quaternion::from_axis_angle(fwd, rot_amt);
-                *cam_rotated = true;
+                if input_settings.scroll_easing > 0. {
+                    inputs.scroll_roll_pending += rot_amt;
+                } else {
+                    let fwd = cam.orientation.rotate_vec(FWD_VEC);
+                    *rotation = Quaternion::from_axis_angle(fwd, rot_amt);
+                    *cam_rotated = true;
+                }
             } else {
                 // Otherwise, move forward and backward.
-                let mut movement = Vec3::new(0., 0., move_amt);
+                let mut movement = move_amt;
                 if inputs.scroll_up {
                     movement *= -1.;
                 }
-                *movement_vec += movement;
 
-                *cam_moved = true;
+                if input_settings.scroll_easing > 0. {
+                    inputs.scroll_move_pending += movement;
+                } else {
+                    movement_vec.z += movement;
+                    *cam_moved = true;
+                }
             }
         }
 
@@ -304,6 +419,42 @@ fn handle_scroll(
         inputs.scroll_down = false;
         inputs.scroll_up = false;
     }
+
+    // Apply part of the pending scroll each frame, approaching the target exponentially.
+    if inputs.scroll_move_pending != 0. || inputs.scroll_roll_pending != 0. {
+        let fraction = if input_settings.scroll_easing > 0. {
+            // In `RenderMode::OnDemand`, the first frame after idling may have a long `dt`; cap it
+            // so that frame doesn't cover the whole scroll at once.
+            1. - (-dt.min(MAX_EASE_DT) / input_settings.scroll_easing).exp()
+        } else {
+            1.
+        };
+
+        let movement = ease_step(&mut inputs.scroll_move_pending, fraction);
+        if movement != 0. {
+            movement_vec.z += movement;
+            *cam_moved = true;
+        }
+
+        let roll = ease_step(&mut inputs.scroll_roll_pending, fraction);
+        if roll != 0. {
+            let fwd = cam.orientation.rotate_vec(FWD_VEC);
+            *rotation = Quaternion::from_axis_angle(fwd, roll) * *rotation;
+            *cam_rotated = true;
+        }
+    }
+}
+
+/// Take `fraction` of `pending`, or all of it once it's small, and return the amount taken.
+fn ease_step(pending: &mut f32, fraction: f32) -> f32 {
+    let step = if pending.abs() < EPS_SCROLL {
+        *pending
+    } else {
+        *pending * fraction
+    };
+
+    *pending -= step;
+    step
 }
 
 /// Used internally for inputs, and externally, e.g. to command an arc rotation.
@@ -317,6 +468,52 @@ pub fn arc_rotation(cam: &mut Camera, axis: Vec3, amt: f32, center: Vec3) {
     cam.position = center - cam.orientation.rotate_vec(FWD_VEC) * dist;
 }
 
+/// A smooth move of the arc camera's center, e.g. to a picked point. The camera keeps its
+/// orientation and distance to the center, so the new center slides to where the old one was.
+#[derive(Debug)]
+pub(crate) struct ArcTransition {
+    from: Vec3,
+    to: Vec3,
+    /// In seconds.
+    elapsed: f32,
+    duration: f32,
+}
+
+impl ArcTransition {
+    pub fn new(from: Vec3, to: Vec3, duration: f32) -> Self {
+        Self {
+            from,
+            to,
+            elapsed: 0.,
+            duration,
+        }
+    }
+
+    /// Advance by `dt` seconds, moving the camera; returns the new center.
+    pub fn step(&mut self, cam: &mut Camera, center: Vec3, dt: f32) -> Vec3 {
+        self.elapsed = (self.elapsed + dt).min(self.duration);
+
+        let t = if self.duration > 0. {
+            self.elapsed / self.duration
+        } else {
+            1.
+        };
+        // Ease in and out.
+        let t = t * t * (3. - 2. * t);
+        let new_center = self.from + (self.to - self.from) * t;
+
+        // The user may zoom during the transition; keep their distance.
+        let dist = (cam.position - center).magnitude();
+        cam.position = new_center - cam.orientation.rotate_vec(FWD_VEC) * dist;
+
+        new_center
+    }
+
+    pub fn done(&self) -> bool {
+        self.elapsed >= self.duration
+    }
+}
+
 /// Adjust the camera orientation and position. Return if there was a change, so we know to update the buffer.
 /// For the free (6DOF first-person) camera.
 pub fn adjust_camera_free(
@@ -363,11 +560,13 @@ pub fn adjust_camera_free(
         cam_moved = true;
     }
 
-    if inputs.roll_cw {
+    let lock_roll = input_settings.lock_roll;
+
+    if inputs.roll_cw && !lock_roll {
         let fwd = cam.orientation.rotate_vec(FWD_VEC);
         rotation = Quaternion::from_axis_angle(fwd, -rotate_key_amt);
         cam_rotated = true;
-    } else if inputs.roll_ccw {
+    } else if inputs.roll_ccw && !lock_roll {
         let fwd = cam.orientation.rotate_vec(FWD_VEC);
         rotation = Quaternion::from_axis_angle(fwd, rotate_key_amt);
         cam_rotated = true;
@@ -377,12 +576,26 @@ pub fn adjust_camera_free(
         && (inputs.mouse_delta_x.abs() > EPS_MOUSE || inputs.mouse_delta_y.abs() > EPS_MOUSE)
     {
         let rotate_amt = input_settings.rotate_sens * dt;
-        let up = cam.orientation.rotate_vec(-UP_VEC);
+        // With roll locked, yaw about the world's up axis, so looking around doesn't roll.
+        let up = if lock_roll {
+            -UP_VEC
+        } else {
+            cam.orientation.rotate_vec(-UP_VEC)
+        };
         let right = cam.orientation.rotate_vec(-RIGHT_VEC);
 
-        rotation = Quaternion::from_axis_angle(up, -inputs.mouse_delta_x * rotate_amt)
-            * Quaternion::from_axis_angle(right, -inputs.mouse_delta_y * rotate_amt)
-            * rotation;
+        let yaw = Quaternion::from_axis_angle(up, -inputs.mouse_delta_x * rotate_amt);
+        let mut pitch = Quaternion::from_axis_angle(right, -inputs.mouse_delta_y * rotate_amt);
+
+        // Don't pitch over the vertical, which would turn the view upside down.
+        if lock_roll {
+            let pitched = pitch * cam.orientation;
+            if pitched.rotate_vec(UP_VEC).y < PITCH_LIMIT {
+                pitch = Quaternion::new_identity();
+            }
+        }
+
+        rotation = yaw * pitch * rotation;
 
         cam_rotated = true;
     }
@@ -448,7 +661,11 @@ pub fn adjust_camera_arc(
     if inputs.free_look
         && (inputs.mouse_delta_x.abs() > EPS_MOUSE || inputs.mouse_delta_y.abs() > EPS_MOUSE)
     {
-        let rotate_amt = input_settings.rotate_sens * dt;
+        let mut rotate_amt = input_settings.rotate_sens * dt;
+        if let Some(reference) = input_settings.arc_sens_reference_dist {
+            let dist = (cam.position - center).magnitude();
+            rotate_amt *= (dist / reference).max(MIN_SENS_SCALE);
+        }
         let up = cam.orientation.rotate_vec(-UP_VEC);
         let right = cam.orientation.rotate_vec(-RIGHT_VEC);
 
@@ -487,10 +704,100 @@ pub fn adjust_camera_arc(
         cam.position += cam.orientation.rotate_vec(movement_vec);
     } else if cam_moved {
         // todo: Bit odd to break this off from the above.
-        let dist = (cam.position - center).magnitude();
+        // Scroll movement, e.g. still easing in, is along the view; i.e. toward the center.
+        let dist = (cam.position - center).magnitude() - movement_vec.z;
         // Update position based on the new orientation.
         cam.position = center - cam.orientation.rotate_vec(FWD_VEC) * dist;
     }
 
+    if cam_moved || cam_rotated {
+        input_settings.arc_constraints.apply(cam, center);
+    }
+
     cam_moved || cam_rotated
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DT: f32 = 1. / 60.;
+
+    fn close(a: (f32, f32), b: (f32, f32)) -> bool {
+        (a.0 - b.0).abs() < 1e-5 && (a.1 - b.1).abs() < 1e-5
+    }
+
+    #[test]
+    fn default_filter_passes_through() {
+        let mut velocity = (0., 0.);
+        let result = InputFilter::default().apply((3., -4.), &mut velocity, DT);
+
+        assert_eq!(result, (3., -4.));
+        assert_eq!(velocity, (0., 0.));
+    }
+
+    #[test]
+    fn dead_zone() {
+        let filter = InputFilter {
+            dead_zone: 1.,
+            smoothing: 0.,
+        };
+        let mut velocity = (0., 0.);
+
+        assert_eq!(filter.apply((0.3, 0.4), &mut velocity, DT), (0., 0.));
+        assert_eq!(filter.apply((0.6, -0.8), &mut velocity, DT), (0., 0.));
+        // Longer movement is shortened by the dead zone, keeping its direction.
+        assert!(close(filter.apply((3., 4.), &mut velocity, DT), (2.4, 3.2)));
+        assert!(close(
+            filter.apply((0., -1.5), &mut velocity, DT),
+            (0., -0.5)
+        ));
+    }
+
+    #[test]
+    fn smoothing_converges_and_settles() {
+        let filter = InputFilter {
+            dead_zone: 0.,
+            smoothing: 0.03,
+        };
+        let mut velocity = (0., 0.);
+
+        // Steady movement: the output rises toward it, without overshooting.
+        let mut prev = 0.;
+        for _ in 0..60 {
+            let (x, y) = filter.apply((2., 0.), &mut velocity, DT);
+            assert!(x >= prev && x <= 2.);
+            assert_eq!(y, 0.);
+            prev = x;
+        }
+        assert!((prev - 2.).abs() < 1e-4);
+
+        // After stopping, it decays, then settles at exactly 0.
+        let mut frames = 0;
+        while velocity != (0., 0.) {
+            let (x, _) = filter.apply((0., 0.), &mut velocity, DT);
+            assert!(x < prev && x >= 0.);
+            prev = x;
+            frames += 1;
+            assert!(frames < 100);
+        }
+        assert_eq!(filter.apply((0., 0.), &mut velocity, DT), (0., 0.));
+    }
+
+    #[test]
+    fn zero_dt() {
+        let filter = InputFilter {
+            dead_zone: 1.,
+            smoothing: 0.03,
+        };
+        let mut velocity = (0., 0.);
+        filter.apply((5., 0.), &mut velocity, DT);
+
+        // Nothing to smooth over; the dead zone still applies, and the result is finite.
+        let result = filter.apply((0., 3.), &mut velocity, 0.);
+        assert!(close(result, (0., 2.)));
+        assert_eq!(velocity, (0., 0.));
+
+        assert_eq!(filter.apply((0., 0.), &mut velocity, 0.), (0., 0.));
+    }
+}
@@ -4,47 +4,128 @@
 //! A 3D rendering engine for rust programs, with GUI integration
 //!
 //! This library is a framework for building PC applications that have 3D graphics, and a GUI. It uses
-//! the [WGPU toolkit](https://wgpu.rs/) with Vulkan backend, and [EGUI](https://docs.rs/egui/latest/egui/).
+//! the [WGPU toolkit](https://wgpu.rs/), and [EGUI](https://docs.rs/egui/latest/egui/). It uses Vulkan, Metal, DX12, or
+//! WebGPU by default; set `GraphicsSettings::backends` to choose others.
 //! It works on Windows, Linux, and Mac.
 //!
 //! This is intended as a general-purpose 3D visualization tool.
 //! Example use cases including wave-function analysis, n-body simulations, and protein structure viewing.
 //! It's also been used to visualize UAS attitude in preflight software. Its goals are to be intuitive and flexible.
 
+mod adaptive_quality;
 #[cfg(feature = "app_utils")]
 pub mod app_utils;
+mod attribute;
+mod background;
+mod buffers;
 mod camera;
+mod color;
+mod color_animation;
+mod colormap;
+mod compressed_texture;
+mod debug_draw;
+mod depth_readback;
+#[cfg(feature = "ecs")]
+pub mod ecs;
+mod ellipsoid;
+mod error;
+mod frame_timing;
 mod gauss;
+mod gauss_field;
 mod graphics;
-mod gui;
+mod group;
+pub mod gui;
+#[cfg(feature = "hot_reload")]
+mod hot_reload;
+pub mod impostor;
 mod input;
-pub mod lighting;
+mod lighting;
+mod lines;
+mod material;
+mod mesh_upload;
 mod meshes;
+mod molecule;
+mod motion_blur;
+mod pipelines;
+mod proxy;
+mod reflection;
+mod resample;
+mod slice;
+#[cfg(not(target_arch = "wasm32"))]
+mod snapshot;
+mod spatial;
+mod stereo;
 mod system;
 mod text_overlay;
 mod texture;
 mod types;
+mod units;
+mod vector_field;
 mod window;
 
-pub use camera::Camera;
-pub use gauss::Gaussian;
+pub use adaptive_quality::AdaptiveQuality;
+pub use attribute::{AttributeTarget, AttributeValues, MeshAttribute};
+pub use background::Background;
+pub use camera::{Camera, HeightFog};
+pub use color::{ColorSpace, linear_to_srgb, srgb_to_linear};
+pub use color_animation::ColorAnimation;
+pub use colormap::Colormap;
+pub use debug_draw::DebugDraw;
+pub use depth_readback::DepthSnapshot;
+pub use ellipsoid::Ellipsoid;
+pub use error::{ErrorHandler, GraphicsError};
+pub use frame_timing::{FrameTiming, FrameTimingHandler};
+pub use gauss::{
+    Gaussian, GaussianBlendMode, GaussianRenderSettings, GaussianUpdate, gaussian_density_grid,
+};
 pub use graphics::{EntityUpdate, FWD_VEC, RIGHT_VEC, UP_VEC};
-pub use input::{InputsCommanded, adjust_camera_free, arc_rotation};
+pub use group::EntityGroup;
+pub use impostor::{CylinderImpostor, SphereImpostor};
+pub use input::{InputFilter, InputsCommanded, adjust_camera_free, arc_rotation};
 pub use lighting::{LightType, Lighting, PointLight};
-pub use system::run;
+pub use lines::{Dash, Line, LineCap, LineJoin};
+pub use material::Material;
+pub use mesh_upload::UploadProgress;
+pub use molecule::{
+    Atom, BallStickSettings, ball_and_stick, ball_and_stick_impostors, update_ball_and_stick,
+};
+pub use proxy::{EngineProxy, EntityDeltas, EntityFields, EventLoopClosed, SceneDelta};
+pub use slice::SlicePlane;
+#[cfg(not(target_arch = "wasm32"))]
+pub use snapshot::{
+    GoldenTolerance, HeadlessRenderer, ImageDiff, UPDATE_GOLDEN_VAR, assert_golden, diff_images,
+    render_headless,
+};
+pub use spatial::SpatialIndex;
+pub use stereo::{Stereo, StereoMode};
+#[cfg(target_arch = "wasm32")]
+pub use system::run_web;
+pub use system::{run, run_with_proxy};
 pub use text_overlay::TextOverlay;
+pub use texture::{TextureHandle, TextureSource};
 pub use types::{
-    AmbientOcclusion, ControlScheme, EngineUpdates, Entity, FramerateDisplay, GraphicsSettings,
-    InputSettings, Mesh, Scene, ScrollBehavior, UiLayoutSides, UiLayoutTopBottom, UiSettings,
-    Vertex,
+    AmbientOcclusion, ArcConstraints, BlendMode, ControlScheme, EngineUpdates, Entity, Fade,
+    FrameClock, FramerateDisplay, GraphicsSettings, InputSettings, Isolines, MemoryUsage, Mesh,
+    MeshHandle, MonitorInfo, PresentMode, RenderMode, RimLight, Scene, SceneStats, SceneStep,
+    ScrollBehavior, Shading, UiLayoutSides, UiLayoutTopBottom, UiSettings, UvTransform, Vertex,
+    WindowPlacement,
+};
+pub use units::{Units, nice_step};
+pub use vector_field::{
+    GlyphColor, GlyphSettings, StreamlineSettings, grid_points, grid_samples, streamline_mesh,
+    trace_streamline, trace_streamlines, vector_glyphs,
 };
-pub use window::viewport_rect;
+pub use window::{letterbox, viewport_rect};
+// Re-export wgpu types used in `GraphicsSettings`, so the calling lib doesn't need wgpu as a
+// dependency directly.
+pub use wgpu::{Backends, Features, Limits, PowerPreference};
 // Re-export winit DeviceEvents for use in the API; this prevents the calling
 // lib from needing to use winit as a dependency directly.
 // todo: the equiv for mouse events too. And in the future, Gamepad events.
 pub use winit::{
     self,
     event::{self, DeviceEvent, ElementState, WindowEvent},
+    window::CursorIcon,
 };
 
 // A helper macro. Not intended for use outside of this crate.
@@ -1,6 +1,7 @@
 use lin_alg::f32::Vec3;
 
 use crate::{
+    color::srgb_to_linear,
     copy_ne,
     types::{F32_SIZE, VEC3_SIZE, VEC3_UNIFORM_SIZE},
 };
@@ -10,6 +11,10 @@ use crate::{
 // The extra 12 is for padding.
 pub const LIGHTING_SIZE_FIXED: usize = VEC3_UNIFORM_SIZE + F32_SIZE + 4 + 8;
 
+// Distance attenuation terms; these match `shader.wgsl`.
+const ATTENUATION_LINEAR: f32 = 0.09;
+const ATTENUATION_QUADRATIC: f32 = 0.032;
+
 // Pad by 4 bytes to align to 16 bytes.
 pub const POINT_LIGHT_SIZE: usize = 3 * VEC3_UNIFORM_SIZE + 4 * F32_SIZE + VEC3_SIZE + 4;
 
@@ -30,10 +35,21 @@ fn array4_to_bytes(a: [f32; 4]) -> [u8; VEC3_UNIFORM_SIZE] {
     result
 }
 
+/// Light colors are sRGB, like other colors we take; the shader works in linear.
+fn linearize_color(c: [f32; 4]) -> [f32; 4] {
+    [
+        srgb_to_linear(c[0]),
+        srgb_to_linear(c[1]),
+        srgb_to_linear(c[2]),
+        c[3],
+    ]
+}
+
 #[derive(Debug, Clone)]
 /// We organize the fields in this order, and serialize them accordingly, to keep the buffer
 /// from being longer than needed, while adhering to alignment rules.
 pub struct Lighting {
+    /// sRGB.
     pub ambient_color: [f32; 4],
     pub ambient_intensity: f32,
     pub point_lights: Vec<PointLight>,
@@ -68,7 +84,7 @@ impl Lighting {
         let mut i = 0;
 
         buf_fixed_size[i..i + VEC3_UNIFORM_SIZE]
-            .clone_from_slice(&array4_to_bytes(self.ambient_color));
+            .clone_from_slice(&array4_to_bytes(linearize_color(self.ambient_color)));
         i += VEC3_UNIFORM_SIZE;
 
         copy_ne!(buf_fixed_size, self.ambient_intensity, i..i + F32_SIZE);
@@ -99,7 +115,9 @@ pub struct PointLight {
     // A point light source
     pub type_: LightType,
     pub position: Vec3,
+    /// sRGB.
     pub diffuse_color: [f32; 4],
+    /// sRGB.
     pub specular_color: [f32; 4],
     pub diffuse_intensity: f32,
     pub specular_intensity: f32,
@@ -119,6 +137,19 @@ impl Default for PointLight {
 }
 
 impl PointLight {
+    /// The distance at which this light's diffuse contribution falls to its color at unit
+    /// intensity; it lights little beyond this. Lights always reach past it, attenuating towards
+    /// 0; this is for visualizing, e.g. with `DebugDraw::light_ranges`.
+    pub fn range(&self) -> f32 {
+        // Solve `intensity / (1 + k1 d + k2 d²) = 1` for d.
+        let (k1, k2) = (ATTENUATION_LINEAR, ATTENUATION_QUADRATIC);
+        let c = 1. - self.diffuse_intensity;
+        if c >= 0. {
+            return 0.;
+        }
+        (-k1 + (k1 * k1 - 4. * k2 * c).sqrt()) / (2. * k2)
+    }
+
     /// todo: assumes point source for now; ignore type_ field.
     pub fn to_bytes(&self) -> [u8; POINT_LIGHT_SIZE] {
         let mut result = [0; POINT_LIGHT_SIZE];
@@ -129,10 +160,12 @@ impl PointLight {
         result[0..VEC3_UNIFORM_SIZE].clone_from_slice(&self.position.to_bytes_uniform());
         i += VEC3_UNIFORM_SIZE;
 
-        result[i..i + VEC3_UNIFORM_SIZE].clone_from_slice(&array4_to_bytes(self.diffuse_color));
+        result[i..i + VEC3_UNIFORM_SIZE]
+            .clone_from_slice(&array4_to_bytes(linearize_color(self.diffuse_color)));
         i += VEC3_UNIFORM_SIZE;
 
-        result[i..i + VEC3_UNIFORM_SIZE].clone_from_slice(&array4_to_bytes(self.specular_color));
+        result[i..i + VEC3_UNIFORM_SIZE]
+            .clone_from_slice(&array4_to_bytes(linearize_color(self.specular_color)));
         i += VEC3_UNIFORM_SIZE;
 
         copy_ne!(result, self.diffuse_intensity, i..i + F32_SIZE);
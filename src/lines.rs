@@ -0,0 +1,351 @@
+//! Lines of any width, for e.g. trajectories, bonds, and outlines. Each segment is drawn as a
+//! quad, expanded to its width in screen space, with anti-aliased edges; this avoids the 1-pixel
+//! limit of hardware lines.
+
+use bytemuck::{Pod, Zeroable};
+use lin_alg::f32::Vec3;
+use wgpu::{
+    BindGroup, BindGroupLayout, Buffer, BufferUsages, Device, Queue, RenderPass, ShaderStages,
+    VertexAttribute, VertexBufferLayout, VertexFormat, VertexStepMode, util::DeviceExt,
+};
+
+use crate::{
+    buffers::{GrowableBuffer, Uploader},
+    color::linearize,
+};
+
+/// The shape of a line's ends.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum LineCap {
+    /// Ends exactly at the end point.
+    Butt,
+    /// A half circle past the end point.
+    #[default]
+    Round,
+    /// Extends past the end point by half the width.
+    Square,
+}
+
+/// The shape of the corner where two segments of a line meet.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum LineJoin {
+    #[default]
+    Round,
+    /// A sharp corner, extending the segments' edges until they meet. Limited to 4 times the
+    /// width, for nearly reversing corners.
+    Miter,
+}
+
+/// A repeating pattern of dashes and gaps along a line, in world units, so it stays fixed to the
+/// line as the camera moves. Dash ends are square, regardless of the line's cap.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Dash {
+    pub length: f32,
+    pub gap: f32,
+    /// Shifts the pattern along the line, from its start.
+    pub offset: f32,
+    /// Moves the pattern along the line, in world units per second, e.g. to show direction. The
+    /// engine animates this, so there's no need to update the line.
+    pub speed: f32,
+}
+
+impl Default for Dash {
+    fn default() -> Self {
+        Self {
+            length: 0.2,
+            gap: 0.1,
+            offset: 0.,
+            speed: 0.,
+        }
+    }
+}
+
+impl Dash {
+    pub fn new(length: f32, gap: f32) -> Self {
+        Self {
+            length,
+            gap,
+            ..Default::default()
+        }
+    }
+}
+
+/// A line through a series of points, of constant width on screen. Add these to `Scene::lines`.
+///
+/// Lines are depth-tested against the rest of the scene, but not against each other: where they
+/// cross, later lines draw over earlier ones.
+#[derive(Clone, Debug)]
+pub struct Line {
+    /// World-space points the line passes through, in order. At least 2 are needed to draw.
+    pub points: Vec<Vec3>,
+    /// sRGB.
+    pub color: (f32, f32, f32),
+    pub opacity: f32,
+    /// In physical pixels. Widths below 1 are drawn 1 pixel wide, fainter.
+    pub width: f32,
+    pub cap: LineCap,
+    pub join: LineJoin,
+    /// If set, the line is drawn dashed, e.g. for hidden edges, or guides. Otherwise, solid.
+    pub dash: Option<Dash>,
+}
+
+impl Default for Line {
+    fn default() -> Self {
+        Self {
+            points: Vec::new(),
+            color: (1., 1., 1.),
+            opacity: 1.,
+            width: 2.,
+            cap: Default::default(),
+            join: Default::default(),
+            dash: None,
+        }
+    }
+}
+
+impl Line {
+    pub fn new(points: Vec<Vec3>, color: (f32, f32, f32), width: f32) -> Self {
+        Self {
+            points,
+            color,
+            width,
+            ..Default::default()
+        }
+    }
+}
+
+// Bits of `SegmentInstance::flags`. Matches `shader_lines.wgsl`.
+/// The segment's start is the line's start; otherwise, it joins the previous segment.
+const FLAG_CAP_START: u32 = 1;
+/// The segment's end is the line's end.
+const FLAG_CAP_END: u32 = 1 << 1;
+const FLAG_MITER: u32 = 1 << 2;
+/// 2 bits of `LineCap`, from this one.
+const CAP_SHIFT: u32 = 3;
+
+/// One segment of a line, with the points around it, for joins. Matches `SegmentIn` in
+/// `shader_lines.wgsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct SegmentInstance {
+    /// The point before `start`; `start` itself if there's none.
+    prev: [f32; 3],
+    start: [f32; 3],
+    end: [f32; 3],
+    /// The point after `end`; `end` itself if there's none.
+    next: [f32; 3],
+    /// Linear RGBA.
+    color: [f32; 4],
+    width: f32,
+    flags: u32,
+    /// The distance along the line to `start`, in world units.
+    dist: f32,
+    /// Length, gap, offset, and speed of the dash pattern. A gap of 0 is a solid line.
+    dash: [f32; 4],
+}
+
+pub(crate) const LINE_SEGMENT_LAYOUT: VertexBufferLayout<'static> = VertexBufferLayout {
+    array_stride: size_of::<SegmentInstance>() as wgpu::BufferAddress,
+    step_mode: VertexStepMode::Instance,
+    attributes: &[
+        VertexAttribute {
+            offset: 0,
+            shader_location: 0,
+            format: VertexFormat::Float32x3,
+        },
+        VertexAttribute {
+            offset: 12,
+            shader_location: 1,
+            format: VertexFormat::Float32x3,
+        },
+        VertexAttribute {
+            offset: 24,
+            shader_location: 2,
+            format: VertexFormat::Float32x3,
+        },
+        VertexAttribute {
+            offset: 36,
+            shader_location: 3,
+            format: VertexFormat::Float32x3,
+        },
+        VertexAttribute {
+            offset: 48,
+            shader_location: 4,
+            format: VertexFormat::Float32x4,
+        },
+        VertexAttribute {
+            offset: 64,
+            shader_location: 5,
+            format: VertexFormat::Float32,
+        },
+        VertexAttribute {
+            offset: 68,
+            shader_location: 6,
+            format: VertexFormat::Uint32,
+        },
+        VertexAttribute {
+            offset: 72,
+            shader_location: 7,
+            format: VertexFormat::Float32,
+        },
+        VertexAttribute {
+            offset: 76,
+            shader_location: 8,
+            format: VertexFormat::Float32x4,
+        },
+    ],
+};
+
+/// Matches `Lines` in `shader_lines.wgsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct LinesUniform {
+    /// The size of the viewport each eye is drawn in, in pixels.
+    viewport: [f32; 2],
+    /// Seconds since the engine started, for animating dashes.
+    time: f32,
+    _pad: f32,
+}
+
+/// GPU state for `Scene::lines`: their segments, uploaded when they change.
+pub(crate) struct LineRenderer {
+    /// Bind group 1 of the line pipeline.
+    pub layout: BindGroupLayout,
+    buf: Buffer,
+    bind_group: BindGroup,
+    segments: GrowableBuffer,
+    segment_count: u32,
+    time: f32,
+    /// Set if any line's dashes move, so frames must keep being drawn.
+    animated: bool,
+}
+
+impl LineRenderer {
+    pub fn new(device: &Device) -> Self {
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Line bind group layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::VERTEX_FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+
+        let buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Line uniform buffer"),
+            contents: bytemuck::bytes_of(&LinesUniform::zeroed()),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Line bind group"),
+            layout: &layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buf.as_entire_binding(),
+            }],
+        });
+
+        Self {
+            layout,
+            buf,
+            bind_group,
+            segments: GrowableBuffer::new(device, "Line segment buffer", BufferUsages::VERTEX),
+            segment_count: 0,
+            time: 0.,
+            animated: false,
+        }
+    }
+
+    /// Upload the segments of these lines. Run this when they change.
+    pub fn upload(&mut self, device: &Device, uploader: &mut Uploader, lines: &[Line]) {
+        let mut segments = Vec::new();
+
+        for line in lines {
+            let n = line.points.len();
+            if n < 2 {
+                continue;
+            }
+
+            let c = linearize(line.color);
+            let color = [c[0], c[1], c[2], line.opacity];
+
+            let mut flags = (line.cap as u32) << CAP_SHIFT;
+            if line.join == LineJoin::Miter {
+                flags |= FLAG_MITER;
+            }
+
+            let dash = match line.dash {
+                Some(d) => [d.length, d.gap, d.offset, d.speed],
+                None => [0.; 4],
+            };
+
+            let mut dist = 0.;
+            for i in 0..n - 1 {
+                let mut flags = flags;
+                if i == 0 {
+                    flags |= FLAG_CAP_START;
+                }
+                if i == n - 2 {
+                    flags |= FLAG_CAP_END;
+                }
+
+                segments.push(SegmentInstance {
+                    prev: line.points[i.saturating_sub(1)].to_arr(),
+                    start: line.points[i].to_arr(),
+                    end: line.points[i + 1].to_arr(),
+                    next: line.points[(i + 2).min(n - 1)].to_arr(),
+                    color,
+                    width: line.width,
+                    flags,
+                    dist,
+                    dash,
+                });
+                dist += (line.points[i + 1] - line.points[i]).magnitude();
+            }
+        }
+
+        self.segment_count = segments.len() as u32;
+        self.animated = lines.iter().any(|l| l.dash.is_some_and(|d| d.speed != 0.));
+        self.segments
+            .write(device, uploader, bytemuck::cast_slice(&segments));
+    }
+
+    /// Set the viewport size, in pixels, that widths are relative to, and advance dash animation.
+    /// Run this each frame.
+    pub fn prepare(&mut self, queue: &Queue, viewport: (f32, f32), dt: f32) {
+        self.time += dt;
+
+        let uniform = LinesUniform {
+            viewport: [viewport.0, viewport.1],
+            time: self.time,
+            _pad: 0.,
+        };
+        queue.write_buffer(&self.buf, 0, bytemuck::bytes_of(&uniform));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.segment_count == 0
+    }
+
+    /// If any dashes are moving.
+    pub fn animated(&self) -> bool {
+        self.animated && !self.is_empty()
+    }
+
+    /// Draw the lines, with the line pipeline and a camera already bound.
+    pub fn draw(&self, rpass: &mut RenderPass) {
+        if self.is_empty() {
+            return;
+        }
+        rpass.set_bind_group(1, &self.bind_group, &[]);
+        rpass.set_vertex_buffer(0, self.segments.buf.slice(..));
+        // Each segment is a quad, generated in the shader.
+        rpass.draw(0..6, 0..self.segment_count);
+    }
+}
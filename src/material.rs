@@ -0,0 +1,62 @@
+//! Materials shared by entities, e.g. one per element in a molecule, or per part type in a
+//! model. Editing one restyles each entity using it, without rewriting the entities.
+
+use lin_alg::f32::Vec3;
+
+use crate::types::{Instance, RimLight, Shading};
+
+/// Surface properties applied to each entity whose `material` is this one's index in
+/// `Scene::materials`, in place of the entity's own. After changing it, send its index in
+/// `EngineUpdates::materials` to update its entities in place.
+///
+/// Opacity and blending stay per entity, as they decide which pass an entity is drawn in. Textures
+/// stay per mesh, as `Mesh::texture`.
+#[derive(Clone, Debug)]
+pub struct Material {
+    /// Up to the application, e.g. to find the material with `Scene::material_index`.
+    pub name: String,
+    /// sRGB.
+    pub color: (f32, f32, f32),
+    /// As `Entity::shinyness`.
+    pub shinyness: f32,
+    pub shading: Shading,
+    pub rim_light: Option<RimLight>,
+    /// As `Entity::reflectivity`.
+    pub reflectivity: f32,
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            color: (1., 1., 1.),
+            shinyness: 0.,
+            shading: Default::default(),
+            rim_light: None,
+            reflectivity: 0.,
+        }
+    }
+}
+
+impl Material {
+    pub fn new(name: &str, color: (f32, f32, f32), shinyness: f32) -> Self {
+        Self {
+            name: name.to_owned(),
+            color,
+            shinyness,
+            ..Default::default()
+        }
+    }
+
+    /// An instance of an entity using this material, with its surface properties replaced.
+    pub(crate) fn apply(&self, instance: Instance) -> Instance {
+        Instance {
+            color: Vec3::new(self.color.0, self.color.1, self.color.2),
+            shinyness: self.shinyness,
+            shading: self.shading,
+            rim_light: self.rim_light,
+            reflectivity: self.reflectivity,
+            ..instance
+        }
+    }
+}
@@ -0,0 +1,246 @@
+//! Uploads meshes from a background thread, for meshes large enough that converting and copying
+//! them on the render thread would stall a frame. The thread converts the mesh to its GPU layout,
+//! and writes it into a staging buffer mapped at creation. Once that's done, the render thread
+//! only records a GPU-side copy from the staging buffer into the shared vertex and index buffers.
+//!
+//! Meshes requested together are staged in order, and each is drawn as soon as it's ready; so a
+//! huge model split into chunks with `Mesh::split` appears progressively. If staging fails, the
+//! render thread uploads the mesh itself.
+
+use std::{
+    collections::HashMap,
+    mem,
+    sync::mpsc::{self, Receiver, TryRecvError},
+    thread,
+};
+
+use wgpu::{Buffer, BufferDescriptor, BufferUsages, Device};
+
+use crate::{
+    graphics::{mesh_indices_bytes, mesh_vertices_raw},
+    types::{Mesh, VertexRaw},
+};
+
+/// A mesh converted to its GPU layout, in a staging buffer ready to copy from.
+pub(crate) struct StagedMesh {
+    /// The vertex data, followed by the index data at `index_offset`.
+    pub staging: Buffer,
+    pub vertices: Vec<VertexRaw>,
+    /// Padded to a multiple of 4 bytes.
+    pub indices: Vec<u8>,
+    pub index_offset: u64,
+}
+
+/// How far along the uploads from `EngineUpdates::meshes_async` are, counted since none were
+/// pending. Set by the engine in `Scene::mesh_upload_progress`.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct UploadProgress {
+    /// Meshes uploaded.
+    pub done: usize,
+    /// Meshes uploaded, or still uploading.
+    pub total: usize,
+}
+
+impl UploadProgress {
+    /// From 0 to 1.
+    pub fn fraction(&self) -> f32 {
+        if self.total == 0 {
+            return 1.;
+        }
+        self.done as f32 / self.total as f32
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct MeshUploads {
+    /// Each batch of meshes requested together, with its id. A batch's meshes are staged in order
+    /// on its own background thread, and sent as each is done.
+    batches: Vec<(u64, Receiver<(usize, StagedMesh)>)>,
+    /// The batch of each mesh still staging, by index into `Scene::meshes`. A mesh cancelled, or
+    /// requested again in a later batch, is discarded from its earlier one when it arrives.
+    in_flight: HashMap<usize, u64>,
+    next_batch: u64,
+    /// Meshes done staging, collected by `wait_all`.
+    ready: Vec<(usize, StagedMesh)>,
+    /// Meshes whose batch ended without them, i.e. staging panicked.
+    lost: Vec<usize>,
+    progress: UploadProgress,
+}
+
+impl MeshUploads {
+    /// Start staging `meshes`, copies of those at their indices in `Scene::meshes`, in order on a
+    /// background thread. This replaces uploads already pending for those indices. There are no threads in
+    /// the browser; there, this stages them immediately, and they're ready at the next `poll`.
+    pub fn request(&mut self, meshes: Vec<(usize, Mesh)>, device: &Device) {
+        if meshes.is_empty() {
+            return;
+        }
+
+        let id = self.next_batch;
+        self.next_batch += 1;
+
+        for (i, _) in &meshes {
+            if self.in_flight.insert(*i, id).is_none() {
+                self.progress.total += 1;
+            }
+        }
+
+        let (tx, rx) = mpsc::channel();
+
+        if cfg!(target_arch = "wasm32") {
+            for (i, mesh) in meshes {
+                let _ = tx.send((i, stage(&mesh, device)));
+            }
+        } else {
+            let device = device.clone();
+            thread::spawn(move || {
+                for (i, mesh) in meshes {
+                    // If the uploads were dropped in the meantime, there's no one to send to.
+                    if tx.send((i, stage(&mesh, &device))).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+
+        self.batches.push((id, rx));
+    }
+
+    /// Discard the upload pending for `index`, if any, e.g. because the application replaced the
+    /// mesh there.
+    pub fn cancel(&mut self, index: usize) {
+        if self.in_flight.remove(&index).is_some() {
+            self.progress.total -= 1;
+        }
+
+        let n_ready = self.ready.len();
+        self.ready.retain(|(i, _)| *i != index);
+        if self.ready.len() < n_ready {
+            self.progress.done -= 1;
+            self.progress.total -= 1;
+        }
+    }
+
+    /// If any uploads are still staging, staged but not yet polled, or lost and not yet taken.
+    pub fn pending(&self) -> bool {
+        !self.in_flight.is_empty() || !self.ready.is_empty() || !self.lost.is_empty()
+    }
+
+    /// Progress since none were pending; `None` if none are.
+    pub fn progress(&mut self) -> Option<UploadProgress> {
+        if !self.pending() {
+            self.progress = Default::default();
+            return None;
+        }
+        Some(self.progress)
+    }
+
+    /// The meshes done staging since the last call, with their indices.
+    pub fn poll(&mut self) -> Vec<(usize, StagedMesh)> {
+        let mut result = mem::take(&mut self.ready);
+
+        let mut batches = mem::take(&mut self.batches);
+        batches.retain(|(id, rx)| {
+            loop {
+                match rx.try_recv() {
+                    Ok((i, staged)) => {
+                        if let Some(staged) = self.accept(*id, i, staged) {
+                            result.push((i, staged));
+                        }
+                    }
+                    Err(TryRecvError::Empty) => return true,
+                    Err(TryRecvError::Disconnected) => return false,
+                }
+            }
+        });
+        self.batches = batches;
+
+        self.drop_lost();
+        result
+    }
+
+    /// Wait for every mesh still staging, e.g. to render a complete frame headlessly. They're
+    /// then returned by the next `poll`.
+    pub fn wait_all(&mut self) {
+        for (id, rx) in mem::take(&mut self.batches) {
+            for (i, staged) in rx.iter() {
+                if let Some(staged) = self.accept(id, i, staged) {
+                    self.ready.push((i, staged));
+                }
+            }
+        }
+
+        self.drop_lost();
+    }
+
+    /// A staged mesh from batch `id`, unless it was cancelled or requested again since.
+    fn accept(&mut self, id: u64, index: usize, staged: StagedMesh) -> Option<StagedMesh> {
+        if self.in_flight.get(&index) != Some(&id) {
+            return None;
+        }
+
+        self.in_flight.remove(&index);
+        self.progress.done += 1;
+        Some(staged)
+    }
+
+    /// The meshes whose staging failed since the last call; upload them another way.
+    pub fn take_lost(&mut self) -> Vec<usize> {
+        mem::take(&mut self.lost)
+    }
+
+    /// Stop waiting on meshes whose batch ended without them, and add them to `lost`.
+    fn drop_lost(&mut self) {
+        let n_prev = self.in_flight.len();
+
+        let batches = &self.batches;
+        let lost = &mut self.lost;
+        self.in_flight.retain(|&i, id| {
+            let alive = batches.iter().any(|(batch, _)| batch == id);
+            if !alive {
+                lost.push(i);
+            }
+            alive
+        });
+
+        self.progress.total -= n_prev - self.in_flight.len();
+    }
+}
+
+/// Convert a mesh to its GPU layout, and write it to a new staging buffer.
+fn stage(mesh: &Mesh, device: &Device) -> StagedMesh {
+    let vertices = mesh_vertices_raw(mesh);
+    let indices = mesh_indices_bytes(mesh);
+
+    let vertex_bytes: &[u8] = bytemuck::cast_slice(&vertices);
+    // Vertices are made of 4-byte fields, so the indices start aligned for copying.
+    let index_offset = vertex_bytes.len();
+    // Mapped buffers can't be empty.
+    let size = (index_offset + indices.len()).max(4);
+
+    let staging = device.create_buffer(&BufferDescriptor {
+        label: Some("Mesh staging buffer"),
+        size: size as u64,
+        usage: BufferUsages::COPY_SRC,
+        mapped_at_creation: true,
+    });
+
+    {
+        let mut view = staging.get_mapped_range_mut(..);
+        if !vertex_bytes.is_empty() {
+            view.slice(..index_offset).copy_from_slice(vertex_bytes);
+        }
+        if !indices.is_empty() {
+            view.slice(index_offset..index_offset + indices.len())
+                .copy_from_slice(&indices);
+        }
+    }
+    staging.unmap();
+
+    StagedMesh {
+        staging,
+        vertices,
+        indices,
+        index_offset: index_offset as u64,
+    }
+}
@@ -8,6 +8,7 @@ use std::{
 };
 
 use lin_alg::f32::Vec3;
+use tracing::warn;
 
 use crate::{
     graphics::UP_VEC,
@@ -44,6 +45,7 @@ impl Mesh {
     //         vertices,
     //         indices,
     //         material: 0,
+    //         texture: None,
     //     }
     // }
 
@@ -158,14 +160,17 @@ impl Mesh {
             vertices,
             indices,
             material: 0,
+            texture: None,
+            two_sided: false,
+            attributes: Vec::new(),
         }
     }
 
     /// Creates an isosphere, from subdividing an icosahedron. 2-3 subdivisions works well for most uses.
     pub fn new_sphere(radius: f32, mut subdivisions: u32) -> Self {
         if subdivisions > 4 {
-            println!(
-                "Warning: Sphere subdivisions > 4 is not allowed due to extreme performance cost. Setting to 4."
+            warn!(
+                "Sphere subdivisions > 4 is not allowed due to extreme performance cost. Setting to 4."
             );
             subdivisions = 4;
         }
@@ -274,6 +279,9 @@ impl Mesh {
             vertices,
             indices,
             material: 0,
+            texture: None,
+            two_sided: false,
+            attributes: Vec::new(),
         }
     }
 
@@ -369,6 +377,9 @@ impl Mesh {
             vertices,
             indices,
             material: 0,
+            texture: None,
+            two_sided: false,
+            attributes: Vec::new(),
         }
     }
 
@@ -481,6 +492,9 @@ impl Mesh {
             vertices,
             indices,
             material: 0,
+            texture: None,
+            two_sided: false,
+            attributes: Vec::new(),
         }
     }
 
@@ -561,6 +575,9 @@ impl Mesh {
             vertices,
             indices,
             material: 0,
+            texture: None,
+            two_sided: false,
+            attributes: Vec::new(),
         }
     }
 
@@ -619,6 +636,9 @@ impl Mesh {
             // index_buffer: Vec<usize>,
             // num_elements: u32,
             material: 0,
+            texture: None,
+            two_sided: false,
+            attributes: Vec::new(),
         }
     }
 
@@ -693,6 +713,9 @@ impl Mesh {
             vertices,
             indices,
             material: 0,
+            texture: None,
+            two_sided: false,
+            attributes: Vec::new(),
         }
     }
 
@@ -759,6 +782,9 @@ impl Mesh {
             vertices,
             indices,
             material: 0,
+            texture: None,
+            two_sided: false,
+            attributes: Vec::new(),
         }
     }
 
@@ -793,6 +819,73 @@ impl Mesh {
             vertices,
             indices,
             material: 0,
+            texture: None,
+            two_sided: false,
+            attributes: Vec::new(),
+        }
+    }
+
+    /// Create an open tube along a polyline, e.g. a streamline. Vertices are in rings of
+    /// `num_sides`, one per point, so ring `i` starts at vertex `i * num_sides`. `num_sides` is at
+    /// least 3. Fewer than 2 points produce an empty mesh.
+    pub fn new_tube(points: &[Vec3], radius: f32, num_sides: usize) -> Self {
+        let num_sides = num_sides.max(3);
+        let mut vertices = Vec::with_capacity(points.len() * num_sides);
+        let mut indices = Vec::new();
+
+        if points.len() < 2 {
+            return Self::default();
+        }
+
+        // A tangent at each point, from its neighbors.
+        let tangent = |i: usize| {
+            let prev = points[i.saturating_sub(1)];
+            let next = points[(i + 1).min(points.len() - 1)];
+            (next - prev).to_normalized()
+        };
+
+        // Carry the ring's orientation along the line, so it doesn't twist.
+        let t0 = tangent(0);
+        let mut normal = if t0.dot(UP_VEC).abs() < 0.9 {
+            t0.cross(UP_VEC)
+        } else {
+            t0.cross(Vec3::new(1., 0., 0.))
+        }
+        .to_normalized();
+
+        for (i, point) in points.iter().enumerate() {
+            let t = tangent(i);
+            normal = (normal - t * normal.dot(t)).to_normalized();
+            let binormal = t.cross(normal);
+
+            for j in 0..num_sides {
+                let (sin, cos) = (j as f32 * TAU / num_sides as f32).sin_cos();
+                let dir = normal * cos + binormal * sin;
+
+                let mut vertex = Vertex::new((*point + dir * radius).to_arr(), dir);
+                vertex.tex_coords = [j as f32 / num_sides as f32, i as f32];
+                vertices.push(vertex);
+            }
+        }
+
+        for i in 0..points.len() - 1 {
+            for j in 0..num_sides {
+                let a = i * num_sides + j;
+                let b = i * num_sides + (j + 1) % num_sides;
+                let c = a + num_sides;
+                let d = b + num_sides;
+
+                indices.extend_from_slice(&[a, c, b, b, c, d]);
+            }
+        }
+
+        Self {
+            vertices,
+            indices,
+            material: 0,
+            texture: None,
+            two_sided: false,
+            attributes: Vec::new(),
         }
     }
 
@@ -832,6 +925,9 @@ impl Mesh {
             vertices,
             indices,
             material: 0,
+            texture: None,
+            two_sided: false,
+            attributes: Vec::new(),
         }
     }
 
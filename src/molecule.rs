@@ -0,0 +1,385 @@
+//! Helpers for ball-and-stick views of molecules, e.g. proteins, or frames of a molecular
+//! dynamics trajectory.
+
+use lin_alg::f32::{Quaternion, Vec3};
+use tracing::warn;
+
+use crate::{
+    Entity, UP_VEC,
+    impostor::{CylinderImpostor, SphereImpostor},
+};
+
+#[derive(Clone, Copy, Debug)]
+pub struct Atom {
+    pub posit: Vec3,
+    pub radius: f32,
+    pub color: (f32, f32, f32),
+}
+
+#[derive(Clone, Debug)]
+pub struct BallStickSettings {
+    /// The index in `Scene::meshes` of a sphere with radius 1, e.g. `Mesh::new_sphere(1., 3)`.
+    pub mesh_atom: usize,
+    /// The index in `Scene::meshes` of a cylinder along +Y, with length and radius 1, e.g.
+    /// `Mesh::new_cylinder(1., 1., 12)`.
+    pub mesh_bond: usize,
+    /// Multiplies each atom's radius, e.g. to shrink van der Waals radii so bonds are visible.
+    pub atom_scale: f32,
+    pub bond_radius: f32,
+    /// If `None`, each bond is drawn in two halves, colored by the atom at each end.
+    pub bond_color: Option<(f32, f32, f32)>,
+    pub opacity: f32,
+    pub shinyness: f32,
+    /// Set on each atom's entity, e.g. to update them with `EntityUpdate::Classes`.
+    pub class_atom: u32,
+    /// Set on each bond's entities.
+    pub class_bond: u32,
+}
+
+impl Default for BallStickSettings {
+    fn default() -> Self {
+        Self {
+            mesh_atom: 0,
+            mesh_bond: 1,
+            atom_scale: 1.,
+            bond_radius: 0.15,
+            bond_color: None,
+            opacity: 1.,
+            shinyness: 0.5,
+            class_atom: 0,
+            class_bond: 1,
+        }
+    }
+}
+
+impl BallStickSettings {
+    fn entities_per_bond(&self) -> usize {
+        if self.bond_color.is_some() { 1 } else { 2 }
+    }
+}
+
+/// Create entities for `atoms`, and the `bonds` between them, given as pairs of indices into
+/// `atoms`. Atoms and bonds each share a mesh, so render in two instanced draws.
+///
+/// The atoms' entities come first, in order, followed by the bonds', in order; each entity's `id`
+/// is the index of its atom or bond. Bonds referencing atoms out of range are skipped.
+pub fn ball_and_stick(
+    atoms: &[Atom],
+    bonds: &[(usize, usize)],
+    settings: &BallStickSettings,
+) -> Vec<Entity> {
+    let bonds = valid_bonds(bonds, atoms.len());
+    let mut result = Vec::with_capacity(atoms.len() + bonds.len() * settings.entities_per_bond());
+
+    for (i, atom) in atoms.iter().enumerate() {
+        let mut entity = Entity::new(
+            settings.mesh_atom,
+            atom.posit,
+            Quaternion::new_identity(),
+            atom.radius * settings.atom_scale,
+            atom.color,
+            settings.shinyness,
+        );
+        entity.id = i as u32;
+        entity.class = settings.class_atom;
+        entity.opacity = settings.opacity;
+
+        result.push(entity);
+    }
+
+    for (i, &(a, b)) in bonds.iter().enumerate() {
+        let colors = match settings.bond_color {
+            Some(c) => vec![c],
+            None => vec![atoms[a].color, atoms[b].color],
+        };
+
+        for color in colors {
+            let mut entity = Entity::new(
+                settings.mesh_bond,
+                Vec3::new_zero(),
+                Quaternion::new_identity(),
+                1.,
+                color,
+                settings.shinyness,
+            );
+            entity.id = i as u32;
+            entity.class = settings.class_bond;
+            entity.opacity = settings.opacity;
+
+            result.push(entity);
+        }
+    }
+
+    set_positions(&mut result, |i| atoms[i].posit, &bonds, settings);
+
+    result
+}
+
+/// Move entities created by `ball_and_stick` to new atom positions, e.g. for each frame of a
+/// trajectory, without rebuilding them. `entities` is the range of `Scene::entities` holding
+/// them; `bonds` and `settings` must be the ones they were created with. Then, send
+/// `EntityUpdate::Indexes` for that range to update their instances in place.
+pub fn update_ball_and_stick(
+    entities: &mut [Entity],
+    positions: &[Vec3],
+    bonds: &[(usize, usize)],
+    settings: &BallStickSettings,
+) {
+    let bonds = valid_bonds(bonds, positions.len());
+
+    let expected = positions.len() + bonds.len() * settings.entities_per_bond();
+    if entities.len() != expected {
+        warn!(
+            "Ball-and-stick update with {} entities; expected {expected}. Skipping",
+            entities.len()
+        );
+        return;
+    }
+
+    set_positions(entities, |i| positions[i], &bonds, settings);
+}
+
+/// As `ball_and_stick`, but as impostors, for `Scene::spheres` and `Scene::cylinders`, instead of
+/// entities. These are far cheaper to draw for large molecules. The settings' meshes, opacity,
+/// and classes are unused; impostors are opaque. Each bond is one cylinder; if
+/// `settings.bond_color` is `None`, its halves are colored by the atoms at each end.
+///
+/// For trajectory playback, rebuild them with each frame's atom positions, and set
+/// `EngineUpdates::spheres` and `EngineUpdates::cylinders`.
+pub fn ball_and_stick_impostors(
+    atoms: &[Atom],
+    bonds: &[(usize, usize)],
+    settings: &BallStickSettings,
+) -> (Vec<SphereImpostor>, Vec<CylinderImpostor>) {
+    let spheres = atoms
+        .iter()
+        .map(|atom| SphereImpostor {
+            position: atom.posit,
+            radius: atom.radius * settings.atom_scale,
+            color: atom.color,
+            shinyness: settings.shinyness,
+        })
+        .collect();
+
+    let cylinders = valid_bonds(bonds, atoms.len())
+        .into_iter()
+        .map(|(a, b)| {
+            let (color, color_end) = match settings.bond_color {
+                Some(c) => (c, None),
+                None => (atoms[a].color, Some(atoms[b].color)),
+            };
+
+            CylinderImpostor {
+                start: atoms[a].posit,
+                end: atoms[b].posit,
+                radius: settings.bond_radius,
+                color,
+                color_end,
+                shinyness: settings.shinyness,
+            }
+        })
+        .collect();
+
+    (spheres, cylinders)
+}
+
+fn valid_bonds(bonds: &[(usize, usize)], atom_count: usize) -> Vec<(usize, usize)> {
+    bonds
+        .iter()
+        .copied()
+        .filter(|&(a, b)| a < atom_count && b < atom_count)
+        .collect()
+}
+
+/// Place atom and bond entities, laid out as by `ball_and_stick`.
+fn set_positions(
+    entities: &mut [Entity],
+    posit: impl Fn(usize) -> Vec3,
+    bonds: &[(usize, usize)],
+    settings: &BallStickSettings,
+) {
+    let atom_count = entities.len() - bonds.len() * settings.entities_per_bond();
+    let (atom_ents, bond_ents) = entities.split_at_mut(atom_count);
+
+    for (i, entity) in atom_ents.iter_mut().enumerate() {
+        entity.position = posit(i);
+    }
+
+    for (ents, &(a, b)) in bond_ents
+        .chunks_mut(settings.entities_per_bond())
+        .zip(bonds)
+    {
+        let (start, end) = (posit(a), posit(b));
+
+        if let [whole] = ents {
+            place_cylinder(whole, start, end, settings.bond_radius);
+        } else if let [half_a, half_b] = ents {
+            let mid = (start + end) / 2.;
+            place_cylinder(half_a, start, mid, settings.bond_radius);
+            place_cylinder(half_b, mid, end, settings.bond_radius);
+        }
+    }
+}
+
+/// Orient and scale a unit cylinder entity to span `start` to `end`.
+fn place_cylinder(entity: &mut Entity, start: Vec3, end: Vec3, radius: f32) {
+    let diff = end - start;
+    let len = diff.magnitude();
+
+    entity.position = (start + end) / 2.;
+    entity.orientation = if len > f32::EPSILON {
+        Quaternion::from_unit_vecs(UP_VEC, diff / len)
+    } else {
+        Quaternion::new_identity()
+    };
+    entity.scale_partial = Some(Vec3::new(radius, len, radius));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Instance;
+
+    const EPS: f32 = 1e-4;
+
+    /// Where `entity`'s instance puts a point of its unit mesh.
+    fn to_world(entity: &Entity, p: Vec3) -> Vec3 {
+        let m = Instance::from(entity).to_raw().model;
+        let row = |r: usize| m[r] * p.x + m[4 + r] * p.y + m[8 + r] * p.z + m[12 + r];
+        Vec3::new(row(0), row(1), row(2))
+    }
+
+    /// Checks that a unit cylinder entity spans `start` to `end`, with `radius`.
+    fn check_cylinder(entity: &Entity, start: Vec3, end: Vec3, radius: f32) {
+        let bottom = to_world(entity, Vec3::new(0., -0.5, 0.));
+        let top = to_world(entity, Vec3::new(0., 0.5, 0.));
+        assert!(
+            (bottom - start).magnitude() < EPS,
+            "{bottom:?} isn't {start:?}"
+        );
+        assert!((top - end).magnitude() < EPS, "{top:?} isn't {end:?}");
+
+        // The rim is `radius` from the axis, and perpendicular to it.
+        for rim in [Vec3::new(1., 0., 0.), Vec3::new(0., 0., 1.)] {
+            let offset = to_world(entity, rim) - entity.position;
+            assert!((offset.magnitude() - radius).abs() < EPS);
+            assert!(offset.dot(end - start).abs() < EPS);
+        }
+    }
+
+    fn atoms() -> Vec<Atom> {
+        [
+            Vec3::new(0., 0., 0.),
+            Vec3::new(1.5, 0., 0.),
+            Vec3::new(1.5, 1., 1.),
+            // Straight down from the first, antiparallel to the cylinder mesh's axis.
+            Vec3::new(0., -1.2, 0.),
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(i, posit)| Atom {
+            posit,
+            radius: 0.5 + i as f32 * 0.1,
+            color: (i as f32 / 4., 0.5, 1.),
+        })
+        .collect()
+    }
+
+    const BONDS: [(usize, usize); 4] = [(0, 1), (1, 2), (0, 3), (2, 9)];
+
+    #[test]
+    fn bonds_span_their_atoms() {
+        let atoms = atoms();
+        let settings = BallStickSettings {
+            bond_color: Some((0.5, 0.5, 0.5)),
+            ..Default::default()
+        };
+        let entities = ball_and_stick(&atoms, &BONDS, &settings);
+
+        // The bond to an atom out of range is skipped.
+        assert_eq!(entities.len(), atoms.len() + 3);
+
+        for (i, (ent, atom)) in entities.iter().zip(&atoms).enumerate() {
+            assert_eq!(ent.id, i as u32);
+            assert_eq!(ent.class, settings.class_atom);
+            assert_eq!(ent.position, atom.posit);
+            assert_eq!(ent.scale, atom.radius);
+        }
+
+        for (i, (ent, &(a, b))) in entities[atoms.len()..].iter().zip(&BONDS).enumerate() {
+            assert_eq!(ent.id, i as u32);
+            assert_eq!(ent.class, settings.class_bond);
+            assert_eq!(ent.color, (0.5, 0.5, 0.5));
+            check_cylinder(ent, atoms[a].posit, atoms[b].posit, settings.bond_radius);
+        }
+    }
+
+    #[test]
+    fn half_bonds_colored_by_atom() {
+        let atoms = atoms();
+        let settings = BallStickSettings::default();
+        let entities = ball_and_stick(&atoms, &BONDS, &settings);
+
+        assert_eq!(entities.len(), atoms.len() + 3 * 2);
+
+        let halves = entities[atoms.len()..].chunks(2);
+        for (i, (pair, &(a, b))) in halves.zip(&BONDS).enumerate() {
+            let (start, end) = (atoms[a].posit, atoms[b].posit);
+            let mid = (start + end) / 2.;
+
+            check_cylinder(&pair[0], start, mid, settings.bond_radius);
+            check_cylinder(&pair[1], mid, end, settings.bond_radius);
+            assert_eq!(pair[0].color, atoms[a].color);
+            assert_eq!(pair[1].color, atoms[b].color);
+            assert!(pair.iter().all(|e| e.id == i as u32));
+        }
+    }
+
+    #[test]
+    fn update_keeps_entities_in_place() {
+        let atoms = atoms();
+        let settings = BallStickSettings::default();
+        let mut entities = ball_and_stick(&atoms, &BONDS, &settings);
+        let before = entities.clone();
+
+        let positions: Vec<_> = atoms
+            .iter()
+            .map(|a| a.posit * 2. + Vec3::new(0., 0., 3.))
+            .collect();
+        update_ball_and_stick(&mut entities, &positions, &BONDS, &settings);
+
+        assert_eq!(entities.len(), before.len());
+        for (ent, prev) in entities.iter().zip(&before) {
+            assert_eq!(ent.id, prev.id);
+            assert_eq!(ent.class, prev.class);
+            assert_eq!(ent.color, prev.color);
+            assert_eq!(ent.mesh, prev.mesh);
+        }
+        for (ent, &p) in entities.iter().zip(&positions) {
+            assert_eq!(ent.position, p);
+        }
+
+        // Matches building from scratch at the new positions.
+        let moved: Vec<_> = atoms
+            .iter()
+            .zip(&positions)
+            .map(|(a, &posit)| Atom { posit, ..*a })
+            .collect();
+        let fresh = ball_and_stick(&moved, &BONDS, &settings);
+        for (ent, f) in entities.iter().zip(&fresh) {
+            assert_eq!(ent.position, f.position);
+            assert_eq!(ent.orientation, f.orientation);
+            assert_eq!(ent.scale_partial, f.scale_partial);
+        }
+
+        // With the wrong number of entities, nothing changes.
+        let mut short = before[1..].to_vec();
+        update_ball_and_stick(&mut short, &positions, &BONDS, &settings);
+        assert!(
+            short
+                .iter()
+                .zip(&before[1..])
+                .all(|(a, b)| a.position == b.position)
+        );
+    }
+}
@@ -0,0 +1,280 @@
+//! Camera motion blur, as a post pass. With it on, the scene renders to an offscreen texture,
+//! which this pass blurs into the window along each pixel's movement since the previous frame.
+
+use bytemuck::{Pod, Zeroable};
+use lin_alg::f32::Mat4;
+use wgpu::{
+    BindGroup, BindGroupLayout, Buffer, BufferUsages, CommandEncoder, Device, Queue,
+    RenderPipeline, Sampler, ShaderStages, StoreOp, TextureFormat, TextureView, util::DeviceExt,
+};
+
+use crate::{Camera, frame_timing::PassTimestamps};
+
+/// Samples along each pixel's path. More is smoother for fast movement, but slower.
+const SAMPLES: u32 = 12;
+
+/// Matches `MotionBlur` in `shader_motion_blur.wgsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct MotionBlurUniform {
+    proj_view_inv: [f32; 16],
+    prev_proj_view: [f32; 16],
+    viewport: [f32; 4],
+    strength: f32,
+    samples: u32,
+    _pad: [u32; 2],
+}
+
+/// The offscreen scene texture, and the bind group reading it with the depth prepass.
+struct SceneTarget {
+    view: TextureView,
+    bind_group: BindGroup,
+    size: (u32, u32),
+}
+
+/// GPU state for `GraphicsSettings::motion_blur`.
+pub(crate) struct MotionBlurRenderer {
+    /// The fraction of each frame's camera movement to blur over. 0 = disabled.
+    pub strength: f32,
+    layout: BindGroupLayout,
+    pipeline: RenderPipeline,
+    sampler: Sampler,
+    buf: Buffer,
+    format: TextureFormat,
+    target: Option<SceneTarget>,
+    /// The camera from the last frame rendered.
+    prev_proj_view: Option<Mat4>,
+}
+
+impl MotionBlurRenderer {
+    pub fn new(device: &Device, format: TextureFormat) -> Self {
+        let fragment_entry = |binding, ty| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStages::FRAGMENT,
+            ty,
+            count: None,
+        };
+
+        let layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Motion blur bind group layout"),
+                entries: &[
+                    fragment_entry(
+                        0,
+                        wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                    ),
+                    fragment_entry(
+                        1,
+                        wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    ),
+                    fragment_entry(
+                        2,
+                        wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Depth,
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                    ),
+                    fragment_entry(
+                        3,
+                        wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: wgpu::BufferSize::new(
+                                size_of::<MotionBlurUniform>() as _
+                            ),
+                        },
+                    ),
+                ],
+            });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Motion blur shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("shader_motion_blur.wgsl").into()),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Motion blur pipeline layout"),
+            bind_group_layouts: &[Some(&layout)],
+            immediate_size: 0,
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Motion blur pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                compilation_options: Default::default(),
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                compilation_options: Default::default(),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: Default::default(),
+            depth_stencil: None,
+            multisample: Default::default(),
+            multiview_mask: None,
+            cache: None,
+        });
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Motion blur sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Motion blur buffer"),
+            contents: bytemuck::bytes_of(&MotionBlurUniform::zeroed()),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+
+        Self {
+            strength: 0.,
+            layout,
+            pipeline,
+            sampler,
+            buf,
+            format,
+            target: None,
+            prev_proj_view: None,
+        }
+    }
+
+    /// Write this frame's camera, along with the previous one's, and return the texture to draw
+    /// the scene into. Call each frame it's enabled. `depth` is the prepass depth texture; it's
+    /// recreated along with the surface, so we rebind it when the size changes.
+    pub fn prepare(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        camera: &Camera,
+        depth: &TextureView,
+        size: (u32, u32),
+        viewport: (f32, f32, f32, f32),
+    ) -> &TextureView {
+        let proj_view = camera.proj_mat.clone() * camera.view_mat();
+        // On the first frame, or after the blur was off, there's no movement to show.
+        let prev_proj_view = self.prev_proj_view.replace(proj_view.clone());
+
+        let uniform = MotionBlurUniform {
+            proj_view_inv: proj_view
+                .clone()
+                .inverse()
+                .unwrap_or_else(Mat4::new_identity)
+                .data,
+            prev_proj_view: prev_proj_view.unwrap_or(proj_view).data,
+            viewport: [viewport.0, viewport.1, viewport.2, viewport.3],
+            strength: self.strength,
+            samples: SAMPLES,
+            _pad: [0; 2],
+        };
+        queue.write_buffer(&self.buf, 0, bytemuck::bytes_of(&uniform));
+
+        if self.target.as_ref().is_none_or(|t| t.size != size) {
+            let texture = device.create_texture(&wgpu::TextureDescriptor {
+                label: Some("Motion blur scene texture"),
+                size: wgpu::Extent3d {
+                    width: size.0,
+                    height: size.1,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: self.format,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                    | wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            });
+            let view = texture.create_view(&Default::default());
+
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("Motion blur bind group"),
+                layout: &self.layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(&self.sampler),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: wgpu::BindingResource::TextureView(depth),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: self.buf.as_entire_binding(),
+                    },
+                ],
+            });
+
+            self.target = Some(SceneTarget {
+                view,
+                bind_group,
+                size,
+            });
+        }
+
+        &self.target.as_ref().unwrap().view
+    }
+
+    /// The offscreen scene texture we allocated, if any; for memory usage.
+    pub fn target_texture(&self) -> Option<&wgpu::Texture> {
+        self.target.as_ref().map(|t| t.view.texture())
+    }
+
+    /// Forget the previous camera, e.g. while the blur is off, so re-enabling it doesn't blur
+    /// across the gap.
+    pub fn reset(&mut self) {
+        self.prev_proj_view = None;
+    }
+
+    /// Blur the scene texture from `prepare` into `output`.
+    pub fn apply(
+        &self,
+        encoder: &mut CommandEncoder,
+        output: &TextureView,
+        timestamps: Option<PassTimestamps>,
+    ) {
+        let Some(target) = &self.target else {
+            return;
+        };
+
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Motion blur"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: output,
+                depth_slice: None,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: timestamps.as_ref().map(PassTimestamps::writes),
+            occlusion_query_set: None,
+            multiview_mask: None,
+        });
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, &target.bind_group, &[]);
+        pass.draw(0..3, 0..1);
+    }
+}
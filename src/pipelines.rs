@@ -0,0 +1,144 @@
+//! Caches render pipelines, keyed by shader and state. A pipeline needed again, e.g. after
+//! switching MSAA back to a previous sample count, is reused instead of recompiled.
+//!
+//! Pipelines can also be compiled on a background thread. Until one is ready, the caller skips
+//! the pass that uses it, instead of stalling the frame on shader compilation.
+
+use std::{
+    collections::HashMap,
+    sync::mpsc::{self, Receiver, TryRecvError},
+    thread,
+};
+
+use wgpu::RenderPipeline;
+
+use crate::gauss::GaussianBlendMode;
+
+/// Identifies a shader, along with the fixed pipeline state it's used with.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub(crate) enum PipelineKind {
+    MeshOpaque,
+    /// As `MeshOpaque`, without back-face culling.
+    MeshOpaqueTwoSided,
+    MeshTransparent,
+    MeshTransparentBack,
+    Halo,
+    #[cfg(feature = "xray")]
+    XRay,
+    Gauss(GaussianBlendMode),
+    Slice,
+    Background,
+    DebugLines,
+    Lines,
+    ImpostorSphere,
+    /// Sphere impostors, depth only, for the 1-sample depth prepass.
+    ImpostorSphereDepth,
+    ImpostorCylinder,
+    ImpostorCylinderDepth,
+}
+
+/// Pipeline state that varies at runtime.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub(crate) struct PipelineKey {
+    pub kind: PipelineKind,
+    pub sample_count: u32,
+}
+
+impl PipelineKey {
+    pub fn new(kind: PipelineKind, sample_count: u32) -> Self {
+        Self { kind, sample_count }
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct PipelineCache {
+    ready: HashMap<PipelineKey, RenderPipeline>,
+    /// Pipelines being compiled on a background thread.
+    pending: HashMap<PipelineKey, Receiver<RenderPipeline>>,
+    /// If set, `request` compiles on the calling thread. Validation errors are only caught by an
+    /// error scope on the thread that caused them.
+    pub blocking: bool,
+}
+
+impl PipelineCache {
+    /// Get the pipeline for `key`, compiling it with `build` on this thread if it's not cached.
+    /// If it's being compiled in the background, this waits for it.
+    pub fn get_or_create(
+        &mut self,
+        key: PipelineKey,
+        build: impl FnOnce() -> RenderPipeline,
+    ) -> RenderPipeline {
+        if let Some(rx) = self.pending.remove(&key)
+            && let Ok(pipeline) = rx.recv()
+        {
+            self.ready.insert(key, pipeline);
+        }
+
+        self.ready.entry(key).or_insert_with(build).clone()
+    }
+
+    /// Start compiling the pipeline for `key` on a background thread, unless it's cached or
+    /// already compiling. Retrieve it with `get`. There are no threads in the browser; there, this
+    /// compiles it immediately, as it does if `blocking` is set.
+    pub fn request(
+        &mut self,
+        key: PipelineKey,
+        build: impl FnOnce() -> RenderPipeline + Send + 'static,
+    ) {
+        if self.ready.contains_key(&key) || self.pending.contains_key(&key) {
+            return;
+        }
+
+        if self.blocking || cfg!(target_arch = "wasm32") {
+            self.ready.insert(key, build());
+            return;
+        }
+
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            // If the cache was dropped in the meantime, there's no one to send to.
+            let _ = tx.send(build());
+        });
+
+        self.pending.insert(key, rx);
+    }
+
+    /// Get the pipeline for `key` if it's ready. Returns `None` if it's still compiling, or if it
+    /// was never requested.
+    pub fn get(&mut self, key: PipelineKey) -> Option<RenderPipeline> {
+        if let Some(rx) = self.pending.get(&key) {
+            match rx.try_recv() {
+                Ok(pipeline) => {
+                    self.pending.remove(&key);
+                    self.ready.insert(key, pipeline);
+                }
+                Err(TryRecvError::Empty) => return None,
+                Err(TryRecvError::Disconnected) => {
+                    // The build panicked; allow a later request to retry.
+                    self.pending.remove(&key);
+                    return None;
+                }
+            }
+        }
+
+        self.ready.get(&key).cloned()
+    }
+
+    /// Wait for every pipeline compiling in the background, e.g. to render a complete frame
+    /// headlessly.
+    pub fn wait_all(&mut self) {
+        for (key, rx) in self.pending.drain() {
+            if let Ok(pipeline) = rx.recv() {
+                self.ready.insert(key, pipeline);
+            }
+        }
+    }
+
+    /// Discard cached pipelines of these kinds, e.g. after their shader changed. Ones still
+    /// compiling are discarded when they finish.
+    #[cfg(feature = "hot_reload")]
+    pub fn invalidate(&mut self, kinds: &[PipelineKind]) {
+        self.ready.retain(|key, _| !kinds.contains(&key.kind));
+        self.pending.retain(|key, _| !kinds.contains(&key.kind));
+    }
+}
@@ -0,0 +1,523 @@
+//! Lets other threads, e.g. a simulation running in the background, wake the event loop and update
+//! the scene. Closures sent through the proxy run on the main thread, between frames.
+//!
+//! For common changes, threads can instead send `SceneDelta`s. The engine applies all pending
+//! deltas before each frame, uploading only what they changed. `EntityDeltas` batch changes to
+//! many entities compactly, holding only the fields that changed.
+//!
+//! The proxy also acts on the window and event loop directly, e.g. to set the title, or quit.
+//! Keep a clone in the application state to do this from the handlers, without an
+//! `EngineUpdates` field for each action.
+
+#[cfg(target_arch = "wasm32")]
+use std::sync::Arc;
+use std::{fmt, ops::BitOr, sync::mpsc};
+
+use image::RgbaImage;
+use lin_alg::f32::{Quaternion, Vec3};
+use tracing::warn;
+#[cfg(target_arch = "wasm32")]
+use winit::window::Window;
+use winit::{event_loop::EventLoopProxy, window::CursorIcon};
+
+#[cfg(target_arch = "wasm32")]
+use crate::system::Gpu;
+use crate::{
+    EngineUpdates, Entity, EntityUpdate, Gaussian, GaussianUpdate, GraphicsError, Mesh, Scene,
+};
+
+/// A scene update sent from another thread; run on the main thread.
+type Update<T> = Box<dyn FnOnce(&mut T, &mut Scene) -> EngineUpdates + Send>;
+
+/// Receives a screenshot on the main thread, with the application state.
+type ScreenshotHandler<T> = Box<dyn FnOnce(&mut T, Result<RgbaImage, GraphicsError>) + Send>;
+
+/// An action on the window or event loop, from `EngineProxy`.
+pub(crate) enum Command<T> {
+    SetWindowTitle(String),
+    SetCursor(CursorIcon),
+    RecenterArc(Vec3),
+    Screenshot(ScreenshotHandler<T>),
+    Quit,
+}
+
+/// Events we send to ourself through the event loop.
+pub(crate) enum EngineEvent<T> {
+    /// Render a frame, in `RenderMode::OnDemand`.
+    Wake,
+    Update(Update<T>),
+    Command(Command<T>),
+    /// The GPU request made at init completed. In the browser, we can't block on it.
+    #[cfg(target_arch = "wasm32")]
+    GpuReady(Arc<Window>, Result<Gpu, GraphicsError>),
+}
+
+/// The event loop has exited, so can no longer receive events.
+#[derive(Clone, Copy, Debug)]
+pub struct EventLoopClosed;
+
+impl fmt::Display for EventLoopClosed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "The event loop has exited")
+    }
+}
+
+impl std::error::Error for EventLoopClosed {}
+
+/// A change to the scene, sent from another thread.
+#[derive(Clone, Debug)]
+pub enum SceneDelta {
+    /// Move the entity at this index in `Scene::entities`. This sets a uniform scale, replacing
+    /// `Entity::scale_partial` if set.
+    EntityTransform {
+        index: usize,
+        position: Vec3,
+        orientation: Quaternion,
+        scale: f32,
+    },
+    /// Append a mesh to `Scene::meshes`.
+    AddMesh(Mesh),
+    /// Replace `Scene::gaussians`.
+    Gaussians(Vec<Gaussian>),
+    /// Replace the gaussian at this index in `Scene::gaussians`, e.g. from a fitting step; it's
+    /// uploaded in place.
+    Gaussian { index: usize, gaussian: Gaussian },
+    /// Change some fields of many entities.
+    Entities(EntityDeltas),
+}
+
+/// A set of entity fields, for `EntityDeltas`. Combine with `|`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct EntityFields(u8);
+
+impl EntityFields {
+    pub const POSITION: Self = Self(1);
+    pub const ORIENTATION: Self = Self(1 << 1);
+    /// A uniform scale, replacing `Entity::scale_partial` if set.
+    pub const SCALE: Self = Self(1 << 2);
+    pub const COLOR: Self = Self(1 << 3);
+    pub const OPACITY: Self = Self(1 << 4);
+
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// The number of values each entity takes, packed.
+    fn stride(self) -> usize {
+        [
+            (Self::POSITION, 3),
+            (Self::ORIENTATION, 4),
+            (Self::SCALE, 1),
+            (Self::COLOR, 3),
+            (Self::OPACITY, 1),
+        ]
+        .iter()
+        .filter(|(f, _)| self.contains(*f))
+        .map(|(_, n)| n)
+        .sum()
+    }
+}
+
+impl BitOr for EntityFields {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+/// Changes to the same fields of many entities, e.g. the positions of every atom after a
+/// simulation step. Only those fields are stored, packed, so this is far smaller to build and send
+/// than whole entities. Send with `SceneDelta::Entities`.
+#[derive(Clone, Debug, Default)]
+pub struct EntityDeltas {
+    fields: EntityFields,
+    indices: Vec<u32>,
+    /// Each entity's values, in the order of `EntityFields`' constants; only those in `fields`.
+    values: Vec<f32>,
+}
+
+impl EntityDeltas {
+    pub fn new(fields: EntityFields) -> Self {
+        Self {
+            fields,
+            ..Default::default()
+        }
+    }
+
+    /// With room for `n` entities.
+    pub fn with_capacity(fields: EntityFields, n: usize) -> Self {
+        Self {
+            fields,
+            indices: Vec::with_capacity(n),
+            values: Vec::with_capacity(n * fields.stride()),
+        }
+    }
+
+    /// Only positions, one per index; the most common case.
+    pub fn positions(indices: &[usize], positions: &[Vec3]) -> Self {
+        let mut result = Self::with_capacity(EntityFields::POSITION, indices.len());
+        for (&i, p) in indices.iter().zip(positions) {
+            result.indices.push(i as u32);
+            result.values.extend([p.x, p.y, p.z]);
+        }
+        result
+    }
+
+    pub fn fields(&self) -> EntityFields {
+        self.fields
+    }
+
+    /// The number of entities changed.
+    pub fn len(&self) -> usize {
+        self.indices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    /// Add a change to the entity at `index` in `Scene::entities`, taking this batch's fields from
+    /// `entity`, e.g. a copy the sending thread keeps. Its other fields are ignored.
+    pub fn push(&mut self, index: usize, entity: &Entity) {
+        let f = self.fields;
+        self.indices.push(index as u32);
+
+        if f.contains(EntityFields::POSITION) {
+            let p = entity.position;
+            self.values.extend([p.x, p.y, p.z]);
+        }
+        if f.contains(EntityFields::ORIENTATION) {
+            let o = entity.orientation;
+            self.values.extend([o.w, o.x, o.y, o.z]);
+        }
+        if f.contains(EntityFields::SCALE) {
+            self.values.push(entity.scale);
+        }
+        if f.contains(EntityFields::COLOR) {
+            let c = entity.color;
+            self.values.extend([c.0, c.1, c.2]);
+        }
+        if f.contains(EntityFields::OPACITY) {
+            self.values.push(entity.opacity);
+        }
+    }
+
+    /// Write one entity's values over its fields.
+    fn apply(&self, values: &[f32], entity: &mut Entity) {
+        let f = self.fields;
+        let mut v = values.iter().copied();
+        let mut next = || v.next().unwrap_or_default();
+
+        if f.contains(EntityFields::POSITION) {
+            entity.position = Vec3::new(next(), next(), next());
+        }
+        if f.contains(EntityFields::ORIENTATION) {
+            entity.orientation = Quaternion::new(next(), next(), next(), next());
+        }
+        if f.contains(EntityFields::SCALE) {
+            entity.scale = next();
+            entity.scale_partial = None;
+        }
+        if f.contains(EntityFields::COLOR) {
+            entity.color = (next(), next(), next());
+        }
+        if f.contains(EntityFields::OPACITY) {
+            entity.opacity = next();
+        }
+    }
+}
+
+/// Apply deltas to the scene, and return the updates required to upload the changes.
+pub(crate) fn apply_scene_deltas(
+    scene: &mut Scene,
+    deltas: impl Iterator<Item = SceneDelta>,
+) -> EngineUpdates {
+    let mut updates = EngineUpdates::default();
+    // The range of entities moved, if any; updated in place.
+    let mut moved: Option<(usize, usize)> = None;
+
+    for delta in deltas {
+        match delta {
+            SceneDelta::EntityTransform {
+                index,
+                position,
+                orientation,
+                scale,
+            } => {
+                let Some(ent) = scene.entities.get_mut(index) else {
+                    warn!("Scene delta for entity {index}, which doesn't exist; skipping");
+                    continue;
+                };
+                ent.position = position;
+                ent.orientation = orientation;
+                ent.scale = scale;
+                ent.scale_partial = None;
+
+                moved = Some(match moved {
+                    Some((start, end)) => (start.min(index), end.max(index + 1)),
+                    None => (index, index + 1),
+                });
+            }
+            SceneDelta::AddMesh(mesh) => {
+                updates.meshes_changed.push(scene.meshes.len());
+                scene.meshes.push(mesh);
+            }
+            SceneDelta::Gaussians(gaussians) => {
+                scene.gaussians = gaussians;
+                scene.gaussians_removed.clear();
+                updates.gaussians = GaussianUpdate::All;
+            }
+            SceneDelta::Gaussian { index, gaussian } => {
+                let Some(g) = scene.gaussians.get_mut(index) else {
+                    warn!("Scene delta for gaussian {index}, which doesn't exist; skipping");
+                    continue;
+                };
+                *g = gaussian;
+                // It's no longer free, if it was removed.
+                scene.gaussians_removed.retain(|&i| i != index);
+                updates.gaussians.push_index(index);
+            }
+            SceneDelta::Entities(deltas) => {
+                let stride = deltas.fields.stride();
+                for (i, &index) in deltas.indices.iter().enumerate() {
+                    let index = index as usize;
+                    let Some(ent) = scene.entities.get_mut(index) else {
+                        warn!("Scene delta for entity {index}, which doesn't exist; skipping");
+                        continue;
+                    };
+                    deltas.apply(&deltas.values[i * stride..(i + 1) * stride], ent);
+
+                    moved = Some(match moved {
+                        Some((start, end)) => (start.min(index), end.max(index + 1)),
+                        None => (index, index + 1),
+                    });
+                }
+            }
+        }
+    }
+
+    updates.entities = if let Some(range) = moved {
+        EntityUpdate::Indexes(range)
+    } else {
+        EntityUpdate::None
+    };
+
+    updates
+}
+
+/// A handle to the event loop that can be cloned, and sent to other threads. Get one with
+/// `run_with_proxy`.
+pub struct EngineProxy<T: 'static> {
+    proxy: EventLoopProxy<EngineEvent<T>>,
+    deltas: mpsc::Sender<SceneDelta>,
+}
+
+impl<T> Clone for EngineProxy<T> {
+    fn clone(&self) -> Self {
+        Self {
+            proxy: self.proxy.clone(),
+            deltas: self.deltas.clone(),
+        }
+    }
+}
+
+impl<T> EngineProxy<T> {
+    pub(crate) fn new(
+        proxy: EventLoopProxy<EngineEvent<T>>,
+        deltas: mpsc::Sender<SceneDelta>,
+    ) -> Self {
+        Self { proxy, deltas }
+    }
+
+    /// Queue a change to the scene, to be applied before the next frame. Cheaper than `update`
+    /// for frequent changes, e.g. moving entities each simulation step.
+    pub fn send_delta(&self, delta: SceneDelta) -> Result<(), EventLoopClosed> {
+        self.deltas.send(delta).map_err(|_| EventLoopClosed)?;
+        self.wake()
+    }
+
+    /// Wake the event loop, e.g. after changing state the render handler reads. In
+    /// `RenderMode::OnDemand`, this renders a frame.
+    pub fn wake(&self) -> Result<(), EventLoopClosed> {
+        self.proxy
+            .send_event(EngineEvent::Wake)
+            .map_err(|_| EventLoopClosed)
+    }
+
+    /// Run `f` on the main thread before the next frame, with the application state and scene.
+    /// The updates it returns are applied as with those returned by the handlers.
+    pub fn update(
+        &self,
+        f: impl FnOnce(&mut T, &mut Scene) -> EngineUpdates + Send + 'static,
+    ) -> Result<(), EventLoopClosed> {
+        self.proxy
+            .send_event(EngineEvent::Update(Box::new(f)))
+            .map_err(|_| EventLoopClosed)
+    }
+
+    fn send_command(&self, command: Command<T>) -> Result<(), EventLoopClosed> {
+        self.proxy
+            .send_event(EngineEvent::Command(command))
+            .map_err(|_| EventLoopClosed)
+    }
+
+    /// Set the window's title, and `Scene::window_title`.
+    pub fn set_window_title(&self, title: impl Into<String>) -> Result<(), EventLoopClosed> {
+        self.send_command(Command::SetWindowTitle(title.into()))
+    }
+
+    /// Set the cursor's icon while it's over the window, e.g. to show what a drag will do.
+    pub fn set_cursor(&self, icon: CursorIcon) -> Result<(), EventLoopClosed> {
+        self.send_command(Command::SetCursor(icon))
+    }
+
+    /// Re-center `ControlScheme::Arc` on this point, as with `EngineUpdates::arc_center`.
+    pub fn recenter_arc(&self, center: Vec3) -> Result<(), EventLoopClosed> {
+        self.send_command(Command::RecenterArc(center))
+    }
+
+    /// Render the 3D view, without the GUI, and pass its sRGB RGBA pixels to `f` on the main
+    /// thread; e.g. to save it. This renders a separate frame, which blocks the event loop until
+    /// the GPU finishes it. Before the window opens, `f` receives an error.
+    pub fn request_screenshot(
+        &self,
+        f: impl FnOnce(&mut T, Result<RgbaImage, GraphicsError>) + Send + 'static,
+    ) -> Result<(), EventLoopClosed> {
+        self.send_command(Command::Screenshot(Box::new(f)))
+    }
+
+    /// Exit the event loop, as if the window was closed; `on_exit` runs, then `run` returns.
+    pub fn quit(&self) -> Result<(), EventLoopClosed> {
+        self.send_command(Command::Quit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIELDS: [EntityFields; 5] = [
+        EntityFields::POSITION,
+        EntityFields::ORIENTATION,
+        EntityFields::SCALE,
+        EntityFields::COLOR,
+        EntityFields::OPACITY,
+    ];
+
+    /// An entity with distinct values in each field `EntityDeltas` covers.
+    fn changed_entity(i: usize) -> Entity {
+        let v = i as f32;
+        Entity {
+            position: Vec3::new(v + 1., v + 2., v + 3.),
+            orientation: Quaternion::new(0.5, -0.5, 0.5, v),
+            scale: v + 4.,
+            color: (0.1, 0.2, v),
+            opacity: 0.25,
+            ..Default::default()
+        }
+    }
+
+    fn scene(n: usize) -> Scene {
+        let entities = (0..n)
+            .map(|_| Entity {
+                scale_partial: Some(Vec3::new(1., 2., 3.)),
+                ..Default::default()
+            })
+            .collect();
+
+        Scene {
+            entities,
+            ..Default::default()
+        }
+    }
+
+    /// Checks `ent` has `source`'s values in `fields`, and `original`'s in the others.
+    fn check_fields(ent: &Entity, source: &Entity, original: &Entity, fields: EntityFields) {
+        let pick = |f| if fields.contains(f) { source } else { original };
+
+        assert_eq!(ent.position, pick(EntityFields::POSITION).position);
+        assert_eq!(ent.orientation, pick(EntityFields::ORIENTATION).orientation);
+        assert_eq!(ent.scale, pick(EntityFields::SCALE).scale);
+        assert_eq!(ent.scale_partial, pick(EntityFields::SCALE).scale_partial);
+        assert_eq!(ent.color, pick(EntityFields::COLOR).color);
+        assert_eq!(ent.opacity, pick(EntityFields::OPACITY).opacity);
+    }
+
+    #[test]
+    fn round_trip_each_field_mask() {
+        // Every combination of fields.
+        for mask in 1..1 << FIELDS.len() {
+            let fields = FIELDS
+                .iter()
+                .enumerate()
+                .filter(|(bit, _)| mask & (1 << bit) != 0)
+                .fold(EntityFields::default(), |acc, (_, &f)| acc | f);
+
+            let mut scene = scene(5);
+            let original = scene.entities[0].clone();
+
+            let mut deltas = EntityDeltas::new(fields);
+            deltas.push(3, &changed_entity(3));
+            deltas.push(1, &changed_entity(1));
+            assert_eq!(deltas.len(), 2);
+            assert_eq!(deltas.values.len(), 2 * fields.stride());
+
+            let updates =
+                apply_scene_deltas(&mut scene, [SceneDelta::Entities(deltas)].into_iter());
+
+            assert_eq!(updates.entities, EntityUpdate::Indexes((1, 4)));
+            for i in [1, 3] {
+                check_fields(&scene.entities[i], &changed_entity(i), &original, fields);
+            }
+            for i in [0, 2, 4] {
+                check_fields(&scene.entities[i], &original, &original, fields);
+            }
+        }
+    }
+
+    #[test]
+    fn positions() {
+        let mut scene = scene(3);
+        let positions = [Vec3::new(1., 2., 3.), Vec3::new(-4., 5., -6.)];
+        let deltas = EntityDeltas::positions(&[2, 0], &positions);
+        assert_eq!(deltas.fields(), EntityFields::POSITION);
+
+        let updates = apply_scene_deltas(&mut scene, [SceneDelta::Entities(deltas)].into_iter());
+
+        assert_eq!(updates.entities, EntityUpdate::Indexes((0, 3)));
+        assert_eq!(scene.entities[2].position, positions[0]);
+        assert_eq!(scene.entities[0].position, positions[1]);
+        assert_eq!(scene.entities[1].position, Vec3::new_zero());
+        // Other fields are left alone.
+        assert_eq!(scene.entities[2].scale_partial, Some(Vec3::new(1., 2., 3.)));
+    }
+
+    #[test]
+    fn out_of_range_index_skipped() {
+        let fields = EntityFields::POSITION | EntityFields::COLOR;
+        let mut scene = scene(3);
+
+        let mut deltas = EntityDeltas::new(fields);
+        deltas.push(7, &changed_entity(7));
+        deltas.push(1, &changed_entity(1));
+        deltas.push(3, &changed_entity(3));
+
+        let updates = apply_scene_deltas(&mut scene, [SceneDelta::Entities(deltas)].into_iter());
+
+        // Entities after the missing one still get their own values.
+        assert_eq!(updates.entities, EntityUpdate::Indexes((1, 2)));
+        assert_eq!(scene.entities.len(), 3);
+        check_fields(
+            &scene.entities[1],
+            &changed_entity(1),
+            &scene.entities[0],
+            fields,
+        );
+
+        // Only missing entities: nothing to update.
+        let deltas = EntityDeltas::positions(&[3], &[Vec3::new(1., 1., 1.)]);
+        let updates = apply_scene_deltas(&mut scene, [SceneDelta::Entities(deltas)].into_iter());
+        assert_eq!(updates.entities, EntityUpdate::None);
+    }
+}
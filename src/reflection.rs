@@ -0,0 +1,247 @@
+//! Planar reflections, for `Scene::reflection_plane`. Each frame, the scene is drawn mirrored
+//! through the plane into an offscreen texture; entities with `reflectivity` blend it into their
+//! color, at the same pixel.
+
+use bytemuck::{Pod, Zeroable};
+use wgpu::{
+    BindGroup, BindGroupLayout, Buffer, BufferUsages, Device, Queue, Sampler, ShaderStages,
+    TextureFormat, TextureView, util::DeviceExt,
+};
+
+use crate::{
+    Camera,
+    graphics::BindGroupData,
+    stereo::{Eye, EyeCamera},
+};
+
+/// Matches `Reflection` in `shader.wgsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct ReflectionUniform {
+    /// The 3D viewport the reflection was drawn in, in pixels: x, y, width, height. A width of 0
+    /// means there's no reflection to show.
+    viewport: [f32; 4],
+    /// 1 while drawing the reflection itself: reflective surfaces are at the mirror plane, so we
+    /// skip them.
+    hide_reflective: u32,
+    _pad: [u32; 3],
+}
+
+/// The texture the mirrored scene is drawn to, and the bind group the mesh shader reads it with.
+struct ReflectionTarget {
+    view: TextureView,
+    bind_group: BindGroup,
+    size: (u32, u32),
+}
+
+/// GPU state for `Scene::reflection_plane`.
+pub(crate) struct ReflectionRenderer {
+    /// Bind group 3 of the mesh pipelines.
+    pub layout: BindGroupLayout,
+    camera: EyeCamera,
+    sampler: Sampler,
+    buf: Buffer,
+    /// Bound instead of the reflection while drawing it, or when there's none; a 1x1 texture.
+    buf_dummy: Buffer,
+    bind_group_dummy: BindGroup,
+    format: TextureFormat,
+    target: Option<ReflectionTarget>,
+    /// Set if the reflection was drawn this frame.
+    active: bool,
+}
+
+impl ReflectionRenderer {
+    pub fn new(
+        device: &Device,
+        bind_groups: &BindGroupData,
+        cam_basis_buf: &Buffer,
+        format: TextureFormat,
+    ) -> Self {
+        let fragment_entry = |binding, ty| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStages::FRAGMENT,
+            ty,
+            count: None,
+        };
+
+        let layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Reflection bind group layout"),
+                entries: &[
+                    fragment_entry(
+                        0,
+                        wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                    ),
+                    fragment_entry(
+                        1,
+                        wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    ),
+                    fragment_entry(
+                        2,
+                        wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: wgpu::BufferSize::new(
+                                size_of::<ReflectionUniform>() as _
+                            ),
+                        },
+                    ),
+                ],
+            });
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Reflection sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let create_buf = |label| {
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(label),
+                contents: bytemuck::bytes_of(&ReflectionUniform::zeroed()),
+                usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            })
+        };
+        let buf = create_buf("Reflection buffer");
+        let buf_dummy = create_buf("Reflection dummy buffer");
+
+        let texture_dummy = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Reflection dummy texture"),
+            size: wgpu::Extent3d {
+                width: 1,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let view_dummy = texture_dummy.create_view(&Default::default());
+
+        Self {
+            bind_group_dummy: create_bind_group(device, &layout, &view_dummy, &sampler, &buf_dummy),
+            layout,
+            camera: EyeCamera::new(device, bind_groups, cam_basis_buf),
+            sampler,
+            buf,
+            buf_dummy,
+            format,
+            target: None,
+            active: false,
+        }
+    }
+
+    /// Write the mirrored camera, and return the texture to draw it into, if there's a reflection
+    /// to draw this frame. `height` is the mirror plane's; we skip it while the camera is below.
+    pub fn prepare(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        camera: &Camera,
+        height: Option<f32>,
+        halo_expansion: f32,
+        size: (u32, u32),
+        viewport: (f32, f32, f32, f32),
+    ) -> Option<TextureView> {
+        let height = height.filter(|h| camera.position.y > *h);
+        self.active = height.is_some();
+
+        let dummy = ReflectionUniform {
+            hide_reflective: self.active as u32,
+            ..ReflectionUniform::zeroed()
+        };
+        queue.write_buffer(&self.buf_dummy, 0, bytemuck::bytes_of(&dummy));
+
+        let height = height?;
+        self.camera
+            .write(queue, &camera.mirrored(height), halo_expansion);
+
+        let uniform = ReflectionUniform {
+            viewport: [viewport.0, viewport.1, viewport.2, viewport.3],
+            ..ReflectionUniform::zeroed()
+        };
+        queue.write_buffer(&self.buf, 0, bytemuck::bytes_of(&uniform));
+
+        if self.target.as_ref().is_none_or(|t| t.size != size) {
+            let texture = device.create_texture(&wgpu::TextureDescriptor {
+                label: Some("Reflection texture"),
+                size: wgpu::Extent3d {
+                    width: size.0,
+                    height: size.1,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: self.format,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                    | wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            });
+            let view = texture.create_view(&Default::default());
+            let bind_group =
+                create_bind_group(device, &self.layout, &view, &self.sampler, &self.buf);
+
+            self.target = Some(ReflectionTarget {
+                view,
+                bind_group,
+                size,
+            });
+        }
+
+        Some(self.target.as_ref().unwrap().view.clone())
+    }
+
+    /// The reflection texture we allocated, if any; for memory usage.
+    pub fn target_texture(&self) -> Option<&wgpu::Texture> {
+        self.target.as_ref().map(|t| t.view.texture())
+    }
+
+    /// Bind groups for the mirrored camera, the Gaussian camera, and the halo camera.
+    pub fn cam_bind_groups(&self) -> (&BindGroup, &BindGroup, &BindGroup) {
+        self.camera.bind_groups()
+    }
+
+    /// Bind group 3 of the mesh pipelines, when drawing from `eye`.
+    pub fn bind_group(&self, eye: Eye) -> &BindGroup {
+        match &self.target {
+            Some(target) if self.active && eye != Eye::Reflection => &target.bind_group,
+            _ => &self.bind_group_dummy,
+        }
+    }
+}
+
+fn create_bind_group(
+    device: &Device,
+    layout: &BindGroupLayout,
+    view: &TextureView,
+    sampler: &Sampler,
+    buf: &Buffer,
+) -> BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Reflection bind group"),
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(sampler),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: buf.as_entire_binding(),
+            },
+        ],
+    })
+}
@@ -0,0 +1,191 @@
+//! Renders the 3D scene at a different resolution from the window. The scene draws into an
+//! offscreen texture of that size, which this pass resamples to the window. It's upsampled with
+//! bilinear filtering, e.g. for `GraphicsSettings::adaptive_quality`, which lowers the resolution
+//! to hold a frame rate. It's downsampled with a box filter, for `GraphicsSettings::supersampling`.
+
+use wgpu::{
+    BindGroup, BindGroupLayout, CommandEncoder, Device, RenderPipeline, Sampler, ShaderStages,
+    StoreOp, TextureFormat, TextureView,
+};
+
+use crate::frame_timing::PassTimestamps;
+
+/// The offscreen scene texture, and the bind group reading it.
+struct SceneTarget {
+    view: TextureView,
+    bind_group: BindGroup,
+    size: (u32, u32),
+}
+
+pub(crate) struct ResampleRenderer {
+    layout: BindGroupLayout,
+    pipeline: RenderPipeline,
+    sampler: Sampler,
+    format: TextureFormat,
+    target: Option<SceneTarget>,
+}
+
+impl ResampleRenderer {
+    pub fn new(device: &Device, format: TextureFormat) -> Self {
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Resample bind group layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Resample shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("shader_resample.wgsl").into()),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Resample pipeline layout"),
+            bind_group_layouts: &[Some(&layout)],
+            immediate_size: 0,
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Resample pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                compilation_options: Default::default(),
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                compilation_options: Default::default(),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: Default::default(),
+            depth_stencil: None,
+            multisample: Default::default(),
+            multiview_mask: None,
+            cache: None,
+        });
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Resample sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        Self {
+            layout,
+            pipeline,
+            sampler,
+            format,
+            target: None,
+        }
+    }
+
+    /// The texture to draw the scene into, at `size`.
+    pub fn target(&mut self, device: &Device, size: (u32, u32)) -> &TextureView {
+        if self.target.as_ref().is_none_or(|t| t.size != size) {
+            let texture = device.create_texture(&wgpu::TextureDescriptor {
+                label: Some("Resample scene texture"),
+                size: wgpu::Extent3d {
+                    width: size.0,
+                    height: size.1,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: self.format,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                    | wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            });
+            let view = texture.create_view(&Default::default());
+
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("Resample bind group"),
+                layout: &self.layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(&self.sampler),
+                    },
+                ],
+            });
+
+            self.target = Some(SceneTarget {
+                view,
+                bind_group,
+                size,
+            });
+        }
+
+        &self.target.as_ref().unwrap().view
+    }
+
+    /// The offscreen scene texture we allocated, if any; for memory usage.
+    pub fn target_texture(&self) -> Option<&wgpu::Texture> {
+        self.target.as_ref().map(|t| t.view.texture())
+    }
+
+    /// Free the offscreen texture, e.g. once the scene renders at the window's resolution again.
+    pub fn release(&mut self) {
+        self.target = None;
+    }
+
+    /// Resample the texture from `target` into `output`, covering it.
+    pub fn apply(
+        &self,
+        encoder: &mut CommandEncoder,
+        output: &TextureView,
+        timestamps: Option<PassTimestamps>,
+    ) {
+        let Some(target) = &self.target else {
+            return;
+        };
+
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Resample"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: output,
+                depth_slice: None,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: timestamps.as_ref().map(PassTimestamps::writes),
+            occlusion_query_set: None,
+            multiview_mask: None,
+        });
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, &target.bind_group, &[]);
+        pass.draw(0..3, 0..1);
+    }
+}
@@ -14,6 +14,13 @@ struct Camera {
     far: f32,
     // Depth-aware halo prepass: > 0 means inflate vertices along normals by this amount.
     halo_expansion: f32,
+    // Interval, offset, width in pixels, and opacity. An interval of 0 means off.
+    isolines: vec4<f32>,
+    isoline_color: vec4<f32>,
+    // Base height, falloff, and density. A density of 0 means off.
+    height_fog: vec4<f32>,
+    // Exposure, and 1 / gamma.
+    exposure_gamma: vec4<f32>,
 }
 
 struct PointLight {
@@ -43,14 +50,35 @@ var<uniform> camera: Camera;
 // this is due to the dynamic-sized point light array.
 var<storage, read> lighting: Lighting;
 
+// The mesh's texture; a 1x1 white one if it has none.
+@group(2) @binding(0)
+var t_diffuse: texture_2d<f32>;
+@group(2) @binding(1)
+var s_diffuse: sampler;
+
+struct Reflection {
+    // The 3D viewport the reflection was drawn in, in pixels. A width of 0 means none.
+    viewport: vec4<f32>,
+    // 1 while drawing the reflection itself.
+    hide_reflective: u32,
+}
+
+// The scene mirrored through `Scene::reflection_plane`, flipped horizontally; a 1x1 texture if
+// there's none.
+@group(3) @binding(0)
+var t_reflection: texture_2d<f32>;
+@group(3) @binding(1)
+var s_reflection: sampler;
+@group(3) @binding(2)
+var<uniform> reflection: Reflection;
 
 struct VertexIn {
     @location(0) position: vec3<f32>,
-    @location(1) tex_coords: vec2<f32>, // unused
+    @location(1) tex_coords: vec2<f32>,
     @location(2) normal: vec3<f32>,
     @location(3) tangent: vec3<f32>,
-    @location(4) bitangent: vec3<f32>,
     @location(5) color: vec4<f32>, // from Unorm8x4 -> converted to 0..1
+    @location(15) scalar: vec2<f32>, // Value, and 1 if present.
 }
 
 // These are matrix columns; we can't pass matrices directly for vertex attributes.
@@ -60,12 +88,12 @@ struct InstanceIn {
     @location(8) model_matrix_2: vec4<f32>,
     @location(9) model_matrix_3: vec4<f32>,
 
-    @location(10) normal_matrix_0: vec3<f32>,
-    @location(11) normal_matrix_1: vec3<f32>,
-    @location(12) normal_matrix_2: vec3<f32>,
-
+    @location(10) rim_light: vec4<f32>, // Color times intensity, and power.
+    @location(11) effects: vec4<f32>, // X-ray opacity, blend mode, reflectivity, then UV rotation.
+    @location(4) uv_transform: vec4<f32>, // UV offset, then scale.
     @location(13) color: vec4<f32>, // entity color + opacity.
-    @location(14) shinyness: f32,
+    @location(14) material: vec4<f32>, // Shinyness, then the shading mode and its parameters.
+    @location(12) custom: vec4<f32>, // Application-defined; see `Entity::custom`. Unused here.
 }
 
 struct VertexOut {
@@ -75,6 +103,12 @@ struct VertexOut {
     @location(2) color: vec4<f32>,
     @location(3) shinyness: f32,
     @location(4) world_posit: vec3<f32>,
+    @location(5) scalar: vec2<f32>,
+    // Mode, and its parameters.
+    @location(6) @interpolate(flat) shading: vec3<f32>,
+    @location(7) @interpolate(flat) rim_light: vec4<f32>,
+    @location(8) @interpolate(flat) effects: vec4<f32>,
+    @location(9) @interpolate(flat) custom: vec4<f32>,
 
 //        @location(1) tangent_position: vec3<f32>,
 //        @location(2) tangent_light_position: vec3<f32>,
@@ -83,6 +117,25 @@ struct VertexOut {
 
 fn saturate(x: f32) -> f32 { return clamp(x, 0.0, 1.0); }
 
+// Matches `srgb_to_linear` in `color.rs`.
+fn srgb_to_linear(c: vec3<f32>) -> vec3<f32> {
+    return select(pow((c + 0.055) / 1.055, vec3<f32>(2.4)), c / 12.92, c <= vec3<f32>(0.04045));
+}
+
+// Shading modes; see `Shading` in `types.rs`.
+const SHADING_TOON: f32 = 1.0;
+const SHADING_UNLIT: f32 = 2.0;
+const SHADING_FLAT: f32 = 3.0;
+
+// Blend modes; see `BlendMode` in `types.rs`.
+const BLEND_ADDITIVE: f32 = 1.0;
+const BLEND_PREMULTIPLIED: f32 = 2.0;
+
+// Quantize lighting from 0 to 1 into `bands` levels, including 0 and 1.
+fn toon_step(x: f32, bands: f32) -> f32 {
+    return min(floor(x * bands) / (bands - 1.0), 1.0);
+}
+
 fn fog_weight_band(distance_to_cam: f32) -> f32 {
     // Map distance into 0..1 between start and end
     let span = max(1e-4, camera.fog_end - camera.fog_start);
@@ -93,6 +146,41 @@ fn fog_weight_band(distance_to_cam: f32) -> f32 {
     return 1.0 - exp(-camera.fog_density * shaped);
 }
 
+// Exposure, then gamma, on a shaded color in linear space.
+fn exposure_gamma(color: vec3<f32>) -> vec3<f32> {
+    return pow(max(color * camera.exposure_gamma.x, vec3<f32>(0.0)), vec3<f32>(camera.exposure_gamma.y));
+}
+
+// Exponential height fog: density `d * exp(-falloff * (y - base))`, integrated along the ray from
+// the camera to `posit`. Returns 0 to 1.
+fn fog_weight_height(posit: vec3<f32>) -> f32 {
+    let base = camera.height_fog.x;
+    let falloff = max(camera.height_fog.y, 1e-4);
+    let ray = posit - camera.position.xyz;
+
+    // Exponents are clamped to keep them finite, far below the base.
+    let at_cam = exp(min(-falloff * (camera.position.y - base), 80.0));
+    let dy = falloff * ray.y;
+
+    // The mean density along the ray, relative to `d`.
+    var mean = at_cam;
+    if abs(dy) > 1e-4 {
+        let at_posit = exp(min(-falloff * (posit.y - base), 80.0));
+        mean = (at_cam - at_posit) / dy;
+    }
+
+    return 1.0 - exp(-camera.height_fog.z * mean * length(ray));
+}
+
+// See `UvTransform` in `types.rs`: rotate about the texture's center, then scale, then offset.
+fn transform_uv(uv: vec2<f32>, offset_scale: vec4<f32>, rotation: f32) -> vec2<f32> {
+    let c = cos(rotation);
+    let s = sin(rotation);
+    let centered = uv - 0.5;
+    let rotated = vec2<f32>(c * centered.x - s * centered.y, s * centered.x + c * centered.y) + 0.5;
+    return rotated * offset_scale.zw + offset_scale.xy;
+}
+
 @vertex
 fn vs_main(
     vertex_in: VertexIn,
@@ -106,18 +194,20 @@ fn vs_main(
         instance.model_matrix_3,
     );
 
-    // The normal matrix includes rotation only.
-    var normal_mat = mat3x3<f32>(
-        instance.normal_matrix_0,
-        instance.normal_matrix_1,
-        instance.normal_matrix_2,
-    );
+    // The normal matrix is the inverse transpose of the model matrix's upper 3x3, so normals stay
+    // perpendicular to surfaces under non-uniform scale. We use its cofactor matrix, which is this
+    // times the determinant; we normalize after anyway. The sign corrects for mirroring.
+    let m0 = model_mat[0].xyz;
+    let m1 = model_mat[1].xyz;
+    let m2 = model_mat[2].xyz;
+    let normal_mat = mat3x3<f32>(cross(m1, m2), cross(m2, m0), cross(m0, m1))
+        * sign(dot(m0, cross(m1, m2)));
 
     // We use the tangent matrix, and tangent out values for normal mapping.
     // This is currently unimplemented.
     var world_normal = normalize(normal_mat * vertex_in.normal);
 //    var world_tangent = normalize(normal_mat * vertex_in.tangent);
-//    var world_bitangent = normalize(normal_mat * vertex_in.bitangent);
+//    var world_bitangent = cross(world_normal, world_tangent);
 
     // Construct the tangent matrix
 //    var tangent_mat = transpose(mat3x3<f32>(
@@ -144,15 +234,22 @@ fn vs_main(
 //    result.tangent_view_position = tangent_mat * camera.position.xyz;
 //    result.tangent_light_position = tangent_matrix * light.position;
     result.normal = world_normal;
+    result.tex_coords = transform_uv(vertex_in.tex_coords, instance.uv_transform, instance.effects.w);
 
     if (vertex_in.color.a == 0.0) {
         result.color = instance.color;
     } else {
-        result.color = vertex_in.color;
+        // Vertex colors are sRGB, as with other colors we take; light in linear.
+        result.color = vec4<f32>(srgb_to_linear(vertex_in.color.rgb), vertex_in.color.a);
     }
 
-    result.shinyness = instance.shinyness;
+    result.shinyness = instance.material.x;
+    result.shading = instance.material.yzw;
+    result.rim_light = instance.rim_light;
+    result.effects = instance.effects;
+    result.custom = instance.custom;
     result.world_posit = world_posit.xyz;
+    result.scalar = vertex_in.scalar;
 
     return result;
 }
@@ -171,6 +268,29 @@ fn fs_main(
     vertex: VertexOut,
     @builtin(front_facing) front: bool,
 ) -> @location(0) vec4<f32> {
+    return shade(vertex, front);
+}
+
+/// For the transparent passes, which blend with premultiplied alpha; this lets each instance
+/// choose its blend mode.
+@fragment
+fn fs_blend(
+    vertex: VertexOut,
+    @builtin(front_facing) front: bool,
+) -> @location(0) vec4<f32> {
+    let color = shade(vertex, front);
+
+    if (vertex.effects.y == BLEND_ADDITIVE) {
+        return vec4<f32>(color.rgb * color.a, 0.0);
+    }
+    if (vertex.effects.y == BLEND_PREMULTIPLIED) {
+        return color;
+    }
+    return vec4<f32>(color.rgb * color.a, color.a);
+}
+
+// The lit color of a fragment, with straight alpha.
+fn shade(vertex: VertexOut, front: bool) -> vec4<f32> {
     // Always renormalise after interpolation
     var normal = normalize(vertex.normal);
 
@@ -179,6 +299,13 @@ fn fs_main(
         normal = -normal;
     }
 
+    // Flat shading: light each triangle with its own normal, from screen-space derivatives of
+    // position. Orient it to agree with the interpolated normal.
+    let face_normal = normalize(cross(dpdx(vertex.world_posit), dpdy(vertex.world_posit)));
+    if (vertex.shading.x == SHADING_FLAT) {
+        normal = select(-face_normal, face_normal, dot(face_normal, normal) >= 0.0);
+    }
+
     // Ambient lighting
     // todo: Don't multiply ambient for every fragment; do it on the CPU.
     var ambient = lighting.ambient_color * lighting.ambient_intensity;
@@ -213,6 +340,11 @@ fn fs_main(
         // Diffuse lighting. This is essentially cosine los.
         var diffuse_attenuation = max(dot(normal, -light_to_vert_dir), 0.);
 
+        let toon = vertex.shading.x == SHADING_TOON;
+        if (toon) {
+            diffuse_attenuation = toon_step(diffuse_attenuation, vertex.shading.y);
+        }
+
         // For directional lights, don't attenuate further if the vertex is inside the light's
         // FOV. If outside, gradually attentuate to 0.
         if light.directional != 0u {
@@ -236,33 +368,105 @@ fn fs_main(
             // Fresnel Effect: Adjust specular based on view angle
             var fresnel = pow(1.0 - dot(view_dir, normal), 5.0);
             var specular_coeff = pow(max(dot(normal, half_dir), 0.), vertex.shinyness);
+            if (toon) {
+                specular_coeff = step(0.5, specular_coeff);
+            }
             specular += fresnel * light.specular_color * specular_coeff * light.specular_intensity * dist_attenuation;
         }
     }
 
     // Modulated combine
-    let base   = vertex.color.rgb;       // Albedo / base colour coming from the mesh
-    let litRGB = (ambient.rgb + diffuse.rgb) * base   // Lambert terms tinted
+    let tex    = textureSample(t_diffuse, s_diffuse, vertex.tex_coords);
+    let base   = vertex.color.rgb * tex.rgb;  // Albedo / base colour coming from the mesh
+    var litRGB = (ambient.rgb + diffuse.rgb) * base   // Lambert terms tinted
                + specular.rgb;                        // Specular left un-tinted
 
-    var result = vec4<f32>(litRGB, vertex.color.a);
+    // Unlit: the color as-is, e.g. for markers and annotations.
+    if (vertex.shading.x == SHADING_UNLIT) {
+        litRGB = base;
+    }
+
+    var alpha = vertex.color.a * tex.a;
+    let n_dot_v = abs(dot(normal, view_dir));
+
+    // Fresnel rim light: strongest where the surface turns away from the viewer. It also makes
+    // transparent surfaces more opaque there, outlining them.
+    if (vertex.rim_light.w > 0.0) {
+        let rim = pow(1.0 - n_dot_v, vertex.rim_light.w);
+        litRGB += vertex.rim_light.rgb * rim;
+
+        let rim_strength = max(vertex.rim_light.r, max(vertex.rim_light.g, vertex.rim_light.b));
+        alpha += (1.0 - alpha) * saturate(rim_strength * rim);
+    }
 
-    // Apply the fog; attentuate pixels that meet the fog criteria.
+    var result = vec4<f32>(litRGB, alpha);
+
+    // Isolines: lines where the scalar crosses a multiple of the interval. We measure distance to
+    // the nearest one in pixels using its screen-space derivative, for a constant line width.
+    // Derivatives must be taken in uniform control flow, so before the branch.
+    let iso_interval = max(camera.isolines.x, 1e-12);
+    let iso_s = (vertex.scalar.x - camera.isolines.y) / iso_interval;
+    let iso_dist = abs(fract(iso_s + 0.5) - 0.5) / max(fwidth(iso_s), 1e-6);
+
+    if (camera.isolines.x > 0.0 && vertex.scalar.y > 0.5) {
+        let half_width = camera.isolines.z * 0.5;
+        let coverage = 1.0 - smoothstep(half_width - 0.5, half_width + 0.5, iso_dist);
+        let lined = mix(result.rgb, camera.isoline_color.rgb, coverage * camera.isolines.w);
+        result = vec4<f32>(lined, result.a);
+    }
+
+    // Toon outlines: black where the surface turns away from the viewer. Smoothed over a pixel.
+    let n_dot_v_width = fwidth(n_dot_v);
+
+    if (vertex.shading.x == SHADING_TOON && vertex.shading.z > 0.0) {
+        let outline = 1.0 - smoothstep(
+            vertex.shading.z - n_dot_v_width,
+            vertex.shading.z + n_dot_v_width,
+            n_dot_v,
+        );
+        result = vec4<f32>(result.rgb * (1.0 - outline), result.a);
+    }
+
+    // Planar reflection: the mirrored scene, at this pixel, flipped back horizontally. Reflective
+    // surfaces are in the mirror plane, so they're left out of the reflection itself.
+    if (vertex.effects.z > 0.0) {
+        if (reflection.hide_reflective != 0u) {
+            discard;
+        }
+
+        if (reflection.viewport.z > 0.0) {
+            let px = vec2<f32>(
+                2.0 * reflection.viewport.x + reflection.viewport.z - vertex.clip_posit.x,
+                vertex.clip_posit.y,
+            );
+            let uv = px / vec2<f32>(textureDimensions(t_reflection));
+            let reflected = textureSampleLevel(t_reflection, s_reflection, uv, 0.0).rgb;
+            result = vec4<f32>(mix(result.rgb, reflected, saturate(vertex.effects.z)), result.a);
+        }
+    }
+
+    // Apply the fog; attentuate pixels that meet the fog criteria. Distance and height fog
+    // combine as layers.
+    var fog_w = 0.0;
     if (camera.fog_end > camera.fog_start) {
         let view_dist = length(view_diff);
-        let w = clamp(fog_weight_band(view_dist), 0.0, 1.0);
-        let fogged = mix(result.rgb, camera.fog_color, w);
+        fog_w = clamp(fog_weight_band(view_dist), 0.0, 1.0);
+    }
+    if camera.height_fog.z > 0.0 {
+        fog_w = 1.0 - (1.0 - fog_w) * (1.0 - fog_weight_height(vertex.world_posit));
+    }
+    if fog_w > 0.0 {
+        let fogged = mix(result.rgb, camera.fog_color, fog_w);
         result = vec4<f32>(fogged, result.a);
     }
 
     // Edge cueing: darken silhouette edges where the surface normal is nearly
     // perpendicular to the view direction (Tarini et al. 2006).
-    if camera.edge_cueing > 0.0 {
-        let n_dot_v = abs(dot(normal, view_dir));
+    if camera.edge_cueing > 0.0 && vertex.shading.x != SHADING_UNLIT {
         let edge_factor = pow(1.0 - n_dot_v, 3.0);
         let darkening = 1.0 - camera.edge_cueing * 0.85 * edge_factor;
         result = vec4<f32>(result.rgb * darkening, result.a);
     }
 
-    return result;
-}
\ No newline at end of file
+    return vec4<f32>(exposure_gamma(result.rgb), result.a);
+}
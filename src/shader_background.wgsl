@@ -0,0 +1,65 @@
+// Backgrounds drawn behind the scene: a screen-space gradient, or a sky and ground that follow
+// the camera.
+
+const MODE_GRADIENT: u32 = 0u;
+const MODE_HORIZON: u32 = 1u;
+
+// Colors are linear. For gradients, `upper` is the top and `lower` the bottom, and `middle` is
+// unused.
+struct Background {
+    proj_view_inv: mat4x4<f32>,
+    upper: vec4<f32>,
+    middle: vec4<f32>,
+    lower: vec4<f32>,
+    mode: u32,
+};
+
+@group(0) @binding(0)
+var<uniform> background: Background;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) ndc: vec2<f32>,
+};
+
+// A triangle covering the viewport. No vertex buffer; corners are indexed by vertex.
+@vertex
+fn vs_main(@builtin(vertex_index) i: u32) -> VertexOutput {
+    var corners = array<vec2<f32>, 3>(
+        vec2<f32>(-1.0, -1.0),
+        vec2<f32>(3.0, -1.0),
+        vec2<f32>(-1.0, 3.0),
+    );
+
+    var result: VertexOutput;
+    result.clip_position = vec4<f32>(corners[i], 1.0, 1.0);
+    result.ndc = corners[i];
+    return result;
+}
+
+fn unproject(ndc: vec2<f32>, depth: f32) -> vec3<f32> {
+    let p = background.proj_view_inv * vec4<f32>(ndc, depth, 1.0);
+    return p.xyz / p.w;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    if (background.mode == MODE_GRADIENT) {
+        let t = in.ndc.y * 0.5 + 0.5;
+        return vec4<f32>(mix(background.lower.rgb, background.upper.rgb, t), 1.0);
+    }
+
+    // The view ray's elevation; +Y is up.
+    let dir = normalize(unproject(in.ndc, 1.0) - unproject(in.ndc, 0.0));
+
+    var color: vec3<f32>;
+    if (dir.y >= 0.0) {
+        // The sky brightens quickly above the horizon, then eases toward its overhead color.
+        color = mix(background.middle.rgb, background.upper.rgb, sqrt(dir.y));
+    } else {
+        // A narrow blend keeps the horizon line soft.
+        color = mix(background.middle.rgb, background.lower.rgb, smoothstep(0.0, 0.05, -dir.y));
+    }
+
+    return vec4<f32>(color, 1.0);
+}
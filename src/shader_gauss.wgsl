@@ -14,6 +14,20 @@ var<uniform> camera: Camera;
 @group(0) @binding(1)
 var<uniform> cameraBasis: CameraBasis;
 
+// From `GaussianRenderSettings`.
+struct Display {
+    window: vec2<f32>,
+    gamma: f32,
+    windowed: u32,
+    // 0 if gaussians keep their own color.
+    colormap_len: u32,
+    // Evenly-spaced colormap points; only the first `colormap_len` are used.
+    colormap: array<vec4<f32>, 11>,
+};
+
+@group(1) @binding(0)
+var<uniform> display: Display;
+
 
 // Vertex input (per-vertex and per-instance):
 struct VertexInput {
@@ -83,12 +97,29 @@ fn fs_main(input: FragmentInput) -> @location(0) vec4<f32> {
     // Gaussian radial falloff: exp(-r^2 / (2σ^2)), σ = inst_width
     let sigma = input.inst_width;
 
-    // intensity = amplitude * exp(-(r^2) / (2 * sigma^2))
-    let amp = input.inst_amplitude * 0.2; // todo: Experimenting. Maybe do this CPU-side.
-    let intensity = amp * exp(-r_sq / (2.0 * sigma * sigma));
+    // value = amplitude * exp(-(r^2) / (2 * sigma^2))
+    let value = input.inst_amplitude * exp(-r_sq / (2.0 * sigma * sigma));
+
+    var intensity = value * 0.2; // todo: Experimenting. Maybe do this CPU-side.
+    if (display.windowed != 0u) {
+        let span = max(display.window.y - display.window.x, 1e-12);
+        intensity = clamp((value - display.window.x) / span, 0.0, 1.0);
+    }
+    intensity = pow(max(intensity, 0.0), display.gamma);
+
+    var base = input.inst_color;
+    if (display.colormap_len > 1u) {
+        base = vec4<f32>(colormap(intensity), input.inst_color.a);
+    }
 
     // Modulate base color by intensity (premultiplied alpha)
-    var color = input.inst_color * intensity;
+    return base * intensity;
+}
+
+fn colormap(t: f32) -> vec3<f32> {
+    let n = display.colormap_len;
+    let pos = clamp(t, 0.0, 1.0) * f32(n - 1u);
+    let i = min(u32(pos), n - 2u);
 
-    return color;
+    return mix(display.colormap[i].rgb, display.colormap[i + 1u].rgb, pos - f32(i));
 }
\ No newline at end of file
@@ -0,0 +1,70 @@
+// Sums gaussian densities on a regular grid, for `TextureSource::GaussianField`. Each invocation
+// evaluates two adjacent grid points along X, and packs them as 16-bit floats into one word of a
+// buffer laid out for copying into the volume texture.
+
+struct Gaussian {
+    center: vec3<f32>,
+    amplitude: f32,
+    width: f32,
+    _pad0: f32,
+    _pad1: f32,
+    _pad2: f32,
+};
+
+struct Params {
+    grid_min: vec4<f32>,
+    // Between adjacent grid points, along each axis.
+    grid_step: vec4<f32>,
+    // Grid points along each axis.
+    dims: vec4<u32>,
+    // The first row, and Z layer, of the block being evaluated.
+    y_offset: u32,
+    z_offset: u32,
+    // Rows per layer of the block.
+    rows: u32,
+    // Words per row in `output`; rows are padded for the texture copy.
+    row_words: u32,
+    gauss_count: u32,
+    _pad0: u32,
+    _pad1: u32,
+    _pad2: u32,
+};
+
+@group(0) @binding(0)
+var<uniform> params: Params;
+@group(0) @binding(1)
+var<storage, read> gaussians: array<Gaussian>;
+@group(0) @binding(2)
+var<storage, read_write> output: array<u32>;
+
+fn density(p: vec3<f32>) -> f32 {
+    var sum = 0.0;
+    for (var i = 0u; i < params.gauss_count; i++) {
+        let g = gaussians[i];
+        let d = p - g.center;
+        sum += g.amplitude * exp(-dot(d, d) / (2.0 * g.width * g.width));
+    }
+    return sum;
+}
+
+@compute @workgroup_size(64)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let word = id.x;
+    let y = id.y + params.y_offset;
+    let z = id.z + params.z_offset;
+
+    let x = word * 2u;
+    if (x >= params.dims.x) {
+        return;
+    }
+
+    let p = params.grid_min.xyz + params.grid_step.xyz * vec3<f32>(f32(x), f32(y), f32(z));
+
+    var values = vec2<f32>(density(p), 0.0);
+    // With an odd count along X, the last word has one point.
+    if (x + 1u < params.dims.x) {
+        values.y = density(p + vec3<f32>(params.grid_step.x, 0.0, 0.0));
+    }
+
+    output[(id.z * params.rows + id.y) * params.row_words + word] = pack2x16float(values);
+}
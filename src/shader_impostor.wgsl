@@ -0,0 +1,459 @@
+// Sphere and cylinder impostors. Each is simple geometry covering the shape on screen: a
+// camera-facing quad for spheres, and a box for cylinders. The fragment shader intersects the
+// view ray with the true shape, and writes the hit's depth, so shapes are smooth at any distance,
+// and intersect other geometry correctly. Lit as in `shader.wgsl`.
+
+// As `Camera` in `shader.wgsl`.
+struct Camera {
+    proj_view: mat4x4<f32>,
+    position: vec4<f32>,
+    fog_density: f32,
+    fog_power: f32,
+    fog_start: f32,
+    fog_end: f32,
+    fog_color: vec3<f32>,
+    edge_cueing: f32,
+    near: f32,
+    far: f32,
+    halo_expansion: f32,
+    isolines: vec4<f32>,
+    isoline_color: vec4<f32>,
+    height_fog: vec4<f32>,
+    exposure_gamma: vec4<f32>,
+}
+
+struct PointLight {
+    position: vec4<f32>,
+    diffuse_color: vec4<f32>,
+    specular_color: vec4<f32>,
+    diffuse_intensity: f32,
+    specular_intensity: f32,
+    directional: u32, // Boolean
+    direction: vec3<f32>,
+    fov: f32,
+}
+
+struct Lighting {
+    ambient_color: vec4<f32>,
+    ambient_intensity: f32,
+    lights_len: i32,
+    point_lights: array<PointLight>
+}
+
+@group(0) @binding(0)
+var<uniform> camera: Camera;
+
+@group(1) @binding(0)
+var<storage, read> lighting: Lighting;
+
+// Set with MSAA: edges are anti-aliased with alpha-to-coverage. Otherwise, they're hard.
+override ALPHA_TO_COVERAGE: bool = false;
+
+struct SphereIn {
+    // Center, and radius.
+    @location(0) sphere: vec4<f32>,
+    // Linear RGB, and shinyness.
+    @location(1) color: vec4<f32>,
+}
+
+struct CylinderIn {
+    // Start, and radius.
+    @location(0) start: vec4<f32>,
+    // End, and shinyness.
+    @location(1) end: vec4<f32>,
+    @location(2) color: vec4<f32>,
+    @location(3) color_end: vec4<f32>,
+}
+
+struct SphereOut {
+    @builtin(position) clip_position: vec4<f32>,
+    // On the quad, which the view ray passes through.
+    @location(0) world_posit: vec3<f32>,
+    @location(1) @interpolate(flat) sphere: vec4<f32>,
+    @location(2) @interpolate(flat) color: vec4<f32>,
+}
+
+struct CylinderOut {
+    @builtin(position) clip_position: vec4<f32>,
+    // On the box, which the view ray passes through.
+    @location(0) world_posit: vec3<f32>,
+    @location(1) @interpolate(flat) start: vec4<f32>,
+    @location(2) @interpolate(flat) end: vec4<f32>,
+    @location(3) @interpolate(flat) color: vec4<f32>,
+    @location(4) @interpolate(flat) color_end: vec4<f32>,
+}
+
+// Where the view ray through a fragment meets its shape.
+struct Hit {
+    posit: vec3<f32>,
+    normal: vec3<f32>,
+    // The fraction of the pixel the shape covers, approximately.
+    coverage: f32,
+    // For cylinders, the fraction of the way from start to end.
+    axial: f32,
+}
+
+// Outside the clip volume, so nothing is drawn.
+const CLIPPED: vec4<f32> = vec4<f32>(0.0, 0.0, 2.0, 1.0);
+
+fn saturate(x: f32) -> f32 { return clamp(x, 0.0, 1.0); }
+
+fn fog_weight_band(distance_to_cam: f32) -> f32 {
+    let span = max(1e-4, camera.fog_end - camera.fog_start);
+    let t = saturate((distance_to_cam - camera.fog_start) / span);
+    let shaped = pow(t, camera.fog_power);
+    return 1.0 - exp(-camera.fog_density * shaped);
+}
+
+// Exposure, then gamma, on a shaded color in linear space.
+fn exposure_gamma(color: vec3<f32>) -> vec3<f32> {
+    return pow(max(color * camera.exposure_gamma.x, vec3<f32>(0.0)), vec3<f32>(camera.exposure_gamma.y));
+}
+
+// Exponential height fog: density `d * exp(-falloff * (y - base))`, integrated along the ray from
+// the camera to `posit`. Returns 0 to 1.
+fn fog_weight_height(posit: vec3<f32>) -> f32 {
+    let base = camera.height_fog.x;
+    let falloff = max(camera.height_fog.y, 1e-4);
+    let ray = posit - camera.position.xyz;
+
+    // Exponents are clamped to keep them finite, far below the base.
+    let at_cam = exp(min(-falloff * (camera.position.y - base), 80.0));
+    let dy = falloff * ray.y;
+
+    // The mean density along the ray, relative to `d`.
+    var mean = at_cam;
+    if abs(dy) > 1e-4 {
+        let at_posit = exp(min(-falloff * (posit.y - base), 80.0));
+        mean = (at_cam - at_posit) / dy;
+    }
+
+    return 1.0 - exp(-camera.height_fog.z * mean * length(ray));
+}
+
+@vertex
+fn vs_sphere(@builtin(vertex_index) vertex_index: u32, inst: SphereIn) -> SphereOut {
+    var out: SphereOut;
+    out.sphere = inst.sphere;
+    out.color = inst.color;
+
+    let center = inst.sphere.xyz;
+    let radius = inst.sphere.w;
+    let to_cam = camera.position.xyz - center;
+    let dist = length(to_cam);
+
+    // Not drawn with the camera inside; there's no silhouette to cover.
+    if dist <= radius * 1.0001 {
+        out.clip_position = CLIPPED;
+        return out;
+    }
+
+    let fwd = to_cam / dist;
+    let up_guess = select(vec3<f32>(0.0, 1.0, 0.0), vec3<f32>(1.0, 0.0, 0.0), abs(fwd.y) > 0.99);
+    let right = normalize(cross(up_guess, fwd));
+    let up = cross(fwd, right);
+
+    // The quad is at the sphere's front, facing the camera, and sized to cover the cone of view
+    // rays tangent to it.
+    let half_size = radius * (dist - radius) / sqrt(dist * dist - radius * radius);
+    // Slightly larger, for the anti-aliased edge.
+    let half_size_edge = half_size * 1.05;
+
+    // Two triangles.
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(-1.0, -1.0),
+        vec2<f32>(1.0, -1.0),
+        vec2<f32>(-1.0, 1.0),
+        vec2<f32>(-1.0, 1.0),
+        vec2<f32>(1.0, -1.0),
+        vec2<f32>(1.0, 1.0),
+    );
+    let corner = corners[vertex_index];
+
+    let posit = center + fwd * radius + (right * corner.x + up * corner.y) * half_size_edge;
+    out.world_posit = posit;
+    out.clip_position = camera.proj_view * vec4<f32>(posit, 1.0);
+
+    return out;
+}
+
+fn intersect_sphere(vertex: SphereOut) -> Hit {
+    let center = vertex.sphere.xyz;
+    let radius = vertex.sphere.w;
+
+    let dir = normalize(vertex.world_posit - camera.position.xyz);
+    let oc = camera.position.xyz - center;
+    let b = dot(oc, dir);
+    // The ray's closest approach to the center.
+    let miss_dist = sqrt(max(dot(oc, oc) - b * b, 0.0));
+
+    // Derivatives must be taken before any fragment is discarded.
+    let coverage = saturate(0.5 + (radius - miss_dist) / max(fwidth(miss_dist), 1e-9));
+
+    var hit: Hit;
+    hit.coverage = coverage;
+
+    let h = radius * radius - miss_dist * miss_dist;
+    if h >= 0.0 {
+        hit.posit = camera.position.xyz + dir * (-b - sqrt(h));
+    } else {
+        // Just outside the silhouette, in the anti-aliased edge: use the nearest point on it.
+        let closest = camera.position.xyz - dir * b;
+        hit.posit = center + normalize(closest - center) * radius;
+    }
+    hit.normal = normalize(hit.posit - center);
+
+    return hit;
+}
+
+// The 12 triangles of a box, as indices of its corners. Corner bit 0 selects +x, bit 1 +y, and
+// bit 2 +z.
+const BOX_INDICES: array<u32, 36> = array<u32, 36>(
+    0u, 2u, 6u, 0u, 6u, 4u,
+    1u, 5u, 7u, 1u, 7u, 3u,
+    0u, 4u, 5u, 0u, 5u, 1u,
+    2u, 3u, 7u, 2u, 7u, 6u,
+    0u, 1u, 3u, 0u, 3u, 2u,
+    4u, 6u, 7u, 4u, 7u, 5u,
+);
+
+@vertex
+fn vs_cylinder(@builtin(vertex_index) vertex_index: u32, inst: CylinderIn) -> CylinderOut {
+    var out: CylinderOut;
+    out.start = inst.start;
+    out.end = inst.end;
+    out.color = inst.color;
+    out.color_end = inst.color_end;
+
+    let start = inst.start.xyz;
+    let end = inst.end.xyz;
+    let radius = inst.start.w;
+
+    let diff = end - start;
+    let len = length(diff);
+    if len < 1e-9 {
+        out.clip_position = CLIPPED;
+        return out;
+    }
+
+    let axis = diff / len;
+    let guess = select(vec3<f32>(0.0, 1.0, 0.0), vec3<f32>(1.0, 0.0, 0.0), abs(axis.y) > 0.99);
+    let u = normalize(cross(guess, axis));
+    let v = cross(axis, u);
+
+    // Slightly larger than the cylinder, for the anti-aliased edge.
+    let r = radius * 1.05;
+
+    var indices = BOX_INDICES;
+    let corner = indices[vertex_index];
+    let x = select(-1.0, 1.0, (corner & 1u) != 0u);
+    let y = select(-1.0, 1.0, (corner & 2u) != 0u);
+    let z = select(-1.0, 1.0, (corner & 4u) != 0u);
+
+    let posit = (start + end) * 0.5 + u * x * r + v * y * r + axis * z * (len * 0.5 + r);
+    out.world_posit = posit;
+    out.clip_position = camera.proj_view * vec4<f32>(posit, 1.0);
+
+    return out;
+}
+
+fn intersect_cylinder(vertex: CylinderOut) -> Hit {
+    let a = vertex.start.xyz;
+    let radius = vertex.start.w;
+    let ba = vertex.end.xyz - a;
+
+    let ro = camera.position.xyz;
+    let rd = normalize(vertex.world_posit - ro);
+    let oc = ro - a;
+
+    let baba = dot(ba, ba);
+    let bard = dot(ba, rd);
+    let baoc = dot(ba, oc);
+    let ocrd = dot(oc, rd);
+
+    // Terms of the quadratic for the ray's intersection with the infinite cylinder.
+    let k2 = baba - bard * bard;
+    let k1 = baba * ocrd - baoc * bard;
+    let k0 = baba * dot(oc, oc) - baoc * baoc - radius * radius * baba;
+
+    // The ray's closest approach to the axis, for the anti-aliased silhouette.
+    let n = cross(rd, ba);
+    let n_len = length(n);
+    let axis_dist = select(
+        length(oc - ba * (baoc / baba)),
+        abs(dot(oc, n)) / n_len,
+        n_len > 1e-9,
+    );
+    // Derivatives must be taken before any fragment is discarded.
+    let coverage = saturate(0.5 + (radius - axis_dist) / max(fwidth(axis_dist), 1e-9));
+
+    var hit: Hit;
+    hit.coverage = 0.0;
+
+    // The side.
+    let h = k1 * k1 - k2 * k0;
+    if h >= 0.0 && k2 > 1e-9 {
+        let t = (-k1 - sqrt(h)) / k2;
+        let y = baoc + t * bard;
+        if y > 0.0 && y < baba {
+            hit.posit = ro + rd * t;
+            hit.normal = (oc + rd * t - ba * (y / baba)) / radius;
+            hit.coverage = coverage;
+            hit.axial = y / baba;
+            return hit;
+        }
+    }
+
+    // The cap facing the ray.
+    if abs(bard) > 1e-9 {
+        let y = select(baba, 0.0, bard > 0.0);
+        let t = (y - baoc) / bard;
+        let radial = oc + rd * t - ba * (y / baba);
+        if t > 0.0 && dot(radial, radial) < radius * radius {
+            hit.posit = ro + rd * t;
+            hit.normal = normalize(ba) * select(1.0, -1.0, bard > 0.0);
+            hit.coverage = 1.0;
+            hit.axial = y / baba;
+            return hit;
+        }
+    }
+
+    // Just outside the side's silhouette, in the anti-aliased edge: use the nearest point on it.
+    if coverage > 0.0 && k2 > 1e-9 {
+        let s = (baoc - bard * ocrd) / k2;
+        if s > 0.0 && s < 1.0 {
+            let t = (bard * baoc / baba - ocrd) * baba / k2;
+            let on_axis = a + ba * s;
+            hit.normal = normalize(ro + rd * t - on_axis);
+            hit.posit = on_axis + hit.normal * radius;
+            hit.coverage = coverage;
+            hit.axial = s;
+        }
+    }
+
+    return hit;
+}
+
+fn depth(posit: vec3<f32>) -> f32 {
+    let clip = camera.proj_view * vec4<f32>(posit, 1.0);
+    return clip.z / clip.w;
+}
+
+fn shade(posit: vec3<f32>, normal: vec3<f32>, base: vec3<f32>, shinyness: f32) -> vec3<f32> {
+    let ambient = lighting.ambient_color * lighting.ambient_intensity;
+
+    let view_diff = camera.position.xyz - posit;
+    let view_dir = normalize(view_diff);
+
+    var diffuse = vec4<f32>(0., 0., 0., 0.);
+    var specular = vec4<f32>(0., 0., 0., 0.);
+
+    for (var i = 0; i < lighting.lights_len; i++) {
+        let light = lighting.point_lights[i];
+
+        let light_to_frag = posit - light.position.xyz;
+        let light_dist = length(light_to_frag);
+        let light_to_frag_dir = light_to_frag / light_dist;
+
+        let k1 = 0.09; // Linear attenuation term
+        let k2 = 0.032; // Quadratic attenuation term
+        let dist_attenuation = 1.0 / (1.0 + k1 * light_dist + k2 * light_dist * light_dist);
+
+        var diffuse_attenuation = max(dot(normal, -light_to_frag_dir), 0.);
+
+        if light.directional != 0u {
+            let light_dir = normalize(light.direction);
+            let angle_diff = acos(dot(-light_dir, normalize(posit - camera.position.xyz)));
+            if angle_diff > light.fov / 2. {
+                diffuse_attenuation = 0.0;
+            }
+        }
+
+        diffuse += light.diffuse_color * diffuse_attenuation * light.diffuse_intensity * dist_attenuation;
+
+        if diffuse_attenuation > 0.0 {
+            let half_dir = normalize(view_dir - light_to_frag_dir);
+            let fresnel = pow(1.0 - dot(view_dir, normal), 5.0);
+            let specular_coeff = pow(max(dot(normal, half_dir), 0.), shinyness);
+            specular += fresnel * light.specular_color * specular_coeff * light.specular_intensity * dist_attenuation;
+        }
+    }
+
+    var result = (ambient.rgb + diffuse.rgb) * base + specular.rgb;
+
+    var fog_w = 0.0;
+    if camera.fog_end > camera.fog_start {
+        fog_w = saturate(fog_weight_band(length(view_diff)));
+    }
+    if camera.height_fog.z > 0.0 {
+        fog_w = 1.0 - (1.0 - fog_w) * (1.0 - fog_weight_height(posit));
+    }
+    result = mix(result, camera.fog_color, fog_w);
+
+    if camera.edge_cueing > 0.0 {
+        let edge_factor = pow(1.0 - abs(dot(normal, view_dir)), 3.0);
+        result *= 1.0 - camera.edge_cueing * 0.85 * edge_factor;
+    }
+
+    return exposure_gamma(result);
+}
+
+struct FragOut {
+    @location(0) color: vec4<f32>,
+    @builtin(frag_depth) depth: f32,
+}
+
+fn frag_out(hit: Hit, base: vec3<f32>, shinyness: f32) -> FragOut {
+    var alpha = 1.0;
+    if ALPHA_TO_COVERAGE {
+        alpha = hit.coverage;
+    }
+
+    var out: FragOut;
+    out.color = vec4<f32>(shade(hit.posit, hit.normal, base, shinyness), alpha);
+    out.depth = depth(hit.posit);
+    return out;
+}
+
+// Without alpha-to-coverage, fragments are kept if they're mostly covered.
+fn min_coverage() -> f32 {
+    return select(0.5, 1e-3, ALPHA_TO_COVERAGE);
+}
+
+@fragment
+fn fs_sphere(vertex: SphereOut) -> FragOut {
+    let hit = intersect_sphere(vertex);
+    if hit.coverage < min_coverage() {
+        discard;
+    }
+    return frag_out(hit, vertex.color.rgb, vertex.color.a);
+}
+
+@fragment
+fn fs_cylinder(vertex: CylinderOut) -> FragOut {
+    let hit = intersect_cylinder(vertex);
+    if hit.coverage < min_coverage() {
+        discard;
+    }
+    let base = select(vertex.color.rgb, vertex.color_end.rgb, hit.axial > 0.5);
+    return frag_out(hit, base, vertex.end.w);
+}
+
+// For depth-only passes, e.g. the prepass for SSAO and depth readback.
+@fragment
+fn fs_sphere_depth(vertex: SphereOut) -> @builtin(frag_depth) f32 {
+    let hit = intersect_sphere(vertex);
+    if hit.coverage < 0.5 {
+        discard;
+    }
+    return depth(hit.posit);
+}
+
+@fragment
+fn fs_cylinder_depth(vertex: CylinderOut) -> @builtin(frag_depth) f32 {
+    let hit = intersect_cylinder(vertex);
+    if hit.coverage < 0.5 {
+        discard;
+    }
+    return depth(hit.posit);
+}
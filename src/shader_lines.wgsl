@@ -0,0 +1,261 @@
+// Lines of any width. Each segment is an instance, drawn as a quad expanded to its width in
+// screen space; the fragment shader shapes caps, round joins, and dashes, and anti-aliases the
+// edges.
+
+// A prefix of `Camera` in `shader.wgsl`; we only need the projection.
+struct Camera {
+    proj_view: mat4x4<f32>,
+    position: vec4<f32>,
+};
+
+@group(0) @binding(0)
+var<uniform> camera: Camera;
+
+// Matches `LinesUniform` in `lines.rs`.
+struct Lines {
+    // Of each eye's viewport, in pixels.
+    viewport: vec2<f32>,
+    // Seconds, for animating dashes.
+    time: f32,
+    _pad: f32,
+};
+
+@group(1) @binding(0)
+var<uniform> lines: Lines;
+
+// Bits of `flags`. Match those in `lines.rs`.
+const FLAG_CAP_START: u32 = 1u;
+const FLAG_CAP_END: u32 = 2u;
+const FLAG_MITER: u32 = 4u;
+const CAP_SHIFT: u32 = 3u;
+
+// `LineCap`, in the 2 bits from `CAP_SHIFT`.
+const CAP_BUTT: u32 = 0u;
+const CAP_SQUARE: u32 = 2u;
+
+// Miters are at most this many half widths from the line's center.
+const MITER_LIMIT: f32 = 4.0;
+// Points with a clip-space w below this are behind the camera; segments are cut off here.
+const W_MIN: f32 = 1e-4;
+
+struct SegmentIn {
+    @location(0) prev: vec3<f32>,
+    @location(1) start: vec3<f32>,
+    @location(2) end: vec3<f32>,
+    @location(3) next: vec3<f32>,
+    @location(4) color: vec4<f32>,
+    @location(5) width: f32,
+    @location(6) flags: u32,
+    // The distance along the line to `start`, in world units.
+    @location(7) dist: f32,
+    // Length, gap, offset, and speed. A gap of 0 is a solid line.
+    @location(8) dash: vec4<f32>,
+};
+
+struct VertexOut {
+    @builtin(position) clip_position: vec4<f32>,
+    // This point, and the segment's end points, in viewport pixels.
+    @location(0) @interpolate(linear) px: vec2<f32>,
+    @location(1) @interpolate(flat) start: vec2<f32>,
+    @location(2) @interpolate(flat) end: vec2<f32>,
+    @location(3) @interpolate(flat) color: vec4<f32>,
+    @location(4) @interpolate(flat) half_width: f32,
+    @location(5) @interpolate(flat) flags: u32,
+    // Along the line, in world units; perspective-correct, so dashes are even in depth.
+    @location(6) dist: f32,
+    @location(7) @interpolate(flat) dash: vec4<f32>,
+};
+
+fn to_px(clip: vec4<f32>) -> vec2<f32> {
+    return (clip.xy / clip.w * 0.5 + 0.5) * lines.viewport;
+}
+
+// The unit direction from `a` to `b`, or +x if they're at the same pixel.
+fn dir_px(a: vec2<f32>, b: vec2<f32>) -> vec2<f32> {
+    let d = b - a;
+    if dot(d, d) < 1e-8 {
+        return vec2<f32>(1.0, 0.0);
+    }
+    return normalize(d);
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32, seg: SegmentIn) -> VertexOut {
+    var out: VertexOut;
+
+    var c0 = camera.proj_view * vec4<f32>(seg.start, 1.0);
+    var c1 = camera.proj_view * vec4<f32>(seg.end, 1.0);
+    var flags = seg.flags;
+    let seg_len = distance(seg.start, seg.end);
+    var dist0 = seg.dist;
+    var dist1 = seg.dist + seg_len;
+
+    // Keep the part of the segment in front of the camera. Cut ends don't join anything.
+    if c0.w < W_MIN && c1.w < W_MIN {
+        // Outside the clip volume, so nothing is drawn.
+        out.clip_position = vec4<f32>(0.0, 0.0, 2.0, 1.0);
+        return out;
+    }
+    if c0.w < W_MIN {
+        let t = (W_MIN - c0.w) / (c1.w - c0.w);
+        c0 = mix(c0, c1, t);
+        dist0 = mix(dist0, dist1, t);
+        flags |= FLAG_CAP_START;
+    }
+    if c1.w < W_MIN {
+        let t = (W_MIN - c1.w) / (c0.w - c1.w);
+        c1 = mix(c1, c0, t);
+        dist1 = mix(dist1, dist0, t);
+        flags |= FLAG_CAP_END;
+    }
+
+    let p0 = to_px(c0);
+    let p1 = to_px(c1);
+    let dir = dir_px(p0, p1);
+    let normal = vec2<f32>(-dir.y, dir.x);
+
+    // Lines narrower than a pixel are drawn a pixel wide, and fainter.
+    let half_width = max(seg.width, 1.0) * 0.5;
+    // Extend a pixel past the edges, for anti-aliasing.
+    let extent = half_width + 1.0;
+
+    // Two triangles. Bit 0 of the corner is the side of the line, and bit 1, which end.
+    var corners = array<u32, 6>(0u, 1u, 2u, 2u, 1u, 3u);
+    let corner = corners[vertex_index];
+    let at_end = (corner & 2u) != 0u;
+    let side = select(-1.0, 1.0, (corner & 1u) != 0u);
+    let outward = select(-1.0, 1.0, at_end);
+
+    let c = select(c0, c1, at_end);
+    let p = select(p0, p1, at_end);
+    let is_cap = (flags & select(FLAG_CAP_START, FLAG_CAP_END, at_end)) != 0u;
+
+    // Round and square caps, and round joins, extend half the width past the end point.
+    var offset = normal * side * extent + dir * outward * extent;
+
+    if is_cap {
+        if ((flags >> CAP_SHIFT) & 3u) == CAP_BUTT {
+            offset = normal * side * extent + dir * outward;
+        }
+    } else if (flags & FLAG_MITER) != 0u {
+        // Meet the neighboring segment at the bisector of the corner; it places its corners at
+        // the same points.
+        let c_other = camera.proj_view * vec4<f32>(select(seg.prev, seg.next, at_end), 1.0);
+        if c_other.w >= W_MIN {
+            let p_other = to_px(c_other);
+            let dir_other = select(dir_px(p_other, p0), dir_px(p1, p_other), at_end);
+            let sum = dir + dir_other;
+
+            // Skip nearly reversing corners, where the bisector is undefined.
+            if dot(sum, sum) > 1e-6 {
+                let tangent = normalize(sum);
+                let miter = vec2<f32>(-tangent.y, tangent.x);
+                let len = extent / max(dot(miter, normal), 1.0 / MITER_LIMIT);
+                offset = miter * side * len;
+            }
+        }
+    }
+
+    let px = p + offset;
+    // Keep the end point's depth and w, for depth testing and perspective-correct interpolation.
+    out.clip_position = vec4<f32>((px / lines.viewport * 2.0 - 1.0) * c.w, c.z, c.w);
+    out.px = px;
+    out.start = p0;
+    out.end = p1;
+    out.color = vec4<f32>(seg.color.rgb, seg.color.a * min(seg.width, 1.0));
+    out.half_width = half_width;
+    out.flags = flags;
+    out.dist = select(dist0, dist1, at_end);
+    out.dash = seg.dash;
+
+    return out;
+}
+
+// The distance of a fragment outside the line, past one of its ends: `beyond` pixels past the end
+// point `p`, along the line. `dist` is its distance outside the line's sides.
+fn end_dist(
+    dist: f32,
+    px: vec2<f32>,
+    p: vec2<f32>,
+    beyond: f32,
+    is_cap: bool,
+    flags: u32,
+    half_width: f32,
+) -> f32 {
+    if is_cap {
+        let cap = (flags >> CAP_SHIFT) & 3u;
+        if cap == CAP_BUTT {
+            return max(dist, beyond);
+        }
+        if cap == CAP_SQUARE {
+            return max(dist, beyond - half_width);
+        }
+    } else if (flags & FLAG_MITER) != 0u {
+        // The quad's corners already shape the join.
+        return dist;
+    }
+
+    // Round caps and joins.
+    return length(px - p) - half_width;
+}
+
+// Coverage of a fragment by the dash pattern, from 0 to 1, anti-aliased along the line.
+fn dash_coverage(dist: f32, dash: vec4<f32>, dist_per_px: f32) -> f32 {
+    let period = dash.x + dash.y;
+    let pos = dist - dash.z - dash.w * lines.time;
+    let phase = pos - floor(pos / period) * period;
+
+    // Distance outside the dash, in world units; negative inside.
+    let outside = max(phase - dash.x, -phase);
+    // Outside near the end of the period is next to the next dash's start.
+    let outside_wrapped = min(outside, period - phase);
+    return clamp(0.5 - outside_wrapped / max(dist_per_px, 1e-9), 0.0, 1.0);
+}
+
+@fragment
+fn fs_main(in: VertexOut) -> @location(0) vec4<f32> {
+    // Derivatives must be taken before any fragment is discarded.
+    let dist_per_px = fwidth(in.dist);
+
+    let dir = dir_px(in.start, in.end);
+    let normal = vec2<f32>(-dir.y, dir.x);
+    let rel = in.px - in.start;
+    let along = dot(rel, dir);
+    let len = dot(in.end - in.start, dir);
+
+    // Distance outside the line, in pixels; negative inside.
+    var dist = abs(dot(rel, normal)) - in.half_width;
+
+    if along < 0.0 {
+        dist = end_dist(
+            dist,
+            in.px,
+            in.start,
+            -along,
+            (in.flags & FLAG_CAP_START) != 0u,
+            in.flags,
+            in.half_width,
+        );
+    } else if along > len {
+        dist = end_dist(
+            dist,
+            in.px,
+            in.end,
+            along - len,
+            (in.flags & FLAG_CAP_END) != 0u,
+            in.flags,
+            in.half_width,
+        );
+    }
+
+    // The fraction of the pixel covered, approximately.
+    var coverage = clamp(0.5 - dist, 0.0, 1.0);
+    if in.dash.y > 0.0 {
+        coverage *= dash_coverage(in.dist, in.dash, dist_per_px);
+    }
+    if coverage <= 0.0 {
+        discard;
+    }
+
+    return vec4<f32>(in.color.rgb, in.color.a * coverage);
+}
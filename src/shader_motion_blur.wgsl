@@ -0,0 +1,70 @@
+// Camera motion blur. Each pixel's world position is rebuilt from the depth prepass, and
+// projected with the previous frame's camera; we average the scene along the path between the
+// two screen positions.
+
+struct MotionBlur {
+    proj_view_inv: mat4x4<f32>,
+    prev_proj_view: mat4x4<f32>,
+    // The 3D viewport, in pixels: x, y, width, height.
+    viewport: vec4<f32>,
+    // The fraction of the movement since the previous frame to blur over.
+    strength: f32,
+    samples: u32,
+};
+
+@group(0) @binding(0)
+var scene: texture_2d<f32>;
+@group(0) @binding(1)
+var scene_sampler: sampler;
+@group(0) @binding(2)
+var depth_tex: texture_depth_2d;
+@group(0) @binding(3)
+var<uniform> blur: MotionBlur;
+
+@vertex
+fn vs_main(@builtin(vertex_index) i: u32) -> @builtin(position) vec4<f32> {
+    var corners = array<vec2<f32>, 3>(
+        vec2<f32>(-1.0, -1.0),
+        vec2<f32>(3.0, -1.0),
+        vec2<f32>(-1.0, 3.0),
+    );
+    return vec4<f32>(corners[i], 0.0, 1.0);
+}
+
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let size = vec2<f32>(textureDimensions(scene));
+    let px = position.xy;
+    let uv = px / size;
+
+    // Outside the 3D viewport, e.g. under the GUI.
+    let rel = (px - blur.viewport.xy) / blur.viewport.zw;
+    if (any(rel < vec2<f32>(0.0)) || any(rel >= vec2<f32>(1.0))) {
+        return textureSampleLevel(scene, scene_sampler, uv, 0.0);
+    }
+    let ndc = vec2<f32>(rel.x * 2.0 - 1.0, 1.0 - rel.y * 2.0);
+
+    // The prepass covers the whole surface, instead of just the viewport.
+    let depth_dims = vec2<f32>(textureDimensions(depth_tex));
+    let depth_px = vec2<i32>(vec2<f32>(ndc.x + 1.0, 1.0 - ndc.y) * 0.5 * depth_dims);
+    let depth = textureLoad(depth_tex, clamp(depth_px, vec2<i32>(0), vec2<i32>(depth_dims) - 1), 0);
+
+    let world = blur.proj_view_inv * vec4<f32>(ndc, depth, 1.0);
+    let prev_clip = blur.prev_proj_view * vec4<f32>(world.xyz / world.w, 1.0);
+    if (prev_clip.w <= 0.0) {
+        return textureSampleLevel(scene, scene_sampler, uv, 0.0);
+    }
+    let prev_ndc = prev_clip.xy / prev_clip.w;
+
+    // Screen-space movement, in UV.
+    let delta_ndc = (ndc - prev_ndc) * blur.strength;
+    let delta = vec2<f32>(delta_ndc.x, -delta_ndc.y) * 0.5 * blur.viewport.zw / size;
+
+    var color = vec4<f32>(0.0);
+    for (var i = 0u; i < blur.samples; i++) {
+        let t = f32(i) / f32(max(blur.samples - 1u, 1u)) - 0.5;
+        color += textureSampleLevel(scene, scene_sampler, uv - delta * t, 0.0);
+    }
+
+    return color / f32(blur.samples);
+}
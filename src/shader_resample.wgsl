@@ -0,0 +1,54 @@
+// Resamples the 3D scene, rendered at a different resolution from the window, to the window.
+
+@group(0) @binding(0)
+var scene: texture_2d<f32>;
+@group(0) @binding(1)
+var scene_sampler: sampler;
+
+struct VertexOut {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) i: u32) -> VertexOut {
+    var corners = array<vec2<f32>, 3>(
+        vec2<f32>(-1.0, -1.0),
+        vec2<f32>(3.0, -1.0),
+        vec2<f32>(-1.0, 3.0),
+    );
+    let c = corners[i];
+
+    var out: VertexOut;
+    out.position = vec4<f32>(c, 0.0, 1.0);
+    out.uv = vec2<f32>(c.x + 1.0, 1.0 - c.y) * 0.5;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOut) -> @location(0) vec4<f32> {
+    // This pixel's extent, in UV, and in scene texels.
+    let pixel_uv = abs(vec2<f32>(dpdx(in.uv.x), dpdy(in.uv.y)));
+    let footprint = pixel_uv * vec2<f32>(textureDimensions(scene));
+
+    // Upsampling.
+    if (all(footprint <= vec2<f32>(1.0))) {
+        return textureSampleLevel(scene, scene_sampler, in.uv, 0.0);
+    }
+
+    // Downsampling: average the texels under the pixel. Each bilinear tap between texels
+    // averages 2x2 of them, so this is exact for 2x and 4x.
+    let taps = vec2<u32>(clamp(ceil(footprint * 0.5), vec2<f32>(1.0), vec2<f32>(4.0)));
+    let step = pixel_uv / vec2<f32>(taps);
+    let start = in.uv - pixel_uv * 0.5 + step * 0.5;
+
+    var color = vec4<f32>(0.0);
+    for (var y = 0u; y < taps.y; y++) {
+        for (var x = 0u; x < taps.x; x++) {
+            let uv = start + step * vec2<f32>(f32(x), f32(y));
+            color += textureSampleLevel(scene, scene_sampler, uv, 0.0);
+        }
+    }
+
+    return color / f32(taps.x * taps.y);
+}
@@ -0,0 +1,93 @@
+// Slice planes: quads through a volume texture, colored by the values they cut through.
+
+struct Camera {
+    proj_view : mat4x4<f32>,
+    position  : vec4<f32>,
+};
+
+// Note: Don't us vec3 in uniforms due to alignment issues.
+struct Slice {
+    model: mat4x4<f32>,
+    // World-space corner, and extent of the volume texture.
+    volume_min: vec4<f32>,
+    volume_size: vec4<f32>,
+    value_range: vec2<f32>,
+    opacity: f32,
+    colormap_len: u32,
+    // Evenly-spaced colormap points; only the first `colormap_len` are used.
+    colormap: array<vec4<f32>, 11>,
+};
+
+@group(0) @binding(0)
+var<uniform> camera: Camera;
+
+@group(1) @binding(0)
+var t_volume: texture_3d<f32>;
+@group(1) @binding(1)
+var s_volume: sampler;
+
+@group(2) @binding(0)
+var<uniform> slice: Slice;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) world_posit: vec3<f32>,
+};
+
+// The quad is in the XZ plane, with +Y as its normal; the model matrix places and sizes it.
+// No vertex buffer; corners are indexed by vertex.
+@vertex
+fn vs_main(@builtin(vertex_index) i: u32) -> VertexOutput {
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(-0.5, -0.5),
+        vec2<f32>(0.5, -0.5),
+        vec2<f32>(0.5, 0.5),
+        vec2<f32>(-0.5, -0.5),
+        vec2<f32>(0.5, 0.5),
+        vec2<f32>(-0.5, 0.5),
+    );
+    let corner = corners[i];
+
+    let world = slice.model * vec4<f32>(corner.x, 0.0, corner.y, 1.0);
+
+    var result: VertexOutput;
+    result.clip_position = camera.proj_view * world;
+    result.world_posit = world.xyz;
+    return result;
+}
+
+fn colormap(t: f32) -> vec3<f32> {
+    let n = slice.colormap_len;
+    let pos = clamp(t, 0.0, 1.0) * f32(n - 1u);
+    let i = min(u32(pos), n - 2u);
+
+    return mix(slice.colormap[i].rgb, slice.colormap[i + 1u].rgb, pos - f32(i));
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    // 0 to 1 across the volume's bounds. A volume flat along an axis, e.g. a 2D slice, extends
+    // through it.
+    let flat = slice.volume_size.xyz == vec3<f32>(0.0);
+    let extent = select(slice.volume_size.xyz, vec3<f32>(1.0), flat);
+    let uvw = select((in.world_posit - slice.volume_min.xyz) / extent, vec3<f32>(0.5), flat);
+
+    // Grid values are at texel centers, and the bounds pass through the outer ones.
+    let dims = vec3<f32>(textureDimensions(t_volume));
+    let coords = (uvw * (dims - 1.0) + 0.5) / dims;
+
+    // Sample before discarding; sampling must be in uniform control flow.
+    let value = textureSample(t_volume, s_volume, coords).r;
+
+    if (any(uvw < vec3<f32>(0.0)) || any(uvw > vec3<f32>(1.0))) {
+        discard;
+    }
+
+    let span = slice.value_range.y - slice.value_range.x;
+    var t = 0.5;
+    if (abs(span) > 1e-12) {
+        t = (value - slice.value_range.x) / span;
+    }
+
+    return vec4<f32>(colormap(t), slice.opacity);
+}
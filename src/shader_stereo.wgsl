@@ -0,0 +1,20 @@
+// Copies the right eye's image over the left one's, for anaglyph stereo. The pipeline's blend
+// constant selects which channels come from each eye.
+
+@group(0) @binding(0)
+var right_eye: texture_2d<f32>;
+
+@vertex
+fn vs_main(@builtin(vertex_index) i: u32) -> @builtin(position) vec4<f32> {
+    var corners = array<vec2<f32>, 3>(
+        vec2<f32>(-1.0, -1.0),
+        vec2<f32>(3.0, -1.0),
+        vec2<f32>(-1.0, 3.0),
+    );
+    return vec4<f32>(corners[i], 0.0, 1.0);
+}
+
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    return textureLoad(right_eye, vec2<i32>(position.xy), 0);
+}
@@ -0,0 +1,13 @@
+// Appended to the mesh shader, `shader.wgsl` or `GraphicsSettings::mesh_shader`, with the `xray`
+// feature; it uses that shader's `VertexOut`.
+//
+// X-ray pass: the parts of entities hidden behind others, drawn faintly. The pipeline's depth test
+// only passes fragments behind what's already drawn.
+@fragment
+fn fs_xray(vertex: VertexOut) -> @location(0) vec4<f32> {
+    if (vertex.effects.x <= 0.0) {
+        discard;
+    }
+
+    return vec4<f32>(vertex.color.rgb, vertex.effects.x);
+}
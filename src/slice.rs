@@ -0,0 +1,167 @@
+//! Slice planes: quads through volumetric data, e.g. a density grid, colored by the values they
+//! cut through.
+
+use bytemuck::{Pod, Zeroable};
+use lin_alg::f32::{Mat4, Quaternion, Vec3};
+use wgpu::{
+    BindGroup, BindGroupLayout, Buffer, BufferUsages, Device, Queue, ShaderStages, util::DeviceExt,
+};
+
+use crate::{Colormap, TextureHandle, UP_VEC, colormap::COLORMAP_MAX};
+
+/// A flat quad showing a cross-section of a volume texture, mapped through a colormap. Only the
+/// part inside the volume's bounds is drawn.
+///
+/// Add these to `Scene::slice_planes`. To move one interactively, change `center` or
+/// `orientation` from an event handler, e.g. with `translate_along_normal` on mouse drag, and set
+/// `EngineUpdates::redraw`.
+#[derive(Clone, Debug)]
+pub struct SlicePlane {
+    /// A texture from `TextureSource::Volume`.
+    pub volume: TextureHandle,
+    /// The world-space (min, max) corners the volume spans. Its first and last grid points lie on
+    /// these, as with `grid_points` and `gaussian_density_grid`.
+    pub bounds: (Vec3, Vec3),
+    pub center: Vec3,
+    /// Rotates the plane from the XZ plane, whose normal is +Y.
+    pub orientation: Quaternion,
+    /// Extent along the plane's local X and Z axes.
+    pub size: (f32, f32),
+    pub colormap: Colormap,
+    /// Values at the start and end of the colormap.
+    pub value_range: (f32, f32),
+    pub opacity: f32,
+}
+
+impl SlicePlane {
+    /// A plane through the center of `bounds`, perpendicular to `normal`, large enough to cut
+    /// through the whole volume at any orientation.
+    pub fn new(
+        volume: TextureHandle,
+        bounds: (Vec3, Vec3),
+        normal: Vec3,
+        value_range: (f32, f32),
+    ) -> Self {
+        let diagonal = (bounds.1 - bounds.0).magnitude();
+
+        Self {
+            volume,
+            bounds,
+            center: (bounds.0 + bounds.1) / 2.,
+            orientation: Quaternion::from_unit_vecs(UP_VEC, normal.to_normalized()),
+            size: (diagonal, diagonal),
+            colormap: Colormap::default(),
+            value_range,
+            opacity: 1.,
+        }
+    }
+
+    /// The unit vector perpendicular to the plane.
+    pub fn normal(&self) -> Vec3 {
+        self.orientation.rotate_vec(UP_VEC)
+    }
+
+    /// Move the plane along its normal by `dist`.
+    pub fn translate_along_normal(&mut self, dist: f32) {
+        self.center += self.normal() * dist;
+    }
+
+    fn to_uniform(&self) -> SliceUniform {
+        let model = Mat4::new_translation(self.center)
+            * self.orientation.to_matrix()
+            * Mat4::new_scaler_partial(Vec3::new(self.size.0, 1., self.size.1));
+
+        let (min, max) = self.bounds;
+        let size = max - min;
+
+        let (colormap, colormap_len) = self.colormap.to_uniform();
+
+        SliceUniform {
+            model: model.data,
+            volume_min: [min.x, min.y, min.z, 0.],
+            volume_size: [size.x, size.y, size.z, 0.],
+            value_range: [self.value_range.0, self.value_range.1],
+            opacity: self.opacity,
+            colormap_len,
+            colormap,
+        }
+    }
+}
+
+/// Matches `Slice` in `shader_slice.wgsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct SliceUniform {
+    model: [f32; 16],
+    volume_min: [f32; 4],
+    volume_size: [f32; 4],
+    value_range: [f32; 2],
+    opacity: f32,
+    colormap_len: u32,
+    colormap: [[f32; 4]; COLORMAP_MAX],
+}
+
+/// GPU state for drawing `Scene::slice_planes`. The volume textures themselves are in the
+/// texture store.
+pub(crate) struct SliceRenderer {
+    /// For each plane's uniform buffer.
+    pub layout: BindGroupLayout,
+    /// A uniform buffer and bind group per plane. Grows to the most planes drawn at once.
+    planes: Vec<(Buffer, BindGroup)>,
+}
+
+impl SliceRenderer {
+    pub fn new(device: &Device) -> Self {
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Slice plane bind group layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::VERTEX | ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: wgpu::BufferSize::new(size_of::<SliceUniform>() as _),
+                },
+                count: None,
+            }],
+        });
+
+        Self {
+            layout,
+            planes: Vec::new(),
+        }
+    }
+
+    /// Write each plane's parameters, creating buffers for planes added since the last call.
+    pub fn prepare(&mut self, device: &Device, queue: &Queue, planes: &[SlicePlane]) {
+        for (i, plane) in planes.iter().enumerate() {
+            let uniform = plane.to_uniform();
+
+            if let Some((buf, _)) = self.planes.get(i) {
+                queue.write_buffer(buf, 0, bytemuck::bytes_of(&uniform));
+                continue;
+            }
+
+            let buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Slice plane buffer"),
+                contents: bytemuck::bytes_of(&uniform),
+                usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            });
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("Slice plane bind group"),
+                layout: &self.layout,
+                entries: &[wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: buf.as_entire_binding(),
+                }],
+            });
+
+            self.planes.push((buf, bind_group));
+        }
+    }
+
+    /// The bind group for `Scene::slice_planes[i]`, as of the last `prepare`.
+    pub fn bind_group(&self, i: usize) -> Option<&BindGroup> {
+        self.planes.get(i).map(|(_, bind_group)| bind_group)
+    }
+}
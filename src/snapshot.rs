@@ -0,0 +1,519 @@
+//! Headless rendering, and comparison against reference ("golden") images; for regression tests
+//! of shaders, meshes, and lighting, e.g. across wgpu upgrades.
+
+use std::{env, path::Path, sync::mpsc, time::Duration};
+
+use image::{Rgba, RgbaImage, imageops};
+use wgpu::{
+    BufferUsages, Device, Instance, InstanceDescriptor, MapMode, PollType, Queue,
+    SurfaceConfiguration, TextureFormat,
+};
+
+use crate::{
+    ColorSpace, EngineUpdates, GraphicsError, GraphicsSettings, Scene, UiSettings,
+    graphics::GraphicsState,
+    letterbox,
+    system::{
+        process_engine_updates, request_device, supports_compute, supports_multi_draw_indirect,
+    },
+    viewport_rect,
+};
+
+/// If this environment variable is set, `assert_golden` writes the images it's given as the new
+/// references, instead of comparing against them.
+pub const UPDATE_GOLDEN_VAR: &str = "GRAPHICS_UPDATE_GOLDEN";
+
+/// Render one frame of `scene`, without a window or GUI, and return it as sRGB RGBA pixels; or
+/// linear, with `ColorSpace::Linear` output. Settings are applied as at init. Background pipeline
+/// compilation is waited for, so the result doesn't depend on timing.
+///
+/// This creates its own GPU device. Use `GraphicsSettings::force_fallback_adapter` to render on
+/// the CPU, e.g. in CI without a GPU; results can differ slightly between adapters, so compare
+/// with a tolerance. To render several frames of a changing scene, use `HeadlessRenderer`.
+pub fn render_headless(
+    scene: &Scene,
+    settings: &GraphicsSettings,
+    width: u32,
+    height: u32,
+) -> Result<RgbaImage, GraphicsError> {
+    HeadlessRenderer::new(scene, settings, width, height)?.step(0.)
+}
+
+/// Renders frames without a window or GUI, as `render_headless`, from a scene kept between them.
+/// Each frame advances time by a `dt` given, instead of the clock, so fades, color animations,
+/// line dashes, and camera transitions step the same way each run; e.g. to test them against
+/// golden images, or to check the scene partway through.
+pub struct HeadlessRenderer {
+    device: Device,
+    queue: Queue,
+    graphics: GraphicsState,
+}
+
+impl HeadlessRenderer {
+    /// Set up a renderer for `scene`. This creates its own GPU device.
+    pub fn new(
+        scene: &Scene,
+        settings: &GraphicsSettings,
+        width: u32,
+        height: u32,
+    ) -> Result<Self, GraphicsError> {
+        let instance = Instance::new(InstanceDescriptor {
+            backends: settings.backends,
+            ..InstanceDescriptor::new_without_display_handle()
+        });
+
+        let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: settings.power_preference,
+            compatible_surface: None,
+            force_fallback_adapter: settings.force_fallback_adapter,
+        }))
+        .map_err(GraphicsError::NoAdapter)?;
+
+        let (device, queue) = pollster::block_on(request_device(&adapter, settings))?;
+
+        let format = match settings.output_color_space {
+            ColorSpace::Srgb => TextureFormat::Rgba8UnormSrgb,
+            ColorSpace::Linear => TextureFormat::Rgba8Unorm,
+        };
+
+        // There's no surface; this sets the format and size of our render targets.
+        let surface_cfg = SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format,
+            width,
+            height,
+            present_mode: wgpu::PresentMode::Fifo,
+            desired_maximum_frame_latency: 2,
+            alpha_mode: wgpu::CompositeAlphaMode::Auto,
+            view_formats: Vec::new(),
+        };
+
+        let mut scene = scene.clone();
+        scene.window_size = (width as f32, height as f32);
+        scene.camera.aspect = width as f32 / height as f32;
+        if let Some(strength) = settings.edge_cueing {
+            scene.camera.edge_cueing = strength;
+        }
+        scene.camera.isolines = settings.isolines;
+        scene.camera.update_proj_mat();
+
+        let error_scope = device.push_error_scope(wgpu::ErrorFilter::Validation);
+
+        let mut graphics = GraphicsState::new(
+            &device,
+            &queue,
+            &surface_cfg,
+            scene,
+            None,
+            settings.msaa_samples,
+            settings.mesh_shader.as_deref(),
+        );
+        graphics.apply_graphics_settings(settings, &queue);
+        graphics.multi_draw_indirect = supports_multi_draw_indirect(&adapter, &device);
+        graphics.compute_supported = supports_compute(&adapter);
+
+        if let Some(e) = pollster::block_on(error_scope.pop()) {
+            return Err(GraphicsError::ShaderCompile(e.to_string()));
+        }
+
+        Ok(Self {
+            device,
+            queue,
+            graphics,
+        })
+    }
+
+    pub fn scene(&self) -> &Scene {
+        &self.graphics.scene
+    }
+
+    /// Change the scene, then send what changed to `update`, as from an event handler.
+    pub fn scene_mut(&mut self) -> &mut Scene {
+        &mut self.graphics.scene
+    }
+
+    /// Apply changes to the scene, as returned from a handler.
+    pub fn update(&mut self, updates: &EngineUpdates) {
+        process_engine_updates(updates, &mut self.graphics, &self.device, &self.queue);
+    }
+
+    /// Advance `dt` seconds, and render a frame.
+    pub fn step(&mut self, dt: f32) -> Result<RgbaImage, GraphicsError> {
+        let dt = Duration::from_secs_f32(dt.max(0.));
+
+        let error_scope = self.device.push_error_scope(wgpu::ErrorFilter::Validation);
+
+        self.graphics.step(&self.device, dt);
+        let image = render_image(
+            &mut self.graphics,
+            &self.device,
+            &self.queue,
+            &UiSettings::default(),
+            (0., 0.),
+        )?;
+
+        if let Some(e) = pollster::block_on(error_scope.pop()) {
+            return Err(GraphicsError::ShaderCompile(e.to_string()));
+        }
+
+        Ok(image)
+    }
+}
+
+/// Render the 3D view as the window shows it, without the GUI, cropped to the viewport; for
+/// `EngineProxy::request_screenshot`. This blocks until the GPU finishes.
+pub(crate) fn screenshot(
+    graphics: &mut GraphicsState,
+    device: &Device,
+    queue: &Queue,
+    ui_settings: &UiSettings,
+) -> Result<RgbaImage, GraphicsError> {
+    let scale = graphics.scene.pixels_per_point;
+    let gui_size = (
+        graphics.scene.gui_size.0 * scale,
+        graphics.scene.gui_size.1 * scale,
+    );
+    let image = render_image(graphics, device, queue, ui_settings, gui_size)?;
+
+    let (x, y, width, height) = letterbox(
+        viewport_rect(gui_size, image.width(), image.height(), ui_settings, 0.),
+        graphics.scene.viewport_aspect,
+    );
+    Ok(imageops::crop_imm(
+        &image,
+        x.round() as u32,
+        y.round() as u32,
+        (width.round() as u32).max(1),
+        (height.round() as u32).max(1),
+    )
+    .to_image())
+}
+
+/// Render a frame at the surface's size, and read it back as RGBA pixels.
+fn render_image(
+    graphics: &mut GraphicsState,
+    device: &Device,
+    queue: &Queue,
+    ui_settings: &UiSettings,
+    gui_size: (f32, f32),
+) -> Result<RgbaImage, GraphicsError> {
+    let format = graphics.surface_cfg.format;
+    let (width, height) = (graphics.surface_cfg.width, graphics.surface_cfg.height);
+
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Offscreen render target"),
+        size: wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        view_formats: &[],
+    });
+    let view = texture.create_view(&Default::default());
+
+    graphics.render_offscreen(device, queue, &view, width, height, ui_settings, gui_size);
+
+    // Rows of the copy must be aligned.
+    let bytes_per_row = (width * 4).next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+    let buf = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Offscreen readback buffer"),
+        size: (bytes_per_row * height) as u64,
+        usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+
+    let mut encoder = device.create_command_encoder(&Default::default());
+    encoder.copy_texture_to_buffer(
+        wgpu::TexelCopyTextureInfo {
+            texture: &texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d::ZERO,
+            aspect: wgpu::TextureAspect::All,
+        },
+        wgpu::TexelCopyBufferInfo {
+            buffer: &buf,
+            layout: wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(bytes_per_row),
+                rows_per_image: Some(height),
+            },
+        },
+        wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+    );
+    queue.submit(Some(encoder.finish()));
+
+    let (tx, rx) = mpsc::channel();
+    buf.map_async(MapMode::Read, .., move |result| {
+        let _ = tx.send(result);
+    });
+    device
+        .poll(PollType::wait_indefinitely())
+        .map_err(|e| GraphicsError::Readback(e.to_string()))?;
+
+    rx.recv_timeout(Duration::from_secs(10))
+        .map_err(|e| GraphicsError::Readback(e.to_string()))?
+        .map_err(|e| GraphicsError::Readback(e.to_string()))?;
+
+    let mapped = buf.get_mapped_range(..);
+    let mut pixels = Vec::with_capacity((width * height * 4) as usize);
+    for row in mapped.chunks(bytes_per_row as usize) {
+        pixels.extend_from_slice(&row[..(width * 4) as usize]);
+    }
+    drop(mapped);
+    buf.unmap();
+
+    // Window surfaces are often BGRA.
+    if matches!(
+        format,
+        TextureFormat::Bgra8Unorm | TextureFormat::Bgra8UnormSrgb
+    ) {
+        for px in pixels.chunks_mut(4) {
+            px.swap(0, 2);
+        }
+    }
+
+    // The window's surface is opaque; match what it would show.
+    for px in pixels.chunks_mut(4) {
+        px[3] = 255;
+    }
+
+    Ok(RgbaImage::from_raw(width, height, pixels).expect("The buffer matches the image's size"))
+}
+
+/// How far an image may differ from its reference, and still match.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct GoldenTolerance {
+    /// The largest difference in any color channel, 0 to 255, for a pixel to count as matching.
+    pub channel: u8,
+    /// The fraction of pixels, 0 to 1, allowed to not match.
+    pub pixels: f32,
+}
+
+impl Default for GoldenTolerance {
+    fn default() -> Self {
+        Self {
+            channel: 2,
+            pixels: 0.001,
+        }
+    }
+}
+
+/// The result of comparing two images of the same size, from `diff_images`.
+#[derive(Clone, Debug)]
+pub struct ImageDiff {
+    /// Pixels with a channel differing by more than the tolerance.
+    pub mismatched: usize,
+    /// The largest difference in any channel, of any pixel.
+    pub max_delta: u8,
+    /// Mismatched pixels in red, over a faded copy of the expected image; for inspection.
+    pub image: RgbaImage,
+}
+
+impl ImageDiff {
+    /// The fraction of pixels that don't match, 0 to 1.
+    pub fn mismatched_fraction(&self) -> f32 {
+        let total = self.image.width() as usize * self.image.height() as usize;
+        self.mismatched as f32 / total.max(1) as f32
+    }
+}
+
+/// Compare two images, pixel by pixel. A pixel matches if no channel differs by more than
+/// `channel_tolerance`. Returns `None` if their sizes differ.
+pub fn diff_images(
+    expected: &RgbaImage,
+    actual: &RgbaImage,
+    channel_tolerance: u8,
+) -> Option<ImageDiff> {
+    if expected.dimensions() != actual.dimensions() {
+        return None;
+    }
+
+    let mut mismatched = 0;
+    let mut max_delta = 0;
+    let mut image = RgbaImage::new(expected.width(), expected.height());
+
+    for ((e, a), out) in expected
+        .pixels()
+        .zip(actual.pixels())
+        .zip(image.pixels_mut())
+    {
+        let delta = (0..4).map(|i| e[i].abs_diff(a[i])).max().unwrap();
+        max_delta = max_delta.max(delta);
+
+        *out = if delta > channel_tolerance {
+            mismatched += 1;
+            Rgba([255, 0, 0, 255])
+        } else {
+            let faded = |c: u8| 191 + c / 4;
+            Rgba([faded(e[0]), faded(e[1]), faded(e[2]), 255])
+        };
+    }
+
+    Some(ImageDiff {
+        mismatched,
+        max_delta,
+        image,
+    })
+}
+
+/// Assert that `actual` matches the reference image at `path`, within `tolerance`; for tests.
+/// On a mismatch, this writes `actual` and a diff image next to the reference, e.g.
+/// `lighting.actual.png` and `lighting.diff.png` for `lighting.png`, then panics.
+///
+/// Set the `GRAPHICS_UPDATE_GOLDEN` environment variable to write `actual` as the reference
+/// instead, e.g. to create it, or after an intended change. Review the new images before
+/// committing them.
+///
+/// ```no_run
+/// # use graphics::{GoldenTolerance, GraphicsSettings, Scene, assert_golden, render_headless};
+/// # fn make_scene() -> Scene { Scene::default() }
+/// let settings = GraphicsSettings {
+///     force_fallback_adapter: true, // Render on the CPU, e.g. in CI.
+///     ..Default::default()
+/// };
+/// let image = render_headless(&make_scene(), &settings, 320, 240).unwrap();
+/// assert_golden(&image, "tests/golden/lighting.png", GoldenTolerance::default());
+/// ```
+pub fn assert_golden(actual: &RgbaImage, path: impl AsRef<Path>, tolerance: GoldenTolerance) {
+    check_golden(
+        actual,
+        path.as_ref(),
+        tolerance,
+        env::var_os(UPDATE_GOLDEN_VAR).is_some(),
+    );
+}
+
+/// `assert_golden`, writing `actual` as the reference if `update` is set.
+fn check_golden(actual: &RgbaImage, path: &Path, tolerance: GoldenTolerance, update: bool) {
+    let sibling = |suffix: &str| {
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        path.with_file_name(format!("{stem}.{suffix}.png"))
+    };
+
+    if update {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .unwrap_or_else(|e| panic!("Unable to create {}: {e}", dir.display()));
+        }
+        actual
+            .save(path)
+            .unwrap_or_else(|e| panic!("Unable to write {}: {e}", path.display()));
+        return;
+    }
+
+    let expected = match image::open(path) {
+        Ok(img) => img.to_rgba8(),
+        Err(e) => {
+            let _ = actual.save(sibling("actual"));
+            panic!(
+                "Unable to load the golden image {}: {e}. To create it, run with \
+                {UPDATE_GOLDEN_VAR}=1.",
+                path.display()
+            );
+        }
+    };
+
+    let Some(diff) = diff_images(&expected, actual, tolerance.channel) else {
+        let _ = actual.save(sibling("actual"));
+        panic!(
+            "Image size {:?} doesn't match the golden image {}, of size {:?}",
+            actual.dimensions(),
+            path.display(),
+            expected.dimensions()
+        );
+    };
+
+    if diff.mismatched_fraction() > tolerance.pixels {
+        let _ = actual.save(sibling("actual"));
+        let _ = diff.image.save(sibling("diff"));
+        panic!(
+            "{} pixels ({:.3}%) differ from the golden image {}, by up to {}; see {} and {}",
+            diff.mismatched,
+            diff.mismatched_fraction() * 100.,
+            path.display(),
+            diff.max_delta,
+            sibling("actual").display(),
+            sibling("diff").display(),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, panic};
+
+    use super::*;
+
+    fn gradient(width: u32, height: u32) -> RgbaImage {
+        RgbaImage::from_fn(width, height, |x, y| {
+            Rgba([(x * 40) as u8, (y * 60) as u8, 128, 255])
+        })
+    }
+
+    #[test]
+    fn diff_identical() {
+        let img = gradient(5, 4);
+        let diff = diff_images(&img, &img, 0).unwrap();
+
+        assert_eq!(diff.mismatched, 0);
+        assert_eq!(diff.max_delta, 0);
+        assert_eq!(diff.mismatched_fraction(), 0.);
+    }
+
+    #[test]
+    fn diff_one_pixel_over_tolerance() {
+        let expected = gradient(5, 4);
+        let mut actual = expected.clone();
+        // One pixel at the tolerance, which matches, and one just over it.
+        actual.get_pixel_mut(0, 0)[2] += 3;
+        actual.get_pixel_mut(3, 2)[1] += 4;
+
+        let diff = diff_images(&expected, &actual, 3).unwrap();
+
+        assert_eq!(diff.mismatched, 1);
+        assert_eq!(diff.max_delta, 4);
+        assert_eq!(diff.mismatched_fraction(), 1. / 20.);
+        assert_eq!(*diff.image.get_pixel(3, 2), Rgba([255, 0, 0, 255]));
+        assert_ne!(*diff.image.get_pixel(0, 0), Rgba([255, 0, 0, 255]));
+    }
+
+    #[test]
+    fn diff_size_mismatch() {
+        assert!(diff_images(&gradient(5, 4), &gradient(4, 5), 255).is_none());
+    }
+
+    #[test]
+    fn golden_update_writes_reference() {
+        let dir = env::temp_dir().join(format!("graphics_golden_{}", std::process::id()));
+        let path = dir.join("nested").join("gradient.png");
+        let img = gradient(5, 4);
+
+        // Creates the directory, and the reference.
+        check_golden(&img, &path, GoldenTolerance::default(), true);
+        assert_eq!(image::open(&path).unwrap().to_rgba8(), img);
+
+        // Which then matches.
+        check_golden(&img, &path, GoldenTolerance::default(), false);
+
+        // A mismatch panics, and writes the actual image and the diff next to the reference.
+        let mut changed = img.clone();
+        changed.get_pixel_mut(1, 1)[0] = 255;
+        let result = panic::catch_unwind(|| {
+            check_golden(&changed, &path, GoldenTolerance::default(), false)
+        });
+        assert!(result.is_err());
+        assert!(path.with_file_name("gradient.actual.png").exists());
+        assert!(path.with_file_name("gradient.diff.png").exists());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
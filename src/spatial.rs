@@ -0,0 +1,242 @@
+//! A spatial index of entity positions, for neighborhood queries like `Scene::entities_within`
+//! and `Scene::nearest_entity`, without scanning every entity.
+
+use std::sync::OnceLock;
+
+use lin_alg::f32::Vec3;
+
+/// A k-d tree over entities' world positions. The engine marks it stale when entities are
+/// updated, i.e. on each `EngineUpdates::entities` other than `None`, and it's rebuilt on the
+/// next query, so moving entities every frame doesn't rebuild it unless it's queried. Query it
+/// with `Scene::entities_within` and `Scene::nearest_entity`.
+#[derive(Clone, Debug, Default)]
+pub struct SpatialIndex {
+    tree: OnceLock<KdTree>,
+}
+
+impl SpatialIndex {
+    /// Mark the tree stale, so the next query rebuilds it.
+    pub(crate) fn invalidate(&mut self) {
+        self.tree.take();
+    }
+
+    /// The tree, built from `positions` (entity world positions, in order) if stale.
+    pub(crate) fn tree<I: Iterator<Item = Vec3>>(&self, positions: impl FnOnce() -> I) -> &KdTree {
+        self.tree.get_or_init(|| KdTree::new(positions()))
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub(crate) struct KdTree {
+    /// Entity positions, and their indices in `Scene::entities`. Stored as an implicit tree: the
+    /// middle element of each range splits it on the axis for its depth; those before it are
+    /// at or below it on that axis, and those after, at or above.
+    points: Vec<(Vec3, usize)>,
+}
+
+impl KdTree {
+    /// From entity positions, in order.
+    pub(crate) fn new(positions: impl Iterator<Item = Vec3>) -> Self {
+        let mut points: Vec<_> = positions.enumerate().map(|(i, p)| (p, i)).collect();
+
+        build(&mut points, 0);
+
+        Self { points }
+    }
+
+    /// Indices of entities whose positions are within `radius` of `center`, in no particular order.
+    pub(crate) fn within(&self, center: Vec3, radius: f32) -> Vec<usize> {
+        let mut result = Vec::new();
+        within(&self.points, 0, center, radius, &mut result);
+        result
+    }
+
+    /// The index of the entity whose position is nearest `point`, or `None` if there are no
+    /// entities.
+    pub(crate) fn nearest(&self, point: Vec3) -> Option<usize> {
+        let mut best = None;
+        nearest(&self.points, 0, point, &mut best);
+        best.map(|(i, _)| i)
+    }
+}
+
+fn coord(v: Vec3, axis: usize) -> f32 {
+    match axis {
+        0 => v.x,
+        1 => v.y,
+        _ => v.z,
+    }
+}
+
+fn build(points: &mut [(Vec3, usize)], depth: usize) {
+    if points.len() <= 1 {
+        return;
+    }
+
+    let axis = depth % 3;
+    let mid = points.len() / 2;
+    points.select_nth_unstable_by(mid, |a, b| coord(a.0, axis).total_cmp(&coord(b.0, axis)));
+
+    let (below, above) = points.split_at_mut(mid);
+    build(below, depth + 1);
+    build(&mut above[1..], depth + 1);
+}
+
+fn within(
+    points: &[(Vec3, usize)],
+    depth: usize,
+    center: Vec3,
+    radius: f32,
+    result: &mut Vec<usize>,
+) {
+    if points.is_empty() {
+        return;
+    }
+
+    let mid = points.len() / 2;
+    let (posit, i) = points[mid];
+    if (posit - center).magnitude_squared() <= radius * radius {
+        result.push(i);
+    }
+
+    // Signed distance from the splitting plane; skip sides the sphere doesn't reach.
+    let dist = coord(center, depth % 3) - coord(posit, depth % 3);
+    if dist <= radius {
+        within(&points[..mid], depth + 1, center, radius, result);
+    }
+    if -dist <= radius {
+        within(&points[mid + 1..], depth + 1, center, radius, result);
+    }
+}
+
+/// `best` is the nearest entity index found so far, and its squared distance.
+fn nearest(points: &[(Vec3, usize)], depth: usize, point: Vec3, best: &mut Option<(usize, f32)>) {
+    if points.is_empty() {
+        return;
+    }
+
+    let mid = points.len() / 2;
+    let (posit, i) = points[mid];
+    let dist_sq = (posit - point).magnitude_squared();
+    if best.is_none_or(|(_, d)| dist_sq < d) {
+        *best = Some((i, dist_sq));
+    }
+
+    // Search the side containing the point first, so the other can usually be skipped.
+    let dist = coord(point, depth % 3) - coord(posit, depth % 3);
+    let (near, far) = if dist <= 0. {
+        (&points[..mid], &points[mid + 1..])
+    } else {
+        (&points[mid + 1..], &points[..mid])
+    };
+
+    nearest(near, depth + 1, point, best);
+    if best.is_none_or(|(_, d)| dist * dist < d) {
+        nearest(far, depth + 1, point, best);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Deterministic points in a 10-unit cube, with some repeated.
+    fn points(n: usize) -> Vec<Vec3> {
+        let mut state = 12_345_u32;
+        let mut next = || {
+            state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            (state >> 8) as f32 / (1 << 24) as f32 * 10.
+        };
+
+        let mut result: Vec<_> = (0..n).map(|_| Vec3::new(next(), next(), next())).collect();
+        for i in (0..n).step_by(7) {
+            result[i] = result[0];
+        }
+        result
+    }
+
+    fn queries() -> Vec<Vec3> {
+        let mut result = points(20);
+        result.push(Vec3::new(-5., 20., 3.));
+        result.push(Vec3::new(5., 5., 5.));
+        result
+    }
+
+    fn brute_within(points: &[Vec3], center: Vec3, radius: f32) -> Vec<usize> {
+        (0..points.len())
+            .filter(|&i| (points[i] - center).magnitude_squared() <= radius * radius)
+            .collect()
+    }
+
+    fn dist_sq(a: Vec3, b: Vec3) -> f32 {
+        (a - b).magnitude_squared()
+    }
+
+    #[test]
+    fn within_matches_brute_force() {
+        let pts = points(200);
+        let tree = KdTree::new(pts.iter().copied());
+
+        for center in queries() {
+            for radius in [0., 0.5, 2., 30.] {
+                let mut found = tree.within(center, radius);
+                found.sort_unstable();
+                assert_eq!(found, brute_within(&pts, center, radius));
+            }
+        }
+    }
+
+    #[test]
+    fn nearest_matches_brute_force() {
+        let pts = points(200);
+        let tree = KdTree::new(pts.iter().copied());
+
+        for q in queries() {
+            let found = tree.nearest(q).unwrap();
+            let best = pts
+                .iter()
+                .map(|&p| dist_sq(p, q))
+                .fold(f32::INFINITY, f32::min);
+            // Ties may resolve to any of the equally near points.
+            assert_eq!(dist_sq(pts[found], q), best);
+        }
+    }
+
+    #[test]
+    fn empty_and_single() {
+        let empty = KdTree::new(std::iter::empty());
+        assert_eq!(empty.nearest(Vec3::new(1., 2., 3.)), None);
+        assert!(empty.within(Vec3::new(1., 2., 3.), 100.).is_empty());
+
+        let single = KdTree::new([Vec3::new(1., 1., 1.)].into_iter());
+        assert_eq!(single.nearest(Vec3::new(-50., 0., 9.)), Some(0));
+        assert_eq!(single.within(Vec3::new(1., 1., 2.), 1.), vec![0]);
+        assert!(single.within(Vec3::new(1., 1., 2.), 0.99).is_empty());
+    }
+
+    #[test]
+    fn duplicate_coordinates() {
+        let p = Vec3::new(2., -1., 4.);
+        let tree = KdTree::new([p; 9].into_iter());
+
+        let mut found = tree.within(p, 0.);
+        found.sort_unstable();
+        assert_eq!(found, (0..9).collect::<Vec<_>>());
+        assert!(tree.nearest(Vec3::new(0., 0., 0.)).is_some_and(|i| i < 9));
+    }
+
+    #[test]
+    fn rebuilds_after_invalidate() {
+        let mut index = SpatialIndex::default();
+        let a = [Vec3::new(0., 0., 0.), Vec3::new(10., 0., 0.)];
+        let b = [Vec3::new(10., 0., 0.), Vec3::new(0., 0., 0.)];
+        let origin = Vec3::new(0., 0., 0.);
+
+        assert_eq!(index.tree(|| a.into_iter()).nearest(origin), Some(0));
+        // Still built from `a` until invalidated.
+        assert_eq!(index.tree(|| b.into_iter()).nearest(origin), Some(0));
+
+        index.invalidate();
+        assert_eq!(index.tree(|| b.into_iter()).nearest(origin), Some(1));
+    }
+}
@@ -0,0 +1,355 @@
+//! Stereo rendering, for depth perception on standard monitors: the scene is drawn once per
+//! eye, side by side, or combined as a red-cyan anaglyph.
+
+use wgpu::{
+    BindGroup, BindGroupLayout, Buffer, BufferUsages, CommandEncoder, Device, Queue,
+    RenderPipeline, ShaderStages, StoreOp, TextureFormat, TextureView,
+};
+
+use crate::{Camera, camera::CAMERA_SIZE, graphics::BindGroupData};
+
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum StereoMode {
+    #[default]
+    Off,
+    /// The left eye's view on the left half of the viewport, and the right eye's on the right;
+    /// for parallel viewing, or displays that combine them.
+    SideBySide,
+    /// Both eyes' views combined, the left in red, and the right in cyan; for red-cyan glasses.
+    Anaglyph,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Stereo {
+    pub mode: StereoMode,
+    /// Distance between the eyes, in world units. Larger values exaggerate depth. About 1/30 of
+    /// `convergence` is comfortable.
+    pub eye_separation: f32,
+    /// Distance from the camera, in world units, that appears at screen depth; nearer objects
+    /// appear in front of the screen, and farther ones behind it. E.g. the distance to the arc
+    /// camera's center.
+    pub convergence: f32,
+}
+
+impl Default for Stereo {
+    fn default() -> Self {
+        Self {
+            mode: Default::default(),
+            eye_separation: 0.3,
+            convergence: 10.,
+        }
+    }
+}
+
+/// Which camera to draw with.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub(crate) enum Eye {
+    /// `Scene::camera`.
+    Center,
+    Left,
+    Right,
+    /// The mirror image below `Scene::reflection_plane`.
+    Reflection,
+}
+
+/// Camera buffers and bind groups for one eye, matching those of the main camera.
+pub(crate) struct EyeCamera {
+    buf: Buffer,
+    buf_halo: Buffer,
+    cam: BindGroup,
+    cam_gauss: BindGroup,
+    cam_halo: BindGroup,
+}
+
+impl EyeCamera {
+    pub fn new(device: &Device, bind_groups: &BindGroupData, cam_basis_buf: &Buffer) -> Self {
+        let create_buf = |label| {
+            device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(label),
+                size: CAMERA_SIZE as u64,
+                usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            })
+        };
+        let buf = create_buf("Eye camera buffer");
+        let buf_halo = create_buf("Eye camera halo buffer");
+
+        let create_cam = |buf: &Buffer, label| {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                layout: &bind_groups.layout_cam,
+                entries: &[wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: buf.as_entire_binding(),
+                }],
+                label: Some(label),
+            })
+        };
+        let cam = create_cam(&buf, "Eye camera bind group");
+        let cam_halo = create_cam(&buf_halo, "Eye camera halo bind group");
+
+        // The basis is the main camera's; stereo eyes share its orientation.
+        let cam_gauss = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Eye Gaussian camera bind group"),
+            layout: &bind_groups.layout_cam_gauss,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: buf.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: cam_basis_buf.as_entire_binding(),
+                },
+            ],
+        });
+
+        Self {
+            buf,
+            buf_halo,
+            cam,
+            cam_gauss,
+            cam_halo,
+        }
+    }
+
+    /// Write this eye's camera, and its halo camera if halos are enabled.
+    pub fn write(&self, queue: &Queue, camera: &Camera, halo_expansion: f32) {
+        queue.write_buffer(&self.buf, 0, &camera.to_bytes());
+
+        if halo_expansion > 0. {
+            let mut camera = camera.clone();
+            camera.halo_expansion = halo_expansion;
+            queue.write_buffer(&self.buf_halo, 0, &camera.to_bytes());
+        }
+    }
+
+    /// Bind groups for the camera, the Gaussian camera, and the halo camera.
+    pub fn bind_groups(&self) -> (&BindGroup, &BindGroup, &BindGroup) {
+        (&self.cam, &self.cam_gauss, &self.cam_halo)
+    }
+}
+
+/// The right eye's image, for anaglyph rendering, and the bind group the composite reads it with.
+struct RightEyeTarget {
+    view: TextureView,
+    bind_group: BindGroup,
+    size: (u32, u32),
+}
+
+/// GPU state for drawing with `GraphicsSettings::stereo`.
+pub(crate) struct StereoRenderer {
+    pub settings: Stereo,
+    eyes: [EyeCamera; 2],
+    layout: BindGroupLayout,
+    pipeline_composite: RenderPipeline,
+    format: TextureFormat,
+    target: Option<RightEyeTarget>,
+}
+
+impl StereoRenderer {
+    pub fn new(
+        device: &Device,
+        bind_groups: &BindGroupData,
+        cam_basis_buf: &Buffer,
+        format: TextureFormat,
+    ) -> Self {
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Stereo composite bind group layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            }],
+        });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Stereo composite shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("shader_stereo.wgsl").into()),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Stereo composite pipeline layout"),
+            bind_group_layouts: &[Some(&layout)],
+            immediate_size: 0,
+        });
+
+        // Takes the blend constant's channels from the right eye, and the rest from the left
+        // eye, already in the target.
+        let blend = wgpu::BlendState {
+            color: wgpu::BlendComponent {
+                src_factor: wgpu::BlendFactor::Constant,
+                dst_factor: wgpu::BlendFactor::OneMinusConstant,
+                operation: wgpu::BlendOperation::Add,
+            },
+            alpha: wgpu::BlendComponent {
+                src_factor: wgpu::BlendFactor::Zero,
+                dst_factor: wgpu::BlendFactor::One,
+                operation: wgpu::BlendOperation::Add,
+            },
+        };
+
+        let pipeline_composite = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Stereo composite pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                compilation_options: Default::default(),
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                compilation_options: Default::default(),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(blend),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: Default::default(),
+            depth_stencil: None,
+            multisample: Default::default(),
+            multiview_mask: None,
+            cache: None,
+        });
+
+        Self {
+            settings: Default::default(),
+            eyes: [
+                EyeCamera::new(device, bind_groups, cam_basis_buf),
+                EyeCamera::new(device, bind_groups, cam_basis_buf),
+            ],
+            layout,
+            pipeline_composite,
+            format,
+            target: None,
+        }
+    }
+
+    /// The eyes to draw in the main pass. In anaglyph mode, this is the left eye; the right one
+    /// is drawn separately, then composited.
+    pub fn main_pass_eyes(&self) -> &'static [Eye] {
+        match self.settings.mode {
+            StereoMode::Off => &[Eye::Center],
+            StereoMode::SideBySide => &[Eye::Left, Eye::Right],
+            StereoMode::Anaglyph => &[Eye::Left],
+        }
+    }
+
+    /// Write each eye's camera.
+    pub fn prepare(&self, queue: &Queue, camera: &Camera, halo_expansion: f32) {
+        if self.settings.mode == StereoMode::Off {
+            return;
+        }
+
+        let mut camera = camera.clone();
+        if self.settings.mode == StereoMode::SideBySide {
+            // Each eye gets half the width.
+            camera.aspect /= 2.;
+            camera.update_proj_mat();
+        }
+
+        let offset = self.settings.eye_separation / 2.;
+        for (eye, offset) in self.eyes.iter().zip([-offset, offset]) {
+            let eye_cam = camera.stereo_eye(offset, self.settings.convergence);
+            eye.write(queue, &eye_cam, halo_expansion);
+        }
+    }
+
+    /// Bind groups for the camera, the Gaussian camera, and the halo camera, for a stereo eye.
+    pub fn bind_groups(&self, eye: Eye) -> Option<(&BindGroup, &BindGroup, &BindGroup)> {
+        match eye {
+            Eye::Left => Some(self.eyes[0].bind_groups()),
+            Eye::Right => Some(self.eyes[1].bind_groups()),
+            Eye::Center | Eye::Reflection => None,
+        }
+    }
+
+    /// The texture to draw the right eye into, in anaglyph mode. Created, or resized, as needed.
+    pub fn right_eye_view(&mut self, device: &Device, width: u32, height: u32) -> &TextureView {
+        if self
+            .target
+            .as_ref()
+            .is_none_or(|t| t.size != (width, height))
+        {
+            let texture = device.create_texture(&wgpu::TextureDescriptor {
+                label: Some("Right eye texture"),
+                size: wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: self.format,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                    | wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            });
+            let view = texture.create_view(&Default::default());
+
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("Stereo composite bind group"),
+                layout: &self.layout,
+                entries: &[wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                }],
+            });
+
+            self.target = Some(RightEyeTarget {
+                view,
+                bind_group,
+                size: (width, height),
+            });
+        }
+
+        &self.target.as_ref().unwrap().view
+    }
+
+    /// The right eye's texture we allocated, if any; for memory usage.
+    pub fn target_texture(&self) -> Option<&wgpu::Texture> {
+        self.target.as_ref().map(|t| t.view.texture())
+    }
+
+    /// Combine the right eye's image, drawn to `right_eye_view`, with the left eye's in `output`.
+    pub fn composite(&self, encoder: &mut CommandEncoder, output: &TextureView) {
+        let Some(target) = &self.target else {
+            return;
+        };
+
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Stereo composite"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: output,
+                depth_slice: None,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+            multiview_mask: None,
+        });
+        pass.set_pipeline(&self.pipeline_composite);
+        pass.set_bind_group(0, &target.bind_group, &[]);
+        // Red from the left eye; green and blue from the right.
+        pass.set_blend_constant(wgpu::Color {
+            r: 0.,
+            g: 1.,
+            b: 1.,
+            a: 0.,
+        });
+        pass.draw(0..3, 0..1);
+    }
+}
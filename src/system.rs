@@ -5,13 +5,18 @@
 // https://github.com/kaphula/winit-egui-wgpu-template/blob/master/src/main.rs
 
 use std::{
-    sync::Arc,
-    time::{Duration, Instant},
+    sync::{
+        Arc,
+        mpsc::{self, Receiver},
+    },
+    time::Duration,
 };
 
+use tracing::{debug, debug_span, info, info_span, warn};
+use web_time::Instant;
 use wgpu::{
-    Adapter, Backends, Device, ExperimentalFeatures, Features, Instance, InstanceDescriptor,
-    PowerPreference, Queue, Surface, SurfaceConfiguration, TextureFormat,
+    Adapter, Device, DownlevelFlags, ErrorFilter, ExperimentalFeatures, Features, Instance,
+    InstanceDescriptor, Limits, Queue, Surface, SurfaceConfiguration, TextureFormat,
 };
 use winit::{
     dpi::PhysicalSize,
@@ -19,15 +24,22 @@ use winit::{
     event_loop::{ControlFlow, EventLoop},
     window::Window,
 };
+#[cfg(target_arch = "wasm32")]
+use winit::{event_loop::EventLoopProxy, platform::web::EventLoopExtWebSys};
 
 use crate::{
     EntityUpdate,
-    graphics::{GraphicsState, create_contour_bind_group, create_ssao_bind_group},
+    color::ColorSpace,
+    error::GraphicsError,
+    graphics::GraphicsState,
     gui::GuiState,
-    texture::Texture,
-    types::{EngineUpdates, GraphicsSettings, Scene, UiSettings},
+    proxy::{EngineEvent, EngineProxy, SceneDelta},
+    types::{EngineUpdates, GraphicsSettings, PresentMode, Scene, UiSettings},
+    window::{update_window, update_window_icon},
 };
 
+/// The format we render to, with `ColorSpace::Srgb` output. With linear output, we use the
+/// non-sRGB equivalent.
 pub const COLOR_FORMAT: TextureFormat = TextureFormat::Bgra8UnormSrgb;
 pub const DEPTH_FORMAT: TextureFormat = TextureFormat::Depth32Float;
 
@@ -40,8 +52,37 @@ pub(crate) struct RenderState {
     pub device: Device,
     pub queue: Queue,
     pub surface_cfg: SurfaceConfiguration,
+    /// Present modes supported by the surface, on this adapter.
+    pub present_modes: Vec<wgpu::PresentMode>,
+    /// The format the surface is configured with. This is `surface_cfg.format` where supported;
+    /// otherwise its sRGB or non-sRGB equivalent, and we render to a view of it in
+    /// `surface_cfg.format`. E.g. browsers only provide non-sRGB surfaces.
+    pub surface_format: TextureFormat,
 }
 
+impl RenderState {
+    /// Configure the surface from `surface_cfg`, with `surface_format`.
+    pub fn configure_surface(&self) {
+        let mut cfg = self.surface_cfg.clone();
+        if cfg.format != self.surface_format {
+            cfg.view_formats = vec![cfg.format];
+            cfg.format = self.surface_format;
+        }
+        self.surface.configure(&self.device, &cfg);
+    }
+}
+
+/// GPU handles, requested before the renderer is initialized.
+pub(crate) struct Gpu {
+    surface: Surface<'static>,
+    adapter: Adapter,
+    device: Device,
+    queue: Queue,
+}
+
+/// Called once as the event loop exits, with the application state and scene.
+type ExitHandler<T> = Box<dyn FnOnce(&mut T, &mut Scene)>;
+
 pub struct State<T: 'static, FRender, FEventDev, FEventWin, FGui>
 where
     FRender: FnMut(&mut T, &mut Scene, f32) -> EngineUpdates + 'static,
@@ -62,11 +103,25 @@ where
     pub gui_handler: FGui,
     pub ui_settings: UiSettings,
     pub graphics_settings: GraphicsSettings,
-    pub scene: Scene,
+    /// The scene, until the renderer initializes. It then moves into `GraphicsState`, so there's
+    /// only ever one copy; changes made before init carry over.
+    pub scene: Option<Scene>,
     pub last_render_time: Instant,
     pub dt: Duration,
     /// Minimized, etc. Indicates not to redraw.
     pub paused: bool,
+    /// Set if initialization failed; returned from `run` once the event loop exits.
+    pub init_error: Option<GraphicsError>,
+    /// Changes sent from other threads, through `EngineProxy`.
+    pub scene_deltas: Receiver<SceneDelta>,
+    pub on_exit: Option<ExitHandler<T>>,
+    /// In the browser, we can't block on requesting the GPU; the request sends its result back
+    /// through the event loop.
+    #[cfg(target_arch = "wasm32")]
+    pub proxy: Option<EventLoopProxy<EngineEvent<T>>>,
+    /// The ID of the canvas element to render to. If `None`, we append one to the page.
+    #[cfg(target_arch = "wasm32")]
+    pub canvas_id: Option<String>,
 }
 
 impl<T: 'static, FRender, FEventDev, FEventWin, FGui> State<T, FRender, FEventDev, FEventWin, FGui>
@@ -87,13 +142,14 @@ where
         event_dev_handler: FEventDev,
         event_win_handler: FEventWin,
         gui_handler: FGui,
+        scene_deltas: Receiver<SceneDelta>,
     ) -> Self {
         let last_render_time = Instant::now();
         let dt = Duration::new(0, 0);
 
         // The instance is a handle to our GPU. Its main purpose is to create Adapters and Surfaces.
         let instance = Instance::new(InstanceDescriptor {
-            backends: Backends::VULKAN,
+            backends: graphics_settings.backends,
             ..InstanceDescriptor::new_without_display_handle()
         });
 
@@ -109,49 +165,119 @@ where
             gui_handler,
             ui_settings,
             graphics_settings,
-            scene,
+            scene: Some(scene),
             last_render_time,
             dt,
             paused: false,
+            init_error: None,
+            scene_deltas,
+            on_exit: None,
+            #[cfg(target_arch = "wasm32")]
+            proxy: None,
+            #[cfg(target_arch = "wasm32")]
+            canvas_id: None,
         }
     }
 
     /// Initializes the renderer and GUI. We launch this from the Window's event loop.
-    pub(crate) fn init(&mut self, window: Window) {
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn init(&mut self, window: Window) -> Result<(), GraphicsError> {
         let window = Arc::new(window);
 
-        let size = window.inner_size();
+        let gpu = pollster::block_on(request_gpu(
+            self.instance.clone(),
+            window.clone(),
+            self.graphics_settings.clone(),
+        ))?;
+
+        self.init_renderer(window, gpu)
+    }
 
-        let surface = self.instance.create_surface(window.clone()).unwrap();
+    /// Start requesting the GPU; we can't block on it in the browser. Once the request completes,
+    /// the event loop receives the result, and passes it to `init_renderer`.
+    #[cfg(target_arch = "wasm32")]
+    pub(crate) fn init(&mut self, window: Window) -> Result<(), GraphicsError> {
+        let window = Arc::new(window);
 
-        let (_adapter, device, queue) = pollster::block_on(setup_async(&self.instance, &surface));
+        let proxy = self.proxy.clone().expect("The proxy is set by `run_web`");
 
-        // The surface is the part of the window that we draw to. We need it to draw directly to the
-        // screen. Our window needs to implement raw-window-handle (opens new window)'s
-        // HasRawWindowHandle trait to create a surface.
+        let request = request_gpu(
+            self.instance.clone(),
+            window.clone(),
+            self.graphics_settings.clone(),
+        );
+        wasm_bindgen_futures::spawn_local(async move {
+            let _ = proxy.send_event(EngineEvent::GpuReady(window, request.await));
+        });
+
+        Ok(())
+    }
+
+    /// Set up the renderer and GUI, once we have the GPU.
+    pub(crate) fn init_renderer(
+        &mut self,
+        window: Arc<Window>,
+        gpu: Gpu,
+    ) -> Result<(), GraphicsError> {
+        let _span = info_span!("init").entered();
+
+        let Gpu {
+            surface,
+            adapter,
+            device,
+            queue,
+        } = gpu;
+
+        let size = window.inner_size();
+
+        let format = match self.graphics_settings.output_color_space {
+            ColorSpace::Srgb => COLOR_FORMAT,
+            ColorSpace::Linear => COLOR_FORMAT.remove_srgb_suffix(),
+        };
+
+        let capabilities = surface.get_capabilities(&adapter);
+        let Some(surface_format) = [
+            format,
+            format.add_srgb_suffix(),
+            format.remove_srgb_suffix(),
+        ]
+        .into_iter()
+        .find(|f| capabilities.formats.contains(f)) else {
+            return Err(GraphicsError::SurfaceUnsupported(format!(
+                "{format:?} isn't among the surface's formats: {:?}",
+                capabilities.formats
+            )));
+        };
+        let present_modes = capabilities.present_modes;
 
         // https://docs.rs/wgpu/latest/wgpu/type.SurfaceConfiguration.html
         let surface_cfg = SurfaceConfiguration {
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
             // format: surface.get_supported_formats(&adapter)[0],
-            format: COLOR_FORMAT,
+            format,
             width: size.width,
             height: size.height,
             // https://docs.rs/wgpu/latest/wgpu/enum.PresentMode.html
             // Note that `Fifo` locks FPS to the speed of the monitor.
-            present_mode: wgpu::PresentMode::Fifo,
-            // todo: Allow config from user.
-            // present_mode: wgpu::PresentMode::Immediate,
-            // present_mode: wgpu::PresentMode::Mailbox,
+            present_mode: select_present_mode(self.graphics_settings.present_mode, &present_modes),
             desired_maximum_frame_latency: 2, // Default
             alpha_mode: wgpu::CompositeAlphaMode::Auto,
             view_formats: Vec::new(),
         };
 
-        surface.configure(&device, &surface_cfg);
+        if let Some(handler) = self.graphics_settings.on_error.clone() {
+            device.on_uncaptured_error(Arc::new(move |e| match e {
+                wgpu::Error::OutOfMemory { .. } => (handler.0)(&GraphicsError::OutOfMemory),
+                // Fatal, as with wgpu's default handler.
+                _ => panic!("wgpu error: {e}"),
+            }));
+        }
 
         let texture_format = surface_cfg.format;
 
+        // Catch shader and pipeline validation errors, instead of panicking.
+        let error_scope = device.push_error_scope(ErrorFilter::Validation);
+
         let render = RenderState {
             size,
             surface,
@@ -159,33 +285,110 @@ where
             device,
             queue,
             surface_cfg,
+            present_modes,
+            surface_format,
         };
+        render.configure_surface();
 
-        // Sync edge cueing into the camera before cloning into GraphicsState,
+        // The renderer owns the scene from here on.
+        let mut scene = self
+            .scene
+            .take()
+            .expect("The renderer is only initialized once");
+
+        // Sync edge cueing into the camera before handing the scene to GraphicsState,
         // so that the initial camera buffer is correct.
         if let Some(strength) = self.graphics_settings.edge_cueing {
-            self.scene.camera.edge_cueing = strength;
+            scene.camera.edge_cueing = strength;
         }
+        scene.camera.isolines = self.graphics_settings.isolines;
+
+        scene.window_position = window.outer_position().ok().map(|p| (p.x, p.y));
+        scene.window_maximized = window.is_maximized();
 
         let mut graphics = GraphicsState::new(
             &render.device,
             &render.queue,
             &render.surface_cfg,
-            self.scene.clone(), // todo: Now we have two scene states... not good.
-            window.clone(),
+            scene,
+            Some(window.clone()),
             self.graphics_settings.msaa_samples,
+            self.graphics_settings.mesh_shader.as_deref(),
         );
         graphics.apply_graphics_settings(&self.graphics_settings, &render.queue);
+        // We've already configured the surface with the requested present mode, or its fallback.
+        graphics.pending_present_mode = None;
+
+        // Otherwise, we fall back to per-mesh draws.
+        graphics.multi_draw_indirect = supports_multi_draw_indirect(&adapter, &render.device);
+        graphics.compute_supported = supports_compute(&adapter);
 
-        self.gui = Some(GuiState::new(
+        let gui = GuiState::new(
             window,
             &render.device,
             texture_format,
             self.graphics_settings.msaa_samples,
-        ));
+        );
+
+        // In the browser, we can't block on this; errors there are reported as uncaptured.
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(e) = pollster::block_on(error_scope.pop()) {
+            // Return the scene, e.g. for `on_exit`.
+            self.scene = Some(graphics.scene);
+            return Err(GraphicsError::ShaderCompile(e.to_string()));
+        }
+        #[cfg(target_arch = "wasm32")]
+        drop(error_scope);
 
+        self.gui = Some(gui);
         self.render = Some(render);
         self.graphics = Some(graphics);
+
+        Ok(())
+    }
+
+    /// Reconfigure the surface to use a different present mode.
+    pub(crate) fn set_present_mode(&mut self, mode: PresentMode) {
+        let (Some(sys), Some(graphics)) = (self.render.as_mut(), self.graphics.as_mut()) else {
+            return;
+        };
+
+        sys.surface_cfg.present_mode = select_present_mode(mode, &sys.present_modes);
+        sys.configure_surface();
+
+        graphics.surface_cfg = sys.surface_cfg.clone();
+        self.graphics_settings.present_mode = mode;
+    }
+
+    /// Reconfigure the surface after it became outdated, e.g. from a resize we haven't handled yet.
+    /// If `recreate`, create a new surface first; this is required after it's lost.
+    pub(crate) fn reconfigure_surface(&mut self, recreate: bool) {
+        let (Some(sys), Some(graphics)) = (self.render.as_mut(), self.graphics.as_mut()) else {
+            return;
+        };
+
+        debug!(recreate, "Reconfiguring the surface");
+
+        // Try again next frame, in `RenderMode::OnDemand`.
+        graphics.redraw_pending = true;
+
+        if recreate {
+            match self.instance.create_surface(graphics.window().clone()) {
+                Ok(surface) => sys.surface = surface,
+                Err(e) => {
+                    warn!("Unable to recreate the lost surface: {e}");
+                    return;
+                }
+            }
+        }
+
+        let size = graphics.window().inner_size();
+        if size != sys.size {
+            // This configures the surface, and updates size-dependent resources.
+            self.resize(size);
+        } else {
+            sys.configure_surface();
+        }
     }
 
     pub(crate) fn resize(&mut self, new_size: PhysicalSize<u32>) {
@@ -193,6 +396,9 @@ where
             return;
         }
 
+        let _span =
+            debug_span!("resize", width = new_size.width, height = new_size.height).entered();
+
         let sys = self.render.as_mut().unwrap();
         let graphics = self.graphics.as_mut().unwrap();
 
@@ -200,7 +406,7 @@ where
             sys.size = new_size;
             sys.surface_cfg.width = new_size.width;
             sys.surface_cfg.height = new_size.height;
-            sys.surface.configure(&sys.device, &sys.surface_cfg);
+            sys.configure_surface();
 
             let (eff_width, eff_height) =
                 (sys.surface_cfg.width as f32, sys.surface_cfg.height as f32);
@@ -215,45 +421,12 @@ where
 
             // Keep the cached surface config on GraphicsState in sync.
             graphics.surface_cfg = sys.surface_cfg.clone();
-
-            graphics.depth_texture = Texture::create_depth_texture(
-                &sys.device,
-                &sys.surface_cfg,
-                "Depth texture",
-                graphics.msaa_samples,
-            );
-
-            graphics.depth_texture_contour = Texture::create_depth_texture(
-                &sys.device,
-                &sys.surface_cfg,
-                "Depth texture contour",
-                1,
-            );
-            graphics.bind_group_contour = create_contour_bind_group(
-                &sys.device,
-                &graphics.layout_contour,
-                &graphics.depth_texture_contour.view,
-                &graphics.contour_uniform_buf,
-            );
-            graphics.bind_group_ssao = create_ssao_bind_group(
-                &sys.device,
-                &graphics.layout_ssao,
-                &graphics.depth_texture_contour.view,
-                &graphics.ssao_uniform_buf,
-            );
-
-            if let Some(t) = &mut graphics.msaa_texture {
-                *t = GraphicsState::create_msaa_texture(
-                    &sys.device,
-                    &sys.surface_cfg,
-                    graphics.msaa_samples,
-                );
-            }
+            graphics.resize_render_targets(&sys.device);
 
             graphics.scene.camera.update_proj_mat();
 
             // This is required to set the correct render aspect-ratio.
-            graphics.update_camera(&sys.queue);
+            graphics.update_camera();
         }
     }
 }
@@ -276,13 +449,58 @@ pub fn run<T: 'static, FRender, FEventDev, FEventWin, FGui>(
     event_dev_handler: FEventDev,
     event_win_handler: FEventWin,
     gui_handler: FGui,
-) where
+) -> Result<(), GraphicsError>
+where
+    FRender: FnMut(&mut T, &mut Scene, f32) -> EngineUpdates + 'static,
+    FEventDev: FnMut(&mut T, DeviceEvent, &mut Scene, bool, f32) -> EngineUpdates + 'static,
+    FEventWin: FnMut(&mut T, WindowEvent, &mut Scene, f32) -> EngineUpdates + 'static,
+    FGui: FnMut(&mut T, &mut egui::Ui, &mut Scene) -> EngineUpdates + 'static,
+{
+    run_with_proxy(
+        user_state,
+        scene,
+        ui_settings,
+        graphics_settings,
+        render_handler,
+        event_dev_handler,
+        event_win_handler,
+        gui_handler,
+        |_, _| (),
+        |_, _| (),
+    )
+}
+
+/// As `run`, but first calls `on_proxy` with a proxy to the event loop. Keep it in the user state,
+/// or move clones of it to other threads, to wake the event loop, and update the scene from them.
+///
+/// `on_exit` runs once as the event loop exits, e.g. when the window is closed. Use it to save
+/// preferences, or stop worker threads, before the process ends.
+pub fn run_with_proxy<T: 'static, FRender, FEventDev, FEventWin, FGui>(
+    mut user_state: T,
+    scene: Scene,
+    ui_settings: UiSettings,
+    graphics_settings: GraphicsSettings,
+    render_handler: FRender,
+    event_dev_handler: FEventDev,
+    event_win_handler: FEventWin,
+    gui_handler: FGui,
+    on_proxy: impl FnOnce(&mut T, EngineProxy<T>),
+    on_exit: impl FnOnce(&mut T, &mut Scene) + 'static,
+) -> Result<(), GraphicsError>
+where
     FRender: FnMut(&mut T, &mut Scene, f32) -> EngineUpdates + 'static,
     FEventDev: FnMut(&mut T, DeviceEvent, &mut Scene, bool, f32) -> EngineUpdates + 'static,
     FEventWin: FnMut(&mut T, WindowEvent, &mut Scene, f32) -> EngineUpdates + 'static,
     FGui: FnMut(&mut T, &mut egui::Ui, &mut Scene) -> EngineUpdates + 'static,
 {
-    let (_frame_count, _accum_time) = (0, 0.0);
+    let event_loop = EventLoop::<EngineEvent<T>>::with_user_event().build()?;
+    event_loop.set_control_flow(ControlFlow::Poll);
+
+    let (delta_tx, delta_rx) = mpsc::channel();
+    on_proxy(
+        &mut user_state,
+        EngineProxy::new(event_loop.create_proxy(), delta_tx),
+    );
 
     let mut state: State<T, FRender, FEventDev, FEventWin, FGui> = State::new(
         scene,
@@ -293,44 +511,209 @@ pub fn run<T: 'static, FRender, FEventDev, FEventWin, FGui>(
         event_dev_handler,
         event_win_handler,
         gui_handler,
+        delta_rx,
     );
+    state.on_exit = Some(Box::new(on_exit));
+
+    event_loop.run_app(&mut state)?;
+
+    match state.init_error {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
 
-    let event_loop = EventLoop::new().unwrap();
+/// The entry point in the browser; as `run_with_proxy`. This renders to the canvas element with ID
+/// `canvas_id`, or to one appended to the page if `None`.
+///
+/// This returns once the event loop starts; the browser drives it from there. Report
+/// initialization errors with `GraphicsSettings::on_init_error`.
+#[cfg(target_arch = "wasm32")]
+pub fn run_web<T: 'static, FRender, FEventDev, FEventWin, FGui>(
+    mut user_state: T,
+    scene: Scene,
+    ui_settings: UiSettings,
+    graphics_settings: GraphicsSettings,
+    render_handler: FRender,
+    event_dev_handler: FEventDev,
+    event_win_handler: FEventWin,
+    gui_handler: FGui,
+    canvas_id: Option<String>,
+    on_proxy: impl FnOnce(&mut T, EngineProxy<T>),
+    on_exit: impl FnOnce(&mut T, &mut Scene) + 'static,
+) -> Result<(), GraphicsError>
+where
+    FRender: FnMut(&mut T, &mut Scene, f32) -> EngineUpdates + 'static,
+    FEventDev: FnMut(&mut T, DeviceEvent, &mut Scene, bool, f32) -> EngineUpdates + 'static,
+    FEventWin: FnMut(&mut T, WindowEvent, &mut Scene, f32) -> EngineUpdates + 'static,
+    FGui: FnMut(&mut T, &mut egui::Ui, &mut Scene) -> EngineUpdates + 'static,
+{
+    let event_loop = EventLoop::<EngineEvent<T>>::with_user_event().build()?;
     event_loop.set_control_flow(ControlFlow::Poll);
 
-    event_loop.run_app(&mut state).expect("Failed to run app");
+    let (delta_tx, delta_rx) = mpsc::channel();
+    on_proxy(
+        &mut user_state,
+        EngineProxy::new(event_loop.create_proxy(), delta_tx),
+    );
+
+    let mut state: State<T, FRender, FEventDev, FEventWin, FGui> = State::new(
+        scene,
+        ui_settings,
+        graphics_settings,
+        user_state,
+        render_handler,
+        event_dev_handler,
+        event_win_handler,
+        gui_handler,
+        delta_rx,
+    );
+    state.on_exit = Some(Box::new(on_exit));
+    state.proxy = Some(event_loop.create_proxy());
+    state.canvas_id = canvas_id;
+
+    event_loop.spawn_app(state);
+
+    Ok(())
 }
 
-/// Quarantine for the Async part of the API
-async fn setup_async(instance: &Instance, surface: &Surface<'static>) -> (Adapter, Device, Queue) {
+/// Use the requested present mode if the surface supports it; otherwise, fall back to `Fifo`,
+/// which is always supported.
+fn select_present_mode(
+    requested: PresentMode,
+    supported: &[wgpu::PresentMode],
+) -> wgpu::PresentMode {
+    let mode = requested.to_wgpu();
+    if supported.contains(&mode) {
+        mode
+    } else {
+        warn!("Present mode {requested:?} is unsupported on this adapter; falling back to Fifo");
+        wgpu::PresentMode::Fifo
+    }
+}
+
+/// Reduce the features and limits requested in settings to those the adapter supports, warning
+/// about any it declined. Requesting unsupported ones would fail device creation.
+fn negotiate_device(adapter: &Adapter, settings: &GraphicsSettings) -> (Features, Limits) {
+    let supported = adapter.features();
+
+    let declined = settings.required_features - supported;
+    if !declined.is_empty() {
+        warn!("Device features unsupported by this adapter; skipping: {declined:?}");
+    }
+
+    // Optional features the engine uses if available, and falls back from otherwise. Without the
+    // texture compression ones, we decompress compressed textures on the CPU where we can.
+    // Without timestamp queries, frame timings have no GPU pass times.
+    let optional = Features::INDIRECT_FIRST_INSTANCE
+        | Features::TIMESTAMP_QUERY
+        | Features::TEXTURE_COMPRESSION_BC
+        | Features::TEXTURE_COMPRESSION_ETC2
+        | Features::TEXTURE_COMPRESSION_ASTC;
+    let features = (settings.required_features | optional) & supported;
+
+    let supported_limits = adapter.limits();
+    settings.required_limits.check_limits_with_fail_fn(
+        &supported_limits,
+        false,
+        |name, requested, allowed| {
+            warn!(
+                "Device limit `{name}` of {requested} unsupported by this adapter; using {allowed}"
+            );
+        },
+    );
+
+    let limits = settings
+        .required_limits
+        .clone()
+        .or_worse_values_from(&supported_limits);
+
+    (features, limits)
+}
+
+/// Quarantine for the Async part of the API. This takes ownership of its arguments, so it can
+/// be spawned in the browser.
+pub(crate) async fn request_gpu(
+    instance: Instance,
+    window: Arc<Window>,
+    settings: GraphicsSettings,
+) -> Result<Gpu, GraphicsError> {
+    // The surface is the part of the window that we draw to. We need it to draw directly to the
+    // screen. Our window needs to implement raw-window-handle (opens new window)'s
+    // HasRawWindowHandle trait to create a surface.
+    let surface = instance.create_surface(window)?;
+
     // The adapter is a handle to our actual graphics card. You can use this to get
     // information about the graphics card such as its name and what backend the
     // adapter uses. We use this to create our Device and Queue.
     let adapter = instance
         .request_adapter(&wgpu::RequestAdapterOptions {
-            // `Default` prefers low power when on battery, high performance when on mains.
-            power_preference: PowerPreference::default(),
-            compatible_surface: Some(surface),
-            force_fallback_adapter: false,
+            power_preference: settings.power_preference,
+            compatible_surface: Some(&surface),
+            force_fallback_adapter: settings.force_fallback_adapter,
         })
         .await
-        .unwrap();
+        .map_err(GraphicsError::NoAdapter)?;
+
+    let info = adapter.get_info();
+    info!(
+        adapter = %info.name,
+        backend = ?info.backend,
+        driver = %info.driver,
+        "Selected GPU adapter"
+    );
+
+    let (device, queue) = request_device(&adapter, &settings).await?;
+
+    Ok(Gpu {
+        surface,
+        adapter,
+        device,
+        queue,
+    })
+}
+
+/// Create the device and queue, with the features and limits in `settings` that the adapter
+/// supports.
+pub(crate) async fn request_device(
+    adapter: &Adapter,
+    settings: &GraphicsSettings,
+) -> Result<(Device, Queue), GraphicsError> {
+    let (required_features, required_limits) = negotiate_device(adapter, settings);
 
-    let (device, queue) = adapter
+    adapter
         .request_device(&wgpu::DeviceDescriptor {
             label: None,
             // https://docs.rs/wgpu/latest/wgpu/struct.Features.html
-            required_features: Features::empty(),
+            required_features,
             // https://docs.rs/wgpu/latest/wgpu/struct.Limits.html
-            required_limits: Default::default(),
+            required_limits,
             memory_hints: Default::default(),
             trace: wgpu::Trace::Off,
             experimental_features: ExperimentalFeatures::disabled(),
         })
         .await
-        .expect("Unable to find a suitable GPU adapter. :(");
+        .map_err(GraphicsError::NoDevice)
+}
+
+/// If we can draw all meshes in a pass with one `multi_draw_indexed_indirect` call. It needs
+/// per-draw instance offsets.
+pub(crate) fn supports_multi_draw_indirect(adapter: &Adapter, device: &Device) -> bool {
+    device
+        .features()
+        .contains(Features::INDIRECT_FIRST_INSTANCE)
+        && adapter
+            .get_downlevel_capabilities()
+            .flags
+            .contains(DownlevelFlags::INDIRECT_EXECUTION)
+}
 
-    (adapter, device, queue)
+/// If the adapter runs compute shaders, e.g. to evaluate gaussian fields. WebGL doesn't.
+pub(crate) fn supports_compute(adapter: &Adapter) -> bool {
+    adapter
+        .get_downlevel_capabilities()
+        .flags
+        .contains(DownlevelFlags::COMPUTE_SHADERS)
 }
 
 /// Process engine updates from render, GUI, or events.
@@ -340,9 +723,40 @@ pub(crate) fn process_engine_updates(
     device: &Device,
     queue: &Queue,
 ) {
+    if updates.requires_redraw() {
+        g_state.redraw_pending = true;
+    }
+
     if updates.meshes {
         g_state.setup_vertices_indices(device);
-        g_state.setup_entities(device, queue);
+        g_state.setup_entities(device);
+    } else if !updates.meshes_changed.is_empty()
+        && g_state.update_meshes(device, &updates.meshes_changed)
+    {
+        // Meshes were added or removed; the instance layout depends on this.
+        g_state.setup_entities(device);
+    }
+
+    if !updates.meshes_async.is_empty()
+        && g_state.upload_meshes_async(device, &updates.meshes_async)
+    {
+        g_state.setup_entities(device);
+    }
+
+    if updates.lines {
+        g_state.setup_lines(device);
+    }
+
+    if updates.spheres {
+        g_state.setup_spheres(device);
+    }
+
+    if updates.cylinders {
+        g_state.setup_cylinders(device);
+    }
+
+    if !updates.textures.is_empty() {
+        g_state.update_textures(device, queue, &updates.textures);
     }
 
     // todo: Alternative structure: Have this function just be for the full replacement,
@@ -362,9 +776,9 @@ pub(crate) fn process_engine_updates(
     // todo: Temp marked all until we sort out how to do this properly.
     match &updates.entities {
         EntityUpdate::None => (),
-        EntityUpdate::All => g_state.setup_entities(device, queue),
+        EntityUpdate::All => g_state.setup_entities(device),
         // Classes, IDs, or indexes.
-        _ => g_state.replace_instance_entries(queue, device, &updates.entities),
+        _ => g_state.replace_instance_entries(device, &updates.entities),
         //
         // EntityUpdate::Classes(classes) => {
         //     if !classes.is_empty() {
@@ -383,21 +797,47 @@ pub(crate) fn process_engine_updates(
         // }
     }
 
+    if !updates.materials.is_empty() {
+        g_state.update_materials(device, &updates.materials);
+    }
+
+    if updates.entities != EntityUpdate::None {
+        g_state.scene.spatial_index.invalidate();
+    }
+
+    g_state.update_gaussians(device, &updates.gaussians);
+
     if updates.camera {
         // Entities have been updated in the scene; update the buffer.
-        g_state.update_camera(queue);
+        g_state.update_camera();
+    } else if updates.fog {
+        g_state.update_fog();
+    }
+
+    if let Some(center) = updates.arc_center {
+        g_state.recenter_arc(center);
     }
 
     if updates.lighting {
         // Entities have been updated in the scene; update the buffer.
-        g_state.update_lighting(queue);
+        g_state.update_lighting();
+    }
+
+    if updates.window {
+        update_window(g_state.window(), &g_state.scene);
+    }
+
+    if let Some(path) = &updates.window_icon {
+        update_window_icon(g_state.window(), path);
     }
 
     if let Some(settings) = &updates.graphics_settings {
         g_state.apply_graphics_settings(settings, queue);
-        // MSAA requires pipeline recreation; flag it for window.rs::redraw().
-        if settings.msaa_samples != g_state.msaa_samples {
-            g_state.pending_msaa = Some(settings.msaa_samples);
+        // MSAA requires pipeline recreation; flag it for window.rs::redraw(). Adaptive quality
+        // may be holding it lower than the setting.
+        let msaa = g_state.quality.msaa();
+        if msaa != g_state.msaa_samples {
+            g_state.pending_msaa = Some(msaa);
         }
     }
 }
@@ -4,7 +4,12 @@ use egui::{Align2, Color32, FontFamily, FontId, Pos2};
 use lin_alg::f32::{Vec3, Vec4};
 
 use crate::{
-    UiSettings, graphics::GraphicsState, gui::GuiState, types::FramerateDisplay, viewport_rect,
+    UiSettings,
+    graphics::GraphicsState,
+    gui::GuiState,
+    letterbox,
+    types::{FramerateDisplay, MemoryUsage},
+    viewport_rect,
 };
 
 #[derive(Debug, Clone)]
@@ -69,8 +74,8 @@ pub(crate) fn draw_text_overlay(
     }
 }
 
-/// Draw the frame rate readout in the corner of the 3D display area selected by
-/// `GraphicsSettings::display_framerate`.
+/// Draw the frame rate readout, and memory usage if enabled, in the corner of the 3D display area
+/// selected by `GraphicsSettings::display_framerate`.
 pub(crate) fn draw_framerate(
     graphics_state: &GraphicsState,
     gui: &GuiState,
@@ -79,10 +84,20 @@ pub(crate) fn draw_framerate(
     width: u32,
     height: u32,
 ) {
-    // Skip drawing until the first measurement window has completed.
-    if graphics_state.framerate_display == FramerateDisplay::Disabled
-        || graphics_state.fps_value <= 0.
+    let mut lines = Vec::new();
+
+    // Skip the frame rate until the first measurement window has completed.
+    if graphics_state.framerate_display != FramerateDisplay::Disabled
+        && graphics_state.fps_value > 0.
     {
+        lines.push(format!("{:.0} FPS", graphics_state.fps_value));
+    }
+
+    if graphics_state.display_memory_usage {
+        lines.extend(memory_lines(&graphics_state.scene.memory_usage));
+    }
+
+    if lines.is_empty() {
         return;
     }
 
@@ -93,17 +108,22 @@ pub(crate) fn draw_framerate(
     let logical_width = (width as f32 / pixels_per_pt).round() as u32;
     let logical_height = (height as f32 / pixels_per_pt).round() as u32;
 
-    let (x, y, eff_width, eff_height) = viewport_rect(
-        gui.size,
-        logical_width,
-        logical_height,
-        ui_settings,
-        pixels_per_pt,
+    let (x, y, eff_width, eff_height) = letterbox(
+        viewport_rect(
+            gui.size,
+            logical_width,
+            logical_height,
+            ui_settings,
+            pixels_per_pt,
+        ),
+        graphics_state.scene.viewport_aspect,
     );
 
     const MARGIN: f32 = 10.;
     let (pos, align) = match graphics_state.framerate_display {
-        FramerateDisplay::TopLeft => (Pos2::new(x + MARGIN, y + MARGIN), Align2::LEFT_TOP),
+        FramerateDisplay::TopLeft | FramerateDisplay::Disabled => {
+            (Pos2::new(x + MARGIN, y + MARGIN), Align2::LEFT_TOP)
+        }
         FramerateDisplay::TopRight => (
             Pos2::new(x + eff_width - MARGIN, y + MARGIN),
             Align2::RIGHT_TOP,
@@ -116,7 +136,6 @@ pub(crate) fn draw_framerate(
             Pos2::new(x + eff_width - MARGIN, y + eff_height - MARGIN),
             Align2::RIGHT_BOTTOM,
         ),
-        FramerateDisplay::Disabled => unreachable!(),
     };
 
     let painter = ctx.layer_painter(egui::LayerId::new(
@@ -124,7 +143,7 @@ pub(crate) fn draw_framerate(
         egui::Id::new("framerate_display"),
     ));
 
-    let text = format!("{:.0} FPS", graphics_state.fps_value);
+    let text = lines.join("\n");
     let font = FontId::new(14., FontFamily::Monospace);
 
     // A dark shadow behind the text, so it's legible over light backgrounds.
@@ -138,6 +157,181 @@ pub(crate) fn draw_framerate(
     painter.text(pos, align, &text, font, Color32::WHITE);
 }
 
+fn memory_lines(usage: &MemoryUsage) -> Vec<String> {
+    let mut result = vec![
+        format!("GPU mem: {}", format_bytes(usage.total())),
+        format!(
+            "  vert {}  idx {}",
+            format_bytes(usage.vertex),
+            format_bytes(usage.index)
+        ),
+        format!(
+            "  inst {}  gauss {}",
+            format_bytes(usage.instance),
+            format_bytes(usage.gauss)
+        ),
+        format!("  tex {}", format_bytes(usage.textures)),
+    ];
+
+    if let Some((allocated, reserved)) = usage.allocator {
+        result.push(format!(
+            "  wgpu {} / {}",
+            format_bytes(allocated),
+            format_bytes(reserved)
+        ));
+    }
+
+    result
+}
+
+/// Draw the progress of uploads from `EngineUpdates::meshes_async` along the top of the 3D display
+/// area, while any are pending: a count of meshes, and a bar.
+pub(crate) fn draw_upload_progress(
+    graphics_state: &GraphicsState,
+    gui: &GuiState,
+    ui_settings: &UiSettings,
+    // These are in physical pixels.
+    width: u32,
+    height: u32,
+) {
+    if !graphics_state.display_upload_progress {
+        return;
+    }
+    let Some(progress) = graphics_state.scene.mesh_upload_progress else {
+        return;
+    };
+
+    let ctx = gui.egui_state.egui_ctx();
+    let pixels_per_pt = ctx.pixels_per_point();
+
+    let logical_width = (width as f32 / pixels_per_pt).round() as u32;
+    let logical_height = (height as f32 / pixels_per_pt).round() as u32;
+
+    let (x, y, eff_width, _) = letterbox(
+        viewport_rect(
+            gui.size,
+            logical_width,
+            logical_height,
+            ui_settings,
+            pixels_per_pt,
+        ),
+        graphics_state.scene.viewport_aspect,
+    );
+
+    const MARGIN: f32 = 10.;
+    const PADDING: f32 = 6.;
+    const BAR_HEIGHT: f32 = 4.;
+
+    let painter = ctx.layer_painter(egui::LayerId::new(
+        egui::Order::Foreground,
+        egui::Id::new("upload_progress"),
+    ));
+
+    let galley = painter.layout_no_wrap(
+        format!("Uploading meshes: {} / {}", progress.done, progress.total),
+        FontId::new(13., FontFamily::Proportional),
+        Color32::WHITE,
+    );
+
+    let size = galley.size() + egui::vec2(0., PADDING + BAR_HEIGHT);
+    let rect = Align2::CENTER_TOP
+        .anchor_size(Pos2::new(x + eff_width / 2., y + MARGIN + PADDING), size)
+        .expand(PADDING);
+
+    painter.rect_filled(rect, 4., Color32::from_rgba_unmultiplied(20, 20, 20, 200));
+
+    let bar = egui::Rect::from_min_size(
+        Pos2::new(rect.min.x + PADDING, rect.max.y - PADDING - BAR_HEIGHT),
+        egui::vec2(size.x, BAR_HEIGHT),
+    );
+    painter.rect_filled(bar, 2., Color32::from_gray(70));
+    painter.rect_filled(
+        egui::Rect::from_min_size(
+            bar.min,
+            egui::vec2(size.x * progress.fraction(), BAR_HEIGHT),
+        ),
+        2.,
+        Color32::from_rgb(90, 160, 240),
+    );
+
+    painter.galley(
+        rect.min + egui::vec2(PADDING, PADDING),
+        galley,
+        Color32::WHITE,
+    );
+}
+
+fn format_bytes(bytes: u64) -> String {
+    const MB: f64 = 1_048_576.;
+    if bytes as f64 >= MB {
+        format!("{:.1} MB", bytes as f64 / MB)
+    } else {
+        format!("{:.1} KB", bytes as f64 / 1_024.)
+    }
+}
+
+/// Draw the last shader compile error along the bottom of the 3D display area, while shader hot
+/// reloading is enabled.
+#[cfg(feature = "hot_reload")]
+pub(crate) fn draw_shader_error(
+    graphics_state: &GraphicsState,
+    gui: &GuiState,
+    ui_settings: &UiSettings,
+    // These are in physical pixels.
+    width: u32,
+    height: u32,
+) {
+    let Some((error, _)) = &graphics_state.shader_error else {
+        return;
+    };
+
+    let ctx = gui.egui_state.egui_ctx();
+    let pixels_per_pt = ctx.pixels_per_point();
+
+    let logical_width = (width as f32 / pixels_per_pt).round() as u32;
+    let logical_height = (height as f32 / pixels_per_pt).round() as u32;
+
+    let (x, y, eff_width, eff_height) = letterbox(
+        viewport_rect(
+            gui.size,
+            logical_width,
+            logical_height,
+            ui_settings,
+            pixels_per_pt,
+        ),
+        graphics_state.scene.viewport_aspect,
+    );
+
+    const MARGIN: f32 = 10.;
+    const PADDING: f32 = 6.;
+
+    let painter = ctx.layer_painter(egui::LayerId::new(
+        egui::Order::Foreground,
+        egui::Id::new("shader_error"),
+    ));
+
+    let galley = painter.layout(
+        error.clone(),
+        FontId::new(13., FontFamily::Monospace),
+        Color32::WHITE,
+        eff_width - 2. * (MARGIN + PADDING),
+    );
+
+    let rect = Align2::CENTER_BOTTOM
+        .anchor_size(
+            Pos2::new(x + eff_width / 2., y + eff_height - MARGIN - PADDING),
+            galley.size(),
+        )
+        .expand(PADDING);
+
+    painter.rect_filled(rect, 4., Color32::from_rgba_unmultiplied(140, 20, 20, 220));
+    painter.galley(
+        rect.min + egui::vec2(PADDING, PADDING),
+        galley,
+        Color32::WHITE,
+    );
+}
+
 impl GraphicsState {
     /// Project a world-space point to screen-space (in egui points).
     /// Returns None if behind camera or outside clip space.
@@ -159,12 +353,15 @@ impl GraphicsState {
         let logical_width = (width as f32 / pixels_per_pt).round() as u32;
         let logical_height = (height as f32 / pixels_per_pt).round() as u32;
 
-        let (x, y, eff_width, eff_height) = viewport_rect(
-            ui_size,
-            logical_width,
-            logical_height,
-            ui_settings,
-            pixels_per_pt,
+        let (x, y, eff_width, eff_height) = letterbox(
+            viewport_rect(
+                ui_size,
+                logical_width,
+                logical_height,
+                ui_settings,
+                pixels_per_pt,
+            ),
+            self.scene.viewport_aspect,
         );
 
         let (in_view, ndc) = self.scene.camera.in_view(world);
@@ -200,12 +397,15 @@ impl GraphicsState {
         let logical_width = (width as f32 / pixels_per_pt).round() as u32;
         let logical_height = (height as f32 / pixels_per_pt).round() as u32;
 
-        let (x, y, eff_width, eff_height) = viewport_rect(
-            ui_size,
-            logical_width,
-            logical_height,
-            ui_settings,
-            pixels_per_pt,
+        let (x, y, eff_width, eff_height) = letterbox(
+            viewport_rect(
+                ui_size,
+                logical_width,
+                logical_height,
+                ui_settings,
+                pixels_per_pt,
+            ),
+            self.scene.viewport_aspect,
         );
 
         let proj_view = self.scene.camera.proj_mat.clone() * self.scene.camera.view_mat();
@@ -213,10 +413,11 @@ impl GraphicsState {
         for e in &self.scene.entities {
             if let Some(overlay) = &e.overlay_text {
                 // Slight vertical offset above the entity (tune as you like).
+                let position = self.scene.world_position(e);
                 let label_world = Vec3 {
-                    x: e.position.x,
-                    y: e.position.y + 0.05 * e.scale, // small lift
-                    z: e.position.z,
+                    x: position.x,
+                    y: position.y + 0.05 * e.scale, // small lift
+                    z: position.z,
                 };
 
                 // Same math as `Camera::in_view`, using the precomputed matrix.
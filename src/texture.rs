@@ -1,12 +1,39 @@
-use image::GenericImageView;
-use wgpu::{Device, Queue, TextureDescriptor, TextureFormat};
+//! Textures: the depth and render targets we create internally, and images the application loads,
+//! which meshes sample in the main shader.
 
-use crate::system::DEPTH_FORMAT;
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    fmt, fs, io, mem,
+    path::PathBuf,
+    sync::{
+        Arc,
+        mpsc::{self, Receiver, TryRecvError},
+    },
+    thread,
+};
+
+#[cfg(feature = "app_utils")]
+use bincode::{Decode, Encode};
+use half::f16;
+use image::{DynamicImage, GenericImageView, Rgba, RgbaImage};
+use lin_alg::f32::Vec3;
+use tracing::{debug, warn};
+use wgpu::{
+    BindGroup, BindGroupLayout, BindingType, Device, Features, Queue, ShaderStages,
+    TextureDescriptor, TextureFormat,
+    util::{DeviceExt, TextureDataOrder},
+};
+
+use crate::{
+    compressed_texture::{self, CompressedImage},
+    system::DEPTH_FORMAT,
+};
 
 pub struct Texture {
     pub _texture: wgpu::Texture,
     pub view: wgpu::TextureView,
-    pub _sampler: wgpu::Sampler,
+    pub sampler: wgpu::Sampler,
 }
 
 impl Texture {
@@ -23,6 +50,13 @@ impl Texture {
             depth_or_array_layers: 1,
         };
 
+        let mut usage =
+            wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING;
+        // Multisampled textures can't be copied; the 1-sample one is, for depth readback.
+        if sample_count == 1 {
+            usage |= wgpu::TextureUsages::COPY_SRC;
+        }
+
         let desc = TextureDescriptor {
             label: Some(label),
             size,
@@ -30,7 +64,7 @@ impl Texture {
             sample_count,
             dimension: wgpu::TextureDimension::D2,
             format: DEPTH_FORMAT,
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            usage,
             view_formats: &[],
         };
 
@@ -52,7 +86,7 @@ impl Texture {
         Self {
             _texture: texture,
             view,
-            _sampler: sampler,
+            sampler,
         }
     }
 
@@ -63,12 +97,17 @@ impl Texture {
         bytes: &[u8],
         label: &str,
         is_normal_map: bool,
-    ) -> Self {
-        let img = image::load_from_memory(bytes).unwrap();
-        Self::from_image(device, queue, &img, Some(label), is_normal_map)
+    ) -> Result<Self, image::ImageError> {
+        let img = image::load_from_memory(bytes)?;
+        Ok(Self::from_image(
+            device,
+            queue,
+            &img,
+            Some(label),
+            is_normal_map,
+        ))
     }
 
-    #[allow(dead_code)]
     pub fn from_image(
         device: &Device,
         queue: &Queue,
@@ -115,21 +154,776 @@ impl Texture {
             size,
         );
 
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = create_image_sampler(device);
+
+        Self {
+            _texture: texture,
+            view,
+            sampler,
+        }
+    }
+
+    /// Upload a texture loaded from a DDS or KTX2 file, with its mip levels. If the device doesn't
+    /// support its format, we decompress it on the CPU, if we can.
+    pub fn from_compressed(
+        device: &Device,
+        queue: &Queue,
+        img: CompressedImage,
+        label: Option<&str>,
+    ) -> Result<Self, TextureError> {
+        let img = img.into_supported(device.features())?;
+
+        let texture = device.create_texture_with_data(
+            queue,
+            &TextureDescriptor {
+                label,
+                size: wgpu::Extent3d {
+                    width: img.width,
+                    height: img.height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: img.mip_level_count,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: img.format,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+                view_formats: &[],
+            },
+            TextureDataOrder::LayerMajor,
+            &img.data,
+        );
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = create_image_sampler(device);
+
+        Ok(Self {
+            _texture: texture,
+            view,
+            sampler,
+        })
+    }
+
+    /// Create a 3D texture from scalar values, e.g. a density sampled on a grid. `values` are in
+    /// x-major order: x varies fastest, then y, then z. We store them as 16-bit floats, which
+    /// filter on all GPUs; samples between grid points interpolate linearly.
+    pub fn from_volume(
+        device: &Device,
+        queue: &Queue,
+        dims: (u32, u32, u32),
+        values: &[f32],
+        label: Option<&str>,
+    ) -> Result<Self, TextureError> {
+        let (x, y, z) = dims;
+
+        let expected = x as usize * y as usize * z as usize;
+        if values.len() != expected || expected == 0 {
+            return Err(TextureError::WrongSize {
+                expected,
+                found: values.len(),
+            });
+        }
+
+        let result = Self::empty_volume(device, dims, label)?;
+
+        let data: Vec<u8> = values
+            .iter()
+            .flat_map(|&v| f16::from_f32(v).to_le_bytes())
+            .collect();
+
+        queue.write_texture(
+            wgpu::TexelCopyTextureInfo {
+                aspect: wgpu::TextureAspect::All,
+                texture: &result._texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+            },
+            &data,
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(2 * x),
+                rows_per_image: Some(y),
+            },
+            result._texture.size(),
+        );
+
+        Ok(result)
+    }
+
+    /// A 3D texture of zeros, as `from_volume`; e.g. for the GPU to fill.
+    pub fn empty_volume(
+        device: &Device,
+        dims: (u32, u32, u32),
+        label: Option<&str>,
+    ) -> Result<Self, TextureError> {
+        let (x, y, z) = dims;
+
+        let max = device.limits().max_texture_dimension_3d;
+        if x.max(y).max(z) > max {
+            return Err(TextureError::TooLarge { dims, max });
+        }
+
+        let texture = device.create_texture(&TextureDescriptor {
+            label,
+            size: wgpu::Extent3d {
+                width: x,
+                height: y,
+                depth_or_array_layers: z,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D3,
+            format: TextureFormat::R16Float,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+
         let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
         let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
             address_mode_u: wgpu::AddressMode::ClampToEdge,
             address_mode_v: wgpu::AddressMode::ClampToEdge,
             address_mode_w: wgpu::AddressMode::ClampToEdge,
             mag_filter: wgpu::FilterMode::Linear,
-            min_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Linear,
             mipmap_filter: wgpu::MipmapFilterMode::Nearest,
             ..Default::default()
         });
 
-        Self {
+        Ok(Self {
             _texture: texture,
             view,
-            _sampler: sampler,
+            sampler,
+        })
+    }
+}
+
+/// Repeats outside 0 to 1, so textures can tile; see `UvTransform`.
+fn create_image_sampler(device: &Device) -> wgpu::Sampler {
+    device.create_sampler(&wgpu::SamplerDescriptor {
+        address_mode_u: wgpu::AddressMode::Repeat,
+        address_mode_v: wgpu::AddressMode::Repeat,
+        address_mode_w: wgpu::AddressMode::ClampToEdge,
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter: wgpu::FilterMode::Nearest,
+        mipmap_filter: wgpu::MipmapFilterMode::Nearest,
+        ..Default::default()
+    })
+}
+
+/// Why a texture failed to load.
+#[derive(Debug)]
+pub(crate) enum TextureError {
+    Io(io::Error),
+    Image(image::ImageError),
+    /// A DDS or KTX2 file we couldn't read.
+    Container(String),
+    /// The device doesn't support this compressed format, and we can't decompress it.
+    UnsupportedFormat(TextureFormat),
+    /// Raw data didn't match the texture's size: in bytes for pixels, or values for volumes.
+    WrongSize {
+        expected: usize,
+        found: usize,
+    },
+    /// A volume is larger than the device's `max_texture_dimension_3d` limit.
+    TooLarge {
+        dims: (u32, u32, u32),
+        max: u32,
+    },
+}
+
+impl fmt::Display for TextureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "{e}"),
+            Self::Image(e) => write!(f, "{e}"),
+            Self::Container(e) => write!(f, "{e}"),
+            Self::UnsupportedFormat(format) => {
+                write!(f, "{format:?} is unsupported by this GPU")
+            }
+            Self::WrongSize { expected, found } => {
+                write!(f, "Expected {expected} elements of data; found {found}")
+            }
+            Self::TooLarge { dims, max } => {
+                write!(
+                    f,
+                    "Volume of {dims:?} exceeds the GPU's limit of {max} per side"
+                )
+            }
+        }
+    }
+}
+
+impl From<io::Error> for TextureError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<image::ImageError> for TextureError {
+    fn from(e: image::ImageError) -> Self {
+        Self::Image(e)
+    }
+}
+
+/// Identifies a texture added with `Scene::load_texture`. Reference it from a mesh with
+/// `Mesh::texture`.
+#[cfg_attr(feature = "app_utils", derive(Encode, Decode))]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct TextureHandle(pub(crate) u32);
+
+/// An image to load as a texture, in any format the `image` crate reads, e.g. PNG or JPEG. DDS and
+/// KTX2 files with block-compressed formats (BCn, ETC2, ASTC) are uploaded compressed, with their
+/// mip levels, which uses much less GPU memory.
+#[derive(Clone)]
+pub enum TextureSource {
+    Path(PathBuf),
+    /// Encoded image bytes, e.g. from `include_bytes!`.
+    Bytes(Arc<[u8]>),
+    /// Raw sRGB RGBA8 pixels, in rows from the top. Use this for textures the application updates
+    /// while running, with `EngineUpdates::textures`, e.g. video frames.
+    Pixels {
+        width: u32,
+        height: u32,
+        data: Arc<[u8]>,
+    },
+    /// A 3D grid of scalar values, e.g. a density or wavefunction, in x-major order. Meshes can't
+    /// reference these; they're for sampling volumes and slices through them.
+    Volume {
+        dims: (u32, u32, u32),
+        values: Arc<[f32]>,
+    },
+    /// A volume of the summed density of `Scene::gaussians`, evaluated on the GPU on a grid from
+    /// the min to max bound inclusive, with `dims` points along each axis; as
+    /// `gaussian_density_grid`, without the CPU cost. It's re-evaluated when the gaussians change.
+    /// For a 2D slice, use 1 point along an axis; the slice is at the min bound on it.
+    GaussianField {
+        dims: (u32, u32, u32),
+        bounds: (Vec3, Vec3),
+    },
+}
+
+impl PartialEq for TextureSource {
+    /// Paths and encoded bytes compare by value, so loading one twice finds the first. Pixels
+    /// compare by identity; textures updated at runtime often start out identical, e.g. black.
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Path(a), Self::Path(b)) => a == b,
+            (Self::Bytes(a), Self::Bytes(b)) => a == b,
+            (Self::Pixels { data: a, .. }, Self::Pixels { data: b, .. }) => Arc::ptr_eq(a, b),
+            (Self::Volume { values: a, .. }, Self::Volume { values: b, .. }) => Arc::ptr_eq(a, b),
+            (
+                Self::GaussianField { dims, bounds },
+                Self::GaussianField {
+                    dims: dims_b,
+                    bounds: bounds_b,
+                },
+            ) => dims == dims_b && bounds.0 == bounds_b.0 && bounds.1 == bounds_b.1,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Debug for TextureSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Path(path) => write!(f, "Path({path:?})"),
+            // Don't print the image data.
+            Self::Bytes(bytes) => write!(f, "Bytes({} bytes)", bytes.len()),
+            Self::Pixels { width, height, .. } => write!(f, "Pixels({width}x{height})"),
+            Self::Volume { dims, .. } => write!(f, "Volume({}x{}x{})", dims.0, dims.1, dims.2),
+            Self::GaussianField { dims, .. } => {
+                write!(f, "GaussianField({}x{}x{})", dims.0, dims.1, dims.2)
+            }
+        }
+    }
+}
+
+impl TextureSource {
+    /// Whether this is the same source as `other`, so its texture needn't be reloaded. As `eq`,
+    /// but encoded bytes compare by identity too, so this is cheap to check each frame.
+    fn same(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Bytes(a), Self::Bytes(b)) => Arc::ptr_eq(a, b),
+            _ => self == other,
         }
     }
+
+    /// Upload the texture, for sources that need no decoding. `None` for image files; decode
+    /// those with `decode`.
+    fn upload(
+        &self,
+        device: &Device,
+        queue: &Queue,
+        label: &str,
+    ) -> Option<Result<Texture, TextureError>> {
+        Some(match self {
+            Self::Path(_) | Self::Bytes(_) => return None,
+            Self::Pixels {
+                width,
+                height,
+                data,
+            } => {
+                let expected = *width as usize * *height as usize * 4;
+                match RgbaImage::from_raw(*width, *height, data.to_vec()) {
+                    Some(img) => Ok(Texture::from_image(
+                        device,
+                        queue,
+                        &DynamicImage::ImageRgba8(img),
+                        Some(label),
+                        false,
+                    )),
+                    None => Err(TextureError::WrongSize {
+                        expected,
+                        found: data.len(),
+                    }),
+                }
+            }
+            Self::Volume { dims, values } => {
+                Texture::from_volume(device, queue, *dims, values, Some(label))
+            }
+            Self::GaussianField { dims, .. } => Texture::empty_volume(device, *dims, Some(label)),
+        })
+    }
+
+    /// Read and decode an image file; the slow part of loading one, which we do on a background
+    /// thread. Compressed images the device can't sample are decompressed here too. `None` for
+    /// other sources.
+    fn decode(&self, features: Features) -> Option<Result<DecodedImage, TextureError>> {
+        let bytes = match self {
+            Self::Path(path) => match fs::read(path) {
+                Ok(b) => Cow::Owned(b),
+                Err(e) => return Some(Err(e.into())),
+            },
+            Self::Bytes(bytes) => Cow::Borrowed(&bytes[..]),
+            _ => return None,
+        };
+
+        Some(match compressed_texture::parse(&bytes) {
+            Some(compressed) => compressed
+                .and_then(|c| c.into_supported(features))
+                .map(DecodedImage::Compressed),
+            None => image::load_from_memory(&bytes)
+                .map(DecodedImage::Image)
+                .map_err(Into::into),
+        })
+    }
+}
+
+/// An image file decoded by `TextureSource::decode`, ready to upload.
+enum DecodedImage {
+    Image(DynamicImage),
+    Compressed(CompressedImage),
+}
+
+impl DecodedImage {
+    fn upload(self, device: &Device, queue: &Queue, label: &str) -> Result<Texture, TextureError> {
+        match self {
+            Self::Image(img) => Ok(Texture::from_image(device, queue, &img, Some(label), false)),
+            Self::Compressed(img) => Texture::from_compressed(device, queue, img, Some(label)),
+        }
+    }
+}
+
+/// An image file decoded on a background thread, with its index into `Scene::textures`, and the
+/// id of the load.
+type Decoded = (usize, u64, Result<DecodedImage, TextureError>);
+
+/// Textures loaded from `Scene::textures`, uploaded to the GPU with a bind group each, for the mesh
+/// shader. Indexed by `TextureHandle`.
+pub(crate) struct TextureStore {
+    pub layout: BindGroupLayout,
+    /// As `layout`, for 3D textures.
+    pub layout_volume: BindGroupLayout,
+    /// A 1x1 white texture; bound for meshes without a texture, and in place of ones that failed
+    /// to load.
+    default: (Texture, BindGroup),
+    /// `None` if loading failed, or the first load is still decoding.
+    entries: Vec<Option<(Texture, BindGroup)>>,
+    /// The source each entry was loaded from; when one in `Scene::textures` changes, we reload it.
+    sources: Vec<TextureSource>,
+    /// The load in progress for each entry decoding on a background thread, by index. A load
+    /// replaced by a later one is discarded when it arrives.
+    loading: HashMap<usize, u64>,
+    next_load: u64,
+    /// Image files decoded together on a background thread, sent as each is done.
+    decoding: Vec<Receiver<Decoded>>,
+    /// Set when a `TextureSource::GaussianField` is uploaded, until it's evaluated.
+    pub fields_added: bool,
+}
+
+impl TextureStore {
+    pub fn new(device: &Device, queue: &Queue) -> Self {
+        let layout = create_layout(
+            device,
+            wgpu::TextureViewDimension::D2,
+            "Texture bind group layout",
+        );
+        let layout_volume = create_layout(
+            device,
+            wgpu::TextureViewDimension::D3,
+            "Volume texture bind group layout",
+        );
+
+        let white = DynamicImage::ImageRgba8(RgbaImage::from_pixel(1, 1, Rgba([255; 4])));
+        let texture = Texture::from_image(device, queue, &white, Some("Default texture"), false);
+        let bind_group = create_bind_group(device, &layout, &texture, "Default texture bind group");
+
+        Self {
+            layout,
+            layout_volume,
+            default: (texture, bind_group),
+            entries: Vec::new(),
+            sources: Vec::new(),
+            loading: HashMap::new(),
+            next_load: 0,
+            decoding: Vec::new(),
+            fields_added: false,
+        }
+    }
+
+    /// Load textures added to `sources` since the last call, and reload ones replaced there; if
+    /// `sources` shrank, we drop the textures past its end. Image files are decoded on a
+    /// background thread, and uploaded by a later call once ready; until then, the texture they
+    /// replace stays in use, or a new one draws as the default.
+    pub fn sync(&mut self, device: &Device, queue: &Queue, sources: &[TextureSource]) {
+        if sources.len() < self.entries.len() {
+            self.entries.truncate(sources.len());
+            self.sources.truncate(sources.len());
+            self.loading.retain(|i, _| *i < sources.len());
+        }
+
+        let mut to_decode = Vec::new();
+        for (i, source) in sources.iter().enumerate() {
+            if self.sources.get(i).is_some_and(|s| s.same(source)) {
+                continue;
+            }
+
+            if i == self.entries.len() {
+                self.entries.push(None);
+                self.sources.push(source.clone());
+            } else {
+                self.sources[i] = source.clone();
+            }
+            self.loading.remove(&i);
+
+            let label = format!("Texture {i}");
+            debug!("Loading {label} from {source:?}");
+            self.fields_added |= matches!(source, TextureSource::GaussianField { .. });
+
+            match source.upload(device, queue, &label) {
+                Some(result) => self.set_entry(device, i, result),
+                None => {
+                    self.loading.insert(i, self.next_load);
+                    to_decode.push((i, self.next_load, source.clone()));
+                    self.next_load += 1;
+                }
+            }
+        }
+
+        self.decode(to_decode, device.features());
+        self.finish_decoded(device, queue);
+    }
+
+    /// Start decoding image files in order, on a background thread. There are no threads in the
+    /// browser; there, this decodes them immediately, and they're uploaded at the next `sync`.
+    fn decode(&mut self, sources: Vec<(usize, u64, TextureSource)>, features: Features) {
+        if sources.is_empty() {
+            return;
+        }
+
+        let (tx, rx) = mpsc::channel();
+        let decode = move || {
+            for (i, id, source) in sources {
+                let Some(decoded) = source.decode(features) else {
+                    continue;
+                };
+                // If the store was dropped in the meantime, there's no one to send to.
+                if tx.send((i, id, decoded)).is_err() {
+                    break;
+                }
+            }
+        };
+
+        if cfg!(target_arch = "wasm32") {
+            decode();
+        } else {
+            thread::spawn(decode);
+        }
+
+        self.decoding.push(rx);
+    }
+
+    /// Upload the images done decoding.
+    fn finish_decoded(&mut self, device: &Device, queue: &Queue) {
+        let mut decoded = Vec::new();
+        self.decoding.retain(|rx| {
+            loop {
+                match rx.try_recv() {
+                    Ok(d) => decoded.push(d),
+                    Err(TryRecvError::Empty) => return true,
+                    Err(TryRecvError::Disconnected) => return false,
+                }
+            }
+        });
+
+        self.upload_decoded(device, queue, decoded);
+    }
+
+    /// Block until every image file still decoding is uploaded, e.g. to render a complete frame
+    /// headlessly.
+    pub fn wait_all(&mut self, device: &Device, queue: &Queue) {
+        let decoded = mem::take(&mut self.decoding)
+            .iter()
+            .flat_map(|rx| rx.iter())
+            .collect();
+        self.upload_decoded(device, queue, decoded);
+    }
+
+    /// Upload decoded images, unless a later load replaced them. Entries whose decoding thread
+    /// ended without them, i.e. it panicked, are marked failed.
+    fn upload_decoded(&mut self, device: &Device, queue: &Queue, decoded: Vec<Decoded>) {
+        for (i, id, result) in decoded {
+            if self.loading.get(&i) != Some(&id) {
+                continue;
+            }
+            self.loading.remove(&i);
+
+            let label = format!("Texture {i}");
+            let result = result.and_then(|img| img.upload(device, queue, &label));
+            self.set_entry(device, i, result);
+        }
+
+        if self.decoding.is_empty() {
+            for (i, _) in self.loading.drain() {
+                warn!(
+                    "Unable to load texture {:?}: decoding failed",
+                    self.sources[i]
+                );
+                self.entries[i] = None;
+            }
+        }
+    }
+
+    /// If any image files are still decoding.
+    pub fn pending(&self) -> bool {
+        !self.loading.is_empty()
+    }
+
+    /// Create the bind group for a loaded texture, and store it at `i`; or, if loading failed,
+    /// mark the entry failed.
+    fn set_entry(&mut self, device: &Device, i: usize, result: Result<Texture, TextureError>) {
+        self.entries[i] = match result {
+            Ok(texture) => {
+                let layout = match texture._texture.dimension() {
+                    wgpu::TextureDimension::D3 => &self.layout_volume,
+                    _ => &self.layout,
+                };
+                let label = format!("Texture {i}");
+                let bind_group = create_bind_group(device, layout, &texture, &label);
+                Some((texture, bind_group))
+            }
+            Err(e) => {
+                warn!("Unable to load texture {:?}: {e}", self.sources[i]);
+                None
+            }
+        };
+    }
+
+    /// Replace a texture's pixels with `data`: sRGB RGBA8, matching its size. Only for textures
+    /// we store uncompressed.
+    pub fn write(&self, queue: &Queue, handle: TextureHandle, data: &[u8]) {
+        let Some(Some((texture, _))) = self.entries.get(handle.0 as usize) else {
+            warn!("Texture update for {handle:?}, which isn't loaded; skipping");
+            return;
+        };
+        let texture = &texture._texture;
+
+        if texture.format() != TextureFormat::Rgba8UnormSrgb {
+            warn!(
+                "Texture update for {handle:?}, stored as {:?}; only RGBA8 textures can be \
+                updated. Skipping",
+                texture.format()
+            );
+            return;
+        }
+
+        let expected = texture.width() as usize * texture.height() as usize * 4;
+        if data.len() != expected {
+            let e = TextureError::WrongSize {
+                expected,
+                found: data.len(),
+            };
+            warn!("Texture update for {handle:?}: {e}. Skipping");
+            return;
+        }
+
+        queue.write_texture(
+            wgpu::TexelCopyTextureInfo {
+                aspect: wgpu::TextureAspect::All,
+                texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+            },
+            data,
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * texture.width()),
+                rows_per_image: Some(texture.height()),
+            },
+            texture.size(),
+        );
+    }
+
+    /// The bind group to draw a mesh with. Falls back to the default texture if `handle` is `None`,
+    /// out of range, failed to load, or is a volume.
+    pub fn bind_group(&self, handle: Option<TextureHandle>) -> &BindGroup {
+        handle
+            .and_then(|h| self.entries.get(h.0 as usize))
+            .and_then(|e| e.as_ref())
+            .filter(|(t, _)| t._texture.dimension() == wgpu::TextureDimension::D2)
+            .map(|(_, bind_group)| bind_group)
+            .unwrap_or(&self.default.1)
+    }
+
+    /// The bind group for a volume texture, for slice planes. `None` if `handle` is out of range,
+    /// failed to load, or isn't a volume.
+    pub fn volume_bind_group(&self, handle: TextureHandle) -> Option<&BindGroup> {
+        self.entries
+            .get(handle.0 as usize)
+            .and_then(|e| e.as_ref())
+            .filter(|(t, _)| t._texture.dimension() == wgpu::TextureDimension::D3)
+            .map(|(_, bind_group)| bind_group)
+    }
+
+    /// The texture for `handle`, if it's loaded.
+    pub fn texture(&self, handle: TextureHandle) -> Option<&wgpu::Texture> {
+        self.entries
+            .get(handle.0 as usize)
+            .and_then(|e| e.as_ref())
+            .map(|(t, _)| &t._texture)
+    }
+
+    /// Every texture we've uploaded, including the default one.
+    pub fn textures(&self) -> impl Iterator<Item = &wgpu::Texture> {
+        std::iter::once(&self.default.0._texture)
+            .chain(self.entries.iter().flatten().map(|(t, _)| &t._texture))
+    }
+}
+
+fn create_layout(
+    device: &Device,
+    view_dimension: wgpu::TextureViewDimension,
+    label: &str,
+) -> BindGroupLayout {
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some(label),
+        entries: &[
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Texture {
+                    multisampled: false,
+                    view_dimension,
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: ShaderStages::FRAGMENT,
+                // This should match the filterable field of the
+                // corresponding Texture entry above.
+                ty: BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            },
+        ],
+    })
+}
+
+fn create_bind_group(
+    device: &Device,
+    layout: &BindGroupLayout,
+    texture: &Texture,
+    label: &str,
+) -> BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&texture.view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(&texture.sampler),
+            },
+        ],
+        label: Some(label),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn png(width: u32, height: u32) -> Arc<[u8]> {
+        let img = RgbaImage::from_pixel(width, height, Rgba([10, 20, 30, 255]));
+        let mut bytes = Cursor::new(Vec::new());
+        img.write_to(&mut bytes, image::ImageFormat::Png).unwrap();
+        bytes.into_inner().into()
+    }
+
+    #[test]
+    fn decodes_image_bytes() {
+        let source = TextureSource::Bytes(png(3, 2));
+        match source.decode(Features::empty()) {
+            Some(Ok(DecodedImage::Image(img))) => {
+                assert_eq!(img.dimensions(), (3, 2));
+                assert_eq!(img.to_rgba8().get_pixel(2, 1), &Rgba([10, 20, 30, 255]));
+            }
+            _ => panic!("expected a decoded image"),
+        }
+    }
+
+    #[test]
+    fn decode_errors() {
+        let missing = TextureSource::Path(PathBuf::from("/nonexistent/texture.png"));
+        assert!(matches!(
+            missing.decode(Features::empty()),
+            Some(Err(TextureError::Io(_)))
+        ));
+
+        let garbage = TextureSource::Bytes(Arc::from(&b"not an image"[..]));
+        assert!(matches!(
+            garbage.decode(Features::empty()),
+            Some(Err(TextureError::Image(_)))
+        ));
+    }
+
+    #[test]
+    fn only_files_decode() {
+        let pixels = TextureSource::Pixels {
+            width: 1,
+            height: 1,
+            data: Arc::from(&[0; 4][..]),
+        };
+        assert!(pixels.decode(Features::empty()).is_none());
+    }
+
+    #[test]
+    fn replaced_sources_differ() {
+        let bytes = png(1, 1);
+        let a = TextureSource::Bytes(bytes.clone());
+
+        // The same bytes, shared, are the same source; a copy of them is a new one to reload.
+        assert!(a.same(&TextureSource::Bytes(bytes.clone())));
+        assert!(!a.same(&TextureSource::Bytes(bytes.to_vec().into())));
+
+        let path = TextureSource::Path(PathBuf::from("a.png"));
+        assert!(path.same(&TextureSource::Path(PathBuf::from("a.png"))));
+        assert!(!path.same(&TextureSource::Path(PathBuf::from("b.png"))));
+        assert!(!path.same(&a));
+    }
 }
@@ -2,11 +2,44 @@
 
 #[cfg(feature = "app_utils")]
 use bincode::{Decode, Encode};
+use std::{collections::BTreeMap, f32::consts::TAU, mem};
+
+use bytemuck::{Pod, Zeroable};
 use lin_alg::f32::{Mat4, Quaternion, Vec3, Vec4};
-use wgpu::{VertexAttribute, VertexBufferLayout, VertexFormat};
+use tracing::warn;
+use wgpu::{
+    Backends, Features, Limits, PowerPreference, VertexAttribute, VertexBufferLayout, VertexFormat,
+    util::DrawIndexedIndirectArgs,
+};
 
 use crate::{
-    EntityUpdate, camera::Camera, gauss::Gaussian, lighting::Lighting, text_overlay::TextOverlay,
+    EntityUpdate,
+    adaptive_quality::AdaptiveQuality,
+    attribute::{AttributeTarget, AttributeValues, MeshAttribute},
+    background::Background,
+    camera::Camera,
+    color::{ColorSpace, linearize},
+    color_animation::ColorAnimation,
+    debug_draw::DebugDraw,
+    depth_readback::DepthSnapshot,
+    error::ErrorHandler,
+    frame_timing::FrameTimingHandler,
+    gauss::{Gaussian, GaussianInstance, GaussianRenderSettings, GaussianUpdate},
+    graphics::{FWD_VEC, mesh_index_bytes},
+    group::EntityGroup,
+    impostor::{CylinderImpostor, CylinderInstance, SphereImpostor, SphereInstance},
+    input::{self, InputFilter, InputsCommanded},
+    letterbox,
+    lighting::Lighting,
+    lines::Line,
+    material::Material,
+    mesh_upload::UploadProgress,
+    slice::SlicePlane,
+    spatial::{KdTree, SpatialIndex},
+    stereo::Stereo,
+    text_overlay::TextOverlay,
+    texture::{TextureHandle, TextureSource},
+    units::{Units, nice_step},
     viewport_rect,
 };
 
@@ -17,13 +50,12 @@ pub const VEC3_SIZE: usize = 3 * F32_SIZE;
 pub const VEC4_SIZE: usize = 4 * F32_SIZE;
 pub const VEC3_UNIFORM_SIZE: usize = 4 * F32_SIZE;
 pub const MAT4_SIZE: usize = 16 * F32_SIZE;
-pub const MAT3_SIZE: usize = 9 * F32_SIZE;
 
-pub const VERTEX_SIZE: usize = 14 * F32_SIZE + 4; // ast 4 are per-vertex color
+pub const VERTEX_SIZE: usize = 16 * F32_SIZE + 4; // 4 of these are per-vertex color
 
 // Note that position, orientation, and scale are combined into a single 4x4 transformation
 // matrix. Note that unlike uniforms, we don't need alignment padding, and can use Vec3 directly.
-pub const INSTANCE_SIZE: usize = MAT4_SIZE + MAT3_SIZE + VEC4_SIZE + F32_SIZE;
+pub const INSTANCE_SIZE: usize = MAT4_SIZE + 6 * VEC4_SIZE;
 
 // Create the vertex buffer memory layout, for our vertexes passed from CPU
 // to the vertex shader. Corresponds to `VertexIn` in the shader. Each
@@ -56,18 +88,21 @@ pub(crate) const VERTEX_LAYOUT: VertexBufferLayout<'static> = VertexBufferLayout
             shader_location: 3,
             format: VertexFormat::Float32x3,
         },
-        // Bitangent (Used to align textures)
-        VertexAttribute {
-            offset: (2 * F32_SIZE + 3 * VEC3_SIZE) as wgpu::BufferAddress,
-            shader_location: 4,
-            format: VertexFormat::Float32x3,
-        },
+        // The bitangent, after the tangent, isn't passed; the shader can derive it from the
+        // normal and tangent. Its location is used by the instance's UV transform; we're at the
+        // default limit of 16 vertex attributes.
         // Per-vertex color.
         VertexAttribute {
             offset: (2 * F32_SIZE + 4 * VEC3_SIZE) as wgpu::BufferAddress,
             shader_location: 5,
             format: VertexFormat::Unorm8x4,
         },
+        // Scalar for isolines, and whether it's present.
+        VertexAttribute {
+            offset: (2 * F32_SIZE + 4 * VEC3_SIZE + 4) as wgpu::BufferAddress,
+            shader_location: 15,
+            format: VertexFormat::Float32x2,
+        },
     ],
 };
 
@@ -110,35 +145,43 @@ pub(crate) const INSTANCE_LAYOUT: VertexBufferLayout<'static> = VertexBufferLayo
             shader_location: 9,
             format: VertexFormat::Float32x4,
         },
-        // Normal matrix, col 0
+        // The normal matrix is derived from the model matrix in the shader.
+
+        // model (and vertex) color
+        VertexAttribute {
+            offset: MAT4_SIZE as wgpu::BufferAddress,
+            shader_location: 13,
+            format: VertexFormat::Float32x4,
+        },
+        // Shinyness, then the shading mode and its parameters
         VertexAttribute {
-            offset: (MAT4_SIZE) as wgpu::BufferAddress,
+            offset: (MAT4_SIZE + VEC4_SIZE) as wgpu::BufferAddress,
+            shader_location: 14,
+            format: VertexFormat::Float32x4,
+        },
+        // Rim light color, pre-multiplied by intensity, and power
+        VertexAttribute {
+            offset: (MAT4_SIZE + 2 * VEC4_SIZE) as wgpu::BufferAddress,
             shader_location: 10,
-            format: VertexFormat::Float32x3,
+            format: VertexFormat::Float32x4,
         },
-        // Normal matrix, col 1
+        // X-ray opacity, blend mode, reflectivity, then UV rotation
         VertexAttribute {
-            offset: (MAT4_SIZE + VEC3_SIZE) as wgpu::BufferAddress,
+            offset: (MAT4_SIZE + 3 * VEC4_SIZE) as wgpu::BufferAddress,
             shader_location: 11,
-            format: VertexFormat::Float32x3,
+            format: VertexFormat::Float32x4,
         },
-        // Normal matrix, col 2
+        // Application-defined; see `Entity::custom`.
         VertexAttribute {
-            offset: (MAT4_SIZE + VEC3_SIZE * 2) as wgpu::BufferAddress,
+            offset: (MAT4_SIZE + 4 * VEC4_SIZE) as wgpu::BufferAddress,
             shader_location: 12,
-            format: VertexFormat::Float32x3,
-        },
-        // model (and vertex) color
-        VertexAttribute {
-            offset: (MAT4_SIZE + MAT3_SIZE) as wgpu::BufferAddress,
-            shader_location: 13,
             format: VertexFormat::Float32x4,
         },
-        // Shinyness
+        // UV offset, then scale
         VertexAttribute {
-            offset: (MAT4_SIZE + MAT3_SIZE + VEC4_SIZE) as wgpu::BufferAddress,
-            shader_location: 14,
-            format: VertexFormat::Float32,
+            offset: (MAT4_SIZE + 5 * VEC4_SIZE) as wgpu::BufferAddress,
+            shader_location: 4,
+            format: VertexFormat::Float32x4,
         },
     ],
 };
@@ -164,8 +207,11 @@ pub struct Vertex {
     /// Tangent which is a unit vector perpendicular to both vectors at a given point.
     /// This is used to orient normal maps; corresponds to the +Y texture direction.
     pub bitangent: Vec3,
-    /// For per-vertex coloring. If opacity is 0, the entity color will be used instead.
+    /// For per-vertex coloring, in sRGB. If opacity is 0, the entity color will be used instead.
     pub color: Option<(u8, u8, u8, u8)>,
+    /// A value to draw isolines of, e.g. elevation, or potential. See `GraphicsSettings::isolines`.
+    /// Meshes without it don't get isolines.
+    pub scalar: Option<f32>,
 }
 
 impl Vertex {
@@ -178,30 +224,52 @@ impl Vertex {
             tangent: Vec3::new_zero(),
             bitangent: Vec3::new_zero(),
             color: None,
+            scalar: None,
         }
     }
 
-    pub fn to_bytes(&self) -> [u8; VERTEX_SIZE] {
-        let mut result = [0; VERTEX_SIZE];
-
-        result[0..4].clone_from_slice(&self.position[0].to_ne_bytes());
-        result[4..8].clone_from_slice(&self.position[1].to_ne_bytes());
-        result[8..12].clone_from_slice(&self.position[2].to_ne_bytes());
-        result[12..16].clone_from_slice(&self.tex_coords[0].to_ne_bytes());
-        result[16..20].clone_from_slice(&self.tex_coords[1].to_ne_bytes());
-
-        result[20..32].clone_from_slice(&self.normal.to_bytes());
-        result[32..44].clone_from_slice(&self.tangent.to_bytes());
-        result[44..56].clone_from_slice(&self.bitangent.to_bytes());
+    /// Converts to the layout the GPU expects.
+    pub(crate) fn to_raw(self) -> VertexRaw {
+        let color = match self.color {
+            Some(c) => [c.0, c.1, c.2, c.3],
+            None => [0; 4],
+        };
 
-        if let Some(color) = self.color {
-            result[56..60].copy_from_slice(&[color.0, color.1, color.2, color.3]);
+        VertexRaw {
+            position: self.position,
+            tex_coords: self.tex_coords,
+            normal: self.normal.to_arr(),
+            tangent: self.tangent.to_arr(),
+            bitangent: self.bitangent.to_arr(),
+            color,
+            scalar: match self.scalar {
+                Some(s) => [s, 1.],
+                None => [0., 0.],
+            },
         }
+    }
 
-        result
+    pub fn to_bytes(&self) -> [u8; VERTEX_SIZE] {
+        bytemuck::cast(self.to_raw())
     }
 }
 
+/// The memory layout of a vertex, as described by `VERTEX_LAYOUT`. Slices of these can be uploaded
+/// directly, without copying each field.
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+pub(crate) struct VertexRaw {
+    pub position: [f32; 3],
+    pub tex_coords: [f32; 2],
+    pub normal: [f32; 3],
+    pub tangent: [f32; 3],
+    pub bitangent: [f32; 3],
+    pub color: [u8; 4],
+    pub scalar: [f32; 2],
+}
+
+const _: () = assert!(size_of::<VertexRaw>() == VERTEX_SIZE);
+
 /// Instances allow the GPU to render the same object multiple times.
 /// "Instancing allows us to draw the same object multiple times with different properties
 /// (position, orientation, size, color, etc.). "
@@ -210,17 +278,24 @@ pub struct Instance {
     pub orientation: Quaternion,
     pub pivot: Option<Vec3>,
     pub scale: Vec3,
+    /// sRGB.
     pub color: Vec3,
     pub opacity: f32,
     pub shinyness: f32,
+    pub shading: Shading,
+    pub rim_light: Option<RimLight>,
+    #[cfg(feature = "xray")]
+    pub xray: Option<f32>,
+    pub blend: BlendMode,
+    pub reflectivity: f32,
+    pub custom: [f32; 4],
+    pub uv_transform: UvTransform,
 }
 
 impl Instance {
-    /// Converts to a model matrix to a byte array, for passing to the GPU.
-    pub fn to_bytes(&self) -> [u8; INSTANCE_SIZE] {
-        let mut result = [0; INSTANCE_SIZE];
-
-        let model_mat = match self.pivot {
+    /// Maps mesh-local positions to world space.
+    pub(crate) fn model_mat(&self) -> Mat4 {
+        match self.pivot {
             Some(p) => {
                 Mat4::new_translation(self.position)
                     * Mat4::new_translation(p)
@@ -233,35 +308,82 @@ impl Instance {
                     * self.orientation.to_matrix()
                     * Mat4::new_scaler_partial(self.scale)
             }
-        };
-
-        let normal_mat = self.orientation.to_matrix3();
-
-        result[0..MAT4_SIZE].clone_from_slice(&model_mat.to_bytes());
-
-        result[MAT4_SIZE..MAT4_SIZE + MAT3_SIZE].clone_from_slice(&normal_mat.to_bytes());
+        }
+    }
 
-        // todo: fn to convert Vec3 to byte array?
-        let mut color_buf = [0; VEC4_SIZE];
-        color_buf[0..F32_SIZE].clone_from_slice(&self.color.x.to_ne_bytes());
-        color_buf[F32_SIZE..2 * F32_SIZE].clone_from_slice(&self.color.y.to_ne_bytes());
-        color_buf[2 * F32_SIZE..3 * F32_SIZE].clone_from_slice(&self.color.z.to_ne_bytes());
-        color_buf[3 * F32_SIZE..4 * F32_SIZE].clone_from_slice(&self.opacity.to_ne_bytes());
+    /// The bytes of `to_raw`, as passed to the GPU.
+    #[allow(dead_code)]
+    pub fn to_bytes(&self) -> [u8; INSTANCE_SIZE] {
+        bytemuck::cast(self.to_raw())
+    }
 
-        result[MAT4_SIZE + MAT3_SIZE..INSTANCE_SIZE - F32_SIZE].clone_from_slice(&color_buf);
+    /// Converts to the layout the GPU expects, including computing the model matrix, and
+    /// linearizing colors.
+    pub(crate) fn to_raw(&self) -> InstanceRaw {
+        let rim_light = match self.rim_light {
+            Some(r) => {
+                let c = linearize(r.color);
+                [
+                    c[0] * r.intensity,
+                    c[1] * r.intensity,
+                    c[2] * r.intensity,
+                    r.power,
+                ]
+            }
+            None => [0.; 4],
+        };
 
-        // todo
-        // result[MAT4_SIZE + MAT3_SIZE..INSTANCE_SIZE - F32_SIZE]
-        //     // .clone_from_slice(&self.color.to_bytes_uniform());
-        //     .clone_from_slice(&self.color.to_bytes());
+        let color = linearize((self.color.x, self.color.y, self.color.z));
 
-        result[INSTANCE_SIZE - F32_SIZE..INSTANCE_SIZE]
-            .clone_from_slice(&self.shinyness.to_ne_bytes());
+        #[cfg(feature = "xray")]
+        let xray = self.xray.unwrap_or(0.);
+        #[cfg(not(feature = "xray"))]
+        let xray = 0.;
 
-        result
+        InstanceRaw {
+            model: self.model_mat().data,
+            color: [color[0], color[1], color[2], self.opacity],
+            shinyness: self.shinyness,
+            shading: self.shading.to_raw(),
+            rim_light,
+            effects: [
+                xray,
+                self.blend.to_raw(),
+                self.reflectivity,
+                self.uv_transform.rotation,
+            ],
+            custom: self.custom,
+            uv_transform: [
+                self.uv_transform.offset.0,
+                self.uv_transform.offset.1,
+                self.uv_transform.scale.0,
+                self.uv_transform.scale.1,
+            ],
+        }
     }
 }
 
+/// The memory layout of an instance, as described by `INSTANCE_LAYOUT`.
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+pub(crate) struct InstanceRaw {
+    pub model: [f32; 16],
+    /// Linear RGB, and opacity.
+    pub color: [f32; 4],
+    pub shinyness: f32,
+    /// Mode, and its parameters; see `Shading::to_raw`.
+    pub shading: [f32; 3],
+    /// Linear RGB times intensity, and power.
+    pub rim_light: [f32; 4],
+    /// X-ray opacity, blend mode (see `BlendMode::to_raw`), reflectivity, then UV rotation.
+    pub effects: [f32; 4],
+    pub custom: [f32; 4],
+    /// UV offset, then scale.
+    pub uv_transform: [f32; 4],
+}
+
+const _: () = assert!(size_of::<InstanceRaw>() == INSTANCE_SIZE);
+
 impl From<&Entity> for Instance {
     fn from(entity: &Entity) -> Self {
         let scale = match entity.scale_partial {
@@ -277,10 +399,32 @@ impl From<&Entity> for Instance {
             color: Vec3::new(entity.color.0, entity.color.1, entity.color.2),
             opacity: entity.opacity,
             shinyness: entity.shinyness,
+            shading: entity.shading,
+            rim_light: entity.rim_light,
+            #[cfg(feature = "xray")]
+            xray: entity.xray,
+            blend: entity.blend,
+            reflectivity: entity.reflectivity,
+            custom: entity.custom,
+            uv_transform: entity.uv_transform,
         }
     }
 }
 
+/// A mesh in `Scene::meshes`, from `Scene::add_mesh`. It stays valid until the mesh is removed;
+/// other meshes being added or removed don't change it.
+#[cfg_attr(feature = "app_utils", derive(Encode, Decode))]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct MeshHandle(pub(crate) u32);
+
+impl MeshHandle {
+    /// The mesh's index in `Scene::meshes`; e.g. for `Entity::mesh`, or
+    /// `EngineUpdates::meshes_changed`.
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
 #[cfg_attr(feature = "app_utils", derive(Encode, Decode))]
 #[derive(Clone, Debug, Default)]
 pub struct Mesh {
@@ -289,6 +433,115 @@ pub struct Mesh {
     /// buffer, we offset them by previous meshes' vertex counts.
     pub indices: Vec<usize>,
     pub material: usize,
+    /// Sampled at each vertex's `tex_coords`, and multiplied with its color. Load one with
+    /// `Scene::load_texture`.
+    pub texture: Option<TextureHandle>,
+    /// Draw back faces of opaque instances too, lit as if facing the viewer. For open surfaces,
+    /// e.g. sheets, slices, and single-sided isosurfaces. Transparent instances always draw both.
+    pub two_sided: bool,
+    /// Named per-vertex data, e.g. a surface property, drawn through the shader input each
+    /// targets. Where several target the same input, the last wins. Set with `set_attribute`.
+    pub attributes: Vec<MeshAttribute>,
+}
+
+impl Mesh {
+    /// Add a named attribute, or replace the values of an existing one, keeping its target.
+    /// Re-upload the mesh after, e.g. with `EngineUpdates::meshes_changed`.
+    pub fn set_attribute(&mut self, name: &str, values: AttributeValues) {
+        match self.attributes.iter_mut().find(|a| a.name == name) {
+            Some(a) => a.values = values,
+            None => self.attributes.push(MeshAttribute {
+                name: name.to_owned(),
+                values,
+                target: None,
+            }),
+        }
+    }
+
+    pub fn attribute(&self, name: &str) -> Option<&MeshAttribute> {
+        self.attributes.iter().find(|a| a.name == name)
+    }
+
+    /// Draw an attribute through `target`, or stop drawing it with `None`. Other attributes
+    /// targeting the same shader input are untargeted, so this switches which one is shown.
+    /// Returns false if there's no attribute of this name. Re-upload the mesh after.
+    pub fn bind_attribute(&mut self, name: &str, target: Option<AttributeTarget>) -> bool {
+        if self.attribute(name).is_none() {
+            return false;
+        }
+
+        for a in &mut self.attributes {
+            if a.name == name {
+                a.target = target;
+            } else if let (Some(t), Some(other)) = (target, a.target)
+                && t.shader_location() == other.shader_location()
+            {
+                a.target = None;
+            }
+        }
+        true
+    }
+
+    /// Split into meshes of at most `max_vertices` vertices each, keeping triangles whole and in
+    /// order, e.g. to upload a huge model in chunks with `EngineUpdates::meshes_async`, drawing
+    /// each as it's ready. Vertices shared by triangles in different chunks are duplicated. Chunks
+    /// of up to 65,536 vertices use 16-bit indices.
+    pub fn split(&self, max_vertices: usize) -> Vec<Mesh> {
+        // A triangle must fit in a chunk.
+        let max_vertices = max_vertices.max(3);
+
+        let mut result = Vec::new();
+        // The chunk each vertex was last added to, and its index there.
+        let mut placed = vec![(usize::MAX, 0); self.vertices.len()];
+        // Indices into `self.vertices` of the current chunk's vertices.
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+
+        for tri in self.indices.chunks_exact(3) {
+            let chunk = result.len();
+            let new = tri.iter().filter(|&&i| placed[i].0 != chunk).count();
+
+            if vertices.len() + new > max_vertices {
+                result.push(self.chunk(&vertices, mem::take(&mut indices)));
+                vertices.clear();
+            }
+
+            let chunk = result.len();
+            for &i in tri {
+                if placed[i].0 != chunk {
+                    placed[i] = (chunk, vertices.len());
+                    vertices.push(i);
+                }
+                indices.push(placed[i].1);
+            }
+        }
+
+        if !indices.is_empty() {
+            result.push(self.chunk(&vertices, indices));
+        }
+
+        result
+    }
+
+    /// A mesh of these vertices, by index into ours, with our other properties.
+    fn chunk(&self, vertices: &[usize], indices: Vec<usize>) -> Mesh {
+        Mesh {
+            vertices: vertices.iter().map(|&i| self.vertices[i]).collect(),
+            indices,
+            material: self.material,
+            texture: self.texture,
+            two_sided: self.two_sided,
+            attributes: self
+                .attributes
+                .iter()
+                .map(|a| MeshAttribute {
+                    name: a.name.clone(),
+                    values: a.values.select(vertices),
+                    target: a.target,
+                })
+                .collect(),
+        }
+    }
 }
 
 /// Represents an entity in the world. This is not fundamental to the WGPU system.
@@ -311,13 +564,43 @@ pub struct Entity {
     /// Scale by axis. If `Some`, overrides scale.
     /// Not set in the constructor; set after manually.
     pub scale_partial: Option<Vec3>,
+    /// sRGB, as with all colors the engine takes. See `ColorSpace`.
     pub color: (f32, f32, f32),
     // /// If present, this overrides `color`.
     // pub color_by_vertex: Option<(u8, u8, u8)>,
     pub opacity: f32,
     pub shinyness: f32, // 0 to 1.
+    pub shading: Shading,
+    pub rim_light: Option<RimLight>,
+    /// If set, parts of this entity hidden behind others are drawn faintly, at this opacity, so
+    /// it can be seen through them. E.g. 0.2 for a ligand inside a protein. Requires the `xray`
+    /// feature.
+    #[cfg(feature = "xray")]
+    pub xray: Option<f32>,
+    /// How this combines with what's behind it, if translucent. Entities with a mode other than
+    /// `Alpha` are drawn in the transparent pass, at any opacity.
+    pub blend: BlendMode,
+    /// How much of `Scene::reflection_plane`'s mirror image shows on this entity, from 0 to 1.
+    /// For the plane itself, e.g. a floor; it should lie in the plane.
+    pub reflectivity: f32,
+    /// An opacity animation in progress, from `fade_to`. The engine advances it each frame.
+    pub fade: Option<Fade>,
+    /// Passed to the mesh shader as-is, for custom effects driven by per-entity values, e.g.
+    /// charge, temperature, or phase. The built-in shader passes it to the fragment stage, as
+    /// `custom` in `InstanceIn` and `VertexOut`, but doesn't use it; use it in a copy set with
+    /// `GraphicsSettings::mesh_shader`.
+    pub custom: [f32; 4],
+    /// An index into `Scene::materials`. If set, the material's color, shinyness, shading, rim
+    /// light, and reflectivity apply in place of this entity's own.
+    pub material: Option<usize>,
+    /// Transforms the mesh's texture coordinates for this entity, e.g. to pick a tile of an
+    /// atlas, or to tile a texture, without a separate mesh or texture.
+    pub uv_transform: UvTransform,
     // todo: Experimenting. Public so we can override defaults in applications,
     // todo, but should not be set by the user.
+    /// An index into `Scene::groups`. If set, the group's transform applies after this entity's
+    /// own, which is relative to it.
+    pub group: Option<usize>,
     /// Used for replacing entities without rebuilding the buffer.
     pub buf_i: Option<usize>,
     /// Used for replacing entities without rebuilding the buffer.
@@ -325,6 +608,14 @@ pub struct Entity {
     pub buf_is_transparent: bool,
     /// Display text over (or near) the element.
     pub overlay_text: Option<TextOverlay>,
+    /// If set, overrides `position`: the engine sets `position` to this, relative to
+    /// `Scene::floating_origin`, when uploading the entity. For simulation state that needs more
+    /// precision than f32 far from the origin.
+    #[cfg(feature = "f64")]
+    pub position_f64: Option<lin_alg::f64::Vec3>,
+    /// If set, overrides `orientation`, as with `position_f64`.
+    #[cfg(feature = "f64")]
+    pub orientation_f64: Option<lin_alg::f64::Quaternion>,
 }
 
 impl Default for Entity {
@@ -342,9 +633,24 @@ impl Default for Entity {
             // color_by_vertex: None,
             opacity: 1.,
             shinyness: 0.,
+            shading: Default::default(),
+            rim_light: None,
+            #[cfg(feature = "xray")]
+            xray: None,
+            blend: Default::default(),
+            reflectivity: 0.,
+            fade: None,
+            custom: [0.; 4],
+            material: None,
+            uv_transform: Default::default(),
+            group: None,
             buf_i: None,
             buf_is_transparent: false,
             overlay_text: None,
+            #[cfg(feature = "f64")]
+            position_f64: None,
+            #[cfg(feature = "f64")]
+            orientation_f64: None,
         }
     }
 }
@@ -368,6 +674,163 @@ impl Entity {
             ..Default::default()
         }
     }
+
+    /// Set the f32 transform from the f64 one, where present, relative to `origin`. The
+    /// subtraction is done in f64, so precision is lost only in the result, which is near the
+    /// origin.
+    #[cfg(feature = "f64")]
+    pub(crate) fn sync_f64(&mut self, origin: lin_alg::f64::Vec3) {
+        if let Some(p) = self.position_f64 {
+            self.position = (p - origin).into();
+        }
+        if let Some(o) = self.orientation_f64 {
+            self.orientation = o.into();
+        }
+    }
+
+    /// If this is drawn in the transparent pass, and its instance is in the transparent buffer.
+    pub(crate) fn is_transparent(&self) -> bool {
+        self.opacity < 0.99 || self.blend != BlendMode::Alpha
+    }
+
+    /// Change opacity gradually, over `duration` seconds, e.g. to fade an entity in or out. The
+    /// engine advances it each frame, and updates the instance; there's no need to send an
+    /// `EntityUpdate`. Replaces any fade in progress.
+    pub fn fade_to(&mut self, opacity: f32, duration: f32) {
+        if duration <= 0. {
+            self.opacity = opacity;
+            self.fade = None;
+            return;
+        }
+
+        self.fade = Some(Fade {
+            from: self.opacity,
+            to: opacity,
+            duration,
+            elapsed: 0.,
+        });
+    }
+}
+
+/// An opacity animation; see `Entity::fade_to`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Fade {
+    from: f32,
+    to: f32,
+    /// In seconds.
+    duration: f32,
+    elapsed: f32,
+}
+
+impl Fade {
+    /// Advance by `dt` seconds, and return the opacity to show.
+    pub(crate) fn step(&mut self, dt: f32) -> f32 {
+        self.elapsed = (self.elapsed + dt).min(self.duration);
+        self.from + (self.to - self.from) * self.elapsed / self.duration
+    }
+
+    pub(crate) fn done(&self) -> bool {
+        self.elapsed >= self.duration
+    }
+}
+
+/// How an entity's surface responds to light.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum Shading {
+    /// Diffuse and specular lighting from the scene's lights.
+    #[default]
+    Lit,
+    /// Cel shading, for schematic or presentation renders: diffuse light is quantized into
+    /// `bands` steps (at least 2), with a hard-edged specular highlight. If `outline` is above 0,
+    /// silhouette edges, where the surface turns away from the viewer, are drawn black; higher
+    /// values are thicker. 0.3 is a good start.
+    Toon { bands: u32, outline: f32 },
+    /// The entity's color as-is, ignoring lights. For markers, gizmos, and annotations.
+    Unlit,
+    /// Lit, but with each triangle's own normal instead of interpolated vertex normals, for a
+    /// faceted look.
+    Flat,
+}
+
+/// How a translucent entity's color combines with what's behind it.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum BlendMode {
+    /// Covers what's behind in proportion to opacity.
+    #[default]
+    Alpha,
+    /// Adds to what's behind, scaled by opacity, without obscuring it. For emission-like
+    /// overlays, e.g. glows and density clouds; overlapping ones brighten.
+    Additive,
+    /// Adds the full color, and dims what's behind by opacity. Between `Alpha` and `Additive`;
+    /// e.g. for glowing smoke.
+    Premultiplied,
+}
+
+impl BlendMode {
+    /// Matches the blend modes in `shader.wgsl`.
+    pub(crate) fn to_raw(self) -> f32 {
+        match self {
+            Self::Alpha => 0.,
+            Self::Additive => 1.,
+            Self::Premultiplied => 2.,
+        }
+    }
+}
+
+/// A Fresnel rim light: brightens edges where the surface turns away from the viewer, independent
+/// of the scene's lights. On transparent surfaces, it also makes edges more opaque; this makes
+/// e.g. molecular surfaces readable against busy backgrounds.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct RimLight {
+    /// sRGB.
+    pub color: (f32, f32, f32),
+    pub intensity: f32,
+    /// Higher values confine the light to a thinner band at the edges. 2 to 5 is typical.
+    pub power: f32,
+}
+
+impl Default for RimLight {
+    fn default() -> Self {
+        Self {
+            color: (1., 1., 1.),
+            intensity: 0.6,
+            power: 3.,
+        }
+    }
+}
+
+/// Maps a mesh's texture coordinates to those its entity samples at: rotated about the center of
+/// the texture, then scaled, then offset. The texture repeats outside 0 to 1, so scales above 1
+/// tile it. For an atlas of 4 by 4 tiles, the one in column 1, row 2 has a scale of 0.25, and an
+/// offset of (0.25, 0.5).
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct UvTransform {
+    pub offset: (f32, f32),
+    pub scale: (f32, f32),
+    /// Counter-clockwise, in radians.
+    pub rotation: f32,
+}
+
+impl Default for UvTransform {
+    fn default() -> Self {
+        Self {
+            offset: (0., 0.),
+            scale: (1., 1.),
+            rotation: 0.,
+        }
+    }
+}
+
+impl Shading {
+    /// Matches the shading modes in `shader.wgsl`.
+    pub(crate) fn to_raw(self) -> [f32; 3] {
+        match self {
+            Self::Lit => [0., 0., 0.],
+            Self::Toon { bands, outline } => [1., bands.max(2) as f32, outline],
+            Self::Unlit => [2., 0., 0.],
+            Self::Flat => [3., 0., 0.],
+        }
+    }
 }
 
 #[cfg_attr(feature = "app_utils", derive(Encode, Decode))]
@@ -390,17 +853,116 @@ pub enum ControlScheme {
 
 #[derive(Clone, Debug)]
 pub struct Scene {
+    /// Push the indices of meshes changed or appended to `EngineUpdates::meshes_changed`. Add and
+    /// remove them with `add_mesh` and `remove_mesh` to keep indices stable over a long session.
     pub meshes: Vec<Mesh>,
+    /// Indices in `meshes` freed by `remove_mesh`, which `add_mesh` reuses. Maintained by those.
+    pub meshes_removed: Vec<usize>,
+    /// Send `EngineUpdates::gaussians` after changing these. Edit them with `add_gaussian` and
+    /// `remove_gaussian` to keep indices stable, or index in directly to modify one.
     pub gaussians: Vec<Gaussian>,
+    /// Indices in `gaussians` freed by `remove_gaussian`, which `add_gaussian` reuses. Maintained
+    /// by those.
+    pub gaussians_removed: Vec<usize>,
+    pub gaussian_settings: GaussianRenderSettings,
     pub entities: Vec<Entity>,
     pub camera: Camera,
     pub lighting: Lighting,
     pub input_settings: InputSettings,
+    /// sRGB. Also shown while a `background` other than `Solid` is loading. Changes apply on the
+    /// next frame; in `RenderMode::OnDemand`, request one with `EngineUpdates::redraw`.
     pub background_color: (f32, f32, f32),
+    /// Set `EngineUpdates::redraw` after changing this.
+    pub background: Background,
     pub window_title: String,
+    /// In logical pixels at startup. After that, the engine keeps this updated with the window's
+    /// size in physical pixels, and changes to it are applied in physical pixels.
     pub window_size: (f32, f32),
-    /// A duplicate of GUI.size, to be available to the application.
+    /// The smallest size the user can resize the window to, in logical pixels.
+    pub window_min_size: Option<(f32, f32)>,
+    /// The largest size the user can resize the window to, in logical pixels.
+    pub window_max_size: Option<(f32, f32)>,
+    /// The window's top left corner, in physical pixels, in desktop coordinates. Set by the
+    /// engine; `None` where the platform doesn't report it, e.g. Wayland.
+    pub window_position: Option<(i32, i32)>,
+    /// Set by the engine.
+    pub window_maximized: bool,
+    /// A duplicate of GUI.size, to be available to the application. In points, as egui reports it.
     pub gui_size: (f32, f32),
+    /// Physical pixels per point, e.g. 2 on a HiDPI display. Set by the engine from the window's
+    /// scale factor. Multiply egui positions, e.g. the pointer's, by this for `screen_to_render`.
+    pub pixels_per_point: f32,
+    /// The 3D viewport's (x, y, width, height) in the window, in physical pixels, as last drawn:
+    /// the space the GUI leaves, with its layout and DPI scale, letterboxed to `viewport_aspect`.
+    /// Set by the engine each frame; `None` before the first.
+    pub viewport_rect: Option<(f32, f32, f32, f32)>,
+    /// If set, the 3D viewport keeps this aspect ratio (width / height), centered in the space the
+    /// GUI leaves, with black bars at the sides or top and bottom. E.g. 16. / 9., for recording
+    /// video. Request a redraw after changing this, in `RenderMode::OnDemand`.
+    pub viewport_aspect: Option<f32>,
+    /// Set by the engine while a GUI widget has keyboard focus, e.g. a text field. Key presses
+    /// don't move the camera then; application event handlers can check this to ignore them too.
+    pub keyboard_in_gui: bool,
+    /// GPU memory used by the engine. Updated by the engine a few times per second; for reading
+    /// from the application, e.g. to diagnose memory growth.
+    pub memory_usage: MemoryUsage,
+    /// How far along uploads from `EngineUpdates::meshes_async` are; `None` if none are pending.
+    /// Set by the engine each frame, e.g. to show loading progress for a model uploaded in chunks.
+    pub mesh_upload_progress: Option<UploadProgress>,
+    /// Monitors connected when the window opened. Set by the engine; index into this with
+    /// `WindowPlacement::monitor`.
+    pub monitors: Vec<MonitorInfo>,
+    /// The features the GPU device was created with: those of `GraphicsSettings::required_features`
+    /// the adapter supports, and optional ones the engine uses if available. Set by the engine.
+    pub device_features: Features,
+    /// The limits the GPU device was created with: `GraphicsSettings::required_limits`, lowered to
+    /// what the adapter supports. Set by the engine.
+    pub device_limits: Limits,
+    /// Images meshes can reference with `Mesh::texture`; a `TextureHandle` is an index into this.
+    /// Add to it with `load_texture`. The engine loads new entries, and reloads ones replaced
+    /// with a different source, before the next frame; image files are decoded in the background.
+    pub textures: Vec<TextureSource>,
+    /// Cross-sections through volume textures, drawn after meshes. Set `EngineUpdates::redraw`
+    /// after changing these.
+    pub slice_planes: Vec<SlicePlane>,
+    /// The height of a horizontal mirror plane. The scene is drawn reflected in it, on entities
+    /// with `reflectivity` above 0. Costs a second render of the scene while any are present.
+    pub reflection_plane: Option<f32>,
+    /// The cursor's position in the window, in physical pixels. Set by the engine; `None` while
+    /// it's outside the window.
+    pub cursor_position: Option<(f32, f32)>,
+    /// The depth buffer of a recent frame, if `GraphicsSettings::depth_readback` is set. Set by
+    /// the engine; query it with `query_depth` and `world_pos_at`.
+    pub depth_snapshot: Option<DepthSnapshot>,
+    /// Entity positions, for `entities_within` and `nearest_entity`. Marked stale by the engine
+    /// when entities are updated, and rebuilt on the next query.
+    pub spatial_index: SpatialIndex,
+    /// Lines of any width, drawn after meshes. Set `EngineUpdates::lines` after changing these.
+    pub lines: Vec<Line>,
+    /// Spheres drawn as impostors: ray-traced on camera-facing quads, instead of as meshes. Far
+    /// cheaper in large numbers, e.g. for atoms. Set `EngineUpdates::spheres` after changing these.
+    pub spheres: Vec<SphereImpostor>,
+    /// Cylinders drawn as impostors, e.g. for bonds. Set `EngineUpdates::cylinders` after
+    /// changing these.
+    pub cylinders: Vec<CylinderImpostor>,
+    /// What a world unit represents. The engine doesn't scale by this; format lengths with
+    /// `format_length`, e.g. for measurement readouts and axis labels.
+    pub units: Units,
+    /// Transforms shared by entities, referenced by `Entity::group`. Send `EntityUpdate::Groups`
+    /// after changing these.
+    pub groups: Vec<EntityGroup>,
+    /// Surface properties shared by entities, referenced by `Entity::material`. Send the indices
+    /// of those changed in `EngineUpdates::materials`.
+    pub materials: Vec<Material>,
+    /// Entity colors by class, driven by a scalar over time. The engine advances these, and
+    /// updates the entities, each frame.
+    pub color_animations: Vec<ColorAnimation>,
+    /// The world position, in f64, that the engine renders at the f32 origin. Entities'
+    /// `position_f64` are drawn relative to it; the camera, lights, and f32 positions are already
+    /// relative to it. Move it near the camera to keep precision where it's viewed, then send
+    /// `EntityUpdate::All`.
+    #[cfg(feature = "f64")]
+    pub floating_origin: lin_alg::f64::Vec3,
 }
 
 impl Default for Scene {
@@ -408,45 +970,127 @@ impl Default for Scene {
         Self {
             meshes: Vec::new(),
             gaussians: Vec::new(),
+            gaussians_removed: Vec::new(),
+            meshes_removed: Vec::new(),
+            gaussian_settings: Default::default(),
             entities: Vec::new(),
             camera: Default::default(),
             lighting: Default::default(),
             input_settings: Default::default(),
             // todo: Consider a separate window struct.
             background_color: (0.7, 0.7, 0.7),
+            background: Default::default(),
             window_title: "(Window title here)".to_owned(),
             window_size: (900., 600.),
+            window_min_size: None,
+            window_max_size: None,
+            window_position: None,
+            window_maximized: false,
             gui_size: (0., 0.),
+            pixels_per_point: 1.,
+            viewport_rect: None,
+            viewport_aspect: None,
+            keyboard_in_gui: false,
+            memory_usage: Default::default(),
+            mesh_upload_progress: None,
+            monitors: Vec::new(),
+            device_features: Features::empty(),
+            device_limits: Default::default(),
+            textures: Vec::new(),
+            slice_planes: Vec::new(),
+            reflection_plane: None,
+            cursor_position: None,
+            depth_snapshot: None,
+            spatial_index: Default::default(),
+            lines: Vec::new(),
+            spheres: Vec::new(),
+            cylinders: Vec::new(),
+            groups: Vec::new(),
+            materials: Vec::new(),
+            color_animations: Vec::new(),
+            units: Default::default(),
+            #[cfg(feature = "f64")]
+            floating_origin: lin_alg::f64::Vec3::new_zero(),
         }
     }
 }
 
 impl Scene {
-    /// Convert a screen position (x, y) to a 3D ray in world space.
+    /// Convert an f64 world position to the f32 one it's rendered at, relative to
+    /// `floating_origin`; e.g. for the camera, or lights.
+    #[cfg(feature = "f64")]
+    pub fn to_render_space(&self, position: lin_alg::f64::Vec3) -> Vec3 {
+        (position - self.floating_origin).into()
+    }
+
+    /// Set each entity's f32 transform from its f64 one, where present.
+    #[cfg(feature = "f64")]
+    pub(crate) fn sync_f64_transforms(&mut self) {
+        let origin = self.floating_origin;
+        for entity in &mut self.entities {
+            entity.sync_f64(origin);
+        }
+    }
+
+    /// Set the shading of every entity in `class`, e.g. to draw one class with toon shading. Send
+    /// `EntityUpdate::Classes` with it after.
+    pub fn set_class_shading(&mut self, class: u32, shading: Shading) {
+        for entity in self.entities.iter_mut().filter(|e| e.class == class) {
+            entity.shading = shading;
+        }
+    }
+
+    /// Set the blend mode of every entity in `class`, e.g. to draw a class of glow effects
+    /// additively. Send `EntityUpdate::Classes` with it after.
+    pub fn set_class_blend(&mut self, class: u32, blend: BlendMode) {
+        for entity in self.entities.iter_mut().filter(|e| e.class == class) {
+            entity.blend = blend;
+        }
+    }
+
+    /// Add a texture to the scene, or find it if already added from the same path or bytes.
+    /// Image files are decoded on a background thread, and used once ready; until then, and if
+    /// loading fails, meshes referencing it render untextured.
+    pub fn load_texture(&mut self, source: TextureSource) -> TextureHandle {
+        let i = match self.textures.iter().position(|t| *t == source) {
+            Some(i) => i,
+            None => {
+                self.textures.push(source);
+                self.textures.len() - 1
+            }
+        };
+
+        TextureHandle(i as u32)
+    }
+
+    /// Convert a screen position (x, y), in physical pixels, to a 3D ray in world space. Accounts
+    /// for the GUI's panels, using the 3D viewport as last drawn.
     ///
     /// The canonical use case for this is finding the object in 3D space a user is intending to select
     /// with the cursor.A follow-up operation, for example, may be to find all objects that this vector
     /// passes near, and possibly select the one closest to the camera.
-    pub fn screen_to_render(&self, mut screen_pos: (f32, f32)) -> (Vec3, Vec3) {
+    pub fn screen_to_render(&self, screen_pos: (f32, f32)) -> (Vec3, Vec3) {
+        self.screen_to_render_in(screen_pos, self.viewport())
+    }
+
+    /// As `screen_to_render`, in a given 3D viewport: (x, y, width, height), in the same units as
+    /// `screen_pos`. E.g. for a viewport from `viewport_rect`, or an offscreen render.
+    pub fn screen_to_render_in(
+        &self,
+        mut screen_pos: (f32, f32),
+        viewport: (f32, f32, f32, f32),
+    ) -> (Vec3, Vec3) {
         let proj_view = self.camera.proj_mat.clone() * self.camera.view_mat();
 
         let proj_view_inv = match proj_view.inverse() {
             Some(p) => p,
             None => {
-                eprintln!("Error inverting the projection matrix.");
+                warn!("Error inverting the projection matrix.");
                 return (Vec3::new_zero(), Vec3::new_zero());
             }
         };
 
-        let (x, y, eff_width, eff_height) = viewport_rect(
-            self.gui_size,
-            // This should be the same as sys.surface_config.width and height.
-            self.window_size.0 as u32,
-            self.window_size.1 as u32,
-            // state.ui_settings,
-            &UiSettings::default(), // todo temp. OK as long as using GUI from top and left.
-            0.,                     // Unused, for now.
-        );
+        let (x, y, eff_width, eff_height) = viewport;
 
         screen_pos.0 -= x;
         screen_pos.1 -= y;
@@ -470,6 +1114,436 @@ impl Scene {
 
         (near_world, far_world)
     }
+
+    /// The ray under the cursor, as `screen_to_render`'s near and far points, in world space; e.g.
+    /// for picking each frame. Follows the camera, so it stays current while the camera moves.
+    /// `None` while the cursor is outside the 3D viewport, e.g. over a GUI panel.
+    pub fn cursor_ray(&self) -> Option<(Vec3, Vec3)> {
+        let (cx, cy) = self.cursor_position?;
+        let (x, y, width, height) = self.viewport();
+
+        if cx < x || cy < y || cx >= x + width || cy >= y + height {
+            return None;
+        }
+
+        Some(self.screen_to_render((cx, cy)))
+    }
+
+    /// The 3D viewport's (x, y, width, height), in physical pixels: as last drawn, or before the
+    /// first frame, an estimate assuming the GUI is at the top and left.
+    fn viewport(&self) -> (f32, f32, f32, f32) {
+        if let Some(rect) = self.viewport_rect {
+            return rect;
+        }
+
+        let scale = self.pixels_per_point;
+        letterbox(
+            viewport_rect(
+                (self.gui_size.0 * scale, self.gui_size.1 * scale),
+                self.window_size.0 as u32,
+                self.window_size.1 as u32,
+                &UiSettings::default(),
+                0.,
+            ),
+            self.viewport_aspect,
+        )
+    }
+
+    /// The world-space size of a physical pixel at `point`'s depth; e.g. to size things on
+    /// screen, or pick a snapping increment. 0 at or behind the camera.
+    pub fn world_per_pixel(&self, point: Vec3) -> f32 {
+        let depth = (point - self.camera.position).dot(self.camera.orientation.rotate_vec(FWD_VEC));
+        let height = self.viewport().3;
+        // The projection's Y scale: 1 / tan(fov_y / 2), for a symmetric frustum.
+        let scale_y = self.camera.proj_mat.data[5];
+
+        if depth <= 0. || height <= 0. || scale_y <= 0. {
+            return 0.;
+        }
+        2. * depth / (scale_y * height)
+    }
+
+    /// A round grid increment for snapping near `point`, from `units::nice_step`: the smallest
+    /// spanning at least `min_pixels` on screen. It adapts as the camera zooms; e.g. for moving
+    /// objects with the mouse, or a measurement tool.
+    pub fn snap_increment(&self, point: Vec3, min_pixels: f32) -> f32 {
+        nice_step(self.world_per_pixel(point) * min_pixels)
+    }
+
+    /// `point`, with each coordinate rounded to the nearest multiple of `snap_increment`.
+    pub fn snap_to_grid(&self, point: Vec3, min_pixels: f32) -> Vec3 {
+        let step = self.snap_increment(point, min_pixels);
+        if step <= 0. {
+            return point;
+        }
+
+        let snap = |v: f32| (v / step).round() * step;
+        Vec3::new(snap(point.x), snap(point.y), snap(point.z))
+    }
+
+    /// A length in world units, formatted in `units`; e.g. "1.54 Å".
+    pub fn format_length(&self, len: f32) -> String {
+        self.units.format(len)
+    }
+
+    /// The depth buffer value at a screen position, in physical pixels, from 0 at the near plane
+    /// to 1 at the far plane. `None` if there's no opaque geometry there, or no depth snapshot;
+    /// see `GraphicsSettings::depth_readback`.
+    pub fn query_depth(&self, screen_pos: (f32, f32)) -> Option<f32> {
+        self.depth_snapshot.as_ref()?.depth_at(screen_pos)
+    }
+
+    /// The world-space position of the opaque surface at a screen position, in physical pixels;
+    /// e.g. to place an object where the user clicks. Uses the camera the snapshot was rendered
+    /// with, so it stays correct while the camera moves.
+    pub fn world_pos_at(&self, screen_pos: (f32, f32)) -> Option<Vec3> {
+        self.depth_snapshot.as_ref()?.world_pos_at(screen_pos)
+    }
+
+    /// The world-space position of the opaque surface under the cursor, if any.
+    pub fn world_pos_under_cursor(&self) -> Option<Vec3> {
+        self.world_pos_at(self.cursor_position?)
+    }
+
+    /// Add a mesh, reusing the slot of a removed one if there is one, so no other mesh's index
+    /// changes. Push its index to `EngineUpdates::meshes_changed`.
+    pub fn add_mesh(&mut self, mesh: Mesh) -> MeshHandle {
+        let i = match self.meshes_removed.pop() {
+            Some(i) => {
+                self.meshes[i] = mesh;
+                i
+            }
+            None => {
+                self.meshes.push(mesh);
+                self.meshes.len() - 1
+            }
+        };
+
+        MeshHandle(i as u32)
+    }
+
+    /// Remove a mesh, without shifting the others: its slot stays, empty, until `add_mesh` reuses
+    /// it. Its vertices and indices are removed from the GPU buffers, and the meshes after them
+    /// moved down. Returns the mesh removed, or `None` if there isn't one. Push its index to
+    /// `EngineUpdates::meshes_changed`.
+    ///
+    /// Entities using it draw nothing. Remove them, or point them to another mesh, before a new mesh
+    /// reuses the slot.
+    pub fn remove_mesh(&mut self, handle: MeshHandle) -> Option<Mesh> {
+        let i = handle.index();
+        if self.meshes_removed.contains(&i) {
+            return None;
+        }
+        let mesh = self.meshes.get_mut(i)?;
+
+        let result = mem::take(mesh);
+        self.meshes_removed.push(i);
+
+        Some(result)
+    }
+
+    /// Add a gaussian, and return its index in `gaussians`. Reuses the slot of a removed one if
+    /// there is one, so no other index changes. Push the index to `EngineUpdates::gaussians`.
+    pub fn add_gaussian(&mut self, gaussian: Gaussian) -> usize {
+        match self.gaussians_removed.pop() {
+            Some(i) => {
+                self.gaussians[i] = gaussian;
+                i
+            }
+            None => {
+                self.gaussians.push(gaussian);
+                self.gaussians.len() - 1
+            }
+        }
+    }
+
+    /// Remove the gaussian at index `i`, without shifting the others: its slot stays, with an
+    /// amplitude of 0, until `add_gaussian` reuses it. Returns the gaussian removed, or `None` if
+    /// there isn't one at `i`. Push `i` to `EngineUpdates::gaussians`.
+    pub fn remove_gaussian(&mut self, i: usize) -> Option<Gaussian> {
+        if self.gaussians_removed.contains(&i) {
+            return None;
+        }
+        let gaussian = self.gaussians.get_mut(i)?;
+
+        let result = *gaussian;
+        gaussian.amplitude = 0.;
+        self.gaussians_removed.push(i);
+
+        Some(result)
+    }
+
+    /// The index into `groups` of the first one named `name`.
+    pub fn group_index(&self, name: &str) -> Option<usize> {
+        self.groups.iter().position(|g| g.name == name)
+    }
+
+    /// The index into `materials` of the first one named `name`.
+    pub fn material_index(&self, name: &str) -> Option<usize> {
+        self.materials.iter().position(|m| m.name == name)
+    }
+
+    /// An entity's position in world space, including its group's transform.
+    pub fn world_position(&self, entity: &Entity) -> Vec3 {
+        match entity.group.and_then(|g| self.groups.get(g)) {
+            Some(group) => group.to_world(entity.position),
+            None => entity.position,
+        }
+    }
+
+    /// An entity's instance, including its group's transform.
+    pub(crate) fn instance(&self, entity: &Entity) -> Instance {
+        let mut instance = Instance::from(entity);
+        if let Some(material) = entity.material.and_then(|m| self.materials.get(m)) {
+            instance = material.apply(instance);
+        }
+
+        match entity.group.and_then(|g| self.groups.get(g)) {
+            Some(group) => group.apply(instance),
+            None => instance,
+        }
+    }
+
+    /// `spatial_index`, rebuilt from entities' world positions if stale.
+    fn spatial_tree(&self) -> &KdTree {
+        self.spatial_index
+            .tree(|| self.entities.iter().map(|e| self.world_position(e)))
+    }
+
+    /// Indices into `entities` of those positioned within `radius` of `center`, in no particular
+    /// order. Uses `spatial_index`, so reflects entities as of the first query after the last
+    /// `EngineUpdates::entities`.
+    pub fn entities_within(&self, center: Vec3, radius: f32) -> Vec<usize> {
+        let mut result = self.spatial_tree().within(center, radius);
+        result.retain(|&i| i < self.entities.len());
+        result
+    }
+
+    /// The index into `entities` of the one positioned nearest `point`. Uses `spatial_index`, so
+    /// reflects entities as of the first query after the last `EngineUpdates::entities`.
+    pub fn nearest_entity(&self, point: Vec3) -> Option<usize> {
+        self.spatial_tree()
+            .nearest(point)
+            .filter(|&i| i < self.entities.len())
+    }
+
+    /// Counts of what's in the scene, and the GPU buffer sizes its data needs; e.g. for display,
+    /// or logging. This scans the scene, so avoid calling it every frame on large ones.
+    pub fn stats(&self) -> SceneStats {
+        let mesh_triangles: Vec<usize> = self.meshes.iter().map(|m| m.indices.len() / 3).collect();
+
+        let mut entities_by_class = BTreeMap::new();
+        let mut drawn_triangles = 0;
+        let mut meshes_used = vec![false; self.meshes.len()];
+        for entity in &self.entities {
+            *entities_by_class.entry(entity.class).or_insert(0) += 1;
+            if let Some(tris) = mesh_triangles.get(entity.mesh) {
+                drawn_triangles += tris;
+                meshes_used[entity.mesh] = true;
+            }
+        }
+
+        let vertices = self.meshes.iter().map(|m| m.vertices.len()).sum();
+        let index_bytes: usize = self.meshes.iter().map(mesh_index_bytes).sum();
+        // One set of draw arguments per mesh with instances, in each of the opaque and
+        // transparent buffers, at most.
+        let meshes_drawn = meshes_used.iter().filter(|u| **u).count();
+
+        SceneStats {
+            meshes: self.meshes.len() - self.meshes_removed.len(),
+            vertices,
+            triangles: mesh_triangles.iter().sum(),
+            drawn_triangles,
+            entities: self.entities.len(),
+            entities_by_class,
+            gaussians: self.gaussians.len() - self.gaussians_removed.len(),
+            lights: self.lighting.point_lights.len(),
+            spheres: self.spheres.len(),
+            cylinders: self.cylinders.len(),
+            vertex_bytes: (vertices * VERTEX_SIZE) as u64,
+            index_bytes: index_bytes as u64,
+            instance_bytes: (self.entities.len() * INSTANCE_SIZE) as u64,
+            gauss_bytes: (self.gaussians.len() * size_of::<GaussianInstance>()) as u64,
+            sphere_bytes: (self.spheres.len() * size_of::<SphereInstance>()) as u64,
+            cylinder_bytes: (self.cylinders.len() * size_of::<CylinderInstance>()) as u64,
+            indirect_bytes: (2 * meshes_drawn * size_of::<DrawIndexedIndirectArgs>()) as u64,
+        }
+    }
+
+    /// Advance the scene by `dt` seconds: fades from `Entity::fade_to`, `color_animations`, and
+    /// the camera, from `inputs` through the control scheme, and along any transition in
+    /// progress. The engine calls this each frame before drawing, and uploads what changed; it
+    /// doesn't touch the GPU, so it can be called directly, e.g. to test an animation.
+    pub fn step(&mut self, inputs: &mut InputsCommanded, dt: f32) -> SceneStep {
+        let mut entities_changed = self.advance_fades(dt);
+        entities_changed.extend(self.advance_color_animations(dt));
+        // An entity may be fading, and in several animations' classes.
+        entities_changed.sort_unstable();
+        entities_changed.dedup();
+
+        let (camera_changed, camera_moving) = self.step_camera(inputs, dt);
+
+        SceneStep {
+            entities_changed,
+            camera_changed,
+            animating: camera_moving
+                || self.entities.iter().any(|e| e.fade.is_some())
+                || self.color_animations.iter().any(|a| a.active()),
+        }
+    }
+
+    /// Advance fades from `Entity::fade_to`; returns the indices of the faded entities.
+    fn advance_fades(&mut self, dt: f32) -> Vec<usize> {
+        let mut faded = Vec::new();
+        for (i, ent) in self.entities.iter_mut().enumerate() {
+            let Some(fade) = &mut ent.fade else {
+                continue;
+            };
+
+            ent.opacity = fade.step(dt);
+            if fade.done() {
+                ent.fade = None;
+            }
+            faded.push(i);
+        }
+        faded
+    }
+
+    /// Advance `color_animations`; returns the indices of entities whose color changed. With an
+    /// entity in several animations' classes, the last wins.
+    fn advance_color_animations(&mut self, dt: f32) -> Vec<usize> {
+        let mut changed = Vec::new();
+        for anim in &mut self.color_animations {
+            anim.step(dt);
+
+            let color = anim.color();
+            for (i, ent) in self.entities.iter_mut().enumerate() {
+                if ent.class == anim.class && ent.color != color {
+                    ent.color = color;
+                    changed.push(i);
+                }
+            }
+        }
+        changed
+    }
+
+    /// Move the camera: from user input, through the control scheme, and along any transition in
+    /// progress. Returns if it moved, and if it's still moving without input.
+    fn step_camera(&mut self, inputs: &mut InputsCommanded, dt: f32) -> (bool, bool) {
+        let mut changed = false;
+        let mut moving = false;
+
+        if inputs.inputs_present() {
+            inputs.filter_mouse(&self.input_settings.mouse_filter, dt);
+
+            changed |= match self.input_settings.control_scheme {
+                ControlScheme::FreeCamera => {
+                    input::adjust_camera_free(&mut self.camera, inputs, &self.input_settings, dt)
+                }
+                ControlScheme::Arc { center } => input::adjust_camera_arc(
+                    &mut self.camera,
+                    inputs,
+                    &self.input_settings,
+                    center,
+                    dt,
+                ),
+                ControlScheme::None => false,
+                ControlScheme::Fps => unimplemented!(),
+            };
+
+            // Reset the mouse inputs; keyboard inputs are reset by their release event.
+            inputs.mouse_delta_x = 0.;
+            inputs.mouse_delta_y = 0.;
+        }
+
+        if self.input_settings.lock_roll
+            && self.input_settings.control_scheme == ControlScheme::FreeCamera
+            && self.camera.roll().abs() > input::ROLL_EPS
+        {
+            let remaining = self.camera.level_horizon(input::ROLL_LEVEL_RATE * dt);
+            changed = true;
+            moving |= remaining.abs() > input::ROLL_EPS;
+        }
+
+        if inputs.recenter {
+            inputs.recenter = false;
+
+            if self.input_settings.recenter_on_key
+                && let Some(posit) = self.world_pos_under_cursor()
+            {
+                inputs.recenter_arc(&self.input_settings, posit);
+            }
+        }
+
+        // Dropped when done, or if the application switched control schemes meanwhile.
+        if let Some(mut transition) = inputs.arc_transition.take()
+            && let ControlScheme::Arc { center } = &mut self.input_settings.control_scheme
+        {
+            *center = transition.step(&mut self.camera, *center, dt);
+            changed = true;
+
+            if !transition.done() {
+                inputs.arc_transition = Some(transition);
+                moving = true;
+            }
+        }
+
+        (changed, moving)
+    }
+}
+
+/// What changed in a `Scene::step`.
+#[derive(Clone, Debug, Default)]
+pub struct SceneStep {
+    /// Indices of entities whose opacity or color changed.
+    pub entities_changed: Vec<usize>,
+    pub camera_changed: bool,
+    /// If a fade, color animation, or camera movement is still in progress, so another step
+    /// would change the scene without input.
+    pub animating: bool,
+}
+
+/// What a scene contains, from `Scene::stats`. Byte sizes are estimates of the GPU buffer data
+/// needed for the scene as it is; `Scene::memory_usage` has what the engine has allocated.
+#[derive(Clone, Debug, Default)]
+pub struct SceneStats {
+    pub meshes: usize,
+    /// Across all meshes, each counted once.
+    pub vertices: usize,
+    /// Across all meshes, each counted once.
+    pub triangles: usize,
+    /// Across all entities: the triangles of each one's mesh. Roughly the work per frame.
+    pub drawn_triangles: usize,
+    pub entities: usize,
+    /// Entity count by `Entity::class`.
+    pub entities_by_class: BTreeMap<u32, usize>,
+    pub gaussians: usize,
+    pub lights: usize,
+    /// Sphere impostors, in `Scene::spheres`.
+    pub spheres: usize,
+    /// Cylinder impostors, in `Scene::cylinders`.
+    pub cylinders: usize,
+    pub vertex_bytes: u64,
+    pub index_bytes: u64,
+    pub instance_bytes: u64,
+    pub gauss_bytes: u64,
+    pub sphere_bytes: u64,
+    pub cylinder_bytes: u64,
+    /// Indirect draw arguments; an upper bound.
+    pub indirect_bytes: u64,
+}
+
+impl SceneStats {
+    /// The sum of the estimated buffer sizes.
+    pub fn total_bytes(&self) -> u64 {
+        self.vertex_bytes
+            + self.index_bytes
+            + self.instance_bytes
+            + self.gauss_bytes
+            + self.sphere_bytes
+            + self.cylinder_bytes
+            + self.indirect_bytes
+    }
 }
 
 #[derive(Clone, Copy, Debug, Default)]
@@ -493,11 +1567,32 @@ pub struct InputSettings {
     /// Move forward and backwards with the scroll wheel; largely independent from
     /// control scheme. For now
     pub scroll_behavior: ScrollBehavior,
+    /// Scroll movement and roll ease in over about this many seconds, instead of jumping: each
+    /// frame covers a fraction of what's left. The total per scroll step is unchanged. 0 jumps.
+    pub scroll_easing: f32,
     pub middle_click_pan: bool,
     /// If true, use device events, instead of window events for the engine's built-in
     /// camera controls. This is a lower-level API. Note that it's incompatible with the Linux
     /// Wayland UI backend.
     pub device_events_for_cam_controls: bool,
+    /// With `ControlScheme::Arc`, pressing F re-centers the rotation on the surface under the
+    /// cursor. Requires `GraphicsSettings::depth_readback`.
+    pub recenter_on_key: bool,
+    /// How long the camera takes to move to a new arc center, in seconds.
+    pub recenter_time: f32,
+    /// With `ControlScheme::FreeCamera`, keep the horizon level: looking around doesn't roll the
+    /// camera, roll inputs are ignored, and existing roll is removed smoothly. Pitch stops short
+    /// of straight up and down.
+    pub lock_roll: bool,
+    /// Limits on where `ControlScheme::Arc` can move the camera.
+    pub arc_constraints: ArcConstraints,
+    /// If set, `ControlScheme::Arc` scales mouse rotation by the camera's distance to the center,
+    /// relative to this distance; e.g. half as fast at half of it. Keeps rotation from feeling
+    /// too fast when zoomed in close, so it feels consistent at all zoom levels.
+    pub arc_sens_reference_dist: Option<f32>,
+    /// Dead zone and smoothing for mouse movement used by the built-in controls, e.g. to remove
+    /// jitter from high-DPI mice and trackpads. Off by default.
+    pub mouse_filter: InputFilter,
 }
 
 impl Default for InputSettings {
@@ -509,9 +1604,109 @@ impl Default for InputSettings {
             rotate_key_sens: 1.0,
             run_factor: 5.,
             scroll_behavior: Default::default(),
+            scroll_easing: 0.1,
             middle_click_pan: true,
             device_events_for_cam_controls: false,
+            recenter_on_key: true,
+            recenter_time: 0.4,
+            lock_roll: false,
+            arc_constraints: Default::default(),
+            arc_sens_reference_dist: None,
+            mouse_filter: Default::default(),
+        }
+    }
+}
+
+/// Limits on the arc camera's position relative to its center, e.g. so a data viewer can't zoom
+/// into its subject, or turn it upside down. Each is off if `None`. Applied to the built-in arc
+/// controls; cameras the application moves are left as they are.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct ArcConstraints {
+    /// The closest and farthest the camera can be from the center.
+    pub distance: Option<(f32, f32)>,
+    /// The lowest and highest the camera can be, as angles in radians above the center's
+    /// horizontal plane, from -TAU/4 (below) to TAU/4 (above). Keep them short of those to
+    /// prevent flipping over the poles, e.g. (-1.5, 1.5).
+    pub pitch: Option<(f32, f32)>,
+    /// The range of angles in radians around the vertical axis the camera can be at, from -TAU/2
+    /// to TAU/2. 0 is on the center's -Z side, and positive angles go toward +X. If the first is
+    /// above the second, the range wraps through TAU/2; e.g. (3., -3.) is a range on the +Z side.
+    pub azimuth: Option<(f32, f32)>,
+}
+
+impl ArcConstraints {
+    /// Move `cam`, which looks at `center`, within these limits, turning it to keep looking at
+    /// `center`. Returns true if it moved.
+    pub fn apply(&self, cam: &mut Camera, center: Vec3) -> bool {
+        if *self == Self::default() {
+            return false;
+        }
+
+        // Measured along the view direction, so it's negative if the camera passed the center.
+        let fwd = cam.orientation.rotate_vec(FWD_VEC);
+        let dist = (center - cam.position).dot(fwd);
+        let dir = -fwd;
+
+        let pitch = dir.y.clamp(-1., 1.).asin();
+        let azimuth = dir.x.atan2(-dir.z);
+
+        let clamp = |v: f32, limits: Option<(f32, f32)>| match limits {
+            Some((min, max)) => v.clamp(min, max),
+            None => v,
+        };
+
+        let new_dist = clamp(dist, self.distance);
+        let new_pitch = clamp(pitch, self.pitch);
+        let new_azimuth = match self.azimuth {
+            Some(limits) => clamp_angle(azimuth, limits),
+            None => azimuth,
+        };
+
+        // Allow for rounding, so a camera at a limit isn't moved each time.
+        const EPS: f32 = 1e-5;
+        let turned = (new_pitch - pitch).abs() > EPS || (new_azimuth - azimuth).abs() > EPS;
+        if !turned && (new_dist - dist).abs() <= EPS * dist.abs().max(1.) {
+            return false;
+        }
+
+        let new_dir = Vec3::new(
+            new_pitch.cos() * new_azimuth.sin(),
+            new_pitch.sin(),
+            -new_pitch.cos() * new_azimuth.cos(),
+        );
+
+        if turned {
+            let rotation = Quaternion::from_unit_vecs(dir, new_dir);
+            cam.orientation = (rotation * cam.orientation).to_normalized();
         }
+        cam.position = center + new_dir * new_dist;
+
+        true
+    }
+}
+
+/// `angle` if it's in the range from `min` to `max` going toward positive angles, which wraps
+/// through TAU/2 if `min` is above `max`. Otherwise, whichever of them is nearer around the circle.
+fn clamp_angle(angle: f32, (min, max): (f32, f32)) -> f32 {
+    let span = if max >= min {
+        max - min
+    } else {
+        max - min + TAU
+    };
+    if span >= TAU {
+        return angle;
+    }
+
+    let past_min = (angle - min).rem_euclid(TAU);
+    if past_min <= span {
+        return angle;
+    }
+
+    // Outside the range: past `max`, or short of `min`.
+    if past_min - span <= TAU - past_min {
+        max
+    } else {
+        min
     }
 }
 
@@ -529,6 +1724,33 @@ pub enum AmbientOcclusion {
     Gtao,
 }
 
+/// Contour lines on meshes with per-vertex scalars (`Vertex::scalar`), at regular intervals of
+/// the scalar; e.g. elevation contours on terrain.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Isolines {
+    /// The scalar difference between adjacent lines.
+    pub interval: f32,
+    /// Lines are drawn where the scalar is this, plus a multiple of `interval`.
+    pub offset: f32,
+    /// In pixels.
+    pub width: f32,
+    /// sRGB.
+    pub color: (f32, f32, f32),
+    pub opacity: f32,
+}
+
+impl Default for Isolines {
+    fn default() -> Self {
+        Self {
+            interval: 1.,
+            offset: 0.,
+            width: 1.5,
+            color: (0., 0., 0.),
+            opacity: 0.8,
+        }
+    }
+}
+
 /// Displays a frame rate (in frames-per-second) readout over the 3D display area.
 #[derive(Clone, Copy, PartialEq, Debug, Default)]
 pub enum FramerateDisplay {
@@ -540,6 +1762,80 @@ pub enum FramerateDisplay {
     BottomRight,
 }
 
+/// GPU memory allocated by the engine, in bytes. Buffer sizes are their capacities, which may
+/// exceed the data in use.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryUsage {
+    pub vertex: u64,
+    /// 16-bit indices, for meshes of up to 65,536 vertices, and 32-bit ones for larger meshes.
+    pub index: u64,
+    /// Opaque and transparent mesh instances.
+    pub instance: u64,
+    pub gauss: u64,
+    /// Indirect draw arguments.
+    pub indirect: u64,
+    /// Render targets: depth, MSAA, and the offscreen ones for reflections, motion blur, stereo,
+    /// and rendering at a different scale; and textures from `Scene::textures`, including volumes.
+    pub textures: u64,
+    /// From wgpu's allocator report: (allocated, reserved) bytes across all GPU memory blocks,
+    /// including those of the GUI and application. None if the backend doesn't provide this.
+    pub allocator: Option<(u64, u64)>,
+}
+
+impl MemoryUsage {
+    /// The sum of the engine's buffers and textures.
+    pub fn total(&self) -> u64 {
+        self.vertex + self.index + self.instance + self.gauss + self.indirect + self.textures
+    }
+}
+
+/// When the engine renders frames.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum RenderMode {
+    /// Render frames back-to-back, whether or not anything changed.
+    #[default]
+    Continuous,
+    /// Only render in response to input, engine updates, or `EngineUpdates::redraw`. The event loop
+    /// sleeps otherwise. This saves power, e.g. on laptops, and for mostly-static scenes.
+    OnDemand,
+}
+
+/// Where the engine gets each frame's `dt`: the time passed to the render handler, and used to
+/// advance fades, animations, and camera movement.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum FrameClock {
+    /// The time since the last frame, capped at 1 second.
+    #[default]
+    Real,
+    /// This many seconds per frame, however long frames take; e.g. to record video at a fixed
+    /// frame rate, or to replay a session the same way each time.
+    Fixed(f32),
+}
+
+/// How rendered frames are presented to the display. If the adapter doesn't support the
+/// selected mode, we fall back to `Fifo`, which is always available.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum PresentMode {
+    /// Vsync. Frame rate is limited to the display's refresh rate; no tearing.
+    #[default]
+    Fifo,
+    /// Renders as fast as possible, presenting the latest complete frame at each refresh. No
+    /// tearing, with lower latency than `Fifo`.
+    Mailbox,
+    /// Presents frames immediately. Lowest latency, but may tear.
+    Immediate,
+}
+
+impl PresentMode {
+    pub(crate) fn to_wgpu(self) -> wgpu::PresentMode {
+        match self {
+            Self::Fifo => wgpu::PresentMode::Fifo,
+            Self::Mailbox => wgpu::PresentMode::Mailbox,
+            Self::Immediate => wgpu::PresentMode::Immediate,
+        }
+    }
+}
+
 /// Settings that affect visual quality, and appearance. Most of These
 /// also impact performance. (i.e. frame rate)
 /// [Article with some details](https://vcg.isti.cnr.it/Publications/2006/TCM06/Tarini_FinalVersionElec.pdf)
@@ -559,7 +1855,87 @@ pub struct GraphicsSettings {
     pub depth_revealing_contour_lines: Option<f32>,
     /// Contour lines with fixed opacity wherever depth jumps at all. None = off. Some(strength).
     pub intersection_revealing_contour_lines: Option<f32>,
+    /// Contour lines of `Vertex::scalar`, on meshes that set it. None = off.
+    pub isolines: Option<Isolines>,
+    /// Multiplies the brightness of shaded surfaces (meshes and impostors), after lighting and
+    /// fog; e.g. to brighten a dim scene without changing every light's intensity. 1 = unchanged.
+    pub exposure: f32,
+    /// Applied to shaded surfaces after `exposure`, in linear space. Above 1 brightens mid-tones,
+    /// leaving black and white as they are; below 1 darkens them. 1 = unchanged.
+    pub gamma: f32,
     pub display_framerate: FramerateDisplay,
+    /// Show GPU memory usage below the frame rate readout, in the same corner. (Top left if the
+    /// frame rate isn't displayed)
+    pub display_memory_usage: bool,
+    /// Show the progress of uploads from `EngineUpdates::meshes_async` along the top of the 3D
+    /// display area, while any are pending.
+    pub display_upload_progress: bool,
+    pub render_mode: RenderMode,
+    pub present_mode: PresentMode,
+    /// How rendered colors are encoded in the window's framebuffer. `Srgb` displays correctly, and
+    /// matches the GUI. `Linear` writes linear values as-is; they look dark on a standard display,
+    /// but suit applications that post-process frames, or composite them with other linear
+    /// images. Input colors are sRGB either way. Set at init only.
+    pub output_color_space: ColorSpace,
+    /// Copy the depth buffer of each frame back to `Scene::depth_snapshot`, for
+    /// `Scene::query_depth` and `Scene::world_pos_under_cursor`. Results lag a frame or two behind.
+    /// Covers opaque meshes only; not transparent ones, Gaussians, or slices.
+    pub depth_readback: bool,
+    /// Draw the scene once per eye, side by side, or as a red-cyan anaglyph. Contour lines, SSAO,
+    /// and motion blur are skipped while it's on.
+    pub stereo: Stereo,
+    /// Blur the scene along the camera's movement, e.g. for smoother recorded fly-throughs.
+    /// None = off. Some(strength): the fraction of each frame's movement to blur over; 1 is like
+    /// a shutter open for the whole frame. Objects moving on their own aren't blurred.
+    pub motion_blur: Option<f32>,
+    /// Draw entity bounding boxes, vertex normals, or tangent frames as lines, e.g. to check
+    /// imported meshes.
+    pub debug_draw: DebugDraw,
+    /// WGSL source to draw meshes with, instead of the built-in shader; e.g. to use
+    /// `Entity::custom`. Start from a copy of this crate's `shader.wgsl`, and keep its bindings,
+    /// inputs, and entry points. With the `xray` feature, `shader_xray.wgsl` is added to it. Set
+    /// at init only.
+    pub mesh_shader: Option<String>,
+    /// Limits the frame rate to this many frames per second. Useful to reduce power use and heat
+    /// when vsync is off, i.e. with `PresentMode::Mailbox` or `Immediate`. None = no limit.
+    pub max_fps: Option<f32>,
+    /// Where each frame's `dt` comes from.
+    pub clock: FrameClock,
+    /// Graphics APIs to consider when selecting an adapter. `PRIMARY` covers Vulkan, Metal, DX12,
+    /// and WebGPU. Set at init only.
+    pub backends: Backends,
+    /// `Default` prefers low power when on battery, and high performance otherwise. Set at init only.
+    pub power_preference: PowerPreference,
+    /// Use a software (CPU) adapter. Slow, but useful where no GPU is available, e.g. CI or remote
+    /// sessions. Set at init only.
+    pub force_fallback_adapter: bool,
+    /// Device features to request, e.g. for compute or large storage buffers in application code.
+    /// Features the adapter doesn't support are skipped with a warning; `Scene::device_features`
+    /// has those granted. Set at init only.
+    pub required_features: Features,
+    /// Device limits to request. Limits the adapter can't meet are lowered to what it supports,
+    /// with a warning; `Scene::device_limits` has those granted. Set at init only.
+    pub required_limits: Limits,
+    /// Called if the GPU runs out of memory. If unset, this panics, as do other GPU errors.
+    pub on_error: Option<ErrorHandler>,
+    /// Called if initialization fails, e.g. to show a dialog before exiting. `run` then returns
+    /// the same error.
+    pub on_init_error: Option<ErrorHandler>,
+    /// Render the 3D scene at this multiple of the window's resolution on each axis, from 2 to 4,
+    /// and downsample it to the window: "render big, show small", for crisp stills. Independent
+    /// of MSAA, and much slower. Limited by the largest texture the GPU supports.
+    pub supersampling: Option<u32>,
+    /// Lower MSAA, then the render resolution, when frames take too long, to hold a frame rate.
+    /// With `supersampling`, the resolution is lowered relative to the supersampled one.
+    pub adaptive_quality: Option<AdaptiveQuality>,
+    /// Called with the timing of each frame, e.g. to lower quality settings when frames take too
+    /// long. GPU pass times are included where the adapter supports timestamp queries.
+    pub on_frame_complete: Option<FrameTimingHandler>,
+    /// Keep two copies of the entity instance buffers, and write each frame's entity updates to
+    /// the one the previous frame didn't draw from, swapping them before drawing. For applications
+    /// updating many entities every frame; this avoids writing to a buffer the GPU may still be
+    /// reading. Costs twice the instance buffer memory.
+    pub double_buffer_instances: bool,
 }
 
 impl Default for GraphicsSettings {
@@ -572,7 +1948,33 @@ impl Default for GraphicsSettings {
             depth_aware_halos: None,
             depth_revealing_contour_lines: None,
             intersection_revealing_contour_lines: None,
+            isolines: None,
+            exposure: 1.,
+            gamma: 1.,
             display_framerate: Default::default(),
+            display_memory_usage: false,
+            display_upload_progress: true,
+            render_mode: Default::default(),
+            present_mode: Default::default(),
+            output_color_space: Default::default(),
+            depth_readback: false,
+            stereo: Default::default(),
+            motion_blur: None,
+            debug_draw: Default::default(),
+            mesh_shader: None,
+            max_fps: None,
+            clock: Default::default(),
+            backends: Backends::PRIMARY,
+            power_preference: Default::default(),
+            force_fallback_adapter: false,
+            required_features: Features::empty(),
+            required_limits: Default::default(),
+            on_error: None,
+            on_init_error: None,
+            supersampling: None,
+            adaptive_quality: None,
+            on_frame_complete: None,
+            double_buffer_instances: false,
         }
     }
 }
@@ -589,6 +1991,29 @@ pub enum UiLayoutTopBottom {
     Bottom,
 }
 
+/// A display connected to the system. Positions and sizes are in physical pixels.
+#[derive(Clone, Debug)]
+pub struct MonitorInfo {
+    pub name: Option<String>,
+    /// The top left corner, in desktop coordinates.
+    pub position: (i32, i32),
+    pub size: (u32, u32),
+    pub scale_factor: f64,
+    pub primary: bool,
+}
+
+/// Where to open the window.
+#[derive(Clone, Debug, Default)]
+pub struct WindowPlacement {
+    /// The index in `Scene::monitors` of the monitor to open on. If `None`, or not connected, the
+    /// primary monitor.
+    pub monitor: Option<usize>,
+    /// The window's top left corner, relative to the monitor's, in logical pixels. If `None`, the
+    /// window is centered on `monitor` if set; otherwise, the platform chooses.
+    pub position: Option<(f32, f32)>,
+    pub maximized: bool,
+}
+
 #[derive(Clone, Debug)]
 /// GUI settings
 pub struct UiSettings {
@@ -596,6 +2021,8 @@ pub struct UiSettings {
     pub layout_sides: UiLayoutSides,
     pub layout_top_bottom: UiLayoutTopBottom,
     pub icon_path: Option<String>,
+    /// Applied when the window opens. Not all platforms support positioning windows; e.g. Wayland.
+    pub window_placement: WindowPlacement,
 }
 
 impl Default for UiSettings {
@@ -604,6 +2031,7 @@ impl Default for UiSettings {
             layout_sides: UiLayoutSides::Left,
             layout_top_bottom: UiLayoutTopBottom::Top,
             icon_path: None,
+            window_placement: Default::default(),
         }
     }
 }
@@ -617,13 +2045,395 @@ impl Default for UiSettings {
 /// etc are only computed and changed when necessary.
 #[derive(Default)]
 pub struct EngineUpdates {
+    /// Re-upload all meshes.
     pub meshes: bool,
+    /// Indices into `Scene::meshes` of meshes that changed, or were appended. Only these are
+    /// re-uploaded, so this is cheaper than `meshes` when editing a few meshes of many.
+    pub meshes_changed: Vec<usize>,
+    /// As `meshes_changed`, but converted and copied to the GPU on a background thread, for very
+    /// large meshes that would otherwise stall a frame. Each stays in `Scene::meshes`; until it's
+    /// uploaded, entities using it draw the mesh's previous data, or nothing if it was just added.
+    pub meshes_async: Vec<usize>,
     pub entities: EntityUpdate,
+    /// Indices into `Scene::materials` of materials that changed. Entities using them are updated
+    /// in place.
+    pub materials: Vec<usize>,
+    pub gaussians: GaussianUpdate,
     pub camera: bool,
+    /// Apply the camera's fog parameters, including `height_fog`, e.g. from `gui::fog_controls`.
+    /// Cheaper than `camera`, which also covers fog.
+    pub fog: bool,
     pub lighting: bool,
     /// X, Y. Reported by the UI, e.g. from SidePanel.response.rect.width()
     /// and TopBottomPanel.response.rect.heigh() etc.
     pub ui_reserved_px: (f32, f32),
     /// For updating graphics settings (MSAA etc) from the application.
     pub graphics_settings: Option<GraphicsSettings>,
+    /// Apply `Scene::window_title`, `window_size`, `window_min_size`, and `window_max_size` to
+    /// the window.
+    pub window: bool,
+    /// Set the window icon to the image at this path.
+    pub window_icon: Option<String>,
+    /// In `RenderMode::OnDemand`, render another frame. Setting any other field has the same
+    /// effect; use this if the application changed something the engine doesn't track, e.g.
+    /// its GUI state.
+    pub redraw: bool,
+    /// Replace the pixels of these textures, e.g. to stream video or simulation output. The data is
+    /// sRGB RGBA8 in rows from the top, matching the texture's size; create such a texture with
+    /// `TextureSource::Pixels`.
+    pub textures: Vec<(TextureHandle, Vec<u8>)>,
+    /// Re-center `ControlScheme::Arc` on this point, moving the camera there smoothly; e.g. on
+    /// `Scene::world_pos_under_cursor`, or a selected entity's position. Ignored by other control
+    /// schemes.
+    pub arc_center: Option<Vec3>,
+    /// Re-upload `Scene::lines`.
+    pub lines: bool,
+    /// Re-upload `Scene::spheres`.
+    pub spheres: bool,
+    /// Re-upload `Scene::cylinders`.
+    pub cylinders: bool,
+}
+
+impl EngineUpdates {
+    /// If anything here changes what's displayed.
+    pub(crate) fn requires_redraw(&self) -> bool {
+        self.redraw
+            || self.meshes
+            || !self.meshes_changed.is_empty()
+            || !self.meshes_async.is_empty()
+            || self.entities != EntityUpdate::None
+            || !self.materials.is_empty()
+            || self.gaussians != GaussianUpdate::None
+            || self.camera
+            || self.fog
+            || self.lighting
+            || self.graphics_settings.is_some()
+            || !self.textures.is_empty()
+            || self.arc_center.is_some()
+            || self.lines
+            || self.spheres
+            || self.cylinders
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPS: f32 = 1e-5;
+
+    #[test]
+    fn instance_bytes_are_raw_layout() {
+        let entity = Entity::new(
+            0,
+            Vec3::new(1., 2., 3.),
+            Quaternion::new_identity(),
+            1.,
+            (1., 1., 1.),
+            0.,
+        );
+        let instance = Instance::from(&entity);
+        let bytes = instance.to_bytes();
+
+        assert_eq!(&bytes[..], bytemuck::bytes_of(&instance.to_raw()));
+        // The model matrix leads, column-major, with the translation in its last column.
+        let translation: Vec<f32> = bytes[48..60]
+            .chunks_exact(4)
+            .map(|b| f32::from_ne_bytes(b.try_into().unwrap()))
+            .collect();
+        assert_eq!(translation, [1., 2., 3.]);
+    }
+
+    /// Positions of each triangle's vertices, in order.
+    fn triangle_positions(mesh: &Mesh) -> Vec<[[f32; 3]; 3]> {
+        mesh.indices
+            .chunks_exact(3)
+            .map(|t| std::array::from_fn(|i| mesh.vertices[t[i]].position))
+            .collect()
+    }
+
+    #[test]
+    fn split_keeps_triangles() {
+        let mut mesh = Mesh::new_sphere(1., 3);
+        let ids: Vec<f32> = (0..mesh.vertices.len()).map(|i| i as f32).collect();
+        mesh.set_attribute("id", AttributeValues::Scalar(ids));
+
+        for max_vertices in [3, 10, 64, 500, 100_000] {
+            let chunks = mesh.split(max_vertices);
+
+            let mut triangles = Vec::new();
+            for chunk in &chunks {
+                assert!(chunk.vertices.len() <= max_vertices);
+                assert!(chunk.indices.iter().all(|&i| i < chunk.vertices.len()));
+                triangles.extend(triangle_positions(chunk));
+
+                // Attributes follow their vertices.
+                let AttributeValues::Scalar(ids) = &chunk.attributes[0].values else {
+                    unreachable!()
+                };
+                assert_eq!(ids.len(), chunk.vertices.len());
+                for (v, &id) in chunk.vertices.iter().zip(ids) {
+                    assert_eq!(v.position, mesh.vertices[id as usize].position);
+                }
+            }
+            assert_eq!(triangles, triangle_positions(&mesh));
+        }
+
+        assert_eq!(mesh.split(100_000).len(), 1);
+    }
+
+    #[test]
+    fn split_duplicates_shared_vertices() {
+        // A strip, whose triangles share 2 vertices with the previous one.
+        let vertices = (0..5)
+            .map(|i| Vertex::new([i as f32, (i % 2) as f32, 0.], Vec3::new(0., 0., 1.)))
+            .collect();
+        let mesh = Mesh {
+            vertices,
+            indices: vec![0, 1, 2, 2, 1, 3, 2, 3, 4],
+            ..Default::default()
+        };
+
+        // The last triangle shares 2 vertices with the first chunk, which is full.
+        let chunks = mesh.split(4);
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0].vertices.len(), 4);
+        assert_eq!(chunks[0].indices, [0, 1, 2, 2, 1, 3]);
+        // Vertices 2 and 3 are duplicated into the second chunk, and re-indexed there.
+        let positions: Vec<_> = chunks[1].vertices.iter().map(|v| v.position[0]).collect();
+        assert_eq!(positions, [2., 3., 4.]);
+        assert_eq!(chunks[1].indices, [0, 1, 2]);
+
+        // At least one triangle fits in each chunk.
+        let chunks = mesh.split(0);
+        assert_eq!(chunks.len(), 3);
+        let triangles: Vec<_> = chunks.iter().flat_map(triangle_positions).collect();
+        assert_eq!(triangles, triangle_positions(&mesh));
+    }
+
+    #[test]
+    fn snap_increment_spans_min_pixels() {
+        let scene = Scene {
+            window_size: (800., 600.),
+            ..Default::default()
+        };
+        let fwd = scene.camera.orientation.rotate_vec(FWD_VEC);
+
+        let mut prev = 0.;
+        for depth in [0.5, 3., 10., 80., 2_000.] {
+            let point = scene.camera.position + fwd * depth;
+            let per_pixel = scene.world_per_pixel(point);
+            let step = scene.snap_increment(point, 20.);
+
+            assert_eq!(step, nice_step(per_pixel * 20.));
+            // At least 20 pixels on screen, and within one 1-2-5 step of it.
+            assert!(step / per_pixel >= 20. * (1. - EPS));
+            assert!(step / per_pixel <= 50.);
+            // Coarser as the point gets farther away.
+            assert!(step >= prev);
+            prev = step;
+        }
+
+        // Behind the camera there's no increment, and nothing snaps.
+        let behind = scene.camera.position - fwd * 5.;
+        assert_eq!(scene.snap_increment(behind, 20.), 0.);
+        assert_eq!(scene.snap_to_grid(behind, 20.), behind);
+        assert_eq!(scene.snap_increment(fwd * 5., 0.), 0.);
+    }
+
+    /// A camera looking at `center` from `dist` away, at `pitch` and `azimuth` as in
+    /// `ArcConstraints`.
+    fn arc_camera(center: Vec3, dist: f32, pitch: f32, azimuth: f32) -> Camera {
+        let dir = Vec3::new(
+            pitch.cos() * azimuth.sin(),
+            pitch.sin(),
+            -pitch.cos() * azimuth.cos(),
+        );
+
+        Camera {
+            position: center + dir * dist,
+            orientation: Quaternion::from_unit_vecs(FWD_VEC, -dir),
+            ..Default::default()
+        }
+    }
+
+    /// Distance, pitch, and azimuth of `cam` from `center`, checking it looks at `center`.
+    fn arc_position(cam: &Camera, center: Vec3) -> (f32, f32, f32) {
+        let offset = cam.position - center;
+        let dir = offset.to_normalized();
+        let fwd = cam.orientation.rotate_vec(FWD_VEC);
+        assert!((fwd + dir).magnitude() < 1e-4, "not looking at the center");
+
+        (offset.magnitude(), dir.y.asin(), dir.x.atan2(-dir.z))
+    }
+
+    #[test]
+    fn arc_constraints_unconstrained() {
+        let center = Vec3::new(1., 2., 3.);
+        let mut cam = arc_camera(center, 50., 1.4, -2.);
+        let before = cam.position;
+
+        assert!(!ArcConstraints::default().apply(&mut cam, center));
+        assert_eq!(cam.position, before);
+
+        // Within all limits.
+        let constraints = ArcConstraints {
+            distance: Some((1., 100.)),
+            pitch: Some((-1.5, 1.5)),
+            azimuth: Some((-3., 3.)),
+        };
+        assert!(!constraints.apply(&mut cam, center));
+        assert_eq!(cam.position, before);
+    }
+
+    #[test]
+    fn arc_constraints_distance() {
+        let center = Vec3::new(1., 2., 3.);
+        let constraints = ArcConstraints {
+            distance: Some((2., 10.)),
+            ..Default::default()
+        };
+
+        for (dist, clamped) in [(20., 10.), (0.5, 2.)] {
+            let mut cam = arc_camera(center, dist, 0.3, 0.8);
+            assert!(constraints.apply(&mut cam, center));
+
+            let (d, pitch, azimuth) = arc_position(&cam, center);
+            assert!((d - clamped).abs() < 1e-4);
+            assert!((pitch - 0.3).abs() < 1e-4);
+            assert!((azimuth - 0.8).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn arc_constraints_pitch() {
+        let center = Vec3::new_zero();
+        let constraints = ArcConstraints {
+            pitch: Some((-0.5, 0.5)),
+            ..Default::default()
+        };
+
+        for (pitch, clamped) in [(1.2, 0.5), (-1.4, -0.5)] {
+            let mut cam = arc_camera(center, 8., pitch, -1.);
+            assert!(constraints.apply(&mut cam, center));
+
+            let (d, p, azimuth) = arc_position(&cam, center);
+            assert!((d - 8.).abs() < 1e-4);
+            assert!((p - clamped).abs() < 1e-4);
+            assert!((azimuth + 1.).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn arc_constraints_azimuth() {
+        let center = Vec3::new(0., -1., 0.);
+        let constraints = ArcConstraints {
+            azimuth: Some((-1., 1.)),
+            ..Default::default()
+        };
+
+        for (azimuth, clamped) in [(2., 1.), (-1.5, -1.), (3., 1.), (-3., -1.)] {
+            let mut cam = arc_camera(center, 5., 0.2, azimuth);
+            assert!(constraints.apply(&mut cam, center));
+
+            let (d, pitch, a) = arc_position(&cam, center);
+            assert!((d - 5.).abs() < 1e-4);
+            assert!((pitch - 0.2).abs() < 1e-4);
+            assert!((a - clamped).abs() < 1e-4, "{azimuth}: {a}");
+        }
+    }
+
+    #[test]
+    fn arc_constraints_azimuth_wraparound() {
+        let center = Vec3::new_zero();
+
+        // Near the top of the range, a camera just past TAU/2 is nearest `max`, not `min`.
+        let constraints = ArcConstraints {
+            azimuth: Some((2.5, 3.)),
+            ..Default::default()
+        };
+        let mut cam = arc_camera(center, 5., 0., -3.);
+        assert!(constraints.apply(&mut cam, center));
+        assert!((arc_position(&cam, center).2 - 3.).abs() < 1e-4);
+
+        // A range through TAU/2.
+        let constraints = ArcConstraints {
+            azimuth: Some((3., -3.)),
+            ..Default::default()
+        };
+        for azimuth in [3.1, -3.1, 3.05] {
+            let mut cam = arc_camera(center, 5., 0., azimuth);
+            assert!(!constraints.apply(&mut cam, center));
+        }
+        for (azimuth, clamped) in [(2.5, 3.), (-2.5, -3.), (0.1, 3.), (-0.1, -3.)] {
+            let mut cam = arc_camera(center, 5., 0., azimuth);
+            assert!(constraints.apply(&mut cam, center));
+
+            let a = arc_position(&cam, center).2;
+            assert!((a - clamped).abs() < 1e-4, "{azimuth}: {a}");
+        }
+    }
+
+    #[test]
+    fn fade_steps_by_dt() {
+        let mut scene = Scene {
+            entities: vec![Entity::default()],
+            ..Default::default()
+        };
+        let mut inputs = InputsCommanded::default();
+
+        scene.entities[0].fade_to(0., 1.);
+
+        let step = scene.step(&mut inputs, 0.5);
+        assert!((scene.entities[0].opacity - 0.5).abs() < EPS);
+        assert_eq!(step.entities_changed, vec![0]);
+        assert!(step.animating);
+
+        let step = scene.step(&mut inputs, 0.5);
+        assert!(scene.entities[0].opacity.abs() < EPS);
+        assert!(scene.entities[0].fade.is_none());
+        assert!(!step.animating);
+
+        // Once done, nothing changes.
+        let step = scene.step(&mut inputs, 0.5);
+        assert!(step.entities_changed.is_empty());
+    }
+
+    #[test]
+    fn arc_transition_finishes_after_duration() {
+        let mut scene = Scene {
+            input_settings: InputSettings {
+                control_scheme: ControlScheme::Arc {
+                    center: Vec3::new_zero(),
+                },
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        scene.camera.position = Vec3::new(0., 0., -10.);
+        let duration = scene.input_settings.recenter_time;
+        let target = Vec3::new(2., -1., 3.);
+
+        let mut inputs = InputsCommanded::default();
+        inputs.recenter_arc(&scene.input_settings, target);
+
+        let step = scene.step(&mut inputs, duration / 2.);
+        assert!(step.camera_changed);
+        assert!(step.animating);
+
+        let step = scene.step(&mut inputs, duration / 2.);
+        assert!(step.camera_changed);
+        assert!(!step.animating);
+        assert!(inputs.arc_transition.is_none());
+
+        let ControlScheme::Arc { center } = scene.input_settings.control_scheme else {
+            unreachable!()
+        };
+        assert!((center - target).magnitude() < EPS);
+        // The camera keeps its distance, and moves with the center.
+        assert!((scene.camera.position - (target + Vec3::new(0., 0., -10.))).magnitude() < 1e-4);
+
+        let step = scene.step(&mut inputs, duration);
+        assert!(!step.camera_changed);
+    }
 }
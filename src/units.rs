@@ -0,0 +1,136 @@
+//! The physical length a world unit represents, for presenting numbers consistently: e.g.
+//! measurement readouts, axis labels, and snapping increments.
+
+use core::fmt;
+
+/// What one world unit is, in `Scene::units`. The engine doesn't scale anything by this; it's for
+/// formatting lengths, with `Scene::format_length`.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum Units {
+    #[default]
+    Meters,
+    /// 10⁻¹⁰ m; e.g. for molecules.
+    Angstroms,
+    /// About 1.496 × 10¹¹ m; e.g. for orbits.
+    AstronomicalUnits,
+}
+
+impl Units {
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Self::Meters => "m",
+            Self::Angstroms => "Å",
+            Self::AstronomicalUnits => "AU",
+        }
+    }
+
+    /// The length of one unit, in meters.
+    pub fn meters(self) -> f64 {
+        match self {
+            Self::Meters => 1.,
+            Self::Angstroms => 1e-10,
+            Self::AstronomicalUnits => 1.495_978_707e11,
+        }
+    }
+
+    /// `value`, a length in these units, with 3 significant figures and the symbol; e.g. "1.54 Å".
+    pub fn format(self, value: f32) -> String {
+        if value == 0. || !value.is_finite() {
+            return format!("{value} {}", self.symbol());
+        }
+
+        let magnitude = value.abs().log10().floor() as i32;
+        if !(-3..6).contains(&magnitude) {
+            return format!("{value:.2e} {}", self.symbol());
+        }
+
+        let decimals = (2 - magnitude).max(0) as usize;
+        format!("{value:.decimals$} {}", self.symbol())
+    }
+}
+
+impl fmt::Display for Units {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.symbol())
+    }
+}
+
+/// The smallest round number, of the form 1, 2, or 5 × 10ⁿ, at least `min`; e.g. for grid spacing
+/// or axis ticks. 0 if `min` isn't positive.
+pub fn nice_step(min: f32) -> f32 {
+    if min.is_nan() || min <= 0. {
+        return 0.;
+    }
+
+    let exp = 10_f32.powf(min.log10().floor());
+
+    [1., 2., 5., 10.]
+        .into_iter()
+        .map(|m| m * exp)
+        .find(|&step| step >= min)
+        .unwrap_or(10. * exp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() <= expected * 1e-5,
+            "{actual} isn't {expected}"
+        );
+    }
+
+    #[test]
+    fn nice_step_round_values() {
+        for (min, step) in [
+            (1., 1.),
+            (2., 2.),
+            (5., 5.),
+            (10., 10.),
+            (0.2, 0.2),
+            (500., 500.),
+        ] {
+            assert_close(nice_step(min), step);
+        }
+    }
+
+    #[test]
+    fn nice_step_just_above() {
+        for (min, step) in [
+            (1.001, 2.),
+            (2.001, 5.),
+            (5.001, 10.),
+            (9.99, 10.),
+            (0.1001, 0.2),
+            (3., 5.),
+            (7_000., 10_000.),
+        ] {
+            assert_close(nice_step(min), step);
+        }
+    }
+
+    #[test]
+    fn nice_step_tiny_and_huge() {
+        assert_close(nice_step(1.3e-12), 2e-12);
+        assert_close(nice_step(4e-7), 5e-7);
+        assert_close(nice_step(1.6e11), 2e11);
+        assert_close(nice_step(6e30), 1e31);
+
+        // Always at least `min`, and at most 2.5 times it.
+        let mut min = 1e-20;
+        while min < 1e20 {
+            let step = nice_step(min);
+            assert!(step >= min && step <= min * 2.5, "{min}: {step}");
+            min *= 1.37;
+        }
+    }
+
+    #[test]
+    fn nice_step_non_positive() {
+        assert_eq!(nice_step(0.), 0.);
+        assert_eq!(nice_step(-3.), 0.);
+        assert_eq!(nice_step(f32::NAN), 0.);
+    }
+}
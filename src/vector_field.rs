@@ -0,0 +1,338 @@
+//! Helpers for visualizing vector fields, e.g. velocities, forces, or fluid flow.
+
+use lin_alg::f32::{Quaternion, Vec3};
+
+use crate::{Colormap, Entity, Mesh, UP_VEC};
+
+/// How to color vector field glyphs.
+#[derive(Clone, Copy, Debug)]
+pub enum GlyphColor {
+    Solid((f32, f32, f32)),
+    /// Color by magnitude. If `range` is `None`, it spans the smallest to largest magnitude of the
+    /// samples.
+    Magnitude {
+        colormap: Colormap,
+        range: Option<(f32, f32)>,
+    },
+}
+
+#[derive(Clone, Debug)]
+pub struct GlyphSettings {
+    /// The index in `Scene::meshes` to draw each glyph with, e.g. of a `Mesh::new_arrow`. The mesh
+    /// should point up, along +Y. Glyphs are centered on their sample position.
+    pub mesh: usize,
+    /// Glyph length, as a multiple of the mesh's, per unit of vector magnitude.
+    pub length_scale: f32,
+    /// If set, every glyph is `length_scale` long, and magnitude only affects color.
+    pub normalize: bool,
+    /// Scale across the glyph's direction.
+    pub thickness: f32,
+    pub color: GlyphColor,
+    pub opacity: f32,
+    pub shinyness: f32,
+    /// Set on each glyph's entity, so the application can find them, e.g. to replace them when
+    /// the field changes.
+    pub class: u32,
+}
+
+impl Default for GlyphSettings {
+    fn default() -> Self {
+        Self {
+            mesh: 0,
+            length_scale: 1.,
+            normalize: false,
+            thickness: 1.,
+            color: GlyphColor::Magnitude {
+                colormap: Colormap::Viridis,
+                range: None,
+            },
+            opacity: 1.,
+            shinyness: 0.5,
+            class: 0,
+        }
+    }
+}
+
+/// Create an entity for each `(position, vector)` sample, pointing along the vector. These share
+/// one mesh, so render in a single instanced draw. Samples with zero length are skipped.
+pub fn vector_glyphs(samples: &[(Vec3, Vec3)], settings: &GlyphSettings) -> Vec<Entity> {
+    let color_range = match settings.color {
+        GlyphColor::Magnitude {
+            range: Some(range), ..
+        } => range,
+        _ => samples
+            .iter()
+            .map(|(_, v)| v.magnitude())
+            .fold((f32::MAX, f32::MIN), |(lo, hi), m| (lo.min(m), hi.max(m))),
+    };
+
+    let mut result = Vec::with_capacity(samples.len());
+
+    for &(posit, vec) in samples {
+        let magnitude = vec.magnitude();
+        if magnitude <= f32::EPSILON {
+            continue;
+        }
+
+        let color = match settings.color {
+            GlyphColor::Solid(c) => c,
+            GlyphColor::Magnitude { colormap, .. } => colormap.map(magnitude, color_range),
+        };
+
+        let len = if settings.normalize {
+            settings.length_scale
+        } else {
+            settings.length_scale * magnitude
+        };
+
+        let mut entity = Entity::new(
+            settings.mesh,
+            posit,
+            Quaternion::from_unit_vecs(UP_VEC, vec / magnitude),
+            1.,
+            color,
+            settings.shinyness,
+        );
+        entity.scale_partial = Some(Vec3::new(settings.thickness, len, settings.thickness));
+        entity.opacity = settings.opacity;
+        entity.class = settings.class;
+
+        result.push(entity);
+    }
+
+    result
+}
+
+/// Sample `field` on a regular grid from `min` to `max` inclusive, with `counts` points along each
+/// axis, for use with `vector_glyphs`.
+pub fn grid_samples(
+    min: Vec3,
+    max: Vec3,
+    counts: (usize, usize, usize),
+    field: impl Fn(Vec3) -> Vec3,
+) -> Vec<(Vec3, Vec3)> {
+    grid_points(min, max, counts)
+        .into_iter()
+        .map(|p| (p, field(p)))
+        .collect()
+}
+
+/// Points on a regular grid from `min` to `max` inclusive, with `counts` points along each axis,
+/// in x-major order. E.g. for seeding streamlines.
+pub fn grid_points(min: Vec3, max: Vec3, counts: (usize, usize, usize)) -> Vec<Vec3> {
+    // With one point along an axis, place it at `min`.
+    let step = |lo: f32, hi: f32, n: usize| {
+        if n > 1 {
+            (hi - lo) / (n - 1) as f32
+        } else {
+            0.
+        }
+    };
+    let dx = step(min.x, max.x, counts.0);
+    let dy = step(min.y, max.y, counts.1);
+    let dz = step(min.z, max.z, counts.2);
+
+    let mut result = Vec::with_capacity(counts.0 * counts.1 * counts.2);
+
+    for k in 0..counts.2 {
+        for j in 0..counts.1 {
+            for i in 0..counts.0 {
+                result.push(Vec3::new(
+                    min.x + i as f32 * dx,
+                    min.y + j as f32 * dy,
+                    min.z + k as f32 * dz,
+                ));
+            }
+        }
+    }
+
+    result
+}
+
+#[derive(Clone, Debug)]
+pub struct StreamlineSettings {
+    /// Distance between points along the line, in world units.
+    pub step: f32,
+    /// The most steps to take in each direction from the seed.
+    pub max_steps: usize,
+    /// Stop where the field's magnitude falls below this, e.g. at a sink.
+    pub min_magnitude: f32,
+    /// Trace backward from the seed as well as forward, so the line passes through it.
+    pub both_directions: bool,
+    /// Stop on leaving this box, given as (min, max) corners.
+    pub bounds: Option<(Vec3, Vec3)>,
+}
+
+impl Default for StreamlineSettings {
+    fn default() -> Self {
+        Self {
+            step: 0.1,
+            max_steps: 500,
+            min_magnitude: 1e-6,
+            both_directions: true,
+            bounds: None,
+        }
+    }
+}
+
+/// Trace a streamline through `field` from `seed`, with 4th-order Runge-Kutta integration. We step
+/// along the field's direction only, so points are evenly spaced regardless of its magnitude.
+pub fn trace_streamline(
+    seed: Vec3,
+    field: &impl Fn(Vec3) -> Vec3,
+    settings: &StreamlineSettings,
+) -> Vec<Vec3> {
+    let forward = trace_one_way(seed, field, settings, 1.);
+    if !settings.both_directions {
+        return forward;
+    }
+
+    // Both start at the seed; keep it once.
+    let mut result = trace_one_way(seed, field, settings, -1.);
+    result.reverse();
+    result.extend_from_slice(&forward[1..]);
+    result
+}
+
+/// Trace a streamline from each seed, e.g. from `grid_points`. Lines shorter than 2 points, e.g.
+/// from seeds where the field is zero, are dropped.
+pub fn trace_streamlines(
+    seeds: &[Vec3],
+    field: &impl Fn(Vec3) -> Vec3,
+    settings: &StreamlineSettings,
+) -> Vec<Vec<Vec3>> {
+    seeds
+        .iter()
+        .map(|&seed| trace_streamline(seed, field, settings))
+        .filter(|line| line.len() >= 2)
+        .collect()
+}
+
+fn trace_one_way(
+    seed: Vec3,
+    field: &impl Fn(Vec3) -> Vec3,
+    settings: &StreamlineSettings,
+    sign: f32,
+) -> Vec<Vec3> {
+    let dir = |p: Vec3| {
+        let v = field(p);
+        let magnitude = v.magnitude();
+        (magnitude > settings.min_magnitude.max(0.) && magnitude.is_finite())
+            .then(|| v * (sign / magnitude))
+    };
+
+    let in_bounds = |p: Vec3| match settings.bounds {
+        Some((min, max)) => {
+            p.x >= min.x
+                && p.y >= min.y
+                && p.z >= min.z
+                && p.x <= max.x
+                && p.y <= max.y
+                && p.z <= max.z
+        }
+        None => true,
+    };
+
+    let h = settings.step;
+    let mut result = vec![seed];
+    let mut p = seed;
+
+    for _ in 0..settings.max_steps {
+        let Some(k1) = dir(p) else { break };
+        let Some(k2) = dir(p + k1 * (h / 2.)) else {
+            break;
+        };
+        let Some(k3) = dir(p + k2 * (h / 2.)) else {
+            break;
+        };
+        let Some(k4) = dir(p + k3 * h) else { break };
+
+        p += (k1 + k2 * 2. + k3 * 2. + k4) * (h / 6.);
+        if !in_bounds(p) {
+            break;
+        }
+        result.push(p);
+    }
+
+    result
+}
+
+/// Build one mesh of tubes along streamlines, e.g. from `trace_streamlines`. With
+/// `GlyphColor::Magnitude`, each point is colored by the field's magnitude there; vertex colors
+/// override the entity's. Tubes have at least 3 sides.
+pub fn streamline_mesh(
+    lines: &[Vec<Vec3>],
+    field: &impl Fn(Vec3) -> Vec3,
+    radius: f32,
+    num_sides: usize,
+    color: GlyphColor,
+) -> Mesh {
+    // As `Mesh::new_tube`, whose rings we color.
+    let num_sides = num_sides.max(3);
+    let magnitudes: Vec<Vec<f32>> = lines
+        .iter()
+        .map(|line| line.iter().map(|&p| field(p).magnitude()).collect())
+        .collect();
+
+    let range = match color {
+        GlyphColor::Magnitude {
+            range: Some(range), ..
+        } => range,
+        _ => magnitudes
+            .iter()
+            .flatten()
+            .fold((f32::MAX, f32::MIN), |(lo, hi), &m| (lo.min(m), hi.max(m))),
+    };
+
+    let to_u8 = |c: (f32, f32, f32)| {
+        let ch = |v: f32| (v.clamp(0., 1.) * 255.).round() as u8;
+        (ch(c.0), ch(c.1), ch(c.2), 255)
+    };
+
+    let mut result = Mesh::default();
+
+    for (line, mags) in lines.iter().zip(&magnitudes) {
+        let mut tube = Mesh::new_tube(line, radius, num_sides);
+
+        for (ring, &magnitude) in tube.vertices.chunks_mut(num_sides).zip(mags) {
+            let c = match color {
+                GlyphColor::Solid(c) => c,
+                GlyphColor::Magnitude { colormap, .. } => colormap.map(magnitude, range),
+            };
+            for vertex in ring {
+                vertex.color = Some(to_u8(c));
+            }
+        }
+
+        let offset = result.vertices.len();
+        result.vertices.append(&mut tube.vertices);
+        result
+            .indices
+            .extend(tube.indices.iter().map(|i| i + offset));
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn streamline_mesh_clamps_sides() {
+        let lines = vec![vec![Vec3::new(0., 0., 0.), Vec3::new(0., 0., 1.)]];
+        let field = |_: Vec3| Vec3::new(0., 0., 1.);
+
+        for num_sides in [0, 1, 2, 3] {
+            let mesh = streamline_mesh(
+                &lines,
+                &field,
+                0.1,
+                num_sides,
+                GlyphColor::Solid((1., 0., 0.)),
+            );
+            assert_eq!(mesh.vertices.len(), 2 * 3);
+            assert!(mesh.indices.iter().all(|&i| i < mesh.vertices.len()));
+        }
+    }
+}
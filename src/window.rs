@@ -1,32 +1,95 @@
 //! Handles window initialization and events, using Winit.
 
-use std::{
-    path::Path,
-    time::{Duration, Instant},
-};
+use std::{path::Path, time::Duration};
 
-use image::ImageError;
+use tracing::{debug, warn};
+use web_time::Instant;
 use wgpu::TextureViewDescriptor;
 use winit::{
     application::ApplicationHandler,
-    event::{DeviceEvent, DeviceId, WindowEvent},
-    event_loop::ActiveEventLoop,
-    window::{Icon, WindowAttributes, WindowId},
+    dpi::{LogicalPosition, LogicalSize, PhysicalPosition, PhysicalSize},
+    event::{DeviceEvent, DeviceId, ElementState, WindowEvent},
+    event_loop::{ActiveEventLoop, ControlFlow},
+    monitor::MonitorHandle,
+    window::{Icon, Window, WindowAttributes, WindowId},
 };
 
 use crate::{
-    EngineUpdates, Scene, UiLayoutSides, UiLayoutTopBottom, UiSettings,
+    EngineUpdates, FrameClock, GraphicsError, MonitorInfo, RenderMode, Scene, UiLayoutSides,
+    UiLayoutTopBottom, UiSettings,
+    proxy::{Command, EngineEvent, apply_scene_deltas},
+    snapshot::screenshot,
     system::{State, process_engine_updates},
 };
 
-fn load_icon(path: &Path) -> Result<Icon, ImageError> {
+/// Apply window properties from the scene, in response to `EngineUpdates::window`.
+pub(crate) fn update_window(window: &Window, scene: &Scene) {
+    window.set_title(&scene.window_title);
+
+    window.set_min_inner_size(scene.window_min_size.map(|(w, h)| LogicalSize::new(w, h)));
+    window.set_max_inner_size(scene.window_max_size.map(|(w, h)| LogicalSize::new(w, h)));
+
+    let size = PhysicalSize::new(scene.window_size.0 as u32, scene.window_size.1 as u32);
+    if size != window.inner_size() {
+        // The resulting resize event updates the surface. The platform may not honor this.
+        let _ = window.request_inner_size(size);
+    }
+}
+
+/// Set the window icon, in response to `EngineUpdates::window_icon`.
+pub(crate) fn update_window_icon(window: &Window, path: &str) {
+    match load_icon(Path::new(path)) {
+        Ok(icon) => window.set_window_icon(Some(icon)),
+        Err(e) => warn!("Unable to load the window icon at {path}: {e}"),
+    }
+}
+
+fn monitor_info(monitor: &MonitorHandle, primary: Option<&MonitorHandle>) -> MonitorInfo {
+    let position = monitor.position();
+    let size = monitor.size();
+
+    MonitorInfo {
+        name: monitor.name(),
+        position: (position.x, position.y),
+        size: (size.width, size.height),
+        scale_factor: monitor.scale_factor(),
+        primary: primary == Some(monitor),
+    }
+}
+
+fn load_icon(path: &Path) -> Result<Icon, String> {
     let (icon_rgba, icon_width, icon_height) = {
-        let image = image::open(path)?.into_rgba8();
+        let image = image::open(path).map_err(|e| e.to_string())?.into_rgba8();
         let (width, height) = image.dimensions();
         let rgba = image.into_raw();
         (rgba, width, height)
     };
-    Ok(Icon::from_rgba(icon_rgba, icon_width, icon_height).expect("Failed to open icon"))
+    Icon::from_rgba(icon_rgba, icon_width, icon_height).map_err(|e| e.to_string())
+}
+
+/// Render to the canvas element with this ID, if set and present; otherwise, append one to the page.
+#[cfg(target_arch = "wasm32")]
+fn with_canvas(attributes: WindowAttributes, canvas_id: Option<&str>) -> WindowAttributes {
+    use wasm_bindgen::JsCast;
+    use winit::platform::web::WindowAttributesExtWebSys;
+
+    let canvas = canvas_id.and_then(|id| {
+        web_sys::window()?
+            .document()?
+            .get_element_by_id(id)?
+            .dyn_into::<web_sys::HtmlCanvasElement>()
+            .ok()
+    });
+
+    match canvas {
+        Some(c) => attributes.with_canvas(Some(c)),
+        None => {
+            if let Some(id) = canvas_id {
+                warn!("No canvas element with ID {id}; appending one to the page");
+            }
+            attributes.with_append(true)
+        }
+    }
 }
 
 impl<T, FRender, FEventDev, FEventWin, FGui> State<T, FRender, FEventDev, FEventWin, FGui>
@@ -36,6 +99,65 @@ where
     FEventWin: FnMut(&mut T, WindowEvent, &mut Scene, f32) -> EngineUpdates + 'static,
     FGui: FnMut(&mut T, &mut egui::Ui, &mut Scene) -> EngineUpdates + 'static,
 {
+    /// Report an initialization failure, and exit; `run` returns the error.
+    fn fail_init(&mut self, event_loop: &ActiveEventLoop, e: GraphicsError) {
+        if let Some(handler) = &self.graphics_settings.on_init_error {
+            (handler.0)(&e);
+        }
+        self.init_error = Some(e);
+        event_loop.exit();
+    }
+
+    /// Act on a command sent through `EngineProxy`.
+    fn run_command(&mut self, event_loop: &ActiveEventLoop, command: Command<T>) {
+        if let Command::Quit = command {
+            event_loop.exit();
+            return;
+        }
+
+        let (Some(render), Some(graphics)) = (&self.render, &mut self.graphics) else {
+            // Prior to init, there's no window yet; the title applies when it opens.
+            match command {
+                Command::SetWindowTitle(title) => {
+                    if let Some(scene) = &mut self.scene {
+                        scene.window_title = title;
+                    }
+                }
+                Command::Screenshot(f) => f(
+                    &mut self.user_state,
+                    Err(GraphicsError::Readback(
+                        "The window hasn't opened yet".to_owned(),
+                    )),
+                ),
+                _ => (),
+            }
+            return;
+        };
+
+        match command {
+            Command::SetWindowTitle(title) => {
+                graphics.window().set_title(&title);
+                graphics.scene.window_title = title;
+            }
+            Command::SetCursor(icon) => graphics.window().set_cursor(icon),
+            Command::RecenterArc(center) => {
+                graphics.recenter_arc(center);
+                graphics.redraw_pending = true;
+            }
+            Command::Screenshot(f) => {
+                let result = if self.paused {
+                    Err(GraphicsError::Readback(
+                        "The window is minimized".to_owned(),
+                    ))
+                } else {
+                    screenshot(graphics, &render.device, &render.queue, &self.ui_settings)
+                };
+                f(&mut self.user_state, result);
+            }
+            Command::Quit => (),
+        }
+    }
+
     fn redraw(&mut self) {
         if self.paused || self.render.is_none() || self.graphics.is_none() {
             return;
@@ -51,6 +173,9 @@ where
         if self.dt.as_secs() > 1 {
             self.dt = Duration::from_secs(1);
         }
+        if let FrameClock::Fixed(dt) = graphics.clock {
+            self.dt = Duration::from_secs_f32(dt.max(0.));
+        }
 
         self.last_render_time = now;
 
@@ -65,52 +190,87 @@ where
             &self.render.as_ref().unwrap().queue,
         );
 
+        // Apply changes sent from other threads.
+        let updates_deltas = apply_scene_deltas(&mut graphics.scene, self.scene_deltas.try_iter());
+        process_engine_updates(&updates_deltas, graphics, &sys.device, &sys.queue);
+
         // Note that the GUI handler can also modify entities, but
         // we do that in the `init_graphics` module.
 
         // todo: move this into `render`?
-        match sys.surface.get_current_texture() {
-            wgpu::CurrentSurfaceTexture::Success(output_frame)
-            | wgpu::CurrentSurfaceTexture::Suboptimal(output_frame) => {
-                let surface_texture = output_frame
-                    .texture
-                    .create_view(&TextureViewDescriptor::default());
-
-                let resize_required = graphics.render(
-                    self.gui.as_mut().unwrap(),
-                    output_frame,
-                    &surface_texture,
-                    &sys.device,
-                    &sys.queue,
-                    self.dt,
-                    sys.surface_cfg.width,
-                    sys.surface_cfg.height,
-                    &mut self.ui_settings,
-                    &mut self.gui_handler,
-                    &mut self.user_state,
-                );
+        let (output_frame, suboptimal) = match sys.surface.get_current_texture() {
+            wgpu::CurrentSurfaceTexture::Success(output_frame) => (output_frame, false),
+            wgpu::CurrentSurfaceTexture::Suboptimal(output_frame) => (output_frame, true),
+            wgpu::CurrentSurfaceTexture::Outdated => {
+                self.reconfigure_surface(false);
+                return;
+            }
+            wgpu::CurrentSurfaceTexture::Lost => {
+                self.reconfigure_surface(true);
+                return;
+            }
+            wgpu::CurrentSurfaceTexture::Validation => {
+                warn!("Validation error acquiring the surface texture; skipping frame");
+                return;
+            }
+            // Timeout, or Occluded; skip the frame.
+            _ => return,
+        };
 
-                if resize_required {
-                    self.resize(sys.size);
-                }
+        // The surface's format may differ from the one we render in, e.g. in the browser, which
+        // only provides linear surfaces; render through a view of ours.
+        let surface_texture = output_frame.texture.create_view(&TextureViewDescriptor {
+            format: Some(sys.surface_cfg.format),
+            ..Default::default()
+        });
+
+        let resize_required = graphics.render(
+            self.gui.as_mut().unwrap(),
+            output_frame,
+            &surface_texture,
+            &sys.device,
+            &sys.queue,
+            self.dt,
+            sys.surface_cfg.width,
+            sys.surface_cfg.height,
+            &mut self.ui_settings,
+            &mut self.gui_handler,
+            &mut self.user_state,
+        );
 
-                // Apply any pending MSAA change.
-                let pending = self.graphics.as_mut().unwrap().pending_msaa.take();
-                if let Some(new_msaa) = pending {
-                    let device = &self.render.as_ref().unwrap().device;
-                    let graphics = self.graphics.as_mut().unwrap();
-                    graphics.msaa_samples = new_msaa;
-                    graphics.apply_msaa_change(device);
-                    self.graphics_settings.msaa_samples = new_msaa;
-                }
-            }
-            // Timeout, Occluded, Outdated, Lost, or Validation — skip frame.
-            _ => (),
+        if resize_required {
+            self.resize(sys.size);
+        }
+
+        #[cfg(feature = "hot_reload")]
+        self.graphics
+            .as_mut()
+            .unwrap()
+            .reload_shaders(&self.render.as_ref().unwrap().device);
+
+        // Apply any pending MSAA change.
+        let pending = self.graphics.as_mut().unwrap().pending_msaa.take();
+        if let Some(new_msaa) = pending {
+            let device = &self.render.as_ref().unwrap().device;
+            let graphics = self.graphics.as_mut().unwrap();
+            graphics.msaa_samples = new_msaa;
+            graphics.apply_msaa_change(device);
+            self.graphics_settings.msaa_samples = new_msaa;
+        }
+
+        // We've presented this frame; reconfigure so the next one matches the surface.
+        if suboptimal {
+            self.reconfigure_surface(false);
+        }
+
+        let pending = self.graphics.as_mut().unwrap().pending_present_mode.take();
+        if let Some(mode) = pending {
+            self.set_present_mode(mode);
         }
     }
 }
 
-impl<T, FRender, FEventDev, FEventWin, FGui> ApplicationHandler
+impl<T, FRender, FEventDev, FEventWin, FGui> ApplicationHandler<EngineEvent<T>>
     for State<T, FRender, FEventDev, FEventWin, FGui>
 where
     FRender: FnMut(&mut T, &mut Scene, f32) -> EngineUpdates + 'static,
@@ -119,21 +279,57 @@ where
     FGui: FnMut(&mut T, &mut egui::Ui, &mut Scene) -> EngineUpdates + 'static,
 {
     fn resumed(&mut self, event_loop: &ActiveEventLoop) {
+        // E.g. on mobile, after being suspended. The renderer already holds the scene; we keep it,
+        // and the window.
+        let Some(scene) = &mut self.scene else {
+            return;
+        };
+
         let icon = match self.ui_settings.icon_path {
-            Some(ref p) => load_icon(Path::new(&p)).ok(),
+            // A missing icon isn't worth failing over.
+            Some(ref p) => load_icon(Path::new(&p))
+                .inspect_err(|e| warn!("Unable to load the window icon at {p}: {e}"))
+                .ok(),
             // No path specified
             None => None,
         };
 
-        let requested_w = self.scene.window_size.0;
-        let requested_h = self.scene.window_size.1;
+        let monitors: Vec<_> = event_loop.available_monitors().collect();
+        let primary = event_loop.primary_monitor();
+
+        scene.monitors = monitors
+            .iter()
+            .map(|m| monitor_info(m, primary.as_ref()))
+            .collect();
 
-        // Check if the requested logical size fits on the primary monitor.
+        let (requested_w, requested_h) = scene.window_size;
+        let mut base_attributes = WindowAttributes::default()
+            .with_title(&scene.window_title)
+            .with_window_icon(icon);
+
+        if let Some((w, h)) = scene.window_min_size {
+            base_attributes = base_attributes.with_min_inner_size(LogicalSize::new(w, h));
+        }
+        if let Some((w, h)) = scene.window_max_size {
+            base_attributes = base_attributes.with_max_inner_size(LogicalSize::new(w, h));
+        }
+
+        let placement = &self.ui_settings.window_placement;
+        let chosen = placement.monitor.and_then(|i| {
+            let m = monitors.get(i).cloned();
+            if m.is_none() {
+                warn!("Monitor {i} isn't connected; opening on the primary monitor");
+            }
+            m
+        });
+        let monitor = chosen.clone().or(primary);
+
+        // Check if the requested logical size fits on the monitor.
         // On HiDPI displays the logical screen size can be smaller than the requested
         // window size (e.g. a Surface tablet at 2× DPI has ~1368×912 logical pixels),
         // so we maximise instead of creating a window that overflows the screen.
-        let fits_on_screen = event_loop
-            .primary_monitor()
+        let fits_on_screen = monitor
+            .as_ref()
             .map(|m| {
                 let scale = m.scale_factor() as f32;
                 let logical_w = m.size().width as f32 / scale;
@@ -142,19 +338,57 @@ where
             })
             .unwrap_or(true); // If monitor info unavailable, try the requested size
 
-        let base_attributes = WindowAttributes::default()
-            .with_title(&self.scene.window_title)
-            .with_window_icon(icon);
+        let maximized = placement.maximized || !fits_on_screen;
+
+        // Place the window on the monitor; a maximized window fills the one it's positioned on.
+        if let Some(m) = &monitor {
+            let scale = m.scale_factor();
+            let origin = m.position();
+
+            let offset = match placement.position {
+                Some((x, y)) => Some(LogicalPosition::new(x, y).to_physical::<i32>(scale)),
+                None if chosen.is_some() && !maximized => {
+                    let size = LogicalSize::new(requested_w, requested_h).to_physical::<u32>(scale);
+                    Some(PhysicalPosition::new(
+                        (m.size().width as i32 - size.width as i32) / 2,
+                        (m.size().height as i32 - size.height as i32) / 2,
+                    ))
+                }
+                None if chosen.is_some() => Some(PhysicalPosition::new(0, 0)),
+                None => None,
+            };
+
+            if let Some(offset) = offset {
+                base_attributes = base_attributes.with_position(PhysicalPosition::new(
+                    origin.x + offset.x,
+                    origin.y + offset.y,
+                ));
+            }
+        }
 
-        let attributes = if fits_on_screen {
-            base_attributes.with_inner_size(winit::dpi::LogicalSize::new(requested_w, requested_h))
-        } else {
+        let attributes = if maximized {
             base_attributes.with_maximized(true)
+        } else {
+            base_attributes.with_inner_size(LogicalSize::new(requested_w, requested_h))
         };
 
-        let window = event_loop.create_window(attributes).unwrap();
+        #[cfg(target_arch = "wasm32")]
+        let attributes = with_canvas(attributes, self.canvas_id.as_deref());
+
+        let result = event_loop
+            .create_window(attributes)
+            .map_err(GraphicsError::Window)
+            .and_then(|window| self.init(window));
+
+        if let Err(e) = result {
+            self.fail_init(event_loop, e);
+            return;
+        }
 
-        self.init(window);
+        // In the browser, this isn't set up until the GPU request completes.
+        if let Some(graphics) = &self.graphics {
+            graphics.window().request_redraw();
+        }
     }
 
     fn window_event(
@@ -171,8 +405,16 @@ where
         let graphics = &mut self.graphics.as_mut().unwrap();
         let gui = &mut self.gui.as_mut().unwrap();
 
+        let keyboard_in_gui = gui.egui_state.egui_ctx().egui_wants_keyboard_input();
+        graphics.scene.keyboard_in_gui = keyboard_in_gui;
+
         if !gui.mouse_in_gui {
-            graphics.handle_input_window(&event, &self.scene.input_settings);
+            let gui_key_press = keyboard_in_gui
+                && matches!(&event, WindowEvent::KeyboardInput { event, .. } if event.state == ElementState::Pressed);
+
+            if !gui_key_press {
+                graphics.handle_input_window(&event);
+            }
 
             // Handle events processed by the application
             let dt_secs = self.dt.as_secs() as f32 + self.dt.subsec_micros() as f32 / 1_000_000.;
@@ -187,34 +429,61 @@ where
             process_engine_updates(&updates_event, graphics, &render.device, &render.queue);
         }
 
-        let window = &graphics.window;
-        let _ = gui.egui_state.on_window_event(window, &event);
+        let window = graphics.window().clone();
+        let _ = gui.egui_state.on_window_event(&window, &event);
+
+        // In on-demand mode, any window input may change what's displayed, e.g. GUI hover states.
+        if !matches!(event, WindowEvent::RedrawRequested) {
+            graphics.redraw_pending = true;
+        }
 
         // Handle events processed by this engine.
         match event {
             WindowEvent::RedrawRequested => {
+                let graphics = self.graphics.as_mut().unwrap();
+                graphics.redraw_pending = false;
+
                 self.redraw();
 
-                // todo: Only request the window redraw when required from an event etc. Will be
-                // todo much more efficient this way.
-                self.graphics.as_ref().unwrap().window.request_redraw();
+                let graphics = self.graphics.as_mut().unwrap();
+                let gui = self.gui.as_ref().unwrap();
+
+                // Keep rendering while the camera is moving from held inputs, or if the render
+                // handler or GUI asked for another frame.
+                if graphics.inputs_commanded.inputs_present()
+                    || graphics.animating
+                    || graphics.lines.animated()
+                    || graphics.mesh_uploads.pending()
+                    || graphics.texture_store.pending()
+                    || gui.egui_state.egui_ctx().has_requested_repaint()
+                {
+                    graphics.redraw_pending = true;
+                }
+
+                // We request the next frame from `about_to_wait`, so it can apply the frame rate
+                // limit.
             }
             WindowEvent::CursorMoved { position, .. } => {
+                // The GUI's size is in points; the cursor's position is in physical pixels.
+                let scale = window.scale_factor();
+                let (gui_w, gui_h) = (gui.size.0 as f64 * scale, gui.size.1 as f64 * scale);
+
                 let in_ui_horizontal = match self.ui_settings.layout_sides {
-                    UiLayoutSides::Left => position.x < gui.size.0 as f64,
-                    UiLayoutSides::Right => {
-                        position.x > window.inner_size().width as f64 - gui.size.0 as f64
-                    }
+                    UiLayoutSides::Left => position.x < gui_w,
+                    UiLayoutSides::Right => position.x > window.inner_size().width as f64 - gui_w,
                 };
 
                 let in_ui_vertical = match self.ui_settings.layout_top_bottom {
-                    UiLayoutTopBottom::Top => position.y < gui.size.1 as f64,
+                    UiLayoutTopBottom::Top => position.y < gui_h,
                     UiLayoutTopBottom::Bottom => {
-                        position.y > window.inner_size().height as f64 - gui.size.1 as f64
+                        position.y > window.inner_size().height as f64 - gui_h
                     }
                 };
                 let mouse_in_gui = in_ui_horizontal || in_ui_vertical;
 
+                self.graphics.as_mut().unwrap().scene.cursor_position =
+                    Some((position.x as f32, position.y as f32));
+
                 if mouse_in_gui {
                     gui.mouse_in_gui = true;
 
@@ -237,8 +506,11 @@ where
                     self.dt = Default::default();
                 }
 
+                let graphics = self.graphics.as_mut().unwrap();
+                graphics.scene.window_maximized = graphics.window().is_maximized();
+
                 // Prevents inadvertent mouse-click-activated free-look.
-                self.graphics.as_mut().unwrap().inputs_commanded.free_look = false;
+                graphics.inputs_commanded.free_look = false;
             }
             // If the window scale changes, update the renderer size, and camera aspect ratio.
             WindowEvent::ScaleFactorChanged {
@@ -247,14 +519,17 @@ where
                 ..
             } => {
                 // Note: This appears to not come up, nor is it required. (Oct 2024)
-                println!("Scale factor changed");
+                debug!("Scale factor changed");
             }
             // If the window is being moved, disable mouse inputs, eg so click+drag
             // doesn't cause a drag when moving the window using the mouse.
-            WindowEvent::Moved(_) => {
+            WindowEvent::Moved(position) => {
                 gui.mouse_in_gui = true;
+
+                let graphics = self.graphics.as_mut().unwrap();
+                graphics.scene.window_position = Some((position.x, position.y));
                 // Prevents inadvertent mouse-click-activated free-look after moving the window.
-                self.graphics.as_mut().unwrap().inputs_commanded.free_look = false;
+                graphics.inputs_commanded.free_look = false;
             }
             WindowEvent::Occluded(occ) => {
                 self.paused = occ;
@@ -281,6 +556,7 @@ where
                 // When the cursor moves out of the window, stop mouse-looking.
                 graphics.inputs_commanded.free_look = false;
                 graphics.inputs_commanded.cursor_out_of_window = true;
+                graphics.scene.cursor_position = None;
             }
             WindowEvent::CursorEntered { device_id: _ } => {
                 self.paused = false;
@@ -290,7 +566,7 @@ where
             WindowEvent::HoveredFile(_)
             | WindowEvent::HoveredFileCancelled
             | WindowEvent::DroppedFile(_) => {
-                self.graphics.as_ref().unwrap().window.request_redraw();
+                self.graphics.as_ref().unwrap().window().request_redraw();
             }
             _ => {}
         }
@@ -310,9 +586,19 @@ where
         let graphics = &mut self.graphics.as_mut().unwrap();
         let gui = &mut self.gui.as_mut().unwrap();
 
+        let keyboard_in_gui = gui.egui_state.egui_ctx().egui_wants_keyboard_input();
+        graphics.scene.keyboard_in_gui = keyboard_in_gui;
+
         if !gui.mouse_in_gui {
+            // Key presses while typing in the GUI are for it, not the camera. Releases still pass,
+            // so keys held before focusing it don't stick.
+            let gui_key_press = keyboard_in_gui
+                && matches!(&event, DeviceEvent::Key(key) if key.state == ElementState::Pressed);
+
             // Handle events processed by this engine.
-            graphics.handle_input_device(&event, &self.scene.input_settings);
+            if !gui_key_press {
+                graphics.handle_input_device(&event);
+            }
             let inputs_present = graphics.inputs_commanded.inputs_present();
 
             // Handle events processed by the application
@@ -326,12 +612,102 @@ where
             );
 
             process_engine_updates(&updates_event, graphics, &render.device, &render.queue);
+
+            if inputs_present {
+                graphics.redraw_pending = true;
+            }
         }
     }
 
-    fn about_to_wait(&mut self, _event_loop: &ActiveEventLoop) {}
+    #[cfg_attr(not(target_arch = "wasm32"), allow(unused_variables))]
+    fn user_event(&mut self, event_loop: &ActiveEventLoop, event: EngineEvent<T>) {
+        #[cfg(target_arch = "wasm32")]
+        let event = match event {
+            EngineEvent::GpuReady(window, gpu) => {
+                match gpu.and_then(|gpu| self.init_renderer(window, gpu)) {
+                    Ok(()) => self.graphics.as_ref().unwrap().window().request_redraw(),
+                    Err(e) => self.fail_init(event_loop, e),
+                }
+                return;
+            }
+            e => e,
+        };
 
-    fn exiting(&mut self, _event_loop: &ActiveEventLoop) {}
+        if let EngineEvent::Command(command) = event {
+            self.run_command(event_loop, command);
+            return;
+        }
+
+        let (Some(render), Some(graphics)) = (&self.render, &mut self.graphics) else {
+            // Prior to init, the scene hasn't been passed to the renderer yet.
+            if let EngineEvent::Update(update) = event
+                && let Some(scene) = &mut self.scene
+            {
+                update(&mut self.user_state, scene);
+            }
+            return;
+        };
+
+        if let EngineEvent::Update(update) = event {
+            let updates = update(&mut self.user_state, &mut graphics.scene);
+            process_engine_updates(&updates, graphics, &render.device, &render.queue);
+        }
+
+        graphics.redraw_pending = true;
+    }
+
+    fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
+        let Some(graphics) = &mut self.graphics else {
+            return;
+        };
+
+        let wants_frame = !self.paused
+            && match graphics.render_mode {
+                RenderMode::Continuous => true,
+                RenderMode::OnDemand => graphics.redraw_pending,
+            };
+
+        if !wants_frame {
+            // Keep polling until readbacks from the last frame arrive, without rendering.
+            let readback_pending = self
+                .render
+                .as_ref()
+                .is_some_and(|sys| graphics.poll_readbacks(&sys.device));
+
+            event_loop.set_control_flow(if readback_pending {
+                ControlFlow::Poll
+            } else {
+                ControlFlow::Wait
+            });
+            return;
+        }
+
+        // If a frame rate limit is set, sleep until the next frame is due, instead of rendering now.
+        if let Some(min_frame_time) = graphics.min_frame_time {
+            let due = self.last_render_time + min_frame_time;
+            if Instant::now() < due {
+                event_loop.set_control_flow(ControlFlow::WaitUntil(due));
+                return;
+            }
+        }
+
+        graphics.window().request_redraw();
+        event_loop.set_control_flow(ControlFlow::Poll);
+    }
+
+    fn exiting(&mut self, _event_loop: &ActiveEventLoop) {
+        let Some(on_exit) = self.on_exit.take() else {
+            return;
+        };
+
+        // After init, the renderer holds the scene.
+        let scene = match (&mut self.graphics, &mut self.scene) {
+            (Some(graphics), _) => &mut graphics.scene,
+            (None, Some(scene)) => scene,
+            (None, None) => return,
+        };
+        on_exit(&mut self.user_state, scene);
+    }
 }
 
 /// Used in render, the text display pipeline, and may be used by applications, e.g. in mapping
@@ -371,3 +747,21 @@ pub fn viewport_rect(
 
     (x, y, eff_width, eff_height)
 }
+
+/// Shrink a viewport from `viewport_rect` to an aspect ratio (width / height), centered in it:
+/// letterboxed if it's wider than the viewport, and pillarboxed if it's narrower. This is how the
+/// engine applies `Scene::viewport_aspect`; `None` returns the viewport as is.
+pub fn letterbox(viewport: (f32, f32, f32, f32), aspect: Option<f32>) -> (f32, f32, f32, f32) {
+    let (x, y, width, height) = viewport;
+    let Some(aspect) = aspect.filter(|a| *a > 0.) else {
+        return viewport;
+    };
+
+    if width / height > aspect {
+        let w = height * aspect;
+        (x + (width - w) / 2., y, w, height)
+    } else {
+        let h = width / aspect;
+        (x, y + (height - h) / 2., width, h)
+    }
+}
@@ -0,0 +1,66 @@
+//! Golden-image tests of headless rendering. These render on the CPU with the fallback adapter,
+//! and are skipped where there isn't one. Run with `GRAPHICS_UPDATE_GOLDEN=1` to update the
+//! references in `tests/golden`.
+
+use graphics::{
+    AmbientOcclusion, Entity, GoldenTolerance, GraphicsError, GraphicsSettings, Mesh, Scene,
+    assert_golden, render_headless,
+};
+use lin_alg::f32::{Quaternion, Vec3};
+
+/// A lit box and sphere, in front of the default camera.
+fn make_scene() -> Scene {
+    let mut scene = Scene {
+        background_color: (0.1, 0.1, 0.1),
+        ..Default::default()
+    };
+    scene.add_mesh(Mesh::new_box(1., 1., 1.));
+    scene.add_mesh(Mesh::new_sphere(0.7, 3));
+
+    scene.entities.push(Entity::new(
+        0,
+        Vec3::new(-1., 0., 8.),
+        Quaternion::from_axis_angle(Vec3::new(1., 1., 0.).to_normalized(), 0.6),
+        1.,
+        (0.6, 0.1, 0.05),
+        0.,
+    ));
+    scene.entities.push(Entity::new(
+        1,
+        Vec3::new(1., 0., 8.),
+        Quaternion::new_identity(),
+        1.,
+        (0.05, 0.2, 0.6),
+        0.,
+    ));
+
+    scene
+}
+
+#[test]
+fn box_and_sphere() {
+    let settings = GraphicsSettings {
+        force_fallback_adapter: true,
+        msaa_samples: 1,
+        ambient_occlusion: AmbientOcclusion::None,
+        ..Default::default()
+    };
+
+    let image = match render_headless(&make_scene(), &settings, 320, 240) {
+        Ok(image) => image,
+        Err(GraphicsError::NoAdapter(e)) => {
+            eprintln!("Skipping; no fallback adapter: {e}");
+            return;
+        }
+        Err(e) => panic!("{e}"),
+    };
+
+    assert_golden(
+        &image,
+        "tests/golden/box_and_sphere.png",
+        GoldenTolerance {
+            channel: 4,
+            pixels: 0.005,
+        },
+    );
+}